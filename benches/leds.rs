@@ -0,0 +1,131 @@
+//! How long matching rows to LEDs and working out the lit LEDs take, on a
+//! synthetic oval track with 20 cars, for races of a few hundred to tens of
+//! thousands of rows. Run with `cargo bench`.
+
+use chrono::{DateTime, Duration, Utc};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use eframe::egui::Color32;
+use std::sync::Arc;
+
+use f1_led_circuit_simulation::data::{LedCoordinate, RunRace};
+use f1_led_circuit_simulation::dataset::Dataset;
+use f1_led_circuit_simulation::drivers::Driver;
+use f1_led_circuit_simulation::sim::{Simulation, DEFAULT_CHECKPOINT_INTERVAL_MS};
+use f1_led_circuit_simulation::track::LedIndex;
+
+const LEDS: usize = 400;
+const CARS: usize = 20;
+/// Rows in the whole race, shared between the cars.
+const RACE_ROWS: [usize; 3] = [400, 4_000, 40_000];
+const ROW_MS: i64 = 250;
+
+/// LEDs around an oval, about one unit apart.
+fn coordinates() -> Vec<LedCoordinate> {
+    (0..LEDS)
+        .map(|led| {
+            let angle = std::f64::consts::TAU * led as f64 / LEDS as f64;
+            LedCoordinate { x_led: 100.0 * angle.cos(), y_led: 40.0 * angle.sin(), ..Default::default() }
+        })
+        .collect()
+}
+
+/// Rows of a car lapping the oval a little off the LEDs, each car a bit
+/// faster than the one before and starting further back on the grid.
+fn rows(car: usize, count: usize) -> Vec<RunRace> {
+    let start = "2023-08-27T13:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let laps_per_row = (1.0 + car as f64 / 100.0) / 240.0;
+    (0..count)
+        .map(|row| {
+            let angle = std::f64::consts::TAU * (row as f64 * laps_per_row - car as f64 / 400.0);
+            let wobble = 1.0 + 0.004 * (row as f64 * 0.7).sin();
+            RunRace {
+                date: start + Duration::milliseconds(row as i64 * ROW_MS + car as i64 * 7),
+                x_led: 100.0 * wobble * angle.cos(),
+                y_led: 40.0 * wobble * angle.sin(),
+                time_delta: ROW_MS as u64,
+                line: row as u64 + 1,
+                telemetry: Default::default(),
+            }
+        })
+        .collect()
+}
+
+fn race(race_rows: usize) -> (Vec<LedCoordinate>, Arc<LedIndex>, Vec<Vec<RunRace>>) {
+    let coordinates = coordinates();
+    let led_index = Arc::new(LedIndex::new(&coordinates));
+    let cars = (0..CARS).map(|car| rows(car, race_rows / CARS)).collect();
+    (coordinates, led_index, cars)
+}
+
+fn simulation(race_rows: usize) -> Simulation {
+    let (coordinates, led_index, cars) = race(race_rows);
+    let drivers = cars.iter().map(|rows| Driver::new(Dataset::from_rows(rows, &led_index, 0), Color32::RED)).collect();
+    let mut sim = Simulation::new(coordinates, led_index, drivers);
+    sim.trail_length = 10;
+    sim
+}
+
+/// Finding the nearest LED of every row, as loading a race does.
+fn match_rows(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match_rows");
+    for race_rows in RACE_ROWS {
+        let (_, led_index, cars) = race(race_rows);
+        group.bench_with_input(BenchmarkId::from_parameter(race_rows), &cars, |b, cars| {
+            b.iter(|| cars.iter().map(|rows| Dataset::from_rows(black_box(rows), &led_index, 0)).collect::<Vec<_>>())
+        });
+    }
+    group.finish();
+}
+
+/// The LEDs lit at a quarter, half and the end of the race, from the
+/// datasets alone.
+fn leds_at(c: &mut Criterion) {
+    let mut group = c.benchmark_group("leds_at");
+    for race_rows in RACE_ROWS {
+        let sim = simulation(race_rows);
+        let moments = sim.moments();
+        for depth in [moments / 4, moments / 2, moments] {
+            group.bench_with_input(BenchmarkId::new(race_rows.to_string(), depth), &depth, |b, &depth| b.iter(|| sim.leds_at(black_box(depth))));
+        }
+    }
+    group.finish();
+}
+
+/// Playing one moment in and lighting the LEDs, as every frame of playback
+/// does, from halfway through the race.
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    for race_rows in RACE_ROWS {
+        let mut sim = simulation(race_rows);
+        let halfway = sim.moments() / 2;
+        sim.seek(halfway);
+        group.bench_function(BenchmarkId::from_parameter(race_rows), |b| {
+            b.iter(|| {
+                if !sim.advance() {
+                    sim.seek(halfway);
+                }
+                black_box(sim.lit_leds())
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Seeking back to the end of the race, as after a step back or a jump on
+/// the timeline, replaying from the start or from the last checkpoint.
+fn seek(c: &mut Criterion) {
+    let mut group = c.benchmark_group("seek");
+    for race_rows in RACE_ROWS {
+        for checkpoints in [None, Some(DEFAULT_CHECKPOINT_INTERVAL_MS / 20)] {
+            let mut sim = simulation(race_rows);
+            sim.set_checkpoint_interval(checkpoints);
+            let end = sim.moments();
+            let name = if checkpoints.is_some() { "checkpoints" } else { "from_start" };
+            group.bench_function(BenchmarkId::new(name, race_rows), |b| b.iter(|| sim.seek(black_box(end))));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, match_rows, leds_at, step, seek);
+criterion_main!(benches);
@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::sim::Transition;
+
+/// Entries the log keeps, the oldest dropped first.
+pub const CAPACITY: usize = 5000;
+
+/// What an entry is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Car(usize), // By dataset
+    Flag,
+    Reload,
+    Output,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub at: DateTime<Utc>, // Race time
+    pub source: Source,
+    pub text: String,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.at.format("%H:%M:%S%.3f"), self.text)
+    }
+}
+
+/// The last `CAPACITY` things playback did: cars moving between LEDs, as
+/// the simulation sends them, flag changes, reloads and output errors.
+/// Every entry is also written as a line to the sink, if there is one, so
+/// the log can be followed without the window.
+pub struct ActivityLog {
+    entries: VecDeque<Entry>,
+    sender: Sender<Transition>,
+    transitions: Receiver<Transition>,
+    sink: Option<Box<dyn Write + Send>>,
+}
+
+impl ActivityLog {
+    pub fn new(sink: Option<Box<dyn Write + Send>>) -> Self {
+        let (sender, transitions) = mpsc::channel();
+        Self { entries: VecDeque::new(), sender, transitions, sink }
+    }
+
+    /// A sender for the simulation to hand its transitions to.
+    pub fn sender(&self) -> Sender<Transition> {
+        self.sender.clone()
+    }
+
+    /// Adds the transitions sent since the last call, dated from
+    /// `session_start` and each car named by its dataset's entry in `codes`.
+    pub fn poll(&mut self, session_start: DateTime<Utc>, codes: &[String]) {
+        while let Ok(Transition { dataset_idx, sim_ms, led }) = self.transitions.try_recv() {
+            let code = codes.get(dataset_idx).map_or("?", String::as_str);
+            let text = match led {
+                Some(led) => format!("{code} → LED {led}"),
+                None => format!("{code} off the map"),
+            };
+            self.push(session_start + chrono::Duration::milliseconds(sim_ms as i64), Source::Car(dataset_idx), text);
+        }
+    }
+
+    pub fn push(&mut self, at: DateTime<Utc>, source: Source, text: String) {
+        let entry = Entry { at, source, text };
+        if let Some(sink) = &mut self.sink {
+            if let Err(e) = writeln!(sink, "{entry}") {
+                eprintln!("warning: activity log stopped: {e}");
+                self.sink = None;
+            }
+        }
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> &VecDeque<Entry> {
+        &self.entries
+    }
+
+    /// The entries `shown` picks, one a line, as copied or saved.
+    pub fn text(&self, shown: impl Fn(&Entry) -> bool) -> String {
+        self.entries.iter().filter(|entry| shown(entry)).map(|entry| format!("{entry}\n")).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A sink whose lines the test can read back.
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn names_the_cars_keeps_the_latest_entries_and_writes_them_out() {
+        let lines = Lines::default();
+        let mut log = ActivityLog::new(Some(Box::new(lines.clone())));
+        let start = "2023-08-27T13:03:04Z".parse::<DateTime<Utc>>().unwrap();
+        let sender = log.sender();
+        sender.send(Transition { dataset_idx: 0, sim_ms: 213, led: Some(47) }).unwrap();
+        sender.send(Transition { dataset_idx: 1, sim_ms: 391, led: None }).unwrap();
+        log.poll(start, &["VER".to_string(), "HAM".to_string()]);
+        log.push(start, Source::Reload, "Reloaded led_coords.csv".to_string());
+        assert_eq!(
+            log.text(|_| true),
+            "13:03:04.213 VER → LED 47\n13:03:04.391 HAM off the map\n13:03:04.000 Reloaded led_coords.csv\n"
+        );
+        assert_eq!(log.text(|entry| entry.source == Source::Car(1)), "13:03:04.391 HAM off the map\n");
+        assert_eq!(String::from_utf8(lines.0.lock().unwrap().clone()).unwrap(), log.text(|_| true));
+
+        for led in 0..CAPACITY {
+            sender.send(Transition { dataset_idx: 0, sim_ms: 1000, led: Some(led) }).unwrap();
+        }
+        log.poll(start, &[]);
+        assert_eq!(log.entries().len(), CAPACITY);
+        assert_eq!(log.entries()[0].text, "? → LED 0", "the oldest three are gone");
+    }
+}
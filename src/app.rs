@@ -0,0 +1,5938 @@
+use clap::Parser;
+use eframe::{egui, App, Frame};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+use web_time::Instant;
+use chrono::{DateTime, NaiveTime, Utc};
+
+use crate::bookmarks::{self, format_sim_ms, Bookmark};
+use crate::cli::Cli;
+use crate::clock::SimulationClock;
+use crate::activity::{ActivityLog, Entry, Source};
+use crate::config::Config;
+use crate::correction::{self, ColorCorrection};
+use crate::cues::{Cue, CueDetector};
+use crate::data::{self, DataIssue, LedCoordinate, RunRace, Validated};
+use crate::dataset::{self, Dataset, TrimWindow};
+use crate::drivers::{ColorOverrides, Driver, DriverTable};
+use crate::event_log::EventLog;
+use crate::events::{self, RaceEvents};
+use crate::export::{Clip, ClipFormat, Export};
+use crate::frames::{self, LedFrame};
+use crate::grid::{self, Grid};
+use crate::heatmap::{self, Gradient};
+use crate::labels;
+use crate::loader::{self, FileProgress, LoadedRace, PendingDataset, PendingRace, ReadOptions};
+use crate::mqtt::{Mqtt, MqttStatus};
+use crate::output::{self, Output, OutputStatus};
+use crate::palette::Palette;
+use crate::perf::{self, FrameStats};
+use crate::playlist::{LoadedEntry, Playlist, PlaylistEntry};
+use crate::progress::{self, CarProgress};
+use crate::recording::{self, Event, Header, RecordedDriver, Recorder, Recording, Replay};
+use crate::render::{self, Backdrop, Bounds, Camera, LedShape, LedSize, Projection, Theme, TrackStyle, OUTLINE_WIDTH, SECTOR_LINE_WIDTH, UNLIT_OUTLINE_WIDTH};
+use crate::results::{self, Classified, Status};
+use crate::sectors::Sectors;
+use crate::serve::{LedServer, ServerStatus};
+use crate::session::{Session, SESSION_VERSION};
+use crate::settings::{Settings, SETTINGS_KEY};
+use crate::setup::Setup;
+use crate::sim::{Simulation, TrailMode, MAX_TRAIL_LENGTH};
+use crate::snapshot::{CarSnapshot, LitLed, Snapshot};
+use crate::timestamp::{ClockMode, RaceTime};
+use crate::track::{LedIndex, Spacing};
+use crate::units;
+use crate::watch::FileWatcher;
+use crate::window::{CursorHider, WindowOptions};
+
+struct PlotApp {
+    sim: Simulation, // Rows shown on the LEDs so far
+    clock: SimulationClock, // Simulated time, rows are shown as it reaches them
+    race_started: bool,
+    race_complete: bool, // Set once every dataset has been played to its end
+    paused: bool, // Holds playback at the current index while the race is started
+    reverse: bool, // Play backwards towards the first row
+    looping: bool, // Start again once the race is complete
+    read_options: ReadOptions, // How datasets are read, also when switching races or reloading
+    loop_pause: Duration, // How long the final state is shown before starting again
+    tick: Duration, // Time between frames while cars move smoothly, from the tick rate
+    loop_at: DateTime<Utc>, // Wall time a complete race starts again when looping
+    skip_gaps: bool, // Wait at most `max_gap` for the next row
+    max_gap: Duration,
+    skipped: Option<(u64, DateTime<Utc>)>, // Simulated time the last skip jumped over, noted until the wall time
+    palette: Palette, // Where the colors come from
+    driver_codes: BTreeMap<String, String>, // Configured codes, used when switching races
+    driver_offsets: BTreeMap<String, f64>, // Configured seconds added to each driver's dates
+    driver_table: DriverTable, // Names, teams, codes and colors from the metadata file; empty without one
+    color_overrides: ColorOverrides, // Colors picked by driver name, over the palette and metadata
+    events: RaceEvents, // Flags and messages from race control; empty without an events file
+    grid: Grid, // Where the drivers are parked before the start; empty without a grid file
+    start_lights: Duration, // How long the red lights take to come on before lights out
+    lights_since: Option<DateTime<Utc>>, // Wall time the start lights came on, until lights out
+    lights_out_at: Option<DateTime<Utc>>, // Wall time of the last lights out, while it shows
+    countdown: bool, // Start lights before every start, not only for a race with a grid
+    sectors: Sectors, // Sector of each LED; empty without a sectors file
+    show_labels: bool,
+    show_led_codes: bool, // Each car's code drawn on its current LED
+    stack_offset: bool, // Cars sharing an LED drawn as a cluster of dots around it
+    keep_aspect: bool, // Letterbox the track instead of stretching it to the view
+    led_shape: LedShape,
+    led_size: LedSize,
+    led_spacing: Spacing, // Of the coordinates, for LEDs sized by their spacing
+    unlit_outline: Option<egui::Color32>,
+    show_outline: bool,
+    smooth: bool, // Move a marker between rows instead of jumping from LED to LED
+    interpolate: bool, // Light the LED nearest to each car's position between rows
+    throttle_brightness: bool, // Brighten the telemetry driver's LEDs with their throttle
+    outline: Vec<Vec<usize>>, // LEDs to join with a line, one list per segment
+    outline_color: egui::Color32,
+    coordinate_issues: Vec<DataIssue>, // Problems found in the coordinates file
+    data_issues: Vec<DataIssue>, // Problems found in the race data files
+    speed: f64, // Playback speed multiplier applied to every time_delta
+    data_dir: PathBuf, // Folder whose subfolders are offered as races
+    races: Vec<String>,
+    selected_race: Option<String>,
+    lap_starts: Vec<u64>, // Simulated time the leader began each lap, the last one being the finish
+    lap_starts_job: Option<Receiver<Vec<u64>>>, // The lap starts of the race loaded, worked out on another thread
+    sound: bool, // Ring the terminal bell when a car completes a lap
+    lap_flash_until: Option<DateTime<Utc>>, // Wall time the start/finish LED stops flashing
+    lap_bell_at: Option<DateTime<Utc>>, // Wall time the bell last rang
+    cues: BTreeSet<Cue>, // Moments of the race that pulse the track white
+    cue_sound: bool, // Ring the terminal bell with each cue too, or play a tone with the sound feature
+    #[cfg(feature = "sound")]
+    chime: std::cell::OnceCell<Option<crate::sound::Chime>>, // Audio output for the cue tone, opened the first time it plays
+    cue_detector: CueDetector,
+    cue_flash_until: Option<DateTime<Utc>>, // Wall time the pulse for the last cue ends
+    highlighted: Option<usize>, // Dataset whose LED gets a ring, chosen in the leaderboard
+    following: Option<usize>, // Dataset whose current LED the track view keeps in the middle
+    follow_zoom: bool, // Following from the fitted view zooms in to `FOLLOW_ZOOM`
+    zoom: f32, // Magnification of the track view, 1.0 fitting the whole track
+    focus: egui::Vec2, // Point of the fitted track shown in the middle of the view, as a fraction of its size
+    compare: Option<Compare>, // Set while the track view shows two drivers side by side
+    reference_path: Option<PathBuf>, // Folder of the race played faintly behind this one
+    reference: Option<ReferenceRace>, // Loaded from `reference_path`, on the same LEDs
+    reference_load: Option<PendingRace>, // The reference race being read on other threads
+    show_reference: bool,
+    metres_per_unit: Option<f64>, // Shows car speeds in km/h and gaps in metres when set
+    session_path: PathBuf, // Where the Save button writes the session
+    status: Option<String>, // Result of the last save, load or screenshot, shown in the top bar
+    jump_text: String, // Race time typed into the jump box
+    jump_error: Option<String>, // Why the jump box text was rejected, shown next to it
+    bookmarks: Vec<Bookmark>, // Sorted by simulated time
+    bookmark_label: String, // Label for the next bookmark
+    defaults: Settings, // Settings from the command line and config, restored by Reset to defaults
+    screenshot_dir: PathBuf,
+    view_size: egui::Vec2, // Size of the track view last frame, used for screenshots
+    export: Option<Export>, // Export running in the background
+    clip_dialog: Option<ClipDialog>, // Set while the clip export window is open
+    led_passes: Option<LedPasses>, // Set while the window for an LED clicked in the track view is open
+    output: Option<Output>, // Network output to external LED controllers
+    mqtt: Option<Mqtt>, // LED colors published to an MQTT broker
+    server: Option<LedServer>, // LED colors served to WebSocket clients
+    record_path: Option<PathBuf>, // Where what the track shows is recorded to
+    recorder: Option<Recorder>,
+    recordings: usize, // Recording files started, the next one numbered after them
+    event_log_path: Option<PathBuf>, // Where a row is logged each time playback moves on
+    event_log: Option<EventLog>,
+    activity: ActivityLog, // What playback just did, fed by the simulation
+    activity_filter: Option<usize>, // Dataset whose moves the activity panel shows alone
+    activity_scroll: bool, // The activity panel keeps to its latest entry
+    results_closed: bool, // The results window was closed, until playback goes back
+    logged_flag: Option<events::Flag>,
+    logged_errors: [Option<String>; 3], // Of the network output, MQTT and the WebSocket server
+    replay: Option<Replay>, // Set when playing a recording instead of race data
+    strip_positions: Vec<usize>, // Position of each LED on the physical strip
+    coordinates_path: PathBuf,
+    snap_distance: Option<f64>, // Furthest a row may be from its LED, see `LedIndex::snap`
+    snapping: Option<Snapping>, // The race read again for a new snap distance
+    reloading: Option<Reloading>, // The race read again for changed files or LEDs
+    snap_dragged: Option<Option<f64>>, // Snap distance the slider is being dragged to, matched once let go
+    trim_times: (Option<RaceTime>, Option<RaceTime>), // Window of the race to keep, from the config
+    trim: Option<TrimWindow>, // Dates the loaded race was trimmed to, for reloads of it
+    dataset_paths: Vec<PathBuf>, // Files the loaded race was read from, including any that failed
+    watch: bool, // Reload the data files when they change
+    watcher: Option<FileWatcher>,
+    reloads: Vec<PendingDataset>, // Changed datasets being read again
+    startup: Option<Startup>, // Set while the datasets are loading in the background
+    startup_failed: Option<(String, Vec<String>)>, // Why the race loaded at startup cannot play, and the data problems behind it
+    playlist: Option<Playlist>, // Races played back to back, from the config
+    stepped: Option<(usize, Vec<SteppedRow>)>, // Rows the last step showed, for the index they are of
+    stepped_reads: Option<(usize, Receiver<Vec<Option<RunRace>>>)>, // Rows of a step being read again from their files, one step at a time
+    setup: Option<Setup>, // Set while the screen for choosing the data files is shown
+    frame_stats: FrameStats,
+    show_frame_stats: bool, // Overlay with the frame rate and where frames spend their time, toggled with F12
+    window: WindowOptions,
+    window_placed: bool, // The window was put where `window` asks, over the state kept from last time
+    cursor: Option<CursorHider>, // Hides the pointer when it stays still, if asked for
+    clock_mode: ClockMode, // What the clock in the top bar shows
+    theme: Theme,
+    backdrop: Backdrop, // What the track is drawn on
+    frame: perf::Frame, // Timings of the frame being drawn
+    wake_reason: &'static str, // Why the next frame was asked for
+}
+
+/// A row the last step showed, as the step inspector lists it.
+struct SteppedRow {
+    dataset_idx: usize,
+    line: u32, // Line of the dataset file, 0 if unknown
+    date: Option<DateTime<Utc>>,
+    position: Option<(f64, f64)>, // As written in the file, if it could be read again
+    led: Option<usize>, // None while off the map
+}
+
+/// Another race on the same LEDs, played faintly behind the loaded one.
+/// Its own first row starts its clock, so it keeps time into the race
+/// however far apart the two were recorded.
+struct ReferenceRace {
+    sim: Simulation,
+    name: String, // Folder it was read from, shown next to the toggle
+}
+
+/// The race being matched to the LEDs again for a new snap distance,
+/// swapped in once every file is read.
+struct Snapping {
+    distance: Option<f64>,
+    led_index: Arc<LedIndex>,
+    race: PendingRace,
+}
+
+/// The race read again on other threads, as files changed or for new
+/// LEDs. `finish_reloading` swaps it in.
+struct Reloading {
+    coordinates: Option<Validated<LedCoordinate>>, // LEDs the race is matched to, `None` for the ones there are
+    moved: Option<Vec<Option<usize>>>, // New id of each LED there is, when some are left out
+    led_index: Arc<LedIndex>,
+    race: PendingRace,
+    done: String, // Said once it is swapped in
+}
+
+/// Work held back until a race's datasets have finished loading, the
+/// startup ones or a race picked after.
+struct Startup {
+    race: PendingRace,
+    strict: bool, // Refuse to play if any data problem turns up
+    autostart: bool,
+    settings: Option<Settings>, // Stored settings, applied once the drivers are known
+    session: Option<Session>, // Session given with --resume
+    start_at: Option<RaceTime>, // Where playback opens, after any session
+    race_dir: Option<PathBuf>, // Folder of a race picked after startup, for its events and grid
+}
+
+/// Smallest font, in points, driver codes are drawn on LEDs in. Smaller
+/// LEDs show no code, as it could not be read.
+const MIN_LED_CODE_SIZE: f32 = 6.0;
+
+/// Longest the window goes without redrawing, so the output status and
+/// anything else not driven by playback stays current.
+const IDLE_REPAINT: Duration = Duration::from_secs(1);
+
+/// Seconds offered for the longest wait when skipping gaps is turned on in the app.
+const DEFAULT_MAX_GAP: f64 = 2.0;
+
+/// How long the top bar says how much a skip jumped over.
+const SKIP_NOTICE: Duration = Duration::from_secs(3);
+
+/// How long the start/finish LED flashes white after a car completes a lap.
+const LAP_FLASH: Duration = Duration::from_millis(300);
+
+/// How long "LIGHTS OUT" fades over the track once the start lights go out.
+const LIGHTS_OUT_SHOWN: Duration = Duration::from_secs(1);
+
+/// How long the whole track pulses white for a cue.
+const CUE_FLASH: Duration = Duration::from_millis(300);
+
+/// Shortest time between two lap bells, so cars crossing close together ring once.
+const LAP_BELL_GAP: Duration = Duration::from_secs(1);
+
+/// How often a running export's progress bar is redrawn.
+const PROGRESS_REPAINT: Duration = Duration::from_millis(100);
+
+/// How far the track view zooms in when following a driver from the fitted view.
+const FOLLOW_ZOOM: f32 = 3.0;
+
+const MAX_ZOOM: f32 = 10.0;
+
+/// Largest snap distance the slider goes to, in LED spacings.
+const MAX_SNAP_SPACINGS: f64 = 50.0;
+
+/// Size of the overview of the whole track shown while zoomed in.
+const MINIMAP_SIZE: egui::Vec2 = egui::vec2(200.0, 140.0);
+
+/// The middle of the track, shown in the middle of the fitted view.
+const FITTED_FOCUS: egui::Vec2 = egui::vec2(0.5, 0.5);
+
+/// Seconds the camera takes to get about two thirds of the way to the
+/// followed car, so it glides instead of jumping from LED to LED.
+const CAMERA_EASE: f32 = 0.2;
+
+/// Fraction of the LED spacing the LEDs get when automatic sizing is turned on in the app.
+const DEFAULT_LED_SPACING: f32 = 0.8;
+
+/// Edge offered for unlit LEDs, dim enough not to pass for a lit one.
+const DEFAULT_UNLIT_OUTLINE: egui::Color32 = egui::Color32::from_rgb(48, 48, 48);
+
+/// How much of its color a ghost keeps: the other driver in comparison
+/// mode, or a car of the reference race.
+const GHOST_ALPHA: f32 = 0.3;
+
+/// How far cars stacked on one LED are fanned out from its center, and how
+/// large each dot is, as fractions of the LED's size.
+const STACK_RADIUS: f32 = 0.6;
+const STACK_DOT_RADIUS: f32 = 0.3;
+
+/// Clips estimated larger than this ask to be confirmed before exporting.
+const LARGE_CLIP_BYTES: u64 = 100_000_000;
+
+/// What the clip export window asks for.
+struct ClipDialog {
+    start_ms: u64, // Simulated time of the first frame
+    end_ms: u64,
+    size: [u32; 2],
+    fps: u32,
+    format: ClipFormat,
+}
+
+/// The cars that went through an LED clicked in the track view.
+struct LedPasses {
+    led: usize,
+    arrivals: Vec<(usize, usize, u64)>, // Dataset, index that shows it on the LED and simulated time
+}
+
+/// Two drivers shown side by side in their own copy of the track view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Compare {
+    drivers: [usize; 2], // Dataset of the left and right pane
+    ghost: bool, // Show the other driver faintly in each pane
+}
+
+/// Startup options taken from the command line and config file.
+struct PlaybackOptions {
+    speed: f64,
+    autostart: bool,
+    looping: bool,
+    loop_pause: f64, // Seconds
+    tick_rate: f64, // Frames per second
+    max_gap: Option<f64>, // Seconds
+    checkpoint_interval: f64, // Seconds, 0 for no checkpoints
+    read_options: ReadOptions,
+    data_dir: PathBuf,
+    race: Option<String>,
+    start_finish_led: Option<usize>,
+    lap_debounce_leds: usize,
+    sound: bool,
+    cues: BTreeSet<Cue>,
+    cue_sound: bool,
+    trail_length: usize,
+    trail_mode: TrailMode,
+    stopped_after: usize,
+    driver_codes: BTreeMap<String, String>,
+    session_start: Option<DateTime<Utc>>,
+    driver_offsets: BTreeMap<String, f64>,
+    drivers: DriverTable,
+    events: RaceEvents,
+    grid: Grid,
+    start_lights: f64, // Seconds
+    countdown: bool,
+    sectors: Sectors,
+    labels: bool,
+    led_codes: bool,
+    stack_offset: bool,
+    keep_aspect: bool,
+    led_shape: LedShape,
+    led_size: LedSize,
+    unlit_outline: Option<[u8; 3]>,
+    outline: bool,
+    outline_color: [u8; 3],
+    smooth: bool,
+    interpolate: bool,
+    metres_per_unit: Option<f64>,
+    palette: Palette,
+    session_path: PathBuf,
+    screenshot_dir: PathBuf,
+    output: Option<Output>,
+    mqtt: Option<Mqtt>,
+    server: Option<LedServer>,
+    record: Option<PathBuf>,
+    event_log: Option<PathBuf>,
+    activity_log: Option<PathBuf>,
+    window: WindowOptions,
+    watch: bool,
+    coordinates_path: PathBuf,
+    snap_distance: Option<f64>,
+    trim_times: (Option<RaceTime>, Option<RaceTime>), // `--start-time` and `--end-time`
+    reference: Option<PathBuf>,
+    playlist: Vec<PlaylistEntry>,
+}
+
+impl PlotApp {
+    fn new(
+        coordinates: Vec<LedCoordinate>,
+        coordinate_issues: Vec<DataIssue>,
+        race: LoadedRace,
+        options: PlaybackOptions,
+    ) -> Self {
+        let defaults = Settings {
+            speed: options.speed,
+            trail_length: options.trail_length,
+            trail_mode: options.trail_mode,
+            palette: options.palette,
+            show_labels: options.labels,
+            show_led_codes: options.led_codes,
+            stack_offset: options.stack_offset,
+            show_frame_stats: false,
+            clock_mode: ClockMode::default(),
+            theme: Theme::default(),
+            backdrop: Backdrop::default(),
+            keep_aspect: options.keep_aspect,
+            led_shape: options.led_shape,
+            led_size: options.led_size,
+            unlit_outline: options.unlit_outline,
+            show_outline: options.outline,
+            follow_zoom: true,
+            snap_distance: options.snap_distance,
+            smooth: options.smooth,
+            interpolate: options.interpolate,
+            throttle_brightness: false,
+            brightness: 100.0,
+            gamma: 1.0,
+            cues: options.cues.clone(),
+            cue_sound: options.cue_sound,
+            visible: BTreeMap::new(),
+            color_overrides: BTreeMap::new(),
+            data_dir: Some(options.data_dir.clone()),
+            coordinates: Some(options.coordinates_path.clone()),
+            driver_offsets: options.driver_offsets.clone(),
+        };
+        let strip_positions = output::strip_positions(&coordinates);
+        let outline = render::outline(&coordinates, &strip_positions);
+        let sectors = options.sectors.with_outline(&outline);
+        let led_index = Arc::new(LedIndex::new(&coordinates).with_snap_distance(options.snap_distance));
+        let led_spacing = Spacing::of(&coordinates);
+        let mut sim = Simulation::new(coordinates, led_index, race.drivers);
+        sim.trail_length = options.trail_length.min(MAX_TRAIL_LENGTH);
+        sim.trail_mode = options.trail_mode;
+        sim.start_finish_led = options.start_finish_led;
+        sim.lap_debounce_leds = options.lap_debounce_leds;
+        sim.stopped_after = options.stopped_after;
+        sim.set_checkpoint_interval(Some((options.checkpoint_interval * 1000.0) as u64));
+        let activity = ActivityLog::new(options.activity_log.as_deref().and_then(activity_sink));
+        sim.send_transitions(Some(activity.sender()));
+        let mut app = Self {
+            sim,
+            clock: SimulationClock::new(options.speed),
+            race_started: options.autostart,
+            race_complete: false,
+            paused: false,
+            reverse: false,
+            looping: options.looping,
+            read_options: options.read_options,
+            loop_pause: Duration::from_secs_f64(options.loop_pause),
+            tick: Duration::from_secs_f64(1.0 / options.tick_rate),
+            skip_gaps: options.max_gap.is_some(),
+            max_gap: Duration::from_secs_f64(options.max_gap.unwrap_or(DEFAULT_MAX_GAP)),
+            skipped: None,
+            loop_at: Utc::now(),
+            palette: options.palette,
+            driver_codes: options.driver_codes,
+            driver_offsets: options.driver_offsets,
+            driver_table: options.drivers,
+            color_overrides: ColorOverrides::default(),
+            events: options.events,
+            grid: options.grid,
+            start_lights: Duration::from_secs_f64(options.start_lights),
+            lights_since: None,
+            lights_out_at: None,
+            countdown: options.countdown,
+            sectors,
+            show_labels: options.labels,
+            show_led_codes: options.led_codes,
+            stack_offset: options.stack_offset,
+            keep_aspect: options.keep_aspect,
+            led_shape: options.led_shape,
+            led_size: options.led_size,
+            led_spacing,
+            unlit_outline: options.unlit_outline.map(|[r, g, b]| egui::Color32::from_rgb(r, g, b)),
+            show_outline: options.outline,
+            smooth: options.smooth,
+            interpolate: options.interpolate,
+            throttle_brightness: false,
+            outline,
+            outline_color: egui::Color32::from_rgb(options.outline_color[0], options.outline_color[1], options.outline_color[2]),
+            coordinate_issues,
+            data_issues: race.issues,
+            speed: options.speed,
+            races: loader::list_races(&options.data_dir),
+            data_dir: options.data_dir,
+            selected_race: options.race,
+            lap_starts: Vec::new(),
+            lap_starts_job: None,
+            sound: options.sound,
+            lap_flash_until: None,
+            cues: options.cues.clone(),
+            cue_sound: options.cue_sound,
+            #[cfg(feature = "sound")]
+            chime: std::cell::OnceCell::new(),
+            cue_detector: CueDetector::default(),
+            cue_flash_until: None,
+            lap_bell_at: None,
+            highlighted: None,
+            following: None,
+            follow_zoom: true,
+            compare: None,
+            reference_path: options.reference,
+            reference: None,
+            reference_load: None,
+            show_reference: true,
+            zoom: 1.0,
+            focus: FITTED_FOCUS,
+            metres_per_unit: options.metres_per_unit,
+            defaults,
+            session_path: options.session_path,
+            status: None,
+            jump_text: String::new(),
+            jump_error: None,
+            bookmarks: Vec::new(),
+            bookmark_label: String::new(),
+            screenshot_dir: options.screenshot_dir,
+            view_size: egui::vec2(800.0, 600.0),
+            export: None,
+            clip_dialog: None,
+            led_passes: None,
+            output: options.output,
+            mqtt: options.mqtt,
+            server: options.server,
+            record_path: options.record,
+            recordings: 0,
+            recorder: None,
+            event_log_path: options.event_log,
+            event_log: None,
+            activity,
+            activity_filter: None,
+            activity_scroll: true,
+            results_closed: false,
+            logged_flag: None,
+            logged_errors: [None, None, None],
+            replay: None,
+            strip_positions,
+            coordinates_path: options.coordinates_path,
+            snap_distance: options.snap_distance,
+            snapping: None,
+            reloading: None,
+            snap_dragged: None,
+            trim_times: options.trim_times,
+            trim: None,
+            dataset_paths: Vec::new(),
+            watch: options.watch,
+            watcher: None,
+            reloads: Vec::new(),
+            startup: None,
+            startup_failed: None,
+            playlist: (!options.playlist.is_empty()).then(|| Playlist::new(options.playlist)),
+            stepped: None,
+            stepped_reads: None,
+            setup: None,
+            frame_stats: FrameStats::default(),
+            show_frame_stats: false,
+            cursor: options.window.hide_cursor_after.map(|after| CursorHider::new(after, Instant::now())),
+            window: options.window,
+            window_placed: false,
+            clock_mode: ClockMode::default(),
+            theme: Theme::default(),
+            backdrop: Backdrop::default(),
+            frame: perf::Frame::default(),
+            wake_reason: "start",
+        };
+        let offsets_ms = app.manual_offsets_ms(&app.sim.drivers);
+        app.sim.set_alignment(options.session_start, offsets_ms);
+        app.apply_driver_info();
+        app.load_reference();
+        app
+    }
+
+    /// Starts reading the reference race onto the loaded LEDs on other
+    /// threads. `finish_reference` shows it once it is read.
+    fn load_reference(&mut self) {
+        self.reference = None;
+        self.reference_load = self.reference_path.as_ref().map(|dir| {
+            let (paths, issues) = loader::race_dir_paths(dir);
+            PendingRace::start(paths, issues, self.sim.led_index.clone(), self.read_options)
+        });
+    }
+
+    /// Takes the reference race once it is read, or leaves it out with a
+    /// warning if it has no datasets on the LEDs.
+    fn finish_reference(&mut self) {
+        if !self.reference_load.as_mut().is_some_and(|race| race.poll()) {
+            return;
+        }
+        let (Some(race), Some(dir)) = (self.reference_load.take(), &self.reference_path) else {
+            return;
+        };
+        let race = race.finish(self.palette, &self.driver_codes);
+        for issue in &race.issues {
+            eprintln!("warning: reference race: {issue}");
+        }
+        if race.drivers.is_empty() {
+            eprintln!("warning: no reference race loaded from {}", dir.display());
+            return;
+        }
+        let name = dir.file_name().map_or_else(|| dir.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let sim = Simulation::new(self.sim.coordinates.clone(), self.sim.led_index.clone(), race.drivers);
+        self.reference = Some(ReferenceRace { sim, name });
+    }
+
+    /// Brings the reference race to the same time into the race as playback.
+    fn sync_reference(&mut self) {
+        if let Some(reference) = &mut self.reference {
+            (reference.sim.trail_length, reference.sim.trail_mode) = (self.sim.trail_length, self.sim.trail_mode);
+            reference.sim.advance_to(self.clock.now_ms().max(0.0) as u64);
+        }
+    }
+
+    /// The configured offset of each of `drivers`, 0 for the others.
+    fn manual_offsets_ms(&self, drivers: &[Driver]) -> Vec<i64> {
+        drivers.iter().map(|driver| self.driver_offsets.get(&driver.key).map_or(0, |seconds| (seconds * 1000.0).round() as i64)).collect()
+    }
+
+    /// Moves the rows of `dataset_idx` by `offset_ms`, 0 taking its offset
+    /// away, and carries on from the same moment.
+    fn set_driver_offset(&mut self, dataset_idx: usize, offset_ms: i64) {
+        let Some(key) = self.sim.drivers.get(dataset_idx).map(|driver| &driver.key) else {
+            return;
+        };
+        if offset_ms == 0 {
+            self.driver_offsets.remove(key);
+        } else {
+            self.driver_offsets.insert(key.clone(), offset_ms as f64 / 1000.0);
+        }
+        self.realign();
+    }
+
+    /// Lines the drivers up again by their offsets. Every car is shown
+    /// where its moved rows put it at the current simulated time, which
+    /// carries on from where it was.
+    fn realign(&mut self) {
+        let now_ms = self.clock.now_ms();
+        self.sim.set_manual_offsets(self.manual_offsets_ms(&self.sim.drivers));
+        self.seek(self.sim.index_at_sim_ms(now_ms.max(0.0) as u64));
+        if self.sim.current_index > 0 && !self.race_complete {
+            self.clock.seek(now_ms);
+        }
+    }
+
+    /// Starts without data and shows a loading screen until `startup.race`
+    /// has been read in the background.
+    fn loading(
+        coordinates: Vec<LedCoordinate>,
+        coordinate_issues: Vec<DataIssue>,
+        startup: Startup,
+        options: PlaybackOptions,
+    ) -> Self {
+        let mut app = Self::new(coordinates, coordinate_issues, LoadedRace::default(), options);
+        app.race_started = false;
+        app.startup = Some(startup);
+        app
+    }
+
+    /// Starts without data and loads the first race of the playlist behind its title card.
+    fn playing_list(coordinates: Vec<LedCoordinate>, coordinate_issues: Vec<DataIssue>, options: PlaybackOptions) -> Self {
+        let mut app = Self::new(coordinates, coordinate_issues, LoadedRace::default(), options);
+        app.race_started = false;
+        app.switch_playlist(0);
+        app
+    }
+
+    /// Starts without data and shows the setup screen, for when the
+    /// coordinates or the datasets cannot be found.
+    fn setting_up(
+        coordinates: Vec<LedCoordinate>,
+        coordinate_issues: Vec<DataIssue>,
+        setup: Setup,
+        options: PlaybackOptions,
+    ) -> Self {
+        let mut app = Self::new(coordinates, coordinate_issues, LoadedRace::default(), options);
+        app.race_started = false;
+        app.setup = Some(setup);
+        app
+    }
+
+    /// Plays `recording` instead of race data: its drivers, without rows.
+    fn replaying(
+        coordinates: Vec<LedCoordinate>,
+        coordinate_issues: Vec<DataIssue>,
+        recording: Recording,
+        options: PlaybackOptions,
+    ) -> Self {
+        let drivers = recording.header.drivers;
+        let no_leds = LedIndex::new(&[]);
+        let race = LoadedRace {
+            drivers: drivers
+                .iter()
+                .map(|driver| Driver {
+                    key: driver.key.clone(),
+                    name: driver.name.clone(),
+                    code: driver.code.clone(),
+                    ..Driver::new(Dataset::from_rows(&[], &no_leds, 0), egui::Color32::from_rgb(driver.color[0], driver.color[1], driver.color[2]))
+                })
+                .collect(),
+            issues: Vec::new(),
+        };
+        let recorded = race.drivers.clone();
+        let mut app = Self::new(coordinates, coordinate_issues, race, options);
+        // As recorded, whatever the driver metadata or palette say now
+        let offsets_ms = app.manual_offsets_ms(&recorded);
+        app.sim.set_drivers(recorded, offsets_ms);
+        (app.record_path, app.event_log_path) = (None, None);
+        app.replay = Some(Replay::new(recording.events));
+        app.race_started = true;
+        app
+    }
+
+    /// Swaps in the datasets being loaded once they have all arrived, then
+    /// applies what was waiting on them.
+    fn finish_loading(&mut self) {
+        let Some(Startup { race, strict, autostart, settings, session, start_at, race_dir }) = self.startup.take() else {
+            return;
+        };
+        self.dataset_paths = race.paths.clone();
+        let mut race = race.finish(self.palette, &self.driver_codes);
+        // The LEDs' problems were told of when they were read, unless they are the startup ones
+        let coordinate_issues = if race_dir.is_none() { &self.coordinate_issues[..] } else { &[] };
+        for issue in coordinate_issues.iter().chain(&race.issues) {
+            eprintln!("warning: {issue}");
+        }
+        if strict && !(self.coordinate_issues.is_empty() && race.issues.is_empty()) {
+            eprintln!("error: data problems found and --strict is set");
+            let issues = self.coordinate_issues.iter().chain(&race.issues).map(ToString::to_string).collect();
+            self.startup_failed = Some(("Data problems found and --strict is set".to_string(), issues));
+            return;
+        }
+        if let Err(e) = self.trim_race(&mut race) {
+            if race_dir.is_some() {
+                eprintln!("warning: not trimmed: {e}");
+            } else {
+                eprintln!("error: {e}");
+                self.startup_failed = Some((e, Vec::new()));
+                return;
+            }
+        }
+
+        self.set_race(race);
+        if let Some(dir) = &race_dir {
+            self.events = Self::read_race_events(dir);
+            self.read_race_grid(dir);
+        }
+        if let Some(settings) = settings {
+            self.apply_settings(settings);
+        }
+        if let Some(next) = &mut self.startup {
+            // The settings went on to another race, the rest waits for it
+            (next.autostart, next.session, next.start_at) = (autostart, session, start_at);
+            return;
+        }
+        self.race_started = autostart;
+        if let Some(session) = session {
+            self.apply_session(session);
+        }
+        if let Some(next) = &mut self.startup {
+            next.start_at = start_at; // And so does a race the session went on to
+            return;
+        }
+        if let Some(at) = start_at {
+            self.start_at(at);
+        }
+        self.watch_files();
+    }
+
+    /// Starts loading the datasets found in a race subfolder, or in the
+    /// data directory itself for `None`, in the background. The loading
+    /// screen shows until `finish_loading` swaps them in.
+    fn load_race(&mut self, race: Option<&str>) {
+        let dir = race.map_or_else(|| self.data_dir.clone(), |race| self.data_dir.join(race));
+        let (paths, issues) = loader::race_dir_paths(&dir);
+        self.reloading = None; // Of the race there was
+        self.selected_race = race.map(str::to_string);
+        let race = PendingRace::start(paths, issues, self.sim.led_index.clone(), self.read_options);
+        self.startup = Some(Startup { race, strict: false, autostart: false, settings: None, session: None, start_at: None, race_dir: Some(dir) });
+    }
+
+    /// The events file in a race folder, empty if there is none or it does not read.
+    fn read_race_events(dir: &Path) -> RaceEvents {
+        let path = dir.join(events::DEFAULT_FILE);
+        if !path.is_file() {
+            return RaceEvents::default();
+        }
+        RaceEvents::read(&path).unwrap_or_else(|e| {
+            eprintln!("warning: {e}");
+            RaceEvents::default()
+        })
+    }
+
+    /// Takes the grid file in a race folder, no grid if there is none or it does not read.
+    fn read_race_grid(&mut self, dir: &Path) {
+        let path = dir.join(grid::DEFAULT_FILE);
+        self.grid = Grid::default();
+        if !path.is_file() {
+            return;
+        }
+        match Grid::read(&path, self.sim.coordinates.len()) {
+            Ok((grid, issues)) => {
+                self.grid = grid;
+                self.data_issues.extend(issues);
+            }
+            Err(e) => eprintln!("warning: {e}"),
+        }
+    }
+
+    /// Starts watching the coordinates and dataset files, with `--watch`.
+    fn watch_files(&mut self) {
+        if !self.watch || self.replay.is_some() {
+            return;
+        }
+        let files: Vec<_> = std::iter::once(self.coordinates_path.clone()).chain(self.dataset_paths.iter().cloned()).collect();
+        self.watcher = FileWatcher::start(&files).map_err(|e| eprintln!("warning: not watching files: {e}")).ok();
+    }
+
+    /// Reloads whatever changed on disk since last time. A file that no
+    /// longer reads leaves the previous data in place.
+    fn reload_changed_files(&mut self) {
+        if let Some(watcher) = &mut self.watcher {
+            let changed = watcher.poll();
+            self.reload_files(changed);
+        }
+        self.finish_reloads();
+    }
+
+    /// A new coordinates file, or a dataset that did not load before, reloads
+    /// the whole race; a loaded driver's dataset is read again on its own in
+    /// the background.
+    fn reload_files(&mut self, changed: Vec<PathBuf>) {
+        if changed.is_empty() {
+            return;
+        }
+        let (drivers, others): (Vec<_>, Vec<_>) =
+            changed.into_iter().partition(|path| self.sim.drivers.iter().any(|driver| driver.key == loader::driver_key(path)));
+        if !others.is_empty() {
+            let names: Vec<_> = others.iter().filter_map(|path| path.file_name()?.to_str()).collect();
+            let done = format!("Reloaded {}", names.join(", "));
+            if others.contains(&self.coordinates_path) {
+                if let Err(e) = self.reload_coordinates(done) {
+                    self.report_reload(Err(e));
+                }
+            } else {
+                self.reload_datasets(done);
+            }
+            return; // The drivers' files are read again as well
+        }
+        for path in drivers {
+            self.reloads.retain(|pending| pending.path != path);
+            self.reloads.push(PendingDataset::start(path, self.sim.led_index.clone(), self.read_options));
+        }
+    }
+
+    /// Swaps in the datasets that finished reading.
+    fn finish_reloads(&mut self) {
+        let mut i = 0;
+        while i < self.reloads.len() {
+            match self.reloads[i].poll() {
+                Some(result) => {
+                    let path = self.reloads.remove(i).path;
+                    let result = self.swap_dataset(&path, result);
+                    self.report_reload(result);
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    /// Replaces one driver's dataset at the current point of the race, so
+    /// that driver is shown where its new data puts it while the others
+    /// carry on from where they are.
+    fn swap_dataset(
+        &mut self,
+        path: &Path,
+        result: Result<(Dataset, Vec<DataIssue>), String>,
+    ) -> Result<String, String> {
+        let (mut dataset, issues) = result.map_err(|e| format!("{}: {e}", path.display()))?;
+        if let Some((from, to)) = self.trim {
+            dataset.trim(from, to);
+        }
+        let key = loader::driver_key(path);
+        let Some(i) = self.sim.drivers.iter().position(|driver| driver.key == key) else {
+            return Err(format!("{key} is no longer loaded"));
+        };
+        let rows = dataset.len();
+        self.sim.replace_dataset(i, dataset);
+        self.data_issues.retain(|issue| issue.file != path);
+        self.data_issues.extend(issues);
+        self.find_lap_starts();
+        (self.stepped, self.stepped_reads) = (None, None); // Its rows may have moved
+        self.race_complete = self.sim.current_index > 0 && self.sim.is_finished();
+        self.race_started &= !self.race_complete;
+        self.sync_cues();
+        let file = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        Ok(format!("Reloaded {file} ({} rows)", format_count(rows)))
+    }
+
+    fn report_reload(&mut self, result: Result<String, String>) {
+        let message = match &result {
+            Ok(message) => message.clone(),
+            Err(e) => format!("Reload failed: {e}"),
+        };
+        self.activity.push(self.sim.date_at(self.sim.sim_elapsed_ms), Source::Reload, message);
+        self.status = Some(match result {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("warning: {e}");
+                format!("Kept the previous data: {e}")
+            }
+        });
+    }
+
+    /// Reads the coordinates file again and starts matching the datasets
+    /// to the new LEDs, saying `done` once they are.
+    fn reload_coordinates(&mut self, done: String) -> Result<(), String> {
+        let coordinates = read_leds(&self.coordinates_path, self.read_options.delimiter)?;
+        let led_index = Arc::new(LedIndex::new(&coordinates.records).with_snap_distance(self.snap_distance));
+        self.start_reloading(Some(coordinates), None, led_index, done);
+        Ok(())
+    }
+
+    /// Leaves the LEDs far outside the others out of the track, as if the
+    /// coordinates file did not have them, and matches the race to the rest.
+    /// LEDs after them move down, the start/finish LED, grid slots and
+    /// sectors with them, once the race is read again for the rest.
+    fn exclude_outliers(&mut self) {
+        let outliers = data::outliers(&self.sim.coordinates);
+        let records: Vec<LedCoordinate> =
+            self.sim.coordinates.iter().enumerate().filter(|(led, _)| !outliers.contains(led)).map(|(_, coord)| coord.clone()).collect();
+        let led_index = Arc::new(LedIndex::new(&records).with_snap_distance(self.snap_distance));
+        let mut issues: Vec<DataIssue> = self.coordinate_issues.iter().filter(|issue| issue.file == self.coordinates_path).cloned().collect();
+        for issue in issues.iter_mut().filter(|issue| issue.message.ends_with(data::OUTLIER)) {
+            issue.message = issue.message.replace(" (kept)", " (excluded)");
+        }
+        let mut kept = 0..;
+        let moved: Vec<Option<usize>> = (0..self.sim.coordinates.len()).map(|led| if outliers.contains(&led) { None } else { kept.next() }).collect();
+        self.start_reloading(Some(Validated { records, issues }), Some(moved), led_index, format!("Excluded {} LEDs", outliers.len()));
+    }
+
+    /// Switches to the LEDs in `coordinates_path` and the race data in
+    /// `data_dir`: its `race` subfolder, or the folder itself for `None`.
+    /// Nothing changes if the coordinates do not read.
+    fn open_data(&mut self, coordinates_path: PathBuf, data_dir: PathBuf, race: Option<String>) -> Result<(), String> {
+        let coordinates = read_leds(&coordinates_path, self.read_options.delimiter)?;
+        let led_index = Arc::new(LedIndex::new(&coordinates.records).with_snap_distance(self.snap_distance));
+        self.coordinates_path = coordinates_path;
+        self.set_coordinates(coordinates, led_index, None)?;
+        self.races = loader::list_races(&data_dir);
+        self.data_dir = data_dir;
+        self.load_race(race.as_deref());
+        Ok(())
+    }
+
+    /// Unloads the race playing, then reads the playlist entry `step` on from
+    /// it in the background while its title card shows.
+    fn switch_playlist(&mut self, step: isize) {
+        if self.playlist.is_none() {
+            return;
+        }
+        // Let go of the race first, some sessions are too large to hold two
+        self.set_race(LoadedRace::default());
+        (self.reference, self.reference_load, self.watcher, self.reloading) = (None, None, None, None);
+        self.dataset_paths.clear();
+        let (options, palette, codes, snap_distance) = (self.read_options, self.palette, self.driver_codes.clone(), self.snap_distance);
+        if let Some(playlist) = &mut self.playlist {
+            playlist.go(step, Instant::now(), move |entry| read_playlist_entry(&entry, options, palette, &codes, snap_distance));
+        }
+    }
+
+    /// Plays the playlist entry that finished loading, or goes on to the
+    /// next one if it did not load.
+    fn play_entry(&mut self, loaded: Result<LoadedEntry, String>) {
+        let Some(entry) = self.playlist.as_ref().map(|playlist| playlist.entry().clone()) else {
+            return;
+        };
+        // Sectors belong to the track of the config, not the playlist's
+        self.sectors = Sectors::default();
+        let result = loaded.and_then(|loaded| {
+            self.coordinates_path = entry.coordinates.clone();
+            self.set_coordinates(loaded.coordinates, loaded.led_index, None)?;
+            Ok((loaded.paths, loaded.race))
+        });
+        let (paths, mut race) = match result {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("warning: {}: {e}", entry.title);
+                self.status = Some(format!("Skipped {}: {e}", entry.title));
+                self.switch_playlist(1);
+                return;
+            }
+        };
+        for issue in self.coordinate_issues.iter().chain(&race.issues) {
+            eprintln!("warning: {issue}");
+        }
+        if let Err(e) = self.trim_race(&mut race) {
+            eprintln!("warning: {}: not trimmed: {e}", entry.title);
+        }
+        (self.races, self.selected_race) = (Vec::new(), None);
+        self.data_dir = entry.data_dir.clone();
+        self.dataset_paths = paths;
+        self.set_race(race);
+        self.events = Self::read_race_events(&entry.data_dir);
+        self.read_race_grid(&entry.data_dir);
+        self.watch_files();
+        self.start();
+    }
+
+    /// Previous and next buttons of the playlist, with the title of the race
+    /// playing. `Some` with the step to take once one is clicked.
+    fn playlist_controls(&self, ui: &mut egui::Ui) -> Option<isize> {
+        let playlist = self.playlist.as_ref()?;
+        let mut step = None;
+        if ui.button("⏮").on_hover_text("Previous race of the playlist").clicked() {
+            step = Some(-1);
+        }
+        ui.label(format!("{} ({}/{})", playlist.entry().title, playlist.current + 1, playlist.entries.len()));
+        if ui.button("⏭").on_hover_text("Next race of the playlist").clicked() {
+            step = Some(1);
+        }
+        step
+    }
+
+    /// Files dropped on the page in the browser go on the setup screen, opened
+    /// for them. The native build gets the paths of dropped files, not their
+    /// contents, and leaves them.
+    fn take_dropped_files(&mut self, ctx: &egui::Context) {
+        let files: Vec<(String, Arc<[u8]>)> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| Some((file.name.clone(), file.bytes.clone()?))).collect());
+        if files.is_empty() {
+            return;
+        }
+        if self.setup.is_none() {
+            self.open_setup();
+        }
+        if let Some(setup) = &mut self.setup {
+            setup.add_files(files);
+        }
+    }
+
+    /// Shows the setup screen over the loaded race, paused, to switch to other files.
+    fn open_setup(&mut self) {
+        if self.race_started && !self.paused {
+            self.set_paused(true);
+        }
+        let mut setup = Setup::new(self.coordinates_path.clone(), self.data_dir.clone(), self.selected_race.clone(), self.read_options.delimiter);
+        setup.cancellable = true;
+        self.setup = Some(setup);
+    }
+
+    /// Loads the demo race from the setup screen, keeping it open with the reason if that fails.
+    fn load_demo(&mut self) {
+        let Some(mut setup) = self.setup.take() else {
+            return;
+        };
+        match write_demo().map_err(|e| format!("Cannot write the demo race: {e}")).and_then(|(dir, coordinates)| self.open_data(coordinates, dir, None)) {
+            Ok(()) => self.sim.start_finish_led = Some(data::DEMO_START_FINISH_LED),
+            Err(e) => {
+                setup.error = Some(e);
+                self.setup = Some(setup);
+            }
+        }
+    }
+
+    /// Loads what the setup screen points at, keeping it open with the reason if that fails.
+    fn start_setup(&mut self) {
+        let Some(mut setup) = self.setup.take() else {
+            return;
+        };
+        if let Err(e) = self.open_data(setup.coordinates_path.clone(), setup.data_dir.clone(), setup.race.clone()) {
+            setup.error = Some(e);
+            self.setup = Some(setup);
+        }
+    }
+
+    /// Puts the datasets on new LEDs, along with everything else that depends on them.
+    /// `moved` gives the new id of each of the LEDs there were by its old
+    /// one, `None` for those left out; without it the LEDs are a file read
+    /// again, their ids those of the file.
+    fn set_coordinates(&mut self, coordinates: Validated<LedCoordinate>, led_index: Arc<LedIndex>, moved: Option<&[Option<usize>]>) -> Result<(), String> {
+        let led_count = coordinates.records.len();
+        let moved_to = |led: usize| match moved {
+            Some(moved) => moved.get(led).copied().flatten(),
+            None => (led < led_count).then_some(led),
+        };
+        self.sim.led_index = led_index;
+        self.strip_positions = output::strip_positions(&coordinates.records);
+        self.led_spacing = Spacing::of(&coordinates.records);
+        self.outline = render::outline(&coordinates.records, &self.strip_positions);
+        (self.sim.coordinates, self.coordinate_issues) = (coordinates.records, coordinates.issues);
+        if let Some(moved) = moved {
+            self.sectors = std::mem::take(&mut self.sectors).remap(moved, led_count).with_outline(&self.outline);
+        } else if let Some(path) = self.sectors.path.clone() {
+            let (sectors, issues) = Sectors::read(&path, led_count).map_err(|e| e.to_string())?;
+            self.sectors = sectors.with_outline(&self.outline);
+            self.coordinate_issues.extend(issues);
+        } else if !self.sectors.listed.is_empty() {
+            let (sectors, issues) = Sectors::from_leds(&self.sectors.listed, led_count);
+            self.sectors = sectors.with_outline(&self.outline);
+            self.coordinate_issues.extend(issues);
+        }
+        if let Some(led) = self.sim.start_finish_led {
+            self.sim.start_finish_led = moved_to(led);
+            if self.sim.start_finish_led.is_none() {
+                eprintln!("warning: the start/finish LED is gone from {}, not counting laps", self.coordinates_path.display());
+            }
+        }
+        for driver in self.grid.remap(moved_to) {
+            eprintln!("warning: the grid slot of {driver} is gone from {}, leaving them off the grid", self.coordinates_path.display());
+        }
+        self.load_reference();
+        Ok(())
+    }
+
+    /// Starts matching the race to the LEDs again on other threads, the
+    /// positions further than `distance` from every LED off the map, or
+    /// none for `None`. `finish_snapping` swaps it in.
+    fn set_snap_distance(&mut self, distance: Option<f64>) {
+        let led_index = Arc::new(LedIndex::new(&self.sim.coordinates).with_snap_distance(distance));
+        let race = PendingRace::start(self.dataset_paths.clone(), Vec::new(), led_index.clone(), self.read_options);
+        self.snapping = Some(Snapping { distance, led_index, race });
+    }
+
+    /// The snap distance playback is at, or is being matched again for.
+    fn snap_target(&self) -> Option<f64> {
+        self.snapping.as_ref().map_or(self.snap_distance, |snapping| snapping.distance)
+    }
+
+    /// Swaps in the race matched again for a new snap distance once it is
+    /// read, and says how many rows that leaves off the map.
+    fn finish_snapping(&mut self) {
+        if !self.snapping.as_mut().is_some_and(|snapping| snapping.race.poll()) {
+            return;
+        }
+        let Some(Snapping { distance, led_index, race }) = self.snapping.take() else {
+            return;
+        };
+        let loaded = race.finish(self.palette, &self.driver_codes);
+        self.status = Some(match self.keeps_drivers(loaded) {
+            Ok(loaded) => {
+                (self.snap_distance, self.sim.led_index) = (distance, led_index);
+                self.reload_race(loaded);
+                self.load_reference();
+                self.snapped_rows(distance)
+            }
+            Err(e) => {
+                eprintln!("warning: keeping the snap distance: {e}");
+                format!("Kept the snap distance: {e}")
+            }
+        });
+    }
+
+    /// How many rows snapping within `distance` leaves off the map.
+    fn snapped_rows(&self, distance: Option<f64>) -> String {
+        let (off_map, rows) = self.sim.drivers.iter().map(|driver| &driver.records).fold((0, 0), |(off_map, rows), dataset| {
+            (off_map + (0..dataset.len()).filter(|&row| dataset.get(row).is_some_and(|sample| sample.on_led().is_none())).count(), rows + dataset.len())
+        });
+        let limit = distance.map_or_else(|| "any distance".to_string(), |distance| format!("{distance:.2}"));
+        format!("Snapping within {limit}: {} of {} rows off the map", format_count(off_map), format_count(rows))
+    }
+
+    /// Starts reading the race's files again on the LEDs there are.
+    fn reload_datasets(&mut self, done: String) {
+        self.start_reloading(None, None, self.sim.led_index.clone(), done);
+    }
+
+    /// Starts reading the race's files again on other threads, matched to
+    /// `led_index`, for `coordinates` if they are new. A reload already
+    /// going is dropped for it.
+    fn start_reloading(&mut self, coordinates: Option<Validated<LedCoordinate>>, moved: Option<Vec<Option<usize>>>, led_index: Arc<LedIndex>, done: String) {
+        let race = PendingRace::start(self.dataset_paths.clone(), Vec::new(), led_index.clone(), self.read_options);
+        self.reloading = Some(Reloading { coordinates, moved, led_index, race, done });
+    }
+
+    /// Swaps in the race read again once it is, with its LEDs, keeping the
+    /// previous data if a driver that loaded before no longer does.
+    fn finish_reloading(&mut self) {
+        if !self.reloading.as_mut().is_some_and(|reloading| reloading.race.poll()) {
+            return;
+        }
+        let Some(Reloading { coordinates, moved, led_index, race, done }) = self.reloading.take() else {
+            return;
+        };
+        let result = self.keeps_drivers(race.finish(self.palette, &self.driver_codes)).and_then(|loaded| {
+            if let Some(coordinates) = coordinates {
+                self.set_coordinates(coordinates, led_index, moved.as_deref())?;
+            }
+            self.reload_race(loaded);
+            Ok(done)
+        });
+        self.report_reload(result);
+    }
+
+    /// The race read again, unless a driver that loaded before no longer does.
+    fn keeps_drivers(&self, loaded: LoadedRace) -> Result<LoadedRace, String> {
+        match self.sim.drivers.iter().map(|driver| &driver.key).find(|&key| !loaded.drivers.iter().any(|driver| driver.key == *key)) {
+            Some(key) => {
+                let issue = loaded.issues.iter().find(|issue| loader::driver_key(&issue.file) == key.as_str());
+                Err(issue.map_or_else(|| format!("{key} no longer loads"), ToString::to_string))
+            }
+            None => Ok(loaded),
+        }
+    }
+
+    /// Trims `race` to the `--start-time` and `--end-time` window, if one
+    /// is set, and keeps the dates it comes to for reloads of the race.
+    fn trim_race(&mut self, race: &mut LoadedRace) -> Result<(), String> {
+        self.trim = None;
+        self.trim = trim_race(race, self.trim_times)?;
+        Ok(())
+    }
+
+    /// Swaps in a reloaded race, keeping the playback position, visibility,
+    /// highlight, followed driver, comparison and bookmarks of the drivers that
+    /// are still there.
+    fn reload_race(&mut self, mut loaded: LoadedRace) {
+        if let Some((from, to)) = self.trim {
+            loaded.drivers.iter_mut().for_each(|driver| driver.records.trim(from, to));
+        }
+        let (index, race_started, paused) = (self.sim.current_index, self.race_started, self.paused);
+        let visible = self.visibility();
+        let highlighted = self.highlighted.map(|i| self.sim.drivers[i].name.clone());
+        let spotlight = self.sim.spotlight.map(|i| self.sim.drivers[i].name.clone());
+        let following = self.following.map(|i| self.sim.drivers[i].name.clone());
+        let compare = self.compare.map(|compare| (compare.drivers.map(|i| self.sim.drivers[i].name.clone()), compare.ghost));
+        let bookmarks = std::mem::take(&mut self.bookmarks);
+        self.set_race(loaded);
+        self.set_visibility(&visible);
+        self.highlighted = highlighted.and_then(|name| self.driver_named(&name));
+        self.sim.spotlight = spotlight.and_then(|name| self.driver_named(&name));
+        self.following = following.and_then(|name| self.driver_named(&name));
+        self.compare = compare.and_then(|([left, right], ghost)| {
+            let position = |name: &String| self.driver_named(name);
+            Some(Compare { drivers: [position(&left)?, position(&right)?], ghost })
+        });
+        self.bookmarks = bookmarks;
+        (self.race_started, self.paused) = (race_started, paused);
+        self.seek(index);
+    }
+
+    fn set_race(&mut self, loaded: LoadedRace) {
+        let offsets_ms = self.manual_offsets_ms(&loaded.drivers);
+        self.sim.set_drivers(loaded.drivers, offsets_ms);
+        self.data_issues = loaded.issues;
+        self.apply_driver_info();
+        self.highlighted = None;
+        self.following = None;
+        self.compare = None;
+        self.bookmarks.clear(); // They point into the previous race
+        self.reloads.clear(); // Read for the previous race or LEDs
+        if let Some(Snapping { distance, .. }) = self.snapping.take() {
+            self.set_snap_distance(distance); // Read again for this race
+        }
+        self.led_passes = None;
+        self.reset();
+        self.find_lap_starts();
+        self.start_recording();
+        self.start_event_log();
+    }
+
+    /// Starts the recording for the drivers first loaded. Races loaded
+    /// after, as when changed files are reloaded, go on into the same
+    /// recording while their drivers and LEDs are the same; other ones go
+    /// to a new file, numbered after the first, with their own header.
+    fn start_recording(&mut self) {
+        let Some(path) = &self.record_path else {
+            return;
+        };
+        let header = Header {
+            version: recording::RECORDING_VERSION,
+            leds: self.sim.coordinates.iter().map(|coord| [coord.x_led, coord.y_led]).collect(),
+            drivers: self
+                .sim
+                .drivers
+                .iter()
+                .map(|driver| RecordedDriver {
+                    key: driver.key.clone(),
+                    name: driver.name.clone(),
+                    code: driver.code.clone(),
+                    color: [driver.color.r(), driver.color.g(), driver.color.b()],
+                })
+                .collect(),
+        };
+        if self.recorder.as_ref().is_some_and(|recorder| recorder.continues(&header)) {
+            return;
+        }
+        self.recordings += 1;
+        let path = if self.recordings == 1 { path.clone() } else { recording::numbered(path, self.recordings) };
+        if self.recordings > 1 {
+            eprintln!("warning: the drivers or LEDs changed, recording on into {}", path.display());
+        }
+        self.recorder = Recorder::create(&path, &header).map_err(|e| eprintln!("warning: not recording: {e}")).ok();
+        if self.recorder.is_none() {
+            self.record_path = None;
+        }
+    }
+
+    /// Records the LED each car is shown on, if it changed.
+    fn record_frame(&mut self) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        let shown: Vec<_> =
+            self.sim.cars.iter().zip(&self.sim.drivers).map(|(car, driver)| car.trail.front().copied().filter(|_| driver.visible)).collect();
+        if let Err(e) = recorder.record(&shown) {
+            eprintln!("warning: recording stopped: {e}");
+            (self.record_path, self.recorder) = (None, None);
+        }
+    }
+
+    /// Starts the event log for the drivers first loaded. Races loaded
+    /// after go on at the end of it, after a new header row if their
+    /// drivers are not the same.
+    fn start_event_log(&mut self) {
+        let Some(path) = &self.event_log_path else {
+            return;
+        };
+        let keys: Vec<_> = self.sim.drivers.iter().map(|driver| driver.key.clone()).collect();
+        let event_log = match self.event_log.take() {
+            Some(event_log) if event_log.keys() == keys => Ok(event_log),
+            Some(event_log) => {
+                drop(event_log); // Flushes the rows before the new header
+                EventLog::append(path, &keys)
+            }
+            None => EventLog::create(path, &keys),
+        };
+        self.event_log = event_log.map_err(|e| eprintln!("warning: no event log: {e}")).ok();
+        if self.event_log.is_none() {
+            self.event_log_path = None;
+        }
+    }
+
+    /// Logs the moments playback moved on since the last frame.
+    fn log_events(&mut self) {
+        let Some(event_log) = &mut self.event_log else {
+            return;
+        };
+        if let Err(e) = event_log.log(&self.sim) {
+            eprintln!("warning: event log stopped: {e}");
+            (self.event_log_path, self.event_log) = (None, None);
+        }
+    }
+
+    /// Adds what playback did since the last frame to the activity log:
+    /// the cars the simulation moved, and a flag or output error that
+    /// differs from the last one logged.
+    fn log_activity(&mut self) {
+        let codes: Vec<_> = self.sim.drivers.iter().map(|driver| driver.code.clone()).collect();
+        self.activity.poll(self.sim.session_start(), &codes);
+        let now = self.sim.date_at(self.sim.sim_elapsed_ms);
+        let flag = self.race_date().and_then(|date| self.events.flag_at(date)).map(|(flag, _)| flag);
+        if flag != self.logged_flag {
+            self.logged_flag = flag;
+            self.activity.push(now, Source::Flag, flag.map_or("Flag cleared", |flag| flag.label()).to_string());
+        }
+        let errors = [
+            self.output.as_ref().and_then(|output| match &output.status {
+                OutputStatus::Error(e) => Some(format!("Output: {e}")),
+                _ => None,
+            }),
+            self.mqtt.as_ref().and_then(|mqtt| match &mqtt.status {
+                MqttStatus::Error(e) => Some(format!("MQTT: {e}")),
+                _ => None,
+            }),
+            self.server.as_ref().and_then(|server| match &server.status {
+                ServerStatus::Error(e) => Some(format!("WebSocket server: {e}")),
+                ServerStatus::Serving(_) => None,
+            }),
+        ];
+        for (error, logged) in errors.into_iter().zip(&mut self.logged_errors) {
+            if error != *logged {
+                if let Some(error) = &error {
+                    self.activity.push(now, Source::Output, error.clone());
+                }
+                *logged = error;
+            }
+        }
+    }
+
+    /// Moves the cars as the recording being replayed says, up to now.
+    fn apply_replay(&mut self) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+        for &Event(at_ms, driver, led) in replay.due() {
+            let car = &mut self.sim.cars[driver];
+            match led {
+                Some(led) => {
+                    car.arrive(led, at_ms);
+                }
+                None => car.clear_trail(),
+            }
+        }
+    }
+
+    /// Starts finding when each lap began, the first time any car reached
+    /// it, on another thread. Until `update_lap_starts` has them there are
+    /// none.
+    fn find_lap_starts(&mut self) {
+        self.lap_starts.clear();
+        self.lap_starts_job = self.sim.lap_starts_job().map(|job| {
+            let (sender, receiver) = mpsc::channel();
+            loader::spawn(move || {
+                let _ = sender.send(job());
+            });
+            receiver
+        });
+    }
+
+    /// Takes the lap starts once they are found.
+    fn update_lap_starts(&mut self) {
+        let Some(receiver) = &self.lap_starts_job else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(lap_starts) => self.lap_starts = lap_starts,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {}
+        }
+        self.lap_starts_job = None;
+    }
+
+    /// Laps in the race: those the leader completed by the end of the data.
+    fn total_laps(&self) -> u32 {
+        self.lap_starts.len().saturating_sub(1) as u32
+    }
+
+    /// Bookmarks the start of every lap, and the finish, skipping any already there.
+    fn add_lap_bookmarks(&mut self) {
+        let total = self.total_laps();
+        for (lap, &sim_ms) in (1..).zip(&self.lap_starts) {
+            let label = if total > 0 && lap > total { "Finish".to_string() } else { format!("Lap {lap}") };
+            if self.bookmarks.iter().any(|bookmark| bookmark.sim_ms == sim_ms && bookmark.label == label) {
+                continue;
+            }
+            let at = self.bookmarks.partition_point(|bookmark| bookmark.sim_ms <= sim_ms);
+            self.bookmarks.insert(at, Bookmark { sim_ms, label });
+        }
+    }
+
+    /// Recolors the drivers from another palette. Drivers in the metadata
+    /// file keep their own colors, and drivers given one by hand keep that.
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.color_overrides.recolor(palette, &mut self.sim.drivers, &self.driver_table);
+    }
+
+    /// Shows the driver named `name` in `color` whatever the palette and
+    /// metadata say, on screen and on every output, for this and later
+    /// races. False when no driver of the loaded race has that name.
+    fn set_driver_color(&mut self, name: &str, color: egui::Color32) -> bool {
+        self.color_overrides.set_driver_color(name, color);
+        self.set_palette(self.palette);
+        self.driver_named(name).is_some()
+    }
+
+    /// Gives every driver back the color of the palette or metadata.
+    fn reset_driver_colors(&mut self) {
+        self.color_overrides.clear();
+        self.set_palette(self.palette);
+    }
+
+    /// Takes names, codes and colors from the driver metadata file for the
+    /// drivers it has. Drivers it lacks keep the palette and are reported.
+    fn apply_driver_info(&mut self) {
+        for driver in &mut self.sim.drivers {
+            driver.trail_color = self.driver_table.get(&driver.key).and_then(|info| info.secondary);
+        }
+        if self.driver_table.is_empty() {
+            return;
+        }
+        for driver in &mut self.sim.drivers {
+            let Some(info) = self.driver_table.get(&driver.key) else {
+                self.data_issues.push(DataIssue {
+                    file: self.driver_table.path.clone(),
+                    line: None,
+                    message: format!("no entry for driver `{}`, using the palette", driver.key),
+                });
+                continue;
+            };
+            driver.apply(info);
+        }
+        self.set_palette(self.palette);
+    }
+
+    /// The drivers in legend order: grouped by team in order of each team's
+    /// first driver when there is metadata, with drivers it lacks last.
+    fn legend_groups(&self) -> Vec<(Option<String>, Vec<usize>)> {
+        let mut groups: Vec<(Option<String>, Vec<usize>)> = Vec::new();
+        for (dataset_idx, driver) in self.sim.drivers.iter().enumerate() {
+            let team = driver.team.clone();
+            match groups.iter_mut().find(|(group, _)| *group == team) {
+                Some((_, members)) => members.push(dataset_idx),
+                None => groups.push((team, vec![dataset_idx])),
+            }
+        }
+        groups.sort_by_key(|(team, _)| team.is_none());
+        groups
+    }
+
+    /// The driver shown as `name`.
+    fn driver_named(&self, name: &str) -> Option<usize> {
+        self.sim.drivers.iter().position(|driver| driver.name == name)
+    }
+
+    /// Whether each driver is lit, by name.
+    fn visibility(&self) -> BTreeMap<String, bool> {
+        self.sim.drivers.iter().map(|driver| (driver.name.clone(), driver.visible)).collect()
+    }
+
+    /// Lights the drivers as `visible` says, and those it does not name.
+    fn set_visibility(&mut self, visible: &BTreeMap<String, bool>) {
+        for driver in &mut self.sim.drivers {
+            driver.visible = visible.get(&driver.name).copied().unwrap_or(true);
+        }
+    }
+
+    /// The settings to keep for the next run.
+    fn settings(&self) -> Settings {
+        Settings {
+            speed: self.speed,
+            trail_length: self.sim.trail_length,
+            trail_mode: self.sim.trail_mode,
+            palette: self.palette,
+            show_labels: self.show_labels,
+            show_led_codes: self.show_led_codes,
+            stack_offset: self.stack_offset,
+            show_frame_stats: self.show_frame_stats,
+            clock_mode: self.clock_mode,
+            theme: self.theme,
+            backdrop: self.backdrop,
+            keep_aspect: self.keep_aspect,
+            led_shape: self.led_shape,
+            led_size: self.led_size,
+            unlit_outline: self.unlit_outline.map(|color| [color.r(), color.g(), color.b()]),
+            show_outline: self.show_outline,
+            follow_zoom: self.follow_zoom,
+            snap_distance: self.snap_distance,
+            smooth: self.smooth,
+            interpolate: self.interpolate,
+            throttle_brightness: self.throttle_brightness,
+            brightness: self.sim.correction.brightness() * 100.0,
+            gamma: self.sim.correction.gamma(),
+            cues: self.cues.clone(),
+            cue_sound: self.cue_sound,
+            visible: self.visibility(),
+            color_overrides: self.color_overrides.iter().map(|(name, color)| (name.to_string(), [color.r(), color.g(), color.b()])).collect(),
+            data_dir: Some(self.data_dir.clone()),
+            coordinates: Some(self.coordinates_path.clone()),
+            driver_offsets: self.driver_offsets.clone(),
+        }
+    }
+
+    /// Takes up the settings from last time in `storage`, except what the
+    /// command line or config sets explicitly.
+    fn restore(&mut self, storage: Option<&dyn eframe::Storage>, cli: &Cli, config: &Config) {
+        let Some(mut settings) = storage.and_then(|storage| eframe::get_value::<Settings>(storage, SETTINGS_KEY)) else {
+            return;
+        };
+        if cli.speed.is_some() {
+            settings.speed = config.speed;
+        }
+        if let Some(palette) = config.palette {
+            settings.palette = palette;
+        }
+        if config.trail_length != Config::default().trail_length {
+            settings.trail_length = config.trail_length;
+        }
+        settings.show_labels |= config.labels;
+        if !config.cues.is_empty() {
+            settings.cues = config.cues.clone();
+        }
+        settings.cue_sound |= config.cue_sound;
+        if config.snap_distance.is_some() {
+            settings.snap_distance = config.snap_distance;
+        }
+        if config.data_dir.is_some() {
+            settings.data_dir = None;
+        }
+        settings.driver_offsets.extend(config.driver_offsets.clone());
+        // The files chosen last time, so the setup screen only shows until some are found
+        if let Some(setup) = self.setup.take() {
+            let coordinates = settings.coordinates.clone().unwrap_or(setup.coordinates_path);
+            let data_dir = settings.data_dir.clone().unwrap_or(setup.data_dir);
+            self.setup = Some(Setup::new(coordinates, data_dir, setup.race, config.delimiter()));
+            if self.setup.as_ref().is_some_and(Setup::is_ready) {
+                self.start_setup();
+            }
+        }
+        self.restore_settings(settings);
+    }
+
+    /// Applies restored settings, or holds them until the startup datasets
+    /// are loaded.
+    fn restore_settings(&mut self, settings: Settings) {
+        match &mut self.startup {
+            Some(startup) => {
+                // Matched with the saved distance as the files are read rather than again once they are
+                if settings.snap_distance != self.snap_distance {
+                    let led_index = Arc::new(LedIndex::new(&self.sim.coordinates).with_snap_distance(settings.snap_distance));
+                    startup.race.restart(led_index.clone());
+                    (self.snap_distance, self.sim.led_index) = (settings.snap_distance, led_index);
+                }
+                startup.settings = Some(settings);
+            }
+            None if self.replay.is_none() => self.apply_settings(settings),
+            None => {}
+        }
+    }
+
+    /// Applies saved settings. Switching the data directory reloads the
+    /// selected race if the new directory has it, the rest waiting for it to
+    /// load; drivers the settings do not mention are shown.
+    fn apply_settings(&mut self, settings: Settings) {
+        if let Some(data_dir) = settings.data_dir.clone().filter(|dir| *dir != self.data_dir) {
+            self.races = loader::list_races(&data_dir);
+            self.data_dir = data_dir;
+            if let Some(race) = self.selected_race.clone().filter(|race| self.races.contains(race)) {
+                self.load_race(Some(&race));
+                // The rest is for the race, once it is loaded
+                if let Some(startup) = &mut self.startup {
+                    startup.settings = Some(settings);
+                }
+                return;
+            }
+        }
+        if settings.speed.is_finite() && settings.speed > 0.0 {
+            self.speed = settings.speed;
+        }
+        self.sim.trail_length = settings.trail_length.min(MAX_TRAIL_LENGTH);
+        self.sim.trail_mode = settings.trail_mode;
+        self.show_labels = settings.show_labels;
+        self.show_led_codes = settings.show_led_codes;
+        self.stack_offset = settings.stack_offset;
+        self.show_frame_stats = settings.show_frame_stats;
+        self.clock_mode = settings.clock_mode;
+        self.theme = settings.theme;
+        self.backdrop = settings.backdrop;
+        self.keep_aspect = settings.keep_aspect;
+        self.led_shape = settings.led_shape;
+        self.led_size = settings.led_size;
+        self.unlit_outline = settings.unlit_outline.map(|[r, g, b]| egui::Color32::from_rgb(r, g, b));
+        self.show_outline = settings.show_outline;
+        self.follow_zoom = settings.follow_zoom;
+        if settings.snap_distance != self.snap_target() {
+            self.set_snap_distance(settings.snap_distance);
+        }
+        self.smooth = settings.smooth;
+        self.interpolate = settings.interpolate;
+        self.throttle_brightness = settings.throttle_brightness;
+        (self.cues, self.cue_sound) = (settings.cues, settings.cue_sound);
+        self.sim.correction = ColorCorrection::new(settings.brightness / 100.0, settings.gamma);
+        self.color_overrides = settings.color_overrides.iter().map(|(name, &[r, g, b])| (name.clone(), egui::Color32::from_rgb(r, g, b))).collect();
+        self.set_palette(settings.palette);
+        self.set_visibility(&settings.visible);
+        if settings.driver_offsets != self.driver_offsets {
+            self.driver_offsets = settings.driver_offsets;
+            self.realign();
+        }
+    }
+
+    fn reset(&mut self) {
+        (self.stepped, self.stepped_reads) = (None, None);
+        self.lights_since = None;
+        self.lights_out_at = None;
+        self.clock.pause();
+        self.clock.seek(0.0);
+        self.sim.reset();
+        self.sync_cues();
+        self.race_started = false;
+        self.race_complete = false;
+        self.results_closed = false;
+        self.paused = false;
+        if let Some(replay) = &mut self.replay {
+            replay.stop();
+        }
+    }
+
+    /// Replays up to `index` from the checkpoint before it without waiting,
+    /// so trails, laps and gaps match what playing up to that point would
+    /// have shown.
+    fn seek(&mut self, index: usize) {
+        let (race_started, paused) = (self.race_started, self.paused);
+        self.reset();
+        self.sim.seek(index);
+        self.sync_cues();
+        self.race_complete = self.sim.current_index > 0 && self.sim.is_finished();
+        self.race_started = race_started && !self.race_complete;
+        self.paused = paused;
+        self.clock.seek(self.sim.sim_elapsed_ms as f64);
+        if self.race_started && !self.paused {
+            self.clock.resume();
+        }
+    }
+
+    /// Seeks to what the jump box names: an offset from the current
+    /// simulated time such as `+90.5s` or `-10s`, or the moment a row is due
+    /// closest to a race time such as `15:04:22.500` on the day of the first
+    /// row. Times outside the data clamp to the start or the end.
+    /// Returns a message for the status line, or why the text was rejected.
+    fn jump_to_time(&mut self, text: &str) -> Result<String, String> {
+        let text = text.trim();
+        if let Some(offset_s) = parse_offset(text) {
+            let target_ms = (self.sim.sim_elapsed_ms as f64 + offset_s * 1000.0).max(0.0) as u64;
+            self.seek(self.sim.index_at_sim_ms(target_ms));
+            return Ok(format!("Jumped to {}", format_sim_ms(self.sim.sim_elapsed_ms)));
+        }
+        let Some(time) = ["%H:%M:%S%.f", "%H:%M"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(text, format).ok())
+        else {
+            return Err(format!("`{text}` is not a time like 15:04:22.500 or an offset like +90.5s"));
+        };
+        let (Some(first), Some(last)) = (self.first_date(), self.last_date()) else {
+            return Err("no data to jump in".to_string());
+        };
+        let (index, place) = self.index_near(RaceTime::TimeOfDay(time).resolve(first, last));
+        self.seek(index);
+        Ok(match place {
+            Ordering::Less => format!("{time} is before the first record at {}, jumped to the start", first.format("%H:%M:%S%.3f")),
+            Ordering::Greater => format!("{time} is after the last record at {}, jumped to the end", last.format("%H:%M:%S%.3f")),
+            Ordering::Equal => format!("Jumped to {}", self.race_date().unwrap_or(first).format("%H:%M:%S%.3f")),
+        })
+    }
+
+    /// Seeks to where `--start-at` says, before the first frame. Times
+    /// outside the data open at its first or last row with a warning.
+    fn start_at(&mut self, at: RaceTime) {
+        let (Some(first), Some(last)) = (self.first_date(), self.last_date()) else {
+            eprintln!("warning: no data to start part way through");
+            return;
+        };
+        let target = at.resolve(first, last);
+        let (index, place) = self.index_near(target);
+        let format = |date: DateTime<Utc>| date.format("%Y-%m-%d %H:%M:%S%.3f");
+        match place {
+            Ordering::Less => eprintln!("warning: start_at {} is before the first row at {}, starting there", format(target), format(first)),
+            Ordering::Greater => eprintln!("warning: start_at {} is after the last row at {}, starting at the end", format(target), format(last)),
+            Ordering::Equal => {}
+        }
+        self.seek(index);
+    }
+
+    /// Date of the first row due, `None` without rows.
+    fn first_date(&self) -> Option<DateTime<Utc>> {
+        self.sim.first_moment_ms().map(|sim_ms| self.sim.date_at(sim_ms))
+    }
+
+    fn last_date(&self) -> Option<DateTime<Utc>> {
+        self.sim.last_moment_ms().map(|sim_ms| self.sim.date_at(sim_ms))
+    }
+
+    /// The index that shows the moment a row is due closest to `target`,
+    /// and whether `target` is before the data (index 0), after it (the
+    /// end) or within it.
+    fn index_near(&self, target: DateTime<Utc>) -> (usize, Ordering) {
+        match (self.first_date(), self.last_date()) {
+            (Some(first), _) if target < first => return (0, Ordering::Less),
+            (_, Some(last)) if target > last => return (self.sim.moments(), Ordering::Greater),
+            (None, _) => return (0, Ordering::Less),
+            _ => {}
+        }
+        let target_ms = (target - self.sim.session_start()).num_milliseconds();
+        let t_ms = |moment: usize| self.sim.moment_ms(moment).map_or(i64::MAX, |ms| ms as i64);
+        let after = match u64::try_from(target_ms) {
+            Ok(ms) if ms > 0 => self.sim.index_at_sim_ms(ms - 1),
+            _ => 0,
+        };
+        let moment = match after.checked_sub(1) {
+            Some(before) if target_ms - t_ms(before) <= t_ms(after) - target_ms => before,
+            _ => after,
+        };
+        (moment + 1, Ordering::Equal)
+    }
+
+    fn add_bookmark(&mut self) {
+        let label = std::mem::take(&mut self.bookmark_label).trim().to_string();
+        let at = self.bookmarks.partition_point(|bookmark| bookmark.sim_ms <= self.sim.sim_elapsed_ms);
+        self.bookmarks.insert(at, Bookmark { sim_ms: self.sim.sim_elapsed_ms, label });
+    }
+
+    /// Bookmarks are kept next to the data of the race they belong to.
+    fn bookmarks_path(&self) -> PathBuf {
+        match &self.selected_race {
+            Some(race) => self.data_dir.join(race),
+            None => self.data_dir.clone(),
+        }
+        .join(bookmarks::BOOKMARKS_FILE)
+    }
+
+    /// Starts playback in the current direction: forwards from the first
+    /// row, or backwards from the current row, or from the end if playback
+    /// is at the start.
+    fn start(&mut self) {
+        if self.replay.is_some() {
+            self.reset();
+            self.replay.as_mut().unwrap().restart();
+            self.race_started = true;
+            return;
+        }
+        if !self.reverse {
+            self.reset();
+            // Cars wait on the grid, or the countdown, until lights out
+            if (!self.grid.is_empty() || self.countdown) && !self.start_lights.is_zero() {
+                self.lights_since = Some(self.clock.wall_now());
+            }
+        } else if self.sim.current_index == 0 {
+            self.seek(usize::MAX);
+        }
+        self.race_started = true;
+        self.paused = false;
+        self.clock.resume();
+    }
+
+    /// Whether the step buttons apply: playback is paused or has finished.
+    fn can_step(&self) -> bool {
+        (self.race_started && self.paused) || self.race_complete
+    }
+
+    /// Shows one row more or less and stays paused. Going back replays from
+    /// the checkpoint before so the trails, laps and gaps are exactly those
+    /// of the new index. Clamped at the first row and at the end of the longest dataset.
+    fn step(&mut self, forward: bool) {
+        if forward {
+            self.sim.advance();
+        } else if let Some(index) = self.sim.current_index.checked_sub(1) {
+            self.seek(index);
+        }
+        self.race_complete = self.sim.current_index > 0 && self.sim.is_finished();
+        self.race_started = !self.race_complete;
+        self.paused = !self.race_complete;
+        self.clock.pause();
+        self.clock.seek(self.sim.sim_elapsed_ms as f64);
+    }
+
+    /// Finds the rows the last step showed, unless they are known for the
+    /// current index, and reads their positions again from the files on
+    /// another thread, filling them in once read.
+    fn update_stepped(&mut self) {
+        let index = self.sim.current_index;
+        let stepped = self.stepped.as_ref().is_some_and(|(stepped_index, _)| *stepped_index == index);
+        if !stepped {
+            let rows = self
+                .sim
+                .last_step_rows()
+                .into_iter()
+                .filter_map(|(dataset_idx, row)| {
+                    let data = &self.sim.drivers[dataset_idx].records;
+                    let sample = data.get(row)?;
+                    Some(SteppedRow { dataset_idx, line: sample.line, date: data.date(row), position: None, led: sample.on_led() })
+                })
+                .collect();
+            self.stepped = Some((index, rows));
+        }
+        let read_index = match &self.stepped_reads {
+            Some((read_index, receiver)) => match receiver.try_recv() {
+                Ok(raws) => {
+                    if let Some((_, rows)) = self.stepped.as_mut().filter(|(stepped_index, _)| stepped_index == read_index) {
+                        for (row, raw) in rows.iter_mut().zip(raws) {
+                            if let Some(raw) = raw {
+                                (row.date, row.position) = (Some(raw.date), Some((raw.x_led, raw.y_led)));
+                            }
+                        }
+                    }
+                    Some(*read_index)
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => Some(*read_index),
+            },
+            None if stepped => return,
+            None => None,
+        };
+        self.stepped_reads = None;
+        if read_index != Some(index) {
+            self.read_stepped(index);
+        }
+    }
+
+    /// Starts reading the rows of the step to `index` again, by their lines.
+    fn read_stepped(&mut self, index: usize) {
+        let Some((_, rows)) = &self.stepped else {
+            return;
+        };
+        let lines: Vec<Option<(PathBuf, u64)>> = rows
+            .iter()
+            .map(|row| Some((self.sim.drivers[row.dataset_idx].records.path.clone()?, row.line as u64)).filter(|_| row.line > 0))
+            .collect();
+        if lines.iter().all(Option::is_none) {
+            return;
+        }
+        let delimiter = self.read_options.delimiter;
+        let (sender, receiver) = mpsc::channel();
+        loader::spawn(move || {
+            let raws = lines
+                .into_iter()
+                .map(|line| {
+                    let (path, line) = line?;
+                    data::read_race_line(&path, delimiter, line).map_err(|e| eprintln!("warning: cannot read {}: {e}", path.display())).ok().flatten()
+                })
+                .collect();
+            let _ = sender.send(raws);
+        });
+        self.stepped_reads = Some((index, receiver));
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if paused {
+            self.clock.pause();
+        } else {
+            self.clock.resume();
+        }
+    }
+
+    /// Writes the playback position, speed and visibility to `path`.
+    fn save_session(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let session = Session {
+            version: SESSION_VERSION,
+            data_dir: Some(self.data_dir.clone()),
+            race: self.selected_race.clone(),
+            current_index: self.sim.current_index,
+            playback_speed: Some(self.speed),
+            race_started: self.race_started,
+            paused: self.paused,
+            visible: self.visibility(),
+        };
+        session.write(path)
+    }
+
+    /// Restores a session written by `save_session`. Fields missing from
+    /// older files keep their current values, and drivers that are not in
+    /// the loaded race are ignored.
+    fn load_session(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.apply_session(Session::read(path)?);
+        Ok(())
+    }
+
+    fn apply_session(&mut self, mut session: Session) {
+        if let Some(data_dir) = session.data_dir.take() {
+            self.races = loader::list_races(&data_dir);
+            self.data_dir = data_dir;
+        }
+        if let Some(race) = session.race.take() {
+            self.load_race(Some(&race));
+            // The rest is for the race, once it is loaded
+            if let Some(startup) = &mut self.startup {
+                startup.session = Some(session);
+            }
+            return;
+        }
+        if let Some(speed) = session.playback_speed.filter(|speed| speed.is_finite() && *speed > 0.0) {
+            self.speed = speed;
+        }
+        for driver in &mut self.sim.drivers {
+            if let Some(&saved) = session.visible.get(&driver.name) {
+                driver.visible = saved;
+            }
+        }
+        self.race_started = session.race_started;
+        self.paused = session.paused;
+        self.seek(session.current_index);
+    }
+
+    /// Keeps the track view on `dataset_idx`, zooming in with `follow_zoom`
+    /// if the whole track is in view, or goes back to the fitted view for `None`.
+    fn follow(&mut self, dataset_idx: Option<usize>) {
+        self.following = dataset_idx;
+        match dataset_idx {
+            Some(_) if self.follow_zoom && self.zoom <= 1.0 => self.zoom = FOLLOW_ZOOM,
+            Some(_) => {}
+            None => (self.zoom, self.focus) = (1.0, FITTED_FOCUS),
+        }
+    }
+
+    /// Stops following a driver once their data has run out.
+    fn check_following(&mut self) {
+        let Some(dataset_idx) = self.following else { return };
+        if self.sim.cars[dataset_idx].ended_at_ms.is_some() {
+            self.follow(None);
+            self.status = Some(format!("{}'s data ended, showing the whole track", self.sim.drivers[dataset_idx].name));
+        }
+    }
+
+    /// Moves the camera `dt` seconds' worth of the way towards `target`.
+    /// Returns whether it still has some way to go.
+    fn move_camera(&mut self, target: egui::Vec2, dt: f32) -> bool {
+        self.focus += (target - self.focus) * (1.0 - (-dt / CAMERA_EASE).exp());
+        (target - self.focus).length() * self.zoom > 0.001
+    }
+
+    /// Current speed of a car from the last two rows shown, in coordinate
+    /// units per second between their LEDs. `None` before two rows have
+    /// been shown, after the dataset has ended, or when both rows carry the
+    /// same timestamp.
+    fn car_speed(&self, dataset_idx: usize) -> Option<f64> {
+        let car = self.sim.cars.get(dataset_idx).filter(|car| car.ended_at_ms.is_none())?;
+        let row = car.rows.checked_sub(1)?;
+        let data = &self.sim.drivers[dataset_idx].records;
+        let (prev, curr) = (data.get(row.checked_sub(1)?)?, data.get(row)?);
+        let seconds = curr.t_ms.saturating_sub(prev.t_ms) as f64 / 1000.0;
+        if seconds <= 0.0 {
+            return None;
+        }
+        let (from, to) = (self.sim.coordinates.get(prev.on_led()?)?, self.sim.coordinates.get(curr.on_led()?)?);
+        Some((to.x_led - from.x_led).hypot(to.y_led - from.y_led) / seconds)
+    }
+
+    /// Shows every row the clock has reached since the last call.
+    ///
+    /// The race ends when the longest dataset runs out: shorter datasets stop
+    /// on their last LED while the others continue, and once no dataset has a
+    /// row left the race stops and is marked complete.
+    ///
+    /// In reverse the clock runs backwards and the previous row is shown
+    /// instead, rebuilt by replaying up to it from the checkpoint before,
+    /// one row per call. Reaching the first row stops the race as STOP
+    /// would.
+    ///
+    /// When looping, a complete race starts again from the first row once
+    /// `loop_pause` has passed; every loop begins from the reset state.
+    fn update_playback(&mut self) {
+        if self.looping && self.race_complete && self.clock.wall_now() >= self.loop_at {
+            self.reset();
+            self.race_started = true;
+            self.clock.resume();
+            return;
+        }
+        let running = self.race_started && !self.paused;
+        self.clock.set_speed(if self.reverse { -self.speed } else { self.speed });
+        if !running {
+            self.clock.pause();
+            return;
+        }
+        if let Some(since) = self.lights_since {
+            if (self.clock.wall_now() - since).to_std().unwrap_or_default() < self.start_lights {
+                self.clock.pause();
+                return;
+            }
+            // Lights out is the first row, the start of the race clock
+            self.lights_since = None;
+            self.lights_out_at = Some(self.clock.wall_now());
+            self.clock.seek(self.sim.first_moment_ms().unwrap_or(0) as f64);
+        }
+        self.clock.resume();
+        self.skip_gap();
+        if self.reverse {
+            if self.next_due_ms().is_some_and(|due_ms| self.clock.reached(due_ms)) {
+                match self.sim.current_index.checked_sub(1) {
+                    Some(index) if index > 0 => self.seek(index),
+                    _ => self.reset(),
+                }
+            }
+            return;
+        }
+        let laps = self.sim.completed_laps();
+        while self.next_due_ms().is_some_and(|due_ms| self.clock.reached(due_ms)) {
+            self.sim.advance();
+            self.skip_gap();
+        }
+        if self.sim.completed_laps() > laps {
+            self.lap_completed();
+        }
+        if self.sim.is_finished() {
+            self.race_started = false;
+            self.race_complete = true;
+            self.loop_at = self.clock.wall_now() + self.loop_pause;
+        }
+    }
+
+    /// Flashes the start/finish LED and, with `sound`, rings the terminal
+    /// bell, at most once every `LAP_BELL_GAP`.
+    fn lap_completed(&mut self) {
+        let now = self.clock.wall_now();
+        self.lap_flash_until = Some(now + LAP_FLASH);
+        if self.sound && self.lap_bell_at.is_none_or(|at| (now - at).to_std().unwrap_or_default() >= LAP_BELL_GAP) {
+            self.lap_bell_at = Some(now);
+            ring_bell();
+        }
+    }
+
+    /// Lights the start/finish LED white while a lap flash lasts.
+    fn update_lap_flash(&mut self) {
+        let flashing = self.lap_flash_until.is_some_and(|until| self.clock.wall_now() < until);
+        self.sim.flash_led = self.sim.start_finish_led.filter(|_| flashing);
+    }
+
+    /// Laps in the race, when they are counted.
+    fn race_laps(&self) -> Option<u32> {
+        Some(self.total_laps()).filter(|&laps| laps > 0)
+    }
+
+    /// Simulated time of the chequered flag in the events file, if it has one.
+    fn chequered_ms(&self) -> Option<u64> {
+        self.events.chequered().map(|date| (date - self.sim.session_start()).num_milliseconds().max(0) as u64)
+    }
+
+    /// Takes up where playback is after it jumped, without cueing what it jumped over.
+    fn sync_cues(&mut self) {
+        self.cue_detector.sync(&self.sim, self.race_laps(), self.chequered_ms());
+    }
+
+    /// Starts a pulse of the whole track, and with `cue_sound` a sound, for
+    /// each cue turned on that playback reached, then fades the
+    /// pulse out over `CUE_FLASH`. The cars underneath are left as they are.
+    fn update_cues(&mut self) {
+        let now = self.clock.wall_now();
+        let cues = self.cue_detector.observe(&self.sim, self.race_laps(), self.chequered_ms());
+        if cues.iter().any(|cue| self.cues.contains(cue)) {
+            self.cue_flash_until = Some(now + CUE_FLASH);
+            if self.cue_sound {
+                self.sound_cue();
+            }
+        }
+        let left = self.cue_flash_until.and_then(|until| (until - now).to_std().ok());
+        self.sim.track_flash = left.map(|left| left.as_secs_f32() / CUE_FLASH.as_secs_f32());
+    }
+
+    /// Plays the cue tone with the sound feature, or rings the bell without
+    /// it or when there is no audio output.
+    fn sound_cue(&self) {
+        #[cfg(feature = "sound")]
+        {
+            let chime = self.chime.get_or_init(|| crate::sound::Chime::open().inspect_err(|e| eprintln!("warning: ringing the bell for cues, {e}")).ok());
+            if let Some(chime) = chime {
+                chime.play();
+                return;
+            }
+        }
+        ring_bell();
+    }
+
+    /// Simulated time at which playback shows another row: the next one's
+    /// time, or in reverse that of the row before the one shown. `None` at
+    /// the end of the data going forwards.
+    fn next_due_ms(&self) -> Option<f64> {
+        if self.reverse {
+            let step_ms = self.sim.current_index.checked_sub(1).and_then(|row| self.sim.step_ms_at(row)).unwrap_or(0);
+            return Some(self.sim.sim_elapsed_ms.saturating_sub(step_ms) as f64);
+        }
+        self.sim.next_step_ms().map(|step_ms| (self.sim.sim_elapsed_ms + step_ms) as f64)
+    }
+
+    /// When playback next has something to do: at `next_due_ms`, or with
+    /// `skip_gaps` once it has waited `max_gap` for a row further away.
+    fn next_wake_ms(&self) -> Option<f64> {
+        let due_ms = self.next_due_ms()?;
+        if !self.skip_gaps {
+            return Some(due_ms);
+        }
+        let from_ms = self.sim.sim_elapsed_ms as f64;
+        let gap_ms = self.max_gap.as_secs_f64() * 1000.0 * self.speed;
+        Some(if (due_ms - from_ms).abs() > gap_ms { from_ms + gap_ms * (due_ms - from_ms).signum() } else { due_ms })
+    }
+
+    /// Jumps the clock to the next row once playback has waited `max_gap`
+    /// for it, noting how much simulated time that skipped.
+    fn skip_gap(&mut self) {
+        let (Some(due_ms), Some(wake_ms)) = (self.next_due_ms(), self.next_wake_ms()) else {
+            return;
+        };
+        if wake_ms != due_ms && self.clock.reached(wake_ms) {
+            self.clock.seek(due_ms);
+            self.skipped = Some(((due_ms - wake_ms).abs() as u64, self.clock.wall_now() + SKIP_NOTICE));
+        }
+    }
+
+    /// How far playback is through the wait for the next row, from 0 just
+    /// after a row was shown to 1 when the next one is due. `None` unless
+    /// playing forwards.
+    fn step_fraction(&self) -> Option<f32> {
+        if !self.race_started || self.paused || self.reverse {
+            return None;
+        }
+        let step_ms = self.sim.next_step_ms()? as f64;
+        let into_ms = self.clock.now_ms() - self.sim.sim_elapsed_ms as f64;
+        (step_ms > 0.0).then(|| (into_ms / step_ms).clamp(0.0, 1.0) as f32)
+    }
+
+    /// With `interpolate`, finds the LED nearest to each car's position at
+    /// the clock's time on the straight line from its current LED to the next row's.
+    fn interpolate_cars(&mut self) {
+        self.sim.interpolated.clear();
+        if !self.interpolate || self.step_fraction().is_none() {
+            return;
+        }
+        self.sim.interpolated = (0..self.sim.cars.len())
+            .map(|dataset_idx| {
+                let (from, to, fraction) = self.car_step(dataset_idx)?;
+                let (a, b) = (&self.sim.coordinates[from], &self.sim.coordinates[to]);
+                let x = a.x_led + (b.x_led - a.x_led) * fraction;
+                let y = a.y_led + (b.y_led - a.y_led) * fraction;
+                self.sim.led_index.nearest(x, y).filter(|&led| led != from)
+            })
+            .collect();
+    }
+
+    /// For smooth motion: each car `shown` picks that is moving to a
+    /// different LED in the next row, as its current LED, the next one, how
+    /// far it is between them by the clock and its color.
+    fn moving_cars(&self, shown: &dyn Fn(usize) -> bool) -> Vec<(usize, usize, f32, egui::Color32)> {
+        if self.step_fraction().is_none() {
+            return Vec::new();
+        }
+        (0..self.sim.cars.len())
+            .filter(|&dataset_idx| shown(dataset_idx))
+            .filter_map(|dataset_idx| {
+                let (from, to, fraction) = self.car_step(dataset_idx)?;
+                (from != to).then_some((from, to, fraction as f32, self.sim.correction.apply(self.sim.drivers[dataset_idx].color)))
+            })
+            .collect()
+    }
+
+    /// A car's current LED, the LED of its next row and how far it is from
+    /// one row to the other by the clock, from 0 to 1.
+    fn car_step(&self, dataset_idx: usize) -> Option<(usize, usize, f64)> {
+        let car = &self.sim.cars[dataset_idx];
+        let from = *car.trail.front()?;
+        let to = self.sim.drivers[dataset_idx].records.get(car.rows)?.on_led()?;
+        let shown_ms = self.sim.row_ms(dataset_idx, car.rows.checked_sub(1)?)?;
+        let step_ms = self.sim.row_ms(dataset_idx, car.rows)?.saturating_sub(shown_ms) as f64;
+        let fraction = if step_ms > 0.0 { ((self.clock.now_ms() - shown_ms as f64) / step_ms).clamp(0.0, 1.0) } else { 1.0 };
+        Some((from, to, fraction))
+    }
+
+    /// Writes the track view as it is currently drawn to a timestamped PNG
+    /// in the screenshot directory and returns its path.
+    fn screenshot(&self, background: egui::Color32) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.screenshot_dir.join(screenshot_name());
+        self.render_frame(background)
+            .save(&path)
+            .map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Where playback is and what it lights, for a bug report.
+    fn snapshot(&self) -> Snapshot {
+        let cars = self
+            .sim
+            .cars
+            .iter()
+            .enumerate()
+            .map(|(dataset_idx, car)| CarSnapshot {
+                key: self.sim.drivers[dataset_idx].key.clone(),
+                name: self.sim.drivers[dataset_idx].name.clone(),
+                visible: self.sim.drivers[dataset_idx].visible,
+                rows: car.rows,
+                of_rows: self.sim.drivers[dataset_idx].records.len(),
+                trail: car.trail.iter().copied().collect(),
+                interpolated: self.sim.interpolated.get(dataset_idx).copied().flatten(),
+                off_map: car.off_map,
+                lap: car.laps.lap(),
+                ended_at_ms: car.ended_at_ms,
+                offset_ms: self.sim.offset_ms(dataset_idx),
+                manual_offset_ms: self.sim.manual_offset_ms(dataset_idx),
+            })
+            .collect();
+        Snapshot {
+            written_at: self.clock.wall_now(),
+            coordinates: self.coordinates_path.clone(),
+            data_dir: self.data_dir.clone(),
+            race: self.selected_race.clone(),
+            leds: self.sim.coordinates.len(),
+            current_index: self.sim.current_index,
+            moments: self.sim.moments(),
+            sim_elapsed_ms: self.sim.sim_elapsed_ms,
+            session_start: self.sim.session_start(),
+            clock_ms: self.clock.now_ms(),
+            clock: self.clock.clone(),
+            speed: self.speed,
+            reverse: self.reverse,
+            looping: self.looping,
+            race_started: self.race_started,
+            paused: self.paused,
+            race_complete: self.race_complete,
+            cars,
+            lit_leds: LitLed::all(&self.sim.lit_leds()),
+            leds_at: LitLed::all(&self.sim.leds_at(self.sim.current_index)),
+        }
+    }
+
+    /// The track view as it is currently drawn, at the size it is drawn.
+    fn render_frame(&self, background: egui::Color32) -> image::RgbaImage {
+        let size = [self.view_size.x.round().max(1.0) as u32, self.view_size.y.round().max(1.0) as u32];
+        render::render_leds(size, &self.sim.coordinates, &self.track_style(), &self.sim.lit_leds(), background)
+    }
+
+    /// How many rows of the whole race put a car on each LED, as a track
+    /// view of `size` pixels with the LEDs colored along `gradient`.
+    fn render_heatmap(&self, size: [u32; 2], gradient: Gradient) -> image::RgbaImage {
+        let counts = heatmap::visit_counts(self.sim.drivers.iter().map(|driver| &driver.records), self.sim.coordinates.len());
+        let lit = heatmap::lit_leds(&counts, gradient);
+        render::render_leds(size, &self.sim.coordinates, &self.track_style(), &lit, self.backdrop.fill(self.theme.visuals().panel_fill))
+    }
+
+    fn track_style(&self) -> TrackStyle<'_> {
+        TrackStyle {
+            keep_aspect: self.keep_aspect,
+            shape: self.led_shape,
+            led_size: self.led_size,
+            spacing: self.led_spacing,
+            unlit_outline: self.unlit_outline,
+            outline: if self.show_outline { &self.outline } else { &[] },
+            outline_color: self.current_outline_color(),
+            outline_shades: &self.sectors.shades,
+            sector_lines: &self.sectors.boundaries,
+            backdrop: self.backdrop,
+        }
+    }
+
+    /// Race time of the row shown, as in the top bar.
+    fn race_date(&self) -> Option<DateTime<Utc>> {
+        let sim_ms = self.sim.moment_ms(self.sim.current_index.saturating_sub(1))?;
+        Some(self.sim.date_at(sim_ms))
+    }
+
+    /// Simulated time since the first row, read from the clock so it runs on
+    /// between rows at the playback speed and holds while paused. `None`
+    /// without rows.
+    fn race_elapsed_ms(&self) -> Option<u64> {
+        let (first_ms, last_ms) = (self.sim.first_moment_ms()?, self.sim.last_moment_ms()?);
+        if self.sim.current_index == 0 {
+            return Some(0);
+        }
+        Some((self.clock.now_ms().clamp(first_ms as f64, last_ms as f64) - first_ms as f64) as u64)
+    }
+
+    /// What the top bar clock shows in `clock_mode`, `None` before the
+    /// first row is shown.
+    fn clock_text(&self) -> Option<String> {
+        let date = self.race_date()?;
+        Some(match self.clock_mode {
+            ClockMode::Utc => format!("{} UTC", date.format("%H:%M:%S%.3f")),
+            ClockMode::Local => date.with_timezone(&chrono::Local).format("%H:%M:%S%.3f").to_string(),
+            ClockMode::Session => format_elapsed(self.race_elapsed_ms()?),
+        })
+    }
+
+    /// How far through the race's rows playback is, from 0.0 to 1.0.
+    fn race_fraction(&self) -> f32 {
+        match self.sim.moments() {
+            0 => 0.0,
+            total => self.sim.current_index as f32 / total as f32,
+        }
+    }
+
+    /// The track outline color, tinted while a yellow or red flag is out.
+    fn current_outline_color(&self) -> egui::Color32 {
+        let flag = self.race_date().and_then(|date| self.events.flag_at(date));
+        flag.and_then(|(flag, _)| flag.outline_tint()).unwrap_or(self.outline_color)
+    }
+
+    /// What `sample` takes of playback at each frame of a clip, as
+    /// `sample_clip` steps through it at the current speed. Playback is
+    /// back where it was afterwards.
+    fn sample_frames<T>(&mut self, start_ms: u64, end_ms: u64, fps: u32, sample: impl FnMut(&Simulation) -> T) -> Vec<T> {
+        let saved = self.sim.current_index;
+        let frames = sample_clip(&mut self.sim, start_ms, end_ms, fps, self.speed, sample);
+        self.seek(saved);
+        frames
+    }
+
+    /// The whole race as a GIF of `size` pixels at `fps`, showing the whole
+    /// track, or with the camera on `following` as the track view follows
+    /// a driver: zoomed in as `follow` does and easing after the car, from
+    /// where they start. It holds still once their data ends.
+    fn race_clip(&mut self, size: [u32; 2], fps: u32, following: Option<usize>) -> Clip {
+        let end_ms = self.sim.last_moment_ms().unwrap_or(0);
+        let (frames, followed_leds): (Vec<_>, Vec<_>) = self
+            .sample_frames(0, end_ms, fps, |sim| (sim.lit_leds(), following.and_then(|idx| sim.cars[idx].trail.front().copied())))
+            .into_iter()
+            .unzip();
+        let mut cameras = Vec::new();
+        if following.is_some() {
+            let saved = (self.following, self.zoom, self.focus);
+            let bounds = Bounds::of(&self.sim.coordinates);
+            let fitted = Projection::new(&bounds, egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(size[0] as f32, size[1] as f32)), self.keep_aspect);
+            let led_size = self.led_size.resolve(self.led_shape, self.led_spacing, &fitted);
+            let target = |led: usize| fitted.fraction(fitted.to_screen(&self.sim.coordinates[led]) + led_size / 2.0);
+            let targets: Vec<_> = followed_leds.iter().map(|led| led.map(target)).collect();
+            (self.zoom, self.focus) = (1.0, targets.iter().flatten().next().copied().unwrap_or(FITTED_FOCUS));
+            self.follow(following);
+            for target in targets {
+                if let Some(target) = target {
+                    self.move_camera(target, 1.0 / fps as f32);
+                }
+                cameras.push(Camera { zoom: self.zoom, focus: self.focus });
+            }
+            (self.following, self.zoom, self.focus) = saved;
+        }
+        let background = self.backdrop.fill(self.theme.visuals().panel_fill);
+        Clip { cameras, ..self.clip(size, fps, ClipFormat::Gif, frames, background) }
+    }
+
+    /// A clip of `frames` drawn as the track view is styled, on the whole track.
+    fn clip(&self, size: [u32; 2], fps: u32, format: ClipFormat, frames: Vec<Vec<(usize, egui::Color32)>>, background: egui::Color32) -> Clip {
+        Clip {
+            size,
+            fps,
+            format,
+            coordinates: self.sim.coordinates.clone(),
+            keep_aspect: self.keep_aspect,
+            shape: self.led_shape,
+            led_size: self.led_size,
+            spacing: self.led_spacing,
+            unlit_outline: self.unlit_outline,
+            outline: if self.show_outline { self.outline.clone() } else { Vec::new() },
+            outline_color: self.outline_color,
+            outline_shades: self.sectors.shades.clone(),
+            sector_lines: self.sectors.boundaries.clone(),
+            background,
+            backdrop: self.backdrop,
+            frames,
+            cameras: Vec::new(),
+        }
+    }
+
+    /// Every LED frame of the race from the start, as the LED output would
+    /// send them, each with the wait since the one before at the current
+    /// speed, capped at `max_gap` when skipping gaps. A frame the same as the one before it is left out, its wait
+    /// going to the next. Playback is back where it was afterwards.
+    fn led_frames(&mut self) -> Vec<LedFrame> {
+        let saved = self.sim.current_index;
+        let (positions, speed, max_gap_ms) = (&self.strip_positions, self.speed, self.skip_gaps.then_some(self.max_gap.as_millis() as u64));
+        let frames = self.sim.replay(|sim| {
+            sim.seek(0);
+            let (mut frames, mut shown_at_ms): (Vec<LedFrame>, u64) = (Vec::new(), 0);
+            while sim.advance() {
+                let rgb = output::strip_frame(&sim.led_colors(), positions);
+                if frames.last().is_some_and(|last| last.rgb == rgb) {
+                    continue;
+                }
+                // From the total so rounding does not add up over the race
+                let at_ms = (sim.sim_elapsed_ms as f64 / speed).round() as u64;
+                let mut delay_ms = at_ms - shown_at_ms;
+                if let Some(max_gap_ms) = max_gap_ms {
+                    delay_ms = delay_ms.min(max_gap_ms);
+                }
+                frames.push(LedFrame { delay_ms: delay_ms as u32, rgb });
+                shown_at_ms = at_ms;
+            }
+            frames
+        });
+        self.seek(saved);
+        frames
+    }
+
+    /// Every driver's key, dataset and manual offset, for the CSV export
+    /// to resolve on its own thread.
+    fn resolved_drivers(&self) -> Vec<(String, Dataset, i64)> {
+        self.sim.drivers
+            .iter()
+            .enumerate()
+            .map(|(dataset_idx, driver)| (driver.key.clone(), driver.records.clone(), self.sim.manual_offset_ms(dataset_idx)))
+            .collect()
+    }
+
+    /// The clip export window: the time range, picked from the current
+    /// time or the bookmarks, the size, frame rate and format, and an
+    /// estimate of the file size that needs confirming when large.
+    fn clip_window(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.clip_dialog.take() else {
+            return;
+        };
+        let (mut open, mut export) = (true, false);
+        egui::Window::new("Export clip").open(&mut open).resizable(false).show(ctx, |ui| {
+            egui::Grid::new("clip_grid").num_columns(2).show(ui, |ui| {
+                for (label, time_ms) in [("Start", &mut dialog.start_ms), ("End", &mut dialog.end_ms)] {
+                    ui.label(label);
+                    ui.horizontal(|ui| {
+                        let mut seconds = *time_ms as f64 / 1000.0;
+                        let drag = egui::DragValue::new(&mut seconds).speed(0.5).clamp_range(0.0..=f64::MAX).suffix(" s");
+                        if ui.add(drag).changed() {
+                            *time_ms = (seconds * 1000.0).round() as u64;
+                        }
+                        ui.label(format_sim_ms(*time_ms));
+                        if ui.button("Now").clicked() {
+                            *time_ms = self.sim.sim_elapsed_ms;
+                        }
+                        ui.add_enabled_ui(!self.bookmarks.is_empty(), |ui| {
+                            ui.menu_button("Bookmark", |ui| {
+                                for bookmark in &self.bookmarks {
+                                    if ui.button(format!("{} {}", format_sim_ms(bookmark.sim_ms), bookmark.label)).clicked() {
+                                        *time_ms = bookmark.sim_ms;
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                        });
+                    });
+                    ui.end_row();
+                }
+                ui.label("Size");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut dialog.size[0]).clamp_range(16..=3840).suffix(" px"));
+                    ui.label("×");
+                    ui.add(egui::DragValue::new(&mut dialog.size[1]).clamp_range(16..=2160).suffix(" px"));
+                });
+                ui.end_row();
+                ui.label("Frame rate");
+                ui.add(egui::DragValue::new(&mut dialog.fps).clamp_range(1..=60).suffix(" fps"));
+                ui.end_row();
+                ui.label("Format");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut dialog.format, ClipFormat::Gif, "GIF");
+                    ui.radio_value(&mut dialog.format, ClipFormat::Apng, "APNG");
+                });
+                ui.end_row();
+            });
+            ui.separator();
+            if dialog.end_ms < dialog.start_ms {
+                ui.colored_label(egui::Color32::RED, "The end is before the start");
+                return;
+            }
+            let frames = clip_frame_count(dialog.start_ms, dialog.end_ms, dialog.fps, self.speed);
+            let bytes = Clip::estimated_bytes(dialog.size, frames, dialog.format);
+            ui.label(format!("{frames} frames at {}x speed, about {:.1} MB", self.speed, bytes as f64 / 1e6));
+            let large = bytes > LARGE_CLIP_BYTES;
+            if large {
+                ui.colored_label(egui::Color32::YELLOW, "This is a large file, consider a shorter range, smaller size or lower frame rate");
+            }
+            export = ui.add_enabled(self.export.is_none(), egui::Button::new(if large { "Export anyway" } else { "Export" })).clicked();
+        });
+        if export {
+            // Sampled on the export thread from a copy, so playback carries on meanwhile
+            let ClipDialog { start_ms, end_ms, size, fps, format } = dialog;
+            let (mut sim, speed) = (self.sim.clone(), self.speed);
+            let background = self.backdrop.fill(ctx.style().visuals.panel_fill);
+            let clip = self.clip(size, fps, format, Vec::new(), background);
+            let total = clip_frame_count(start_ms, end_ms, fps, speed);
+            self.export = Export::clip(clip, total, move || sample_clip(&mut sim, start_ms, end_ms, fps, speed, Simulation::lit_leds));
+        } else if open {
+            self.clip_dialog = Some(dialog);
+        }
+    }
+
+    /// The window listing when each car arrived on the clicked LED. Clicking
+    /// a time jumps there.
+    fn led_window(&mut self, ctx: &egui::Context) {
+        let Some(passes) = &self.led_passes else {
+            return;
+        };
+        let (mut open, mut jump) = (true, None);
+        let title = match &self.sim.coordinates[passes.led].designator {
+            Some(designator) => format!("LED {} ({designator})", passes.led),
+            None => format!("LED {}", passes.led),
+        };
+        egui::Window::new(title).id(egui::Id::new("led_window")).open(&mut open).show(ctx, |ui| {
+            if passes.arrivals.is_empty() {
+                ui.label("No car went through this LED");
+                return;
+            }
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("led_grid").num_columns(2).show(ui, |ui| {
+                    for (dataset_idx, Driver { name, .. }) in self.sim.drivers.iter().enumerate() {
+                        let mut arrivals = passes.arrivals.iter().filter(|arrival| arrival.0 == dataset_idx).peekable();
+                        if arrivals.peek().is_none() {
+                            continue;
+                        }
+                        ui.colored_label(self.sim.drivers[dataset_idx].color, name);
+                        ui.horizontal_wrapped(|ui| {
+                            for &(_, index, sim_ms) in arrivals {
+                                if ui.link(format_sim_ms(sim_ms)).clicked() {
+                                    jump = Some(index);
+                                }
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+        if let Some(index) = jump {
+            self.seek(index);
+        }
+        if !open {
+            self.led_passes = None;
+        }
+    }
+
+    /// The finishing order, shown once the race is complete or the chequered
+    /// flag is out, with buttons that save it.
+    fn results_window(&mut self, ctx: &egui::Context) {
+        let chequered = self.race_date().and_then(|date| self.events.flag_at(date)).is_some_and(|(flag, _)| flag == events::Flag::Chequered);
+        if self.results_closed || !(self.race_complete || chequered) {
+            return;
+        }
+        let results = results::classify(&self.sim, self.chequered_ms());
+        let mut open = true;
+        let mut save = None;
+        egui::Window::new("Results").open(&mut open).resizable(false).anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO).show(ctx, |ui| {
+            egui::Grid::new("results_grid").striped(true).show(ui, |ui| {
+                for header in ["", "Driver", "Laps", "Last LED", "Time", "Rows", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (position, Classified { dataset_idx, laps, led, elapsed_ms, rows, status, retired_at_ms }) in results.iter().enumerate() {
+                    ui.label(format!("P{}", position + 1));
+                    ui.colored_label(self.sim.drivers[*dataset_idx].color, &self.sim.drivers[*dataset_idx].name);
+                    ui.label(laps.to_string());
+                    ui.label(led.map_or(String::new(), |led| led.to_string()));
+                    ui.label(format_sim_ms(*elapsed_ms));
+                    ui.label(rows.to_string());
+                    match (status, retired_at_ms) {
+                        (Status::Retired, Some(at_ms)) => ui.label(format!("Retired at {}", format_sim_ms(*at_ms))),
+                        _ => ui.label(""),
+                    };
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.add_enabled_ui(self.export.is_none(), |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Save CSV…").clicked() {
+                        save = Some(("CSV", &["csv"][..], results::to_csv(&results, &self.sim.drivers)));
+                    }
+                    if ui.button("Save JSON…").clicked() {
+                        save = Some(("JSON", &["json"][..], results::to_json(&results, &self.sim.drivers)));
+                    }
+                });
+            });
+        });
+        match save {
+            Some((filter, extensions, Ok(text))) => self.export = Export::document(text, filter, extensions, format!("results.{}", extensions[0])),
+            Some((_, _, Err(e))) => self.status = Some(format!("Cannot export the results: {e}")),
+            None => {}
+        }
+        if !open {
+            self.results_closed = true;
+        }
+    }
+
+    /// Paints the track through `projection`: the outline, every LED unlit,
+    /// then the cars `shown` picks with their trails, markers, labels and
+    /// highlight, over a faint `ghost` of one more car.
+    fn paint_track(
+        &self,
+        painter: &egui::Painter,
+        projection: &Projection<'_>,
+        rect: egui::Rect,
+        shown: &dyn Fn(usize) -> bool,
+        ghost: Option<usize>,
+    ) {
+        let led_size = self.led_size.resolve(self.led_shape, self.led_spacing, projection);
+        let positions: Vec<egui::Pos2> = self.sim.coordinates.iter().map(|coord| projection.to_screen(coord)).collect();
+
+        // The outline goes underneath everything, through the LED centers
+        if self.show_outline {
+            let outline_color = self.current_outline_color();
+            for segment in &self.outline {
+                for (shade, run) in render::outline_runs(segment, &self.sectors.shades) {
+                    let points = run.iter().map(|&led| positions[led] + led_size / 2.0).collect();
+                    let stroke = egui::Stroke::new(OUTLINE_WIDTH, render::shade(outline_color, shade));
+                    painter.add(egui::Shape::line(points, stroke));
+                }
+            }
+        }
+        // With a line across it where each sector begins
+        let stroke = egui::Stroke::new(SECTOR_LINE_WIDTH, self.current_outline_color());
+        for &(from, to) in &self.sectors.boundaries {
+            if let Some(line) = render::sector_line(positions[from] + led_size / 2.0, positions[to] + led_size / 2.0, led_size) {
+                painter.line_segment(line, stroke);
+            }
+        }
+
+        // Then draw all LEDs unlit, inside a faint edge if one is set
+        for (&pos, coord) in positions.iter().zip(&self.sim.coordinates) {
+            let rect = egui::Rect::from_min_size(pos, led_size);
+            if let Some(edge) = self.unlit_outline {
+                self.led_shape.paint(painter, rect, edge);
+                self.led_shape.paint(painter, rect.shrink(UNLIT_OUTLINE_WIDTH), render::led_fill(coord.class, None, self.backdrop));
+            } else {
+                self.led_shape.paint(painter, rect, render::led_fill(coord.class, None, self.backdrop));
+            }
+        }
+
+        // The reference race shows faintly under the loaded one
+        if let Some(reference) = self.reference.as_ref().filter(|_| self.show_reference && self.replay.is_none()) {
+            for (led, color) in reference.sim.lit_leds() {
+                self.led_shape.paint(painter, egui::Rect::from_min_size(positions[led], led_size), color.gamma_multiply(GHOST_ALPHA));
+            }
+        }
+
+        // A ghost of another car shows faintly under the others
+        if let Some(ghost) = ghost {
+            for (led, color) in self.sim.leds_at_of(self.sim.current_index, |dataset_idx| dataset_idx == ghost) {
+                self.led_shape.paint(painter, egui::Rect::from_min_size(positions[led], led_size), color.gamma_multiply(GHOST_ALPHA));
+            }
+        }
+
+        // Then light each car's current LED and its trail, inside an edge on a light background
+        let edge = render::lit_edge(self.backdrop.fill(self.theme.visuals().panel_fill));
+        for (led, color) in self.sim.leds_at_of(self.sim.current_index, shown) {
+            let class = self.sim.coordinates[led].class;
+            let rect = egui::Rect::from_min_size(positions[led], led_size);
+            match edge {
+                Some(edge) => {
+                    self.led_shape.paint(painter, rect, edge);
+                    self.led_shape.paint(painter, rect.shrink(UNLIT_OUTLINE_WIDTH), render::led_fill(class, None, self.backdrop));
+                    self.led_shape.paint(painter, rect.shrink(UNLIT_OUTLINE_WIDTH), render::led_fill(class, Some(color), self.backdrop));
+                }
+                None => self.led_shape.paint(painter, rect, render::led_fill(class, Some(color), self.backdrop)),
+            }
+        }
+
+        // Before the first row the drivers wait on their grid slots
+        if self.sim.current_index == 0 {
+            for (dataset_idx, led) in self.grid_leds().into_iter().filter(|&(dataset_idx, _)| shown(dataset_idx)) {
+                let color = render::led_fill(self.sim.coordinates[led].class, Some(self.sim.correction.apply(self.sim.drivers[dataset_idx].color)), self.backdrop);
+                self.led_shape.paint(painter, egui::Rect::from_min_size(positions[led], led_size), color);
+            }
+        }
+
+        // Cars stacked on one LED fan out around it, each in its own color
+        if self.stack_offset {
+            let size = led_size.min_elem();
+            for (led, cars) in self.sim.stacked_cars(shown) {
+                let center = egui::Rect::from_min_size(positions[led], led_size).center();
+                for (dataset_idx, offset) in cars.iter().zip(render::stack_offsets(cars.len(), size * STACK_RADIUS)) {
+                    let color = self.sim.correction.apply(self.sim.marker_color(*dataset_idx));
+                    painter.circle(center + offset, size * STACK_DOT_RADIUS, color, egui::Stroke::new(1.0, egui::Color32::BLACK));
+                }
+            }
+        }
+
+        // Markers on their way to the next row's LED, drawn by the frame clock
+        if self.smooth {
+            for (from, to, fraction, color) in self.moving_cars(shown) {
+                let center = positions[from].lerp(positions[to], fraction) + led_size / 2.0;
+                painter.circle_filled(center, led_size.x / 2.0, color);
+            }
+        }
+
+        // Driver codes next to each car's current LED, sized with the window
+        if self.show_labels {
+            let font = egui::FontId::proportional((rect.height() / 50.0).clamp(10.0, 20.0));
+            let galleys: Vec<_> = self
+                .sim
+                .cars
+                .iter()
+                .enumerate()
+                .filter(|&(dataset_idx, _)| shown(dataset_idx))
+                .filter_map(|(dataset_idx, car)| {
+                    let led = *car.trail.front()?;
+                    let galley = painter.layout_no_wrap(
+                        self.sim.drivers[dataset_idx].code.clone(),
+                        font.clone(),
+                        self.sim.drivers[dataset_idx].color,
+                    );
+                    Some((egui::Rect::from_min_size(positions[led], led_size), galley))
+                })
+                .collect();
+            let anchors: Vec<_> = galleys.iter().map(|(anchor, galley)| (*anchor, galley.size())).collect();
+            for (label_rect, (_, galley)) in labels::place_labels(&anchors).into_iter().zip(galleys) {
+                painter.rect_filled(label_rect, egui::Rounding::same(2.0), egui::Color32::from_black_alpha(160));
+                painter.galley(label_rect.min, galley, egui::Color32::WHITE);
+            }
+        }
+
+        // Each car's code on its current LED only, so trails stay uncluttered
+        let code_font = egui::FontId::monospace(led_size.x.min(led_size.y * 1.6) / 2.0);
+        if self.show_led_codes && code_font.size >= MIN_LED_CODE_SIZE {
+            for (dataset_idx, car) in self.sim.cars.iter().enumerate().filter(|&(dataset_idx, _)| shown(dataset_idx)) {
+                let Some(&led) = car.trail.front() else {
+                    continue;
+                };
+                let color = render::led_fill(self.sim.coordinates[led].class, Some(self.sim.correction.apply(self.sim.drivers[dataset_idx].color)), self.backdrop);
+                let center = egui::Rect::from_min_size(positions[led], led_size).center();
+                painter.text(center, egui::Align2::CENTER_CENTER, &self.sim.drivers[dataset_idx].code, code_font.clone(), render::text_on(color));
+            }
+        }
+
+        // Ring around the LED of the driver selected in the leaderboard
+        let highlighted_led = self.highlighted.filter(|&idx| shown(idx)).and_then(|idx| self.sim.cars.get(idx)?.trail.front().copied());
+        if let Some(led) = highlighted_led {
+            let center = egui::Rect::from_min_size(positions[led], led_size).center();
+            painter.circle_stroke(center, led_size.x, egui::Stroke::new(3.0, egui::Color32::WHITE));
+        }
+    }
+
+    /// The grid slot of each driver on the grid, by dataset.
+    fn grid_leds(&self) -> Vec<(usize, usize)> {
+        (0..self.sim.drivers.len())
+            .filter_map(|dataset_idx| Some((dataset_idx, self.grid.led_of(&self.sim.drivers[dataset_idx].key, &self.sim.drivers[dataset_idx].name, &self.sim.led_index)?)))
+            .collect()
+    }
+
+    /// The five red lights over the middle of the track view, each fading
+    /// in as it comes on, then "LIGHTS OUT" fading away once they go out.
+    fn paint_start_lights(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let now = self.clock.wall_now();
+        if let Some(shown) = self.lights_out_at.and_then(|at| (now - at).to_std().ok()).filter(|&shown| shown < LIGHTS_OUT_SHOWN) {
+            let fade = 1.0 - shown.as_secs_f32() / LIGHTS_OUT_SHOWN.as_secs_f32();
+            painter.text(rect.center(), egui::Align2::CENTER_CENTER, "LIGHTS OUT", egui::FontId::proportional(48.0), egui::Color32::WHITE.gamma_multiply(fade));
+        }
+        let Some(since) = self.lights_since else {
+            return;
+        };
+        let elapsed = (now - since).to_std().unwrap_or_default();
+        let width = (rect.width() * 0.08).clamp(24.0, 60.0);
+        let (light, gap) = (egui::vec2(width, 1.6 * width), 0.25 * width);
+        let size = egui::vec2(grid::START_LIGHTS as f32 * (light.x + gap) + gap, light.y + 2.0 * gap);
+        let housing = egui::Rect::from_center_size(rect.center(), size);
+        painter.rect_filled(housing, egui::Rounding::same(gap), egui::Color32::from_gray(20));
+        for idx in 0..grid::START_LIGHTS {
+            let min = housing.left_top() + egui::vec2(gap + idx as f32 * (light.x + gap), gap);
+            // From a dark red when off to full red
+            let level = grid::light_level(idx, elapsed, self.start_lights);
+            let color = egui::Color32::from_rgb((60.0 + 195.0 * level) as u8, 0, 0);
+            painter.rect_filled(egui::Rect::from_min_size(min, light), egui::Rounding::same(0.3 * gap), color);
+        }
+    }
+
+    /// How long until the window needs redrawing without any input: until
+    /// the next row is due while playing or waiting to loop, one tick while
+    /// cars move smoothly, sooner while an export reports progress or the
+    /// lap starts, a new snap distance or a reload are being worked out, and
+    /// `IDLE_REPAINT` when nothing is moving, with why for the frame stats.
+    /// `None` while paused or finished with nothing else to keep current.
+    fn wake(&self) -> (Option<Duration>, &'static str) {
+        let progress = [
+            (self.export.is_some(), "export progress"),
+            (self.lap_starts_job.is_some(), "lap starts"),
+            (self.snapping.is_some(), "snapping"),
+            (self.reloading.is_some(), "reloading"),
+            (self.reference_load.is_some(), "reference race"),
+        ]
+        .into_iter()
+        .find_map(|(waiting, reason)| waiting.then_some(reason));
+        let idle = progress.map_or((IDLE_REPAINT, "idle"), |reason| (PROGRESS_REPAINT, reason));
+        let sooner = |until: Duration, reason| if until < idle.0 { (Some(until), reason) } else { (Some(idle.0), idle.1) };
+        let resting = if progress.is_some() || self.keeps_current() { (Some(idle.0), idle.1) } else { (None, "paused") };
+        if let Some(replay) = &self.replay {
+            return replay.until_next().map_or(resting, |until| sooner(until, "replay"));
+        }
+        if let Some(until) = self.lap_flash_until.and_then(|until| (until - self.clock.wall_now()).to_std().ok()) {
+            return (Some(until.min(self.tick)), "lap flash");
+        }
+        if let Some(until) = self.cue_flash_until.and_then(|until| (until - self.clock.wall_now()).to_std().ok()) {
+            return (Some(until.min(self.tick)), "cue flash");
+        }
+        let waiting = (self.race_started && !self.paused) || (self.looping && self.race_complete);
+        if !waiting {
+            return resting;
+        }
+        if let Some(since) = self.lights_since {
+            let elapsed = (self.clock.wall_now() - since).to_std().unwrap_or_default();
+            if grid::fading(elapsed, self.start_lights) {
+                return (Some(self.tick), "start lights");
+            }
+            return (Some(grid::until_lights_change(elapsed, self.start_lights)), "start lights");
+        }
+        if self.lights_out_at.is_some_and(|at| (self.clock.wall_now() - at).to_std().is_ok_and(|shown| shown < LIGHTS_OUT_SHOWN)) {
+            return (Some(self.tick), "lights out");
+        }
+        if (self.smooth || self.interpolate) && self.step_fraction().is_some() {
+            return (Some(self.tick), "smooth motion");
+        }
+        if self.race_complete {
+            sooner((self.loop_at - self.clock.wall_now()).to_std().unwrap_or(Duration::ZERO), "loop")
+        } else {
+            sooner(self.next_wake_ms().and_then(|wake_ms| self.clock.until(wake_ms)).unwrap_or(Duration::ZERO), "next row")
+        }
+    }
+
+    /// Something besides playback changes what the window shows: the output
+    /// and reload status, the playlist or a skip notice.
+    fn keeps_current(&self) -> bool {
+        let notice = self.skipped.is_some_and(|(_, until)| self.clock.wall_now() < until);
+        self.output.is_some() || self.mqtt.is_some() || self.server.is_some() || self.watcher.is_some() || !self.reloads.is_empty() || self.playlist.is_some() || notice
+    }
+
+    /// Where the coordinates and the race data are, what was found in them,
+    /// and the button that loads them.
+    fn setup_screen(&mut self, ctx: &egui::Context) {
+        let Some(setup) = &mut self.setup else {
+            return;
+        };
+        let (mut start, mut cancel, mut demo) = (false, false, false);
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Choose the race data");
+            ui.label(format!(
+                "The LED coordinates are a CSV or TSV file with x_led and y_led columns. The data folder holds one {prefix}<driver>{} (or {}) file per driver, or one subfolder of them per race.",
+                loader::DATASET_SUFFIX,
+                loader::TSV_DATASET_SUFFIX,
+                prefix = loader::DATASET_PREFIX
+            ));
+            if cfg!(target_arch = "wasm32") {
+                ui.label("Drop the coordinates file and the dataset files on the page to load them.");
+            }
+            ui.add_space(8.0);
+            let found = |ui: &mut egui::Ui, result: &Result<usize, String>, what: &str| match result {
+                Ok(count) => ui.label(format!("{} {what} found", format_count(*count))),
+                Err(e) => ui.colored_label(egui::Color32::RED, e),
+            };
+            egui::Grid::new("setup_grid").num_columns(3).show(ui, |ui| {
+                ui.label("LED coordinates");
+                ui.label(setup.coordinates_path.display().to_string());
+                if ui.button("Browse…").clicked() {
+                    setup.browse_coordinates();
+                }
+                ui.end_row();
+                ui.label("");
+                found(ui, &setup.leds, "LEDs");
+                ui.end_row();
+
+                ui.label("Data folder");
+                ui.label(setup.data_dir.display().to_string());
+                if ui.button("Browse…").clicked() {
+                    setup.browse_data_dir();
+                }
+                ui.end_row();
+                if !setup.races.is_empty() {
+                    ui.label("Race");
+                    let mut race = setup.race.clone();
+                    egui::ComboBox::from_id_source("setup_race")
+                        .selected_text(race.as_deref().unwrap_or("The folder itself"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut race, None, "The folder itself");
+                            for name in &setup.races {
+                                ui.selectable_value(&mut race, Some(name.clone()), name);
+                            }
+                        });
+                    if race != setup.race {
+                        setup.set_race(race);
+                    }
+                    ui.end_row();
+                }
+                ui.label("");
+                found(ui, &setup.drivers, "drivers");
+                ui.end_row();
+            });
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                start = ui.add_enabled(setup.is_ready(), egui::Button::new("Start simulation")).clicked();
+                // Browsers have no folder to write the demo files to
+                demo = !cfg!(target_arch = "wasm32")
+                    && ui.button("Load demo").on_hover_text("A made-up race of four cars on an oval, to try things out without data").clicked();
+                cancel = setup.cancellable && ui.button("Cancel").clicked();
+            });
+            if !setup.rejected.is_empty() {
+                ui.colored_label(egui::Color32::RED, format!("Left out {}: neither LED coordinates nor a dataset", setup.rejected.join(", ")));
+            }
+            if let Some(e) = &setup.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        });
+        if cancel {
+            self.setup = None;
+        } else if demo {
+            self.load_demo();
+        } else if start {
+            self.start_setup();
+        }
+    }
+}
+
+impl PlotApp {
+    /// Everything the window shows, one frame of it.
+    fn show(&mut self, ctx: &egui::Context) {
+        self.take_dropped_files(ctx);
+        if self.setup.is_some() {
+            self.setup_screen(ctx);
+            return;
+        }
+        if let Some((error, issues)) = &self.startup_failed {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.heading("The race cannot play");
+                ui.colored_label(egui::Color32::RED, error);
+                if ui.button("Close").clicked() {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for issue in issues {
+                        ui.label(issue);
+                    }
+                });
+            });
+            return;
+        }
+        if let Some(startup) = &mut self.startup {
+            if startup.race.poll() {
+                self.finish_loading();
+            } else {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.heading("Loading race data");
+                    egui::Grid::new("loading_grid").show(ui, |ui| {
+                        for (path, progress) in startup.race.paths.iter().zip(&startup.race.progress) {
+                            ui.label(loader::driver_name(path));
+                            match progress {
+                                FileProgress::Loading => ui.spinner(),
+                                FileProgress::Loaded(rows) => ui.label(format!("{rows} rows")),
+                                FileProgress::Failed(e) => ui.colored_label(egui::Color32::RED, e),
+                            };
+                            ui.end_row();
+                        }
+                    });
+                });
+                ctx.request_repaint();
+                return;
+            }
+        }
+
+        self.update_lap_starts();
+        self.finish_snapping();
+        self.finish_reloading();
+        self.finish_reference();
+        for driver in &self.sim.drivers {
+            self.data_issues.extend(driver.records.take_issues()); // Streamed files that failed since the scan
+        }
+        // Between two races of the playlist, the title card of the next
+        let now = Instant::now();
+        if let Some(playlist) = &mut self.playlist {
+            if let Some(loaded) = playlist.poll(now) {
+                self.play_entry(loaded);
+            } else if playlist.is_over(self.race_complete, now) {
+                self.switch_playlist(1);
+            }
+        }
+        if self.playlist.as_ref().is_some_and(Playlist::is_switching) {
+            let mut step = None;
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(ui.available_height() / 3.0);
+                    if let Some(playlist) = &self.playlist {
+                        ui.heading(egui::RichText::new(&playlist.entry().title).size(48.0));
+                    }
+                    ui.add_space(16.0);
+                    ui.spinner();
+                    ui.add_space(16.0);
+                    ui.horizontal(|ui| step = self.playlist_controls(ui));
+                });
+            });
+            if let Some(step) = step {
+                self.switch_playlist(step);
+            }
+            ctx.request_repaint();
+            return;
+        }
+
+        self.reload_changed_files();
+
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("my_layer")));
+
+        let bounds = Bounds::of(&self.sim.coordinates);
+
+        if self.replay.is_some() {
+            self.apply_replay();
+        } else {
+            let leds_start = Instant::now();
+            self.update_playback();
+            self.sync_reference();
+            self.frame.leds = leds_start.elapsed();
+        }
+        self.update_lap_flash();
+        self.update_cues();
+        self.interpolate_cars();
+        self.check_following();
+        self.sim.throttle_car = self.telemetry_driver().filter(|_| self.throttle_brightness);
+        self.record_frame();
+        self.log_events();
+        self.log_activity();
+        // The output threads send at their own rate, this only hands over the latest frame
+        if self.output.is_some() || self.mqtt.is_some() {
+            let frame = output::strip_frame(&self.sim.led_colors(), &self.strip_positions);
+            if let Some(mqtt) = &mut self.mqtt {
+                mqtt.send(frame.clone());
+                mqtt.poll_status();
+            }
+            if let Some(output) = &mut self.output {
+                output.send(frame);
+                output.poll_status();
+            }
+        }
+        if let Some(server) = &mut self.server {
+            server.send(self.sim.sim_elapsed_ms, self.sim.led_colors());
+            server.poll_status();
+        }
+
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                // Add the date field in the center of the menu bar
+                ui.separator(); // Align items to center
+                if let Some(text) = self.clock_text() {
+                    let next = self.clock_mode.next();
+                    let clock = ui.add(egui::Label::new(egui::RichText::new(text).monospace()).sense(egui::Sense::click()));
+                    if clock.on_hover_text(format!("{}, click for {}", self.clock_mode.label(), next.label().to_lowercase())).clicked() {
+                        self.clock_mode = next;
+                    }
+                }
+                if let Some(elapsed_ms) = self.race_elapsed_ms() {
+                    if self.clock_mode != ClockMode::Session {
+                        ui.label(egui::RichText::new(format_elapsed(elapsed_ms)).monospace());
+                    }
+                    let fraction = self.race_fraction();
+                    ui.add(egui::ProgressBar::new(fraction).desired_width(120.0).text(format!("{:.0}%", fraction * 100.0)));
+                }
+                if let Some((skipped_ms, _)) = self.skipped.filter(|&(_, until)| self.clock.wall_now() < until) {
+                    ui.label(format!("⏩ skipped {}", format_skipped(skipped_ms)));
+                }
+                let lap = self.sim.leader_lap();
+                if self.sim.start_finish_led.is_some() && lap > 0 {
+                    ui.separator();
+                    match self.total_laps() {
+                        0 => ui.label(format!("LAP {lap}")),
+                        total => ui.label(format!("LAP {}/{total}", lap.min(total))),
+                    };
+                }
+                if let Some(date) = self.race_date() {
+                    if let Some((flag, event)) = self.events.flag_at(date) {
+                        ui.separator();
+                        let (fill, text) = flag.colors();
+                        let label = match event.message.as_str() {
+                            "" => flag.label().to_string(),
+                            message => format!("{} · {message}", flag.label()),
+                        };
+                        ui.label(egui::RichText::new(format!(" {label} ")).strong().color(text).background_color(fill));
+                    }
+                    // Other kinds are shown as they are, until something newer comes along
+                    if let Some(info) = self.events.latest_at(date).filter(|event| event.flag.is_none()) {
+                        ui.separator();
+                        ui.label(if info.message.is_empty() { info.kind.clone() } else { info.message.clone() });
+                    }
+                }
+                ui.separator(); // Align items to center
+
+                if ui.button("START").clicked() {
+                    self.start();
+                }
+                if ui.button("STOP").clicked() {
+                    self.reset();
+                }
+                if self.race_started && ui.button(if self.paused { "RESUME" } else { "PAUSE" }).clicked() {
+                    self.set_paused(!self.paused);
+                }
+                if ui.toggle_value(&mut self.reverse, "REVERSE").changed() {
+                    self.clock.seek(self.sim.sim_elapsed_ms as f64); // Wait for the step in the new direction
+                }
+                let jump = ui.add(egui::TextEdit::singleline(&mut self.jump_text).hint_text("hh:mm:ss.fff").desired_width(90.0));
+                if ui.button("Go").clicked() || (jump.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) {
+                    let text = self.jump_text.clone();
+                    match self.jump_to_time(&text) {
+                        Ok(message) => {
+                            self.status = Some(message);
+                            self.jump_error = None;
+                        }
+                        Err(e) => self.jump_error = Some(e),
+                    }
+                }
+                if let Some(e) = &self.jump_error {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                if self.can_step() {
+                    let keyboard = !ui.ctx().wants_keyboard_input();
+                    let back = ui.input(|i| i.key_pressed(egui::Key::ArrowLeft)) && keyboard;
+                    let forward = ui.input(|i| i.key_pressed(egui::Key::ArrowRight)) && keyboard;
+                    let back_button = ui.add_enabled(self.sim.current_index > 0, egui::Button::new("◀")).on_hover_text("Step back one row (←)");
+                    if back_button.clicked() || back {
+                        self.step(false);
+                    }
+                    let forward_button = ui.add_enabled(!self.race_complete, egui::Button::new("▶")).on_hover_text("Step forward one row (→)");
+                    if forward_button.clicked() || forward {
+                        self.step(true);
+                    }
+                }
+                if self.race_complete {
+                    ui.label("Race complete");
+                }
+
+                ui.separator();
+                if ui.button("Save").clicked() {
+                    self.status = Some(match self.save_session(&self.session_path) {
+                        Ok(()) => format!("Saved {}", self.session_path.display()),
+                        Err(e) => e.to_string(),
+                    });
+                }
+                let screenshot_key = ui.input(|i| i.key_pressed(egui::Key::S)) && !ui.ctx().wants_keyboard_input();
+                if ui.button("Screenshot").clicked() || screenshot_key {
+                    self.status = Some(match self.screenshot(self.backdrop.fill(ui.visuals().panel_fill)) {
+                        Ok(path) => format!("Saved {}", path.display()),
+                        Err(e) => e.to_string(),
+                    });
+                }
+                if ui.button("Dump state").on_hover_text("Write where playback is and what it lights to a JSON file, for a bug report").clicked() {
+                    self.status = Some(match self.snapshot().write(&self.screenshot_dir) {
+                        Ok(path) => format!("Saved {}", path.display()),
+                        Err(e) => e.to_string(),
+                    });
+                }
+                ui.add_enabled_ui(self.export.is_none(), |ui| {
+                    ui.menu_button("Export", |ui| {
+                        if ui.button("Frame as PNG…").clicked() {
+                            let image = self.render_frame(self.backdrop.fill(ui.visuals().panel_fill));
+                            self.export = Export::png(image, screenshot_name());
+                            ui.close_menu();
+                        }
+                        if ui.button("Resolved CSV per driver…").clicked() {
+                            let leds = self.sim.coordinates.iter().map(|coord| (coord.x_led, coord.y_led)).collect();
+                            self.export = Export::resolved(self.resolved_drivers(), leds);
+                            ui.close_menu();
+                        }
+                        if ui.button("Clip as GIF or APNG…").clicked() {
+                            let end_ms = self.sim.sim_elapsed_ms.max(self.bookmarks.last().map_or(0, |bookmark| bookmark.sim_ms));
+                            self.clip_dialog = Some(ClipDialog {
+                                start_ms: self.bookmarks.first().map_or(0, |bookmark| bookmark.sim_ms).min(end_ms),
+                                end_ms,
+                                size: [self.view_size.x.round().max(1.0) as u32, self.view_size.y.round().max(1.0) as u32],
+                                fps: 10,
+                                format: ClipFormat::Gif,
+                            });
+                            ui.close_menu();
+                        }
+                    });
+                });
+                if let Some(result) = self.export.as_ref().and_then(Export::poll) {
+                    self.export = None;
+                    self.status = Some(result.unwrap_or_else(|e| e));
+                }
+                if let Some(export) = &self.export {
+                    if export.total > 0 {
+                        let done = export.done.load(std::sync::atomic::Ordering::Relaxed);
+                        let progress = done as f32 / export.total as f32;
+                        ui.add(egui::ProgressBar::new(progress).desired_width(120.0).text(format!("{done}/{}", export.total)));
+                        if ui.button("Cancel").clicked() {
+                            export.cancel();
+                        }
+                    } else {
+                        ui.spinner();
+                    }
+                }
+                if ui.button("Load").clicked() {
+                    let path = self.session_path.clone();
+                    self.status = match self.load_session(&path) {
+                        Ok(()) => Some(format!("Loaded {}", path.display())),
+                        Err(e) => Some(e.to_string()),
+                    };
+                }
+                if let Some(status) = &self.status {
+                    ui.label(status);
+                }
+                if let Some(output) = &self.output {
+                    let (color, text) = match &output.status {
+                        OutputStatus::Waiting => (egui::Color32::GRAY, "waiting".to_string()),
+                        OutputStatus::Sending => (egui::Color32::GREEN, format!("sending to {}", output.config.target)),
+                        OutputStatus::Error(e) => (egui::Color32::RED, e.clone()),
+                    };
+                    ui.colored_label(color, "● Output").on_hover_text(text);
+                }
+                if let Some(mqtt) = &self.mqtt {
+                    let (color, text) = match &mqtt.status {
+                        MqttStatus::Connecting => (egui::Color32::GRAY, format!("connecting to {}", mqtt.config.broker)),
+                        MqttStatus::Connected => (egui::Color32::GREEN, format!("publishing to {}", mqtt.config.broker)),
+                        MqttStatus::DryRun => (egui::Color32::YELLOW, "dry run, printing messages".to_string()),
+                        MqttStatus::Error(e) => (egui::Color32::RED, e.clone()),
+                    };
+                    ui.colored_label(color, "● MQTT").on_hover_text(text);
+                }
+                if let Some(server) = &self.server {
+                    let (color, text) = match &server.status {
+                        ServerStatus::Serving(0) => (egui::Color32::GRAY, format!("waiting for clients on {}", server.address)),
+                        ServerStatus::Serving(1) => (egui::Color32::GREEN, format!("serving a client on {}", server.address)),
+                        ServerStatus::Serving(clients) => (egui::Color32::GREEN, format!("serving {clients} clients on {}", server.address)),
+                        ServerStatus::Error(e) => (egui::Color32::RED, e.clone()),
+                    };
+                    ui.colored_label(color, "● WebSocket").on_hover_text(text);
+                }
+                ui.menu_button("Theme", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Window");
+                        for theme in Theme::ALL {
+                            ui.radio_value(&mut self.theme, theme, theme.label());
+                        }
+                    });
+                    ui.label("Background").on_hover_text("Drawn behind the track whatever the theme, the unlit LEDs shaded to show on it");
+                    for backdrop in Backdrop::ALL {
+                        ui.radio_value(&mut self.backdrop, backdrop, backdrop.label());
+                    }
+                });
+                ui.menu_button("Settings", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Speed");
+                        ui.add(egui::DragValue::new(&mut self.speed).speed(0.1).clamp_range(0.1..=100.0).suffix("x"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Trail length");
+                        ui.add(egui::Slider::new(&mut self.sim.trail_length, 0..=MAX_TRAIL_LENGTH).suffix(" LEDs"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Trail").on_hover_text("Speed gradient shifts the trail towards orange-red where the car slowed");
+                        for mode in TrailMode::ALL {
+                            ui.radio_value(&mut self.sim.trail_mode, mode, mode.label());
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Stopped after").on_hover_text("Rows on one LED before a car is dimmed as stopped, 0 for never");
+                        ui.add(egui::DragValue::new(&mut self.sim.stopped_after).clamp_range(0..=1000).suffix(" rows"));
+                    });
+                    ui.horizontal(|ui| {
+                        let mut distance = self.snap_dragged.unwrap_or(self.snap_target());
+                        let max = MAX_SNAP_SPACINGS * self.led_spacing.average.max(f64::EPSILON);
+                        let (mut limited, mut value) = (distance.is_some(), distance.unwrap_or(max / 10.0));
+                        ui.checkbox(&mut limited, "Snap within").on_hover_text("Positions further than this from every LED are off the map");
+                        let slider = ui.add_enabled(limited, egui::Slider::new(&mut value, 0.0..=max).logarithmic(true).max_decimals(2));
+                        distance = limited.then_some(value);
+                        if slider.dragged() {
+                            self.snap_dragged = Some(distance);
+                        } else if distance != self.snap_target() {
+                            self.snap_dragged = None;
+                            self.set_snap_distance(distance);
+                        } else {
+                            self.snap_dragged = None;
+                        }
+                    });
+                    if self.snapping.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.weak("Matching the race to the LEDs again");
+                        });
+                    } else {
+                        ui.weak(format!("{} of {} cars off the map now", self.sim.off_map_cars(), self.sim.cars.len()));
+                    }
+                    ui.menu_button("Cues", |ui| {
+                        for cue in Cue::ALL {
+                            let mut on = self.cues.contains(&cue);
+                            if ui.checkbox(&mut on, cue.label()).changed() {
+                                if on {
+                                    self.cues.insert(cue);
+                                } else {
+                                    self.cues.remove(&cue);
+                                }
+                            }
+                        }
+                        ui.checkbox(&mut self.cue_sound, if cfg!(feature = "sound") { "Play a tone too" } else { "Ring the bell too" });
+                    })
+                    .response
+                    .on_hover_text("Pulse the whole track white at these moments");
+                    ui.checkbox(&mut self.show_labels, "Driver labels");
+                    ui.checkbox(&mut self.show_led_codes, "Driver codes on LEDs");
+                    ui.checkbox(&mut self.stack_offset, "Fan out stacked cars")
+                        .on_hover_text("Cars sharing an LED are drawn as dots around it instead of one mixed color");
+                    if let Some(reference) = &self.reference {
+                        ui.checkbox(&mut self.show_reference, format!("Reference race ({})", reference.name))
+                            .on_hover_text("Played faintly behind this race, lined up by time since each race's first row");
+                    }
+                    ui.checkbox(&mut self.keep_aspect, "Keep track aspect ratio");
+                    ui.checkbox(&mut self.show_outline, "Track outline");
+                    ui.checkbox(&mut self.smooth, "Smooth motion between rows");
+                    ui.checkbox(&mut self.interpolate, "Light LEDs between rows");
+                    // The lookup table is only rebuilt when a slider moves
+                    let (mut brightness, mut gamma) = (self.sim.correction.brightness() * 100.0, self.sim.correction.gamma());
+                    let brightness_changed = ui.add(egui::Slider::new(&mut brightness, 0.0..=100.0).suffix("%").text("Brightness")).changed();
+                    let gamma_changed = ui.add(egui::Slider::new(&mut gamma, correction::GAMMA_RANGE).text("Gamma")).changed();
+                    if brightness_changed || gamma_changed {
+                        self.sim.correction = ColorCorrection::new(brightness / 100.0, gamma);
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("LEDs");
+                        for shape in LedShape::ALL {
+                            ui.radio_value(&mut self.led_shape, shape, shape.label());
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Size");
+                        match &mut self.led_size {
+                            LedSize::Points(points) => ui.add(egui::DragValue::new(points).clamp_range(render::LED_POINTS_RANGE).suffix(" pt")),
+                            LedSize::Spacing(fraction) => ui.add(egui::Slider::new(fraction, render::LED_SPACING_RANGE).text("of the spacing")),
+                        };
+                        let mut auto = matches!(self.led_size, LedSize::Spacing(_));
+                        let hint = "Size the LEDs by how far apart they are, never so large that neighbours overlap";
+                        if ui.checkbox(&mut auto, "Auto").on_hover_text(hint).changed() {
+                            self.led_size = if auto { LedSize::Spacing(DEFAULT_LED_SPACING) } else { LedSize::default() };
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut edged = self.unlit_outline.is_some();
+                        if ui.checkbox(&mut edged, "Edge around unlit LEDs").changed() {
+                            self.unlit_outline = edged.then_some(DEFAULT_UNLIT_OUTLINE);
+                        }
+                        if let Some(color) = &mut self.unlit_outline {
+                            ui.color_edit_button_srgba(color);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.looping, "Loop, pausing");
+                        let mut pause = self.loop_pause.as_secs_f64();
+                        if ui.add(egui::DragValue::new(&mut pause).speed(0.5).clamp_range(0.0..=600.0).suffix(" s")).changed() {
+                            self.loop_pause = Duration::from_secs_f64(pause);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.skip_gaps, "Skip gaps longer than");
+                        let mut gap = self.max_gap.as_secs_f64();
+                        if ui.add(egui::DragValue::new(&mut gap).speed(0.1).clamp_range(0.1..=600.0).suffix(" s")).changed() {
+                            self.max_gap = Duration::from_secs_f64(gap);
+                        }
+                    });
+                    ui.separator();
+                    ui.label("Palette");
+                    for palette in Palette::ALL {
+                        if ui.radio(self.palette == palette, palette.label()).clicked() {
+                            self.set_palette(palette);
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.show_frame_stats, "Frame stats (F12)");
+                    if ui.button("Choose data files…").clicked() {
+                        self.open_setup();
+                        ui.close_menu();
+                    }
+                    if ui.button("Reset to defaults").clicked() {
+                        self.apply_settings(self.defaults.clone());
+                        ui.close_menu();
+                    }
+                });
+
+                if self.playlist.is_some() {
+                    ui.separator();
+                    if let Some(step) = self.playlist_controls(ui) {
+                        self.switch_playlist(step);
+                    }
+                }
+
+                if !self.races.is_empty() {
+                    ui.separator();
+                    let mut selected = self.selected_race.clone();
+                    egui::ComboBox::from_label("Race")
+                        .selected_text(selected.as_deref().unwrap_or("-"))
+                        .show_ui(ui, |ui| {
+                            for race in &self.races {
+                                ui.selectable_value(&mut selected, Some(race.clone()), race);
+                            }
+                        });
+                    if selected != self.selected_race {
+                        if let Some(race) = selected {
+                            self.load_race(Some(&race));
+                        }
+                    }
+                }
+
+                // The camera on one driver as they go round, or free again for "None"
+                if !self.sim.drivers.is_empty() {
+                    ui.separator();
+                    let mut following = self.following;
+                    egui::ComboBox::from_label("Follow")
+                        .selected_text(following.map_or("None", |dataset_idx| self.sim.drivers[dataset_idx].name.as_str()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut following, None, "None");
+                            for (dataset_idx, Driver { name, .. }) in self.sim.drivers.iter().enumerate() {
+                                ui.selectable_value(&mut following, Some(dataset_idx), name);
+                            }
+                        });
+                    if following != self.following {
+                        self.follow(following);
+                    }
+                    ui.checkbox(&mut self.follow_zoom, "Zoom").on_hover_text("Zoom in on the driver when following from the whole track");
+                }
+
+                // Two drivers side by side on the same clock, back to every driver when turned off
+                if self.sim.drivers.len() >= 2 {
+                    ui.separator();
+                    let mut comparing = self.compare.is_some();
+                    if ui.checkbox(&mut comparing, "Compare").changed() {
+                        self.compare = comparing.then_some(Compare { drivers: [0, 1], ghost: true });
+                    }
+                    if let Some(compare) = &mut self.compare {
+                        for (side, driver) in ["Left", "Right"].into_iter().zip(&mut compare.drivers) {
+                            egui::ComboBox::from_id_source(side)
+                                .selected_text(&self.sim.drivers[*driver].name)
+                                .show_ui(ui, |ui| {
+                                    for (dataset_idx, Driver { name, .. }) in self.sim.drivers.iter().enumerate() {
+                                        ui.selectable_value(driver, dataset_idx, name);
+                                    }
+                                });
+                        }
+                        ui.checkbox(&mut compare.ghost, "Ghost")
+                            .on_hover_text("Show the other driver faintly in each view");
+                    }
+                }
+            });
+        });
+
+        self.clip_window(ctx);
+        self.led_window(ctx);
+        self.results_window(ctx);
+
+        egui::SidePanel::left("drivers_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Show all").clicked() {
+                    self.sim.drivers.iter_mut().for_each(|driver| driver.visible = true);
+                }
+                if ui.button("Hide all").clicked() {
+                    self.sim.drivers.iter_mut().for_each(|driver| driver.visible = false);
+                }
+                if !self.color_overrides.is_empty() && ui.button("Reset colors").on_hover_text("Back to the palette colors").clicked() {
+                    self.reset_driver_colors();
+                }
+            });
+            ui.separator();
+            for (team, members) in self.legend_groups() {
+                if let Some(team) = team {
+                    ui.label(egui::RichText::new(team).strong());
+                }
+                for dataset_idx in members {
+                    let speed = self.car_speed(dataset_idx);
+                    let info = self.driver_table.get(&self.sim.drivers[dataset_idx].key).cloned();
+                    ui.horizontal(|ui| {
+                        let (swatch, swatch_response) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::click());
+                        let stopped = self.sim.is_stopped(dataset_idx);
+                        ui.painter().rect_filled(swatch, egui::Rounding::same(2.0), self.sim.marker_color(dataset_idx));
+                        let following = self.following == Some(dataset_idx);
+                        if following {
+                            ui.painter().rect_stroke(swatch.expand(2.0), egui::Rounding::same(3.0), egui::Stroke::new(1.5, egui::Color32::WHITE));
+                        }
+                        let follow_hint = if following { "Stop following" } else { "Follow with the camera" };
+                        if swatch_response.on_hover_text(follow_hint).clicked() {
+                            self.follow((!following).then_some(dataset_idx));
+                        }
+                        if let Some(secondary) = info.as_ref().and_then(|info| info.secondary) {
+                            ui.painter().rect_filled(swatch.shrink(3.0), egui::Rounding::same(1.0), secondary);
+                        }
+                        let label = match info.and_then(|info| info.number) {
+                            Some(number) => format!("{number} {}", self.sim.drivers[dataset_idx].name),
+                            None => self.sim.drivers[dataset_idx].name.clone(),
+                        };
+                        ui.checkbox(&mut self.sim.drivers[dataset_idx].visible, label);
+                        let mut color = self.sim.drivers[dataset_idx].color;
+                        if ui.color_edit_button_srgba(&mut color).changed() {
+                            let name = self.sim.drivers[dataset_idx].name.clone();
+                            self.set_driver_color(&name, color);
+                        }
+                        let spotlit = self.sim.spotlight == Some(dataset_idx);
+                        if ui.selectable_label(spotlit, "☀").on_hover_text("Dim the other cars").clicked() {
+                            self.sim.spotlight = (!spotlit).then_some(dataset_idx);
+                        }
+                        if self.sim.cars[dataset_idx].off_map {
+                            ui.weak("off map").on_hover_text("Too far from every LED to be shown");
+                        } else if stopped {
+                            ui.weak("stopped").on_hover_text(format!("On one LED for {} rows or more, retired or crashed", self.sim.stopped_after));
+                        }
+                        if self.sim.start_finish_led.is_some() {
+                            ui.label(format!("Lap {}", self.sim.cars[dataset_idx].laps.laps));
+                        }
+                        if let Some(speed) = speed {
+                            let (speed, unit) = units::speed(speed, self.metres_per_unit);
+                            ui.label(format!("{speed:.0} {unit}"));
+                        }
+                    });
+                }
+            }
+            ui.separator();
+            ui.collapsing(format!("Bookmarks ({})", self.bookmarks.len()), |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.bookmark_label).hint_text("label").desired_width(100.0));
+                    if ui.button("Add").clicked() {
+                        self.add_bookmark();
+                    }
+                    if !self.lap_starts.is_empty() && ui.button("Add laps").clicked() {
+                        self.add_lap_bookmarks();
+                    }
+                });
+                let (mut seek_to, mut remove) = (None, None);
+                for (i, bookmark) in self.bookmarks.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.link(format!("{} {}", format_sim_ms(bookmark.sim_ms), bookmark.label)).clicked() {
+                            seek_to = Some(bookmark.sim_ms);
+                        }
+                        if ui.small_button("x").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(sim_ms) = seek_to {
+                    self.seek(self.sim.index_at_sim_ms(sim_ms));
+                }
+                if let Some(i) = remove {
+                    self.bookmarks.remove(i);
+                }
+                ui.horizontal(|ui| {
+                    let path = self.bookmarks_path();
+                    if ui.button("Save").clicked() {
+                        self.status = Some(match bookmarks::write(&path, &self.bookmarks) {
+                            Ok(()) => format!("Saved {}", path.display()),
+                            Err(e) => e.to_string(),
+                        });
+                    }
+                    if ui.button("Load").clicked() {
+                        self.status = Some(match bookmarks::read(&path) {
+                            Ok(loaded) => {
+                                self.bookmarks = loaded;
+                                format!("Loaded {}", path.display())
+                            }
+                            Err(e) => e.to_string(),
+                        });
+                    }
+                });
+            });
+            if !self.sim.drivers.is_empty() && self.replay.is_none() {
+                ui.separator();
+                ui.collapsing("Alignment", |ui| {
+                    ui.label(format!("Session start {}", self.sim.session_start().format("%H:%M:%S%.3f")));
+                    let mut changed = None;
+                    let any_offset = (0..self.sim.drivers.len()).any(|dataset_idx| self.sim.manual_offset_ms(dataset_idx) != 0);
+                    if ui.add_enabled(any_offset, egui::Button::new("Reset all offsets")).clicked() {
+                        changed = Some(None);
+                    }
+                    egui::Grid::new("alignment_grid").show(ui, |ui| {
+                        for (dataset_idx, Driver { name, .. }) in self.sim.drivers.iter().enumerate() {
+                            ui.label(name);
+                            ui.label(format!("{:+.3} s", self.sim.offset_ms(dataset_idx) as f64 / 1000.0));
+                            // Dragged while playing, so the driver can be lined up by eye
+                            let mut manual_ms = self.sim.manual_offset_ms(dataset_idx);
+                            let drag = egui::DragValue::new(&mut manual_ms).speed(10.0).suffix(" ms");
+                            if ui.add(drag).on_hover_text("Moves this driver's rows later, or earlier when negative").changed() {
+                                changed = Some(Some((dataset_idx, manual_ms)));
+                            }
+                            if ui.add_enabled(manual_ms != 0, egui::Button::new("Reset").small()).clicked() {
+                                changed = Some(Some((dataset_idx, 0)));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    match changed {
+                        Some(Some((dataset_idx, offset_ms))) => self.set_driver_offset(dataset_idx, offset_ms),
+                        Some(None) => {
+                            self.driver_offsets.clear();
+                            self.realign();
+                        }
+                        None => {}
+                    }
+                });
+            }
+            // What the step buttons just showed, for working out why a car jumped
+            if self.can_step() && self.replay.is_none() {
+                ui.separator();
+                ui.collapsing("Last step", |ui| {
+                    ui.label(format!("Row {} of {} at {}", self.sim.current_index, self.sim.moments(), format_sim_ms(self.sim.sim_elapsed_ms)));
+                    self.update_stepped();
+                    if self.stepped_reads.is_some() {
+                        ui.ctx().request_repaint_after(PROGRESS_REPAINT);
+                    }
+                    let rows = self.stepped.as_ref().map_or(&[][..], |(_, rows)| rows.as_slice());
+                    if rows.is_empty() {
+                        ui.weak("Nothing shown yet");
+                    }
+                    egui::Grid::new("step_grid").striped(true).show(ui, |ui| {
+                        for row in rows {
+                            ui.label(&self.sim.drivers[row.dataset_idx].name);
+                            ui.label(if row.line > 0 { format!("line {}", row.line) } else { String::new() });
+                            ui.label(row.position.map_or(String::new(), |(x, y)| format!("({x}, {y})")));
+                            ui.label(row.led.map_or("off map".to_string(), |led| format!("LED {led}")));
+                            ui.label(row.date.map_or(String::new(), |date| date.format("%H:%M:%S%.3f").to_string()));
+                            ui.end_row();
+                        }
+                    });
+                });
+            }
+            let issue_count = self.coordinate_issues.len() + self.data_issues.len();
+            if issue_count > 0 {
+                ui.separator();
+                ui.collapsing(format!("Data problems ({issue_count})"), |ui| {
+                    let outlying = self.coordinate_issues.iter().filter(|issue| issue.message.ends_with(data::OUTLIER)).count();
+                    if outlying > 0 && ui.button(format!("Exclude {outlying} outlying LEDs")).on_hover_text("Leave them out of the track").clicked() {
+                        self.exclude_outliers();
+                    }
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for issue in self.coordinate_issues.iter().chain(&self.data_issues) {
+                            ui.label(issue.to_string());
+                        }
+                    });
+                });
+            }
+        });
+
+        egui::SidePanel::right("leaderboard_panel").show(ctx, |ui| {
+            ui.heading("Leaderboard");
+            let cars: Vec<(&CarProgress, u32, u64)> = self
+                .sim
+                .cars
+                .iter()
+                .map(|car| (&car.progress, car.laps.laps, car.ended_at_ms.unwrap_or(self.sim.sim_elapsed_ms)))
+                .collect();
+            egui::Grid::new("leaderboard_grid").striped(true).show(ui, |ui| {
+                for (position, gap) in progress::gaps_to_leader(&cars).iter().enumerate() {
+                    let dataset_idx = gap.dataset_idx;
+                    ui.label(format!("P{}", position + 1));
+                    let (swatch, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+                    ui.painter().rect_filled(swatch, egui::Rounding::same(2.0), self.sim.drivers[dataset_idx].color);
+                    let selected = self.highlighted == Some(dataset_idx);
+                    if ui.selectable_label(selected, &self.sim.drivers[dataset_idx].name).clicked() {
+                        self.highlighted = if selected { None } else { Some(dataset_idx) };
+                    }
+                    if !self.sectors.is_empty() {
+                        let led = self.sim.cars[dataset_idx].trail.front().copied();
+                        ui.label(led.and_then(|led| self.sectors.sector(led)).map_or(String::new(), |sector| format!("S{sector}")));
+                    }
+                    if position == 0 {
+                        ui.label("Leader");
+                    } else {
+                        match gap.seconds {
+                            Some(seconds) => ui.label(format!("+{seconds:.3}s")),
+                            None => {
+                                let (distance, unit) = units::distance(gap.distance, self.metres_per_unit);
+                                ui.label(format!("+{distance:.0} {unit}"))
+                            }
+                        };
+                    }
+                    // Data that ran out before the others is a retirement, at the end it is a finish
+                    if self.sim.cars[dataset_idx].ended_at_ms.is_some() {
+                        ui.label(if self.race_complete { "FIN" } else { "OUT" });
+                    } else if self.sim.in_pit_lane(dataset_idx) {
+                        ui.label("PIT");
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        egui::TopBottomPanel::bottom("activity_panel").show(ctx, |ui| {
+            egui::CollapsingHeader::new(format!("Activity ({})", self.activity.entries().len())).id_source("activity").show(ui, |ui| {
+                let filter = self.activity_filter;
+                let shown = |entry: &Entry| filter.is_none_or(|dataset_idx| !matches!(entry.source, Source::Car(car) if car != dataset_idx));
+                ui.horizontal(|ui| {
+                    let selected = filter.map_or("All drivers", |dataset_idx| &self.sim.drivers[dataset_idx].name);
+                    egui::ComboBox::from_id_source("activity_filter").selected_text(selected).show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.activity_filter, None, "All drivers");
+                        for (dataset_idx, driver) in self.sim.drivers.iter().enumerate() {
+                            ui.selectable_value(&mut self.activity_filter, Some(dataset_idx), &driver.name);
+                        }
+                    });
+                    ui.checkbox(&mut self.activity_scroll, "Follow").on_hover_text("Keep scrolling to the latest entry");
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|output| output.copied_text = self.activity.text(shown));
+                    }
+                    if ui.add_enabled(self.export.is_none(), egui::Button::new("Save…")).clicked() {
+                        self.export = Export::text(self.activity.text(shown), "activity.log".to_string());
+                    }
+                });
+                let entries: Vec<&Entry> = self.activity.entries().iter().filter(|entry| shown(entry)).collect();
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::vertical()
+                    .max_height(160.0)
+                    .auto_shrink([false, true])
+                    .stick_to_bottom(self.activity_scroll)
+                    .show_rows(ui, row_height, entries.len(), |ui, rows| {
+                        for entry in &entries[rows] {
+                            ui.monospace(entry.to_string());
+                        }
+                    });
+            });
+        });
+
+        // Only kept up to date while it is open, as it does some work for every row
+        egui::TopBottomPanel::bottom("gap_chart_panel").show(ctx, |ui| {
+            let open = egui::CollapsingHeader::new("Gap to leader").id_source("gap_chart").show(ui, |ui| {
+                let Some(gaps) = self.sim.gap_history() else {
+                    return;
+                };
+                egui_plot::Plot::new("gap_chart")
+                    .height(180.0)
+                    .legend(egui_plot::Legend::default())
+                    .include_y(0.0)
+                    .x_axis_label("Race time (s)")
+                    .y_axis_label("Gap (s)")
+                    .label_formatter(|name, value| match name {
+                        "" => String::new(),
+                        name => format!("{name}\n{} +{:.3}s", format_sim_ms((value.x * 1000.0).max(0.0) as u64), value.y),
+                    })
+                    .show(ui, |plot_ui| {
+                        for dataset_idx in (0..self.sim.drivers.len()).filter(|&dataset_idx| self.sim.drivers[dataset_idx].visible) {
+                            let line = egui_plot::Line::new(egui_plot::PlotPoints::new(gaps.series(dataset_idx).to_vec()))
+                                .color(self.sim.drivers[dataset_idx].color)
+                                .name(&self.sim.drivers[dataset_idx].name);
+                            plot_ui.line(line);
+                        }
+                    });
+            });
+            self.sim.track_gaps(open.body_returned.is_some());
+        });
+
+        let backdrop = self.backdrop.fill(ctx.style().visuals.panel_fill);
+        egui::CentralPanel::default().frame(egui::Frame::central_panel(&ctx.style()).fill(backdrop)).show(ctx, |ui| {
+            let rect = ui.max_rect();
+            let painter = painter.with_clip_rect(rect);
+
+            self.view_size = rect.size();
+            let fitted = Projection::new(&bounds, rect, self.keep_aspect);
+
+            // Each driver in their own half, always shown, over a ghost of the other
+            if let Some(Compare { drivers, ghost }) = self.compare {
+                let (left, right) = rect.split_left_right_at_fraction(0.5);
+                for (pane, (driver, other)) in [left, right].into_iter().zip([(drivers[0], drivers[1]), (drivers[1], drivers[0])]) {
+                    let painter = painter.with_clip_rect(pane.shrink(2.0));
+                    let projection = Projection::new(&bounds, pane.shrink(8.0), self.keep_aspect);
+                    let paint_start = Instant::now();
+                    self.paint_track(&painter, &projection, pane, &|dataset_idx| dataset_idx == driver, ghost.then_some(other));
+                    self.frame.paint += paint_start.elapsed();
+                    let corner = pane.left_top() + egui::vec2(8.0, 8.0);
+                    let font = egui::FontId::proportional(16.0);
+                    painter.text(corner, egui::Align2::LEFT_TOP, &self.sim.drivers[driver].name, font, self.sim.drivers[driver].color);
+                }
+                let divider = egui::Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color);
+                painter.vline(left.right(), rect.y_range(), divider);
+                return;
+            }
+
+            // Scroll to zoom and drag to look around; following a driver moves the camera for you
+            let response = ui.interact(rect, ui.id().with("track"), egui::Sense::click_and_drag());
+            if response.hovered() {
+                let scroll = ui.input(|i| i.scroll_delta.y);
+                self.zoom = (self.zoom * (scroll / 200.0).exp()).clamp(1.0, MAX_ZOOM);
+            }
+            if response.dragged() && self.following.is_none() {
+                self.focus -= response.drag_delta() / (rect.size() * self.zoom);
+            }
+            let followed_led = self.following.and_then(|idx| self.sim.cars[idx].trail.front().copied());
+            if let Some(led) = followed_led {
+                let led_size = self.led_size.resolve(self.led_shape, self.led_spacing, &fitted);
+                let target = fitted.fraction(fitted.to_screen(&self.sim.coordinates[led]) + led_size / 2.0);
+                if self.move_camera(target, ui.input(|i| i.stable_dt)) {
+                    ctx.request_repaint();
+                }
+            }
+            let projection = fitted.zoomed(self.zoom, self.focus);
+            let led_size = self.led_size.resolve(self.led_shape, self.led_spacing, &projection);
+            let positions: Vec<egui::Pos2> = self.sim.coordinates.iter().map(|coord| projection.to_screen(coord)).collect();
+
+            let paint_start = Instant::now();
+            self.paint_track(&painter, &projection, rect, &|dataset_idx| self.sim.drivers[dataset_idx].visible, None);
+            self.frame.paint += paint_start.elapsed();
+            self.paint_start_lights(&painter, rect);
+
+            // Wider ring in the driver's color around the followed car, and who it is in the corner
+            if let (Some(dataset_idx), Some(led)) = (self.following, followed_led) {
+                let center = egui::Rect::from_min_size(positions[led], led_size).center();
+                let color = self.sim.drivers[dataset_idx].color;
+                painter.circle_stroke(center, led_size.x * 1.4, egui::Stroke::new(3.0, color));
+                let corner = rect.left_top() + egui::vec2(8.0, 8.0);
+                let font = egui::FontId::proportional(16.0);
+                painter.text(corner, egui::Align2::LEFT_TOP, format!("Following {}", self.sim.drivers[dataset_idx].name), font, color);
+            }
+
+            if self.zoom > 1.0 {
+                self.minimap(ctx, &bounds, rect, projection.visible_fraction(rect));
+            }
+
+            // Clicking an LED lists the cars that went through it and follows
+            // a car on it, clicking elsewhere closes the list and stops following
+            if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+                let led = projection.led_at(&self.sim.coordinates, led_size, pos);
+                self.led_passes = led.map(|led| LedPasses { led, arrivals: self.sim.arrivals(led) });
+                let car = led.and_then(|led| {
+                    (0..self.sim.cars.len()).find(|&idx| self.sim.drivers[idx].visible && self.sim.cars[idx].trail.front() == Some(&led))
+                });
+                if car.is_some() || self.following.is_some() {
+                    self.follow(car);
+                }
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) && !ui.ctx().wants_keyboard_input() {
+                self.follow(None);
+            }
+        });
+
+        self.telemetry_panel(ctx);
+
+        // Input repaints on its own, otherwise only wake up when there is something new to show, if ever
+        let (delay, reason) = self.wake();
+        self.wake_reason = reason;
+        if let Some(delay) = delay {
+            ctx.request_repaint_after(delay);
+        }
+    }
+
+    /// The whole track in the bottom right corner of the track view, `view`,
+    /// with the visible cars as dots and the part of the track shown, given
+    /// as by `Projection::visible_fraction`, outlined. Clicking or dragging
+    /// on it moves the view there, and stops following a driver.
+    fn minimap(&mut self, ctx: &egui::Context, bounds: &Bounds, view: egui::Rect, visible: egui::Rect) {
+        egui::Area::new("minimap")
+            .order(egui::Order::Foreground)
+            .pivot(egui::Align2::RIGHT_BOTTOM)
+            .fixed_pos(view.right_bottom() + egui::vec2(-8.0, -8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let (rect, response) = ui.allocate_exact_size(MINIMAP_SIZE, egui::Sense::click_and_drag());
+                    let minimap = Projection::new(bounds, rect.shrink(4.0), true);
+                    let painter = ui.painter_at(rect);
+                    let unlit = ui.visuals().weak_text_color();
+                    for coord in &self.sim.coordinates {
+                        painter.circle_filled(minimap.to_screen(coord), 1.0, unlit);
+                    }
+                    for (dataset_idx, car) in self.sim.cars.iter().enumerate().filter(|&(dataset_idx, _)| self.sim.drivers[dataset_idx].visible) {
+                        if let Some(&led) = car.trail.front() {
+                            painter.circle_filled(minimap.to_screen(&self.sim.coordinates[led]), 3.0, self.sim.drivers[dataset_idx].color);
+                        }
+                    }
+                    let shown = egui::Rect::from_min_max(minimap.at_fraction(visible.min.to_vec2()), minimap.at_fraction(visible.max.to_vec2()));
+                    painter.rect_stroke(shown, 0.0, egui::Stroke::new(1.5, ui.visuals().strong_text_color()));
+                    if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked() || response.dragged()) {
+                        self.following = None;
+                        self.focus = minimap.fraction(pos).clamp(egui::Vec2::ZERO, egui::Vec2::splat(1.0));
+                    }
+                });
+            });
+    }
+
+    /// The driver whose telemetry is shown: the one followed, else the one
+    /// in the spotlight, if their data has telemetry.
+    fn telemetry_driver(&self) -> Option<usize> {
+        let dataset_idx = self.following.or(self.sim.spotlight)?;
+        self.sim.drivers.get(dataset_idx)?.records.has_telemetry().then_some(dataset_idx)
+    }
+
+    /// Speed, gear and throttle of the telemetry driver at the row shown,
+    /// in the bottom left corner of the track view, with whether their LEDs
+    /// follow the throttle.
+    fn telemetry_panel(&mut self, ctx: &egui::Context) {
+        let Some(dataset_idx) = self.telemetry_driver() else {
+            return;
+        };
+        let telemetry = self.sim.telemetry(dataset_idx).unwrap_or_default();
+        let value = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        let text = format!(
+            "{}\n{} km/h\ngear {}\nthrottle {} %",
+            self.sim.drivers[dataset_idx].name,
+            value(telemetry.speed.map(|speed| format!("{speed:.0}"))),
+            value(telemetry.gear.map(|gear| if gear == 0 { "N".to_string() } else { gear.to_string() })),
+            value(telemetry.throttle.map(|throttle| format!("{throttle:.0}"))),
+        );
+        egui::Area::new("telemetry")
+            .order(egui::Order::Foreground)
+            .pivot(egui::Align2::LEFT_BOTTOM)
+            .fixed_pos(ctx.available_rect().left_bottom() + egui::vec2(8.0, -8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(egui::RichText::new(text).monospace().color(self.sim.drivers[dataset_idx].color));
+                    ui.checkbox(&mut self.throttle_brightness, "LEDs follow throttle");
+                });
+            });
+    }
+
+    /// The frame rate, time spent in `update()` and on its LED and paint
+    /// passes, rows played in per frame and why the last frame was drawn, in
+    /// the top right corner of the track view. It takes no input, so clicks
+    /// go through to the LEDs.
+    fn frame_stats_overlay(&self, ctx: &egui::Context) {
+        let stats = &self.frame_stats;
+        let format = |value: Option<f64>, digits: usize| value.map_or_else(|| "-".to_string(), |value| format!("{value:.digits$}"));
+        let text = format!(
+            "{} FPS\nupdate {} ms\n  LEDs {} ms\n  paint {} ms\nrows {}/frame\noff map {} cars\nwoken by {}",
+            format(stats.fps(), 0),
+            format(stats.update_ms(), 2),
+            format(stats.leds_ms(), 2),
+            format(stats.paint_ms(), 2),
+            format(stats.rows(), 1),
+            self.sim.off_map_cars(),
+            stats.reason().unwrap_or("-"),
+        );
+        egui::Area::new("frame_stats")
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .pivot(egui::Align2::RIGHT_TOP)
+            .fixed_pos(ctx.available_rect().right_top() + egui::vec2(-8.0, 8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(egui::RichText::new(text).monospace());
+                });
+            });
+    }
+
+    /// Puts the window where it was asked for once it is open, goes in and
+    /// out of fullscreen on F11 and Escape, and hides a pointer that stays still.
+    fn update_window(&mut self, ctx: &egui::Context) {
+        if !self.window_placed {
+            if let Some(commands) = self.window.startup_commands(ctx.input(|i| i.viewport().monitor_size)) {
+                commands.into_iter().for_each(|command| ctx.send_viewport_cmd(command));
+                self.window_placed = true;
+            }
+        }
+        let (fullscreen, f11, escape) =
+            ctx.input(|i| (i.viewport().fullscreen.unwrap_or(false), i.key_pressed(egui::Key::F11), i.key_pressed(egui::Key::Escape)));
+        if let Some(fullscreen) = self.window.toggle_fullscreen(fullscreen, f11, escape && !ctx.wants_keyboard_input()) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(fullscreen));
+        }
+        if let Some(cursor) = &mut self.cursor {
+            let now = Instant::now();
+            let moved = ctx.input(|i| i.events.iter().any(|event| matches!(event, egui::Event::PointerMoved(_) | egui::Event::PointerButton { .. })));
+            if let Some(visible) = cursor.update(moved, now) {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CursorVisible(visible));
+            }
+            if let Some(until) = cursor.until_hidden(now) {
+                ctx.request_repaint_after(until);
+            }
+        }
+    }
+}
+
+impl App for PlotApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        let start = Instant::now();
+        if ctx.input(|i| i.key_pressed(egui::Key::F12)) {
+            self.show_frame_stats = !self.show_frame_stats;
+        }
+        self.update_window(ctx);
+        let reason = if ctx.input(|i| i.events.is_empty()) { self.wake_reason } else { "input" };
+        let rows = self.sim.rows_applied;
+        self.frame = perf::Frame::default();
+        if ctx.style().visuals.dark_mode != (self.theme == Theme::Dark) {
+            ctx.set_visuals(self.theme.visuals());
+        }
+        self.show(ctx);
+        // Measured before the overlay, which shows the figures up to the previous frame
+        let frame = perf::Frame { update: start.elapsed(), rows: self.sim.rows_applied.saturating_sub(rows), reason, ..self.frame };
+        if self.show_frame_stats {
+            self.frame_stats_overlay(ctx);
+        }
+        self.frame_stats.record(start, frame);
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings());
+    }
+}
+
+/// What `sample` takes of `sim` at each frame of a clip from `start_ms` to
+/// `end_ms`, sampled every `1000 / fps` milliseconds of playback at
+/// `speed`. Each frame shows the last row due by its time, as playing
+/// would. `sim` is left at the end of the clip.
+fn sample_clip<T>(sim: &mut Simulation, start_ms: u64, end_ms: u64, fps: u32, speed: f64, mut sample: impl FnMut(&Simulation) -> T) -> Vec<T> {
+    let (frame_count, frame_ms) = (clip_frame_count(start_ms, end_ms, fps, speed), 1000.0 * speed / fps as f64);
+    sim.replay(|sim| {
+        sim.seek(sim.index_at_sim_ms(start_ms));
+        (0..frame_count)
+            .map(|frame| {
+                let t_ms = start_ms as f64 + frame as f64 * frame_ms;
+                sim.advance_to(t_ms as u64);
+                sample(sim)
+            })
+            .collect()
+    })
+}
+
+/// Frames in a clip from `start_ms` to `end_ms` of simulated time, both
+/// shown, at `fps` frames per second of playback at `speed`.
+fn clip_frame_count(start_ms: u64, end_ms: u64, fps: u32, speed: f64) -> usize {
+    let frame_ms = 1000.0 * speed / fps as f64;
+    (end_ms.saturating_sub(start_ms) as f64 / frame_ms) as usize + 1
+}
+
+/// Time since the start of the race such as `+00:42.317`, or `+1:02:03.004` past an hour.
+fn format_elapsed(ms: u64) -> String {
+    let (minutes, seconds, millis) = (ms / 60_000, ms / 1000 % 60, ms % 1000);
+    match minutes / 60 {
+        0 => format!("+{minutes:02}:{seconds:02}.{millis:03}"),
+        hours => format!("+{hours}:{:02}:{seconds:02}.{millis:03}", minutes % 60),
+    }
+}
+
+/// A skipped stretch of the race such as `1m42s` or `4.5s`.
+fn format_skipped(ms: u64) -> String {
+    if ms >= 60_000 {
+        format!("{}m{:02}s", ms / 60_000, ms / 1000 % 60)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+/// Rings the bell of the terminal the app was started from. The web build
+/// has none.
+fn ring_bell() {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::io::Write;
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(b"\x07").and_then(|()| stdout.flush());
+    }
+}
+
+fn screenshot_name() -> String {
+    format!("screenshot-{}.png", Utc::now().format("%Y%m%d-%H%M%S%.3f"))
+}
+
+/// `count` with its thousands separated by commas, e.g. `4,211`.
+fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut text = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            text.push(',');
+        }
+        text.push(digit);
+    }
+    text
+}
+
+/// Parses a signed offset in seconds such as `+90.5s` or `-10`.
+fn parse_offset(text: &str) -> Option<f64> {
+    if !text.starts_with(['+', '-']) {
+        return None;
+    }
+    let seconds: f64 = text.strip_suffix('s').unwrap_or(text).parse().ok()?;
+    seconds.is_finite().then_some(seconds)
+}
+
+/// The LEDs in `path`, failing if it does not read or has none.
+fn read_leds(path: &Path, delimiter: Option<u8>) -> Result<Validated<LedCoordinate>, String> {
+    let coordinates = data::read_coordinates(path, delimiter).map_err(|e| format!("{}: {e}", path.display()))?;
+    if coordinates.records.is_empty() {
+        return Err(format!("{}: no LEDs", path.display()));
+    }
+    Ok(coordinates)
+}
+
+/// Reads the LEDs and datasets of a playlist entry, on the thread
+/// `Playlist::go` starts.
+fn read_playlist_entry(
+    entry: &PlaylistEntry,
+    options: ReadOptions,
+    palette: Palette,
+    codes: &BTreeMap<String, String>,
+    snap_distance: Option<f64>,
+) -> Result<LoadedEntry, String> {
+    let coordinates = read_leds(&entry.coordinates, options.delimiter)?;
+    let led_index = Arc::new(LedIndex::new(&coordinates.records).with_snap_distance(snap_distance));
+    let (paths, issues) = loader::race_dir_paths(&entry.data_dir);
+    if paths.is_empty() {
+        return Err(issues.first().map_or_else(|| format!("no datasets in {}", entry.data_dir.display()), ToString::to_string));
+    }
+    let mut race = loader::load_datasets(&paths, &led_index, options, palette, codes);
+    race.issues.splice(0..0, issues);
+    Ok(LoadedEntry { coordinates, led_index, paths, race })
+}
+
+/// Trims every dataset of `race` to the window `times` give, from its first
+/// row to its last if neither is set, giving the dates it comes to. A race
+/// with no rows is left as it is.
+fn trim_race(race: &mut LoadedRace, (start, end): (Option<RaceTime>, Option<RaceTime>)) -> Result<Option<TrimWindow>, String> {
+    if (start, end) == (None, None) || race.drivers.iter().all(|driver| driver.records.is_empty()) {
+        return Ok(None);
+    }
+    let (from, to) = dataset::trim_range(race.drivers.iter().map(|driver| &driver.records), start, end)?;
+    race.drivers.iter_mut().for_each(|driver| driver.records.trim(from, to));
+    Ok(Some((from, to)))
+}
+
+/// How the config has the dataset files read.
+fn read_options(config: &Config) -> ReadOptions {
+    ReadOptions {
+        stream: config.stream,
+        min_step_ms: config.min_step_ms,
+        repair: config.repair,
+        drop_out_of_order: config.drop_out_of_order,
+        delimiter: config.delimiter(),
+        downsample_ms: config.downsample_ms(),
+    }
+}
+
+/// The dataset files to load, the configured list or everything in the race
+/// folder, with any problem finding them.
+fn dataset_paths(config: &Config) -> (Vec<PathBuf>, Vec<DataIssue>) {
+    let listed = || config.datasets.iter().map(|path| config.resolve(path)).collect();
+    match config.scan_dir() {
+        // The built-in list is for a folder that cannot be listed
+        Some(dir) if config.race.is_none() && config.uses_default_datasets() && loader::scan_datasets(&dir).is_err() => (listed(), Vec::new()),
+        Some(dir) => loader::race_dir_paths(&dir),
+        None => (listed(), Vec::new()),
+    }
+}
+
+/// Writes the demo race to a folder of its own under the temporary
+/// directory, returning the folder and the coordinates file in it.
+fn write_demo() -> std::io::Result<(PathBuf, PathBuf)> {
+    let dir = std::env::temp_dir().join("f1-led-demo");
+    let coordinates = data::demo().write(&dir)?;
+    Ok((dir, coordinates))
+}
+
+/// Runs the simulator as the command line asks: in a window, or writing
+/// the LED frames out with `--export-frames`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run() -> eframe::Result<()> {
+    let cli = Cli::parse();
+    let mut config = Config::from_cli(&cli).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    });
+    if cli.demo {
+        let (dir, coordinates) = write_demo().unwrap_or_else(|e| {
+            eprintln!("error: cannot write the demo race: {e}");
+            std::process::exit(1);
+        });
+        config.use_demo(&dir, coordinates);
+    }
+    if cli.validate {
+        let (paths, missing) = dataset_paths(&config);
+        let checked = crate::validate::check(&config.resolve(&config.coordinates), &paths, missing, read_options(&config), config.snap_distance);
+        let (report, passed) = crate::validate::report(&checked, config.strict);
+        print!("{report}");
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    let Some(mut app) = open(&cli, &config) else {
+        return Ok(());
+    };
+
+    // The window state kept from last time is applied over this, the app puts back what was asked for once it opens
+    let native_options = eframe::NativeOptions { viewport: WindowOptions::from_config(&config).viewport(), ..Default::default() };
+    eframe::run_native(
+        "F1-LED-CIRCUIT SIMULATION",
+        native_options,
+        Box::new(move |cc| {
+            app.restore(cc.storage, &cli, &config);
+            Box::new(app)
+        }),
+    )
+}
+
+/// Runs the simulator in the browser, on the canvas `canvas_id`. The data
+/// files are fetched from `data_url` first, as its `source::MANIFEST` lists
+/// them, and the defaults are used for everything a config file would set,
+/// so the datasets and `led_coords.csv` sit directly under `data_url`.
+/// Without them the setup screen takes files dropped on the page instead.
+#[cfg(target_arch = "wasm32")]
+pub async fn run_web(canvas_id: &str, data_url: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
+    let files = crate::source::fetch(data_url).await.unwrap_or_else(|e| {
+        log::warn!("nothing fetched, waiting for dropped files: {e}");
+        crate::source::MemoryFiles::default()
+    });
+    crate::source::set(files);
+    let cli = Cli::parse_from([env!("CARGO_PKG_NAME")]);
+    let config = Config::default();
+    let mut app = open(&cli, &config).ok_or("nothing to show")?;
+    eframe::WebRunner::new()
+        .start(
+            canvas_id,
+            eframe::WebOptions::default(),
+            Box::new(move |cc| {
+                app.restore(cc.storage, &cli, &config);
+                Box::new(app)
+            }),
+        )
+        .await
+}
+
+/// Where the activity log is written as it goes: stdout for `-`, else the
+/// file at `path`, a line at a time so a crash loses none of it.
+fn activity_sink(path: &Path) -> Option<Box<dyn Write + Send>> {
+    if path == Path::new("-") {
+        return Some(Box::new(std::io::stdout()));
+    }
+    match File::create(path) {
+        Ok(file) => Some(Box::new(LineWriter::new(file))),
+        Err(e) => {
+            eprintln!("warning: no activity log: cannot write {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// The app the command line and config ask for, or `None` once a headless
+/// run has written its file.
+fn open(cli: &Cli, config: &Config) -> Option<PlotApp> {
+    let coordinates_path = config.resolve(&config.coordinates);
+    let headless = cli.export_frames.is_some() || cli.heatmap.is_some() || cli.export_gif.is_some();
+    let (coordinates, mut coordinate_issues) = match data::read_coordinates(&coordinates_path, config.delimiter()) {
+        Ok(coordinates) => (coordinates.records, coordinates.issues),
+        // The playlist brings its own
+        Err(_) if !headless && cli.replay.is_none() && !config.playlist.is_empty() => (Vec::new(), Vec::new()),
+        // The window opens without a track and lists why, so the file can be fixed and reloaded
+        Err(e) if !headless && cli.replay.is_none() => {
+            eprintln!("warning: {}: {e}", coordinates_path.display());
+            let issue = DataIssue { file: coordinates_path.clone(), line: None, message: e.to_string() };
+            (Vec::new(), vec![issue])
+        }
+        Err(e) => {
+            eprintln!("error: {}: {e}", coordinates_path.display());
+            std::process::exit(1);
+        }
+    };
+    let sectors = match config.sectors_path() {
+        Some(path) => {
+            let (sectors, issues) = Sectors::read(&path, coordinates.len()).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            });
+            coordinate_issues.extend(issues);
+            sectors
+        }
+        None if !config.sector_leds.is_empty() => {
+            let (sectors, issues) = Sectors::from_leds(&config.sector_leds, coordinates.len());
+            coordinate_issues.extend(issues);
+            sectors
+        }
+        None => Sectors::default(),
+    };
+
+    let grid = match config.grid_path() {
+        Some(path) => {
+            let (grid, issues) = Grid::read(&path, coordinates.len()).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            });
+            coordinate_issues.extend(issues);
+            grid
+        }
+        None => Grid::default(),
+    };
+
+    let led_index = Arc::new(LedIndex::new(&coordinates).with_snap_distance(config.snap_distance));
+    let start_finish_led = match (config.start_finish_led, config.start_finish) {
+        (Some(led), _) if led >= coordinates.len() => {
+            eprintln!("error: start_finish_led {led} is out of range ({} LEDs)", coordinates.len());
+            std::process::exit(2);
+        }
+        (Some(led), _) => Some(led),
+        (None, Some([x, y])) => led_index.nearest(x, y),
+        (None, None) => None,
+    };
+
+    let options = PlaybackOptions {
+        speed: config.speed,
+        autostart: config.autostart,
+        looping: config.looping,
+        loop_pause: config.loop_pause,
+        tick_rate: config.tick_rate,
+        max_gap: config.max_gap,
+        checkpoint_interval: config.checkpoint_interval,
+        read_options: read_options(config),
+        data_dir: config.data_root(),
+        race: config.race.clone(),
+        start_finish_led,
+        lap_debounce_leds: config.lap_debounce_leds,
+        sound: config.sound,
+        cues: config.cues.clone(),
+        cue_sound: config.cue_sound,
+        trail_length: config.trail_length,
+        trail_mode: config.trail_mode,
+        stopped_after: config.stopped_after,
+        driver_codes: config.driver_codes.clone(),
+        session_start: config.session_start(),
+        driver_offsets: config.driver_offsets.clone(),
+        drivers: match config.drivers_path() {
+            Some(path) => DriverTable::read(&path).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }),
+            None => DriverTable::default(),
+        },
+        events: match config.events_path() {
+            Some(path) => RaceEvents::read(&path).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }),
+            None => RaceEvents::default(),
+        },
+        grid,
+        start_lights: config.start_lights,
+        countdown: config.countdown,
+        sectors,
+        labels: config.labels,
+        led_codes: config.led_codes,
+        stack_offset: config.stack_offset,
+        outline: config.outline,
+        outline_color: config.outline_color,
+        smooth: config.smooth,
+        interpolate: config.interpolate,
+        keep_aspect: config.keep_aspect,
+        led_shape: config.led_shape,
+        led_size: config.led_size,
+        unlit_outline: config.unlit_outline,
+        metres_per_unit: config.metres_per_unit,
+        palette: config.palette.unwrap_or_default(),
+        session_path: cli.resume.clone().unwrap_or_else(|| PathBuf::from("session.json")),
+        screenshot_dir: config.screenshot_dir.clone(),
+        output: config.output.clone().and_then(|output_config| {
+            Output::start(output_config)
+                .map_err(|e| eprintln!("warning: LED output disabled: {e}"))
+                .ok()
+        }),
+        mqtt: config.mqtt.clone().and_then(|mqtt_config| {
+            Mqtt::start(mqtt_config)
+                .map_err(|e| eprintln!("warning: MQTT output disabled: {e}"))
+                .ok()
+        }),
+        server: config.serve.and_then(|address| {
+            LedServer::start(address)
+                .map_err(|e| eprintln!("warning: WebSocket server disabled: {address}: {e}"))
+                .ok()
+        }),
+        record: config.record.clone(),
+        event_log: config.event_log.clone(),
+        activity_log: config.activity_log.clone(),
+        window: WindowOptions::from_config(config),
+        watch: config.watch,
+        coordinates_path: coordinates_path.clone(),
+        snap_distance: config.snap_distance,
+        trim_times: config.trim(),
+        reference: config.reference_path(),
+        playlist: config.playlist(),
+    };
+    // Headless runs that write a file and exit
+    if headless {
+        let (paths, issues) = dataset_paths(config);
+        if paths.is_empty() {
+            let why = issues.first().map_or_else(|| "no dataset files configured".to_string(), ToString::to_string);
+            eprintln!("error: nothing to export: {why}");
+            std::process::exit(1);
+        }
+        let mut race = loader::load_datasets(&paths, &led_index, options.read_options, options.palette, &options.driver_codes);
+        race.issues.splice(0..0, issues);
+        for issue in coordinate_issues.iter().chain(&race.issues) {
+            eprintln!("warning: {issue}");
+        }
+        if config.strict && !(coordinate_issues.is_empty() && race.issues.is_empty()) {
+            eprintln!("error: data problems found and --strict is set");
+            std::process::exit(1);
+        }
+        if let Err(e) = trim_race(&mut race, options.trim_times) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        let mut app = PlotApp::new(coordinates, coordinate_issues, race, options);
+        if let Some(path) = &cli.heatmap {
+            let image = app.render_heatmap(config.heatmap_size, config.heatmap_gradient);
+            if let Err(e) = image.save(path) {
+                eprintln!("error: cannot write {}: {e}", path.display());
+                std::process::exit(1);
+            }
+            println!("Wrote a heatmap of {} rows to {}", format_count(app.sim.drivers.iter().map(|driver| driver.records.len()).sum()), path.display());
+        }
+        if let Some(path) = &cli.export_frames {
+            let (start, rows) = (Instant::now(), app.sim.rows_applied);
+            let frames = app.led_frames();
+            app.log_activity();
+            let rows = app.sim.rows_applied - rows;
+            if let Err(e) = frames::write(path, &frames) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            println!("Wrote {} frames to {}", frames.len(), path.display());
+            let rate = perf::per_second(rows, start.elapsed()).map_or_else(String::new, |rate| format!(", {} rows/s", format_count(rate as usize)));
+            println!("Played {} rows in {:.2} s{rate}", format_count(rows as usize), start.elapsed().as_secs_f64());
+        }
+        if let Some(path) = &cli.export_gif {
+            let followed: Vec<_> = if cli.per_driver { (0..app.sim.drivers.len()).map(Some).collect() } else { vec![None] };
+            if cli.per_driver {
+                if let Err(e) = std::fs::create_dir_all(path) {
+                    eprintln!("error: cannot create {}: {e}", path.display());
+                    std::process::exit(1);
+                }
+            }
+            for (n, following) in followed.iter().enumerate() {
+                let out = match *following {
+                    Some(dataset_idx) => path.join(format!("{}.gif", app.sim.drivers[dataset_idx].key)),
+                    None => path.clone(),
+                };
+                if let Some(dataset_idx) = *following {
+                    println!("[{}/{}] Following {}", n + 1, followed.len(), app.sim.drivers[dataset_idx].name);
+                }
+                let clip = app.race_clip(config.gif_size, config.gif_fps, *following);
+                if let Err(e) = clip.write(&out, &AtomicUsize::new(0), &AtomicBool::new(false)) {
+                    eprintln!("error: cannot write {}: {e}", out.display());
+                    std::process::exit(1);
+                }
+                println!("Wrote {} frames to {}", clip.frames.len(), out.display());
+            }
+        }
+        return None;
+    }
+
+    let app = match &cli.replay {
+        Some(path) => {
+            let recording = Recording::read(path).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            });
+            if let Err(e) = recording.check_layout(&coordinates) {
+                eprintln!("error: {} does not match {}: {e}", path.display(), coordinates_path.display());
+                std::process::exit(1);
+            }
+            PlotApp::replaying(coordinates, coordinate_issues, recording, options)
+        }
+        None if !config.playlist.is_empty() => PlotApp::playing_list(coordinates, coordinate_issues, options),
+        None => {
+            let (paths, issues) = dataset_paths(config);
+            // Nothing to show yet, ask where the files are
+            if coordinates.is_empty() || paths.is_empty() {
+                let setup = Setup::new(coordinates_path.clone(), config.data_root(), config.race.clone(), config.delimiter());
+                PlotApp::setting_up(coordinates, coordinate_issues, setup, options)
+            } else {
+                // Read multiple datasets in the background, either the configured list or everything in the race folder
+                let startup = Startup {
+                    race: PendingRace::start(paths, issues, led_index, options.read_options),
+                    strict: config.strict,
+                    autostart: config.autostart,
+                    settings: None,
+                    session: cli.resume.as_deref().map(|path| {
+                        Session::read(path).unwrap_or_else(|e| {
+                            eprintln!("error: {e}");
+                            std::process::exit(1);
+                        })
+                    }),
+                    start_at: config.start_at(),
+                    race_dir: None,
+                };
+                PlotApp::loading(coordinates, coordinate_issues, startup, options)
+            }
+        }
+    };
+    Some(app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::fixtures;
+    use proptest::collection::vec;
+    use proptest::prelude::{prop, prop_assert_eq};
+
+    fn row(x_led: f64, time_delta: u64) -> RunRace {
+        RunRace {
+            date: DateTime::UNIX_EPOCH, // Dated by `app`
+            x_led,
+            y_led: 0.0,
+            time_delta,
+            line: 0,
+            telemetry: Default::default(),
+        }
+    }
+
+    /// A race on five LEDs at x = 0..4. Each row is dated `time_delta` after
+    /// the previous one, starting at 15:04:22. The wall clock stands still
+    /// until a test moves it.
+    fn app(run_race_data: Vec<Vec<RunRace>>) -> PlotApp {
+        let coordinates: Vec<LedCoordinate> =
+            (0..5).map(|x| LedCoordinate { x_led: x as f64, y_led: 0.0, ..Default::default() }).collect();
+        let led_index = LedIndex::new(&coordinates);
+        let start = "2023-08-27T15:04:22Z".parse::<DateTime<Utc>>().unwrap();
+        let n = run_race_data.len();
+        let colors = Palette::Default.colors(&vec![String::new(); n]);
+        let drivers = run_race_data
+            .into_iter()
+            .zip(colors)
+            .enumerate()
+            .map(|(i, (mut rows, color))| {
+                let mut date = start - chrono::Duration::milliseconds(rows.first().map_or(0, |row| row.time_delta as i64));
+                for row in &mut rows {
+                    date += chrono::Duration::milliseconds(row.time_delta as i64);
+                    row.date = date;
+                }
+                Driver {
+                    key: format!("driver{i}"),
+                    name: format!("Driver {i}"),
+                    code: format!("D{i}"),
+                    ..Driver::new(Dataset::from_rows(&rows, &led_index, 0), color)
+                }
+            })
+            .collect();
+        let race = LoadedRace { drivers, issues: Vec::new() };
+        let options = PlaybackOptions {
+            speed: 1.0,
+            autostart: true,
+            looping: false,
+            loop_pause: 0.0,
+            tick_rate: 60.0,
+            max_gap: None,
+            checkpoint_interval: crate::sim::DEFAULT_CHECKPOINT_INTERVAL_MS as f64 / 1000.0,
+            read_options: ReadOptions { stream: false, min_step_ms: 0, repair: true, drop_out_of_order: false, delimiter: None, downsample_ms: 0 },
+            data_dir: PathBuf::from("does-not-exist"),
+            race: None,
+            start_finish_led: None,
+            lap_debounce_leds: 10,
+            sound: false,
+            cues: BTreeSet::new(),
+            cue_sound: false,
+            trail_length: 0,
+            trail_mode: TrailMode::Fade,
+            stopped_after: 0,
+            driver_codes: BTreeMap::new(),
+            session_start: None,
+            driver_offsets: BTreeMap::new(),
+            drivers: DriverTable::default(),
+            events: RaceEvents::default(),
+            grid: Grid::default(),
+            start_lights: 0.0,
+            countdown: false,
+            sectors: Sectors::default(),
+            labels: false,
+            led_codes: false,
+            stack_offset: false,
+            keep_aspect: false,
+            led_shape: LedShape::Square,
+            led_size: LedSize::default(),
+            unlit_outline: None,
+            outline: false,
+            outline_color: [64, 64, 64],
+            smooth: false,
+            interpolate: false,
+            metres_per_unit: None,
+            palette: Palette::Default,
+            session_path: PathBuf::from("session.json"),
+            screenshot_dir: std::env::temp_dir(),
+            output: None,
+            mqtt: None,
+            server: None,
+            record: None,
+            event_log: None,
+            activity_log: None,
+            window: WindowOptions::default(),
+            watch: false,
+            coordinates_path: PathBuf::from("led_coords.csv"),
+            snap_distance: None,
+            trim_times: (None, None),
+            reference: None,
+            playlist: Vec::new(),
+        };
+        let mut app = PlotApp::new(coordinates, Vec::new(), race, options);
+        app.clock.set_wall(start);
+        app
+    }
+
+    fn current_leds(app: &PlotApp) -> Vec<Option<usize>> {
+        app.sim.cars.iter().map(|car| car.trail.front().copied()).collect()
+    }
+
+    /// Waits for the lap starts being found on another thread.
+    fn await_lap_starts(app: &mut PlotApp) {
+        while app.lap_starts_job.is_some() {
+            std::thread::sleep(Duration::from_millis(1));
+            app.update_lap_starts();
+        }
+    }
+
+    fn await_reference(app: &mut PlotApp) {
+        while app.reference_load.is_some() {
+            std::thread::sleep(Duration::from_millis(1));
+            app.finish_reference();
+        }
+    }
+
+    fn keys(app: &PlotApp) -> Vec<String> {
+        app.sim.drivers.iter().map(|driver| driver.key.clone()).collect()
+    }
+
+    fn visible(app: &PlotApp) -> Vec<bool> {
+        app.sim.drivers.iter().map(|driver| driver.visible).collect()
+    }
+
+    /// Waits for the race being read again and gives what the status bar says of it.
+    fn reloaded(app: &mut PlotApp) -> String {
+        while app.reloading.is_some() {
+            std::thread::sleep(Duration::from_millis(1));
+            app.finish_reloading();
+        }
+        app.status.clone().unwrap_or_default()
+    }
+
+    /// Waits for the race being loaded and swaps it in.
+    fn loaded(app: &mut PlotApp) {
+        if let Some(startup) = &mut app.startup {
+            startup.race.wait();
+        }
+        app.finish_loading();
+    }
+
+    fn reload(app: &mut PlotApp) -> String {
+        app.reload_datasets("Reloaded".to_string());
+        reloaded(app)
+    }
+
+    #[test]
+    fn no_datasets_is_finished_immediately() {
+        let mut app = app(Vec::new());
+        assert!(!app.sim.advance());
+        app.reset();
+        assert_eq!(app.sim.current_index, 0);
+        assert!(!app.sim.advance());
+    }
+
+    #[test]
+    fn empty_first_dataset_does_not_stall_the_others() {
+        let mut app = app(vec![Vec::new(), vec![row(1.0, 100), row(2.0, 100), row(3.0, 100)]]);
+        assert_eq!(app.sim.next_step_ms(), Some(100));
+        for _ in 0..3 {
+            assert!(app.sim.advance());
+        }
+        assert_eq!(current_leds(&app), [None, Some(3)]);
+        assert!(!app.sim.advance());
+        assert_eq!(app.sim.current_index, 3);
+
+        app.reset();
+        assert_eq!(app.sim.current_index, 0);
+        assert_eq!(current_leds(&app), [None, None]);
+        assert!(app.sim.advance());
+    }
+
+    #[test]
+    fn mismatched_lengths_run_to_the_longest_dataset() {
+        let mut app = app(vec![
+            vec![row(1.0, 100), row(2.0, 100)],
+            vec![row(1.0, 50), row(2.0, 50), row(3.0, 50), row(4.0, 50)],
+        ]);
+        let mut steps = 0;
+        while app.sim.advance() {
+            steps += 1;
+        }
+        assert_eq!(steps, 4);
+        // The shorter dataset stays on its last LED, the race runs to the last date of any dataset
+        assert_eq!(current_leds(&app), [Some(2), Some(4)]);
+        assert_eq!(app.sim.sim_elapsed_ms, 100 + 50 + 50 + 50);
+    }
+
+    #[test]
+    fn following_a_driver_stops_when_their_data_ends() {
+        let mut app = app(vec![vec![row(1.0, 0), row(2.0, 100)], vec![row(1.0, 0), row(2.0, 100), row(3.0, 100)]]);
+        app.zoom = 2.0;
+        app.follow(Some(0));
+        assert_eq!(app.zoom, 2.0, "keeps the zoom it had");
+        app.follow(None);
+        assert_eq!((app.zoom, app.focus), (1.0, FITTED_FOCUS));
+
+        app.follow_zoom = false;
+        app.follow(Some(1));
+        assert_eq!(app.zoom, 1.0, "only pans");
+        app.follow(None);
+        app.follow_zoom = true;
+        app.follow(Some(0));
+        assert_eq!(app.zoom, FOLLOW_ZOOM);
+        assert!(app.move_camera(egui::vec2(1.0, 0.5), CAMERA_EASE));
+        assert!((app.focus.x - (1.0 - 0.5 * (-1.0f32).exp())).abs() < 1e-6);
+        for _ in 0..2 {
+            app.sim.advance();
+            app.check_following();
+        }
+        assert_eq!(app.following, Some(0), "still on its last LED");
+        app.sim.advance();
+        app.check_following();
+        assert_eq!((app.following, app.zoom), (None, 1.0));
+        assert_eq!(app.status.as_deref(), Some("Driver 0's data ended, showing the whole track"));
+    }
+
+    #[test]
+    fn playback_stops_and_completes_at_the_end() {
+        let mut app = app(vec![vec![row(1.0, 0), row(2.0, 100)]]);
+        app.update_playback();
+        assert!(app.race_started && !app.race_complete);
+        assert_eq!(app.sim.current_index, 1);
+        app.clock.advance_wall(Duration::from_millis(100));
+        app.update_playback();
+        assert!(!app.race_started && app.race_complete);
+        assert_eq!(app.sim.current_index, 2);
+
+        app.clock.advance_wall(Duration::from_secs(1));
+        app.update_playback();
+        assert_eq!(app.sim.current_index, 2);
+        app.reset();
+        assert!(!app.race_complete);
+    }
+
+    #[test]
+    fn elapsed_time_follows_the_clock_and_the_progress_bar_the_rows() {
+        let mut app = app(vec![vec![row(1.0, 100), row(2.0, 100), row(3.0, 100), row(4.0, 100)]]);
+        assert_eq!((app.race_elapsed_ms(), app.race_fraction()), (Some(0), 0.0));
+        app.speed = 2.0;
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(130)); // 260 ms at 2x, 160 ms after the first row
+        app.update_playback();
+        assert_eq!((app.race_elapsed_ms(), app.race_fraction()), (Some(160), 0.5));
+        app.set_paused(true);
+        app.clock.advance_wall(Duration::from_secs(1));
+        assert_eq!(app.race_elapsed_ms(), Some(160), "holds while paused");
+        app.set_paused(false);
+        app.clock.advance_wall(Duration::from_secs(1));
+        app.update_playback();
+        assert_eq!((app.race_elapsed_ms(), app.race_fraction()), (Some(300), 1.0), "stops at the last row");
+        assert!(app.race_complete);
+
+        assert_eq!([0, 42_317, 3_723_004].map(format_elapsed), ["+00:00.000", "+00:42.317", "+1:02:03.004"]);
+    }
+
+    #[test]
+    fn reverse_playback_rewinds_to_the_reset_state() {
+        let rows = vec![row(1.0, 10), row(2.0, 20), row(3.0, 30), row(4.0, 40)];
+        let mut app = app(vec![rows]);
+        app.speed = 2.0;
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(30)); // 60 ms at 2x, the first three rows
+        app.update_playback();
+        assert_eq!(current_leds(&app), [Some(3)]);
+
+        app.reverse = true;
+        app.clock.seek(app.sim.sim_elapsed_ms as f64);
+        app.update_playback();
+        let wait = app.wake().0.unwrap();
+        assert!(wait <= Duration::from_millis(15) && wait > Duration::from_millis(14)); // 30 ms at 2x
+        app.clock.advance_wall(Duration::from_millis(15));
+        app.update_playback();
+        assert_eq!((app.sim.current_index, app.sim.sim_elapsed_ms), (2, 30));
+        assert_eq!(current_leds(&app), [Some(2)]);
+        assert!(app.race_started);
+
+        for _ in 0..2 {
+            app.clock.advance_wall(Duration::from_secs(1));
+            app.update_playback(); // One row per call
+        }
+        assert_eq!(app.sim.current_index, 0);
+        assert!(!app.race_started && !app.race_complete);
+        assert_eq!(current_leds(&app), [None]);
+
+        app.start(); // Rewinds from the end
+        assert_eq!(app.sim.current_index, 4);
+        assert!(app.race_started);
+        app.clock.advance_wall(Duration::from_secs(1));
+        app.update_playback();
+        assert_eq!(app.sim.current_index, 3);
+    }
+
+    #[test]
+    fn every_loop_counts_laps_from_zero() {
+        let mut app = app(vec![[1.0, 2.0, 3.0, 1.0, 2.0].map(|x| row(x, 10)).into()]);
+        (app.sim.start_finish_led, app.sim.lap_debounce_leds) = (Some(1), 2);
+        (app.looping, app.loop_pause) = (true, Duration::ZERO);
+        for _ in 0..3 {
+            app.update_playback();
+            app.clock.advance_wall(Duration::from_secs(1));
+            app.update_playback();
+            assert!(app.race_complete);
+            assert_eq!((app.sim.cars[0].laps.laps, app.sim.leader_lap()), (1, 2));
+            app.clock.advance_wall(Duration::from_millis(1));
+            app.update_playback(); // Restarts
+            assert_eq!((app.sim.cars[0].laps.laps, app.sim.leader_lap()), (0, 0));
+        }
+    }
+
+    #[test]
+    fn a_cue_pulses_the_track_on_every_loop_and_fades_out_over_the_cars() {
+        let mut app = app(vec![vec![row(1.0, 10), row(2.0, 10), row(3.0, 10)]]);
+        (app.looping, app.loop_pause) = (true, Duration::ZERO);
+        app.cues = [Cue::Finish].into();
+        app.update_playback();
+        for _ in 0..3 {
+            app.clock.advance_wall(Duration::from_secs(1));
+            app.update_playback();
+            assert!(app.race_complete);
+            app.sim.track_flash = None;
+            let cars = app.sim.lit_leds();
+            app.update_cues();
+            assert_eq!((app.sim.track_flash, app.wake().1), (Some(1.0), "cue flash"));
+            app.clock.advance_wall(CUE_FLASH / 2);
+            app.update_cues();
+            assert_eq!(app.sim.track_flash, Some(0.5));
+            assert_eq!(app.sim.lit_leds().len(), app.sim.coordinates.len(), "every LED pulses");
+            app.clock.advance_wall(CUE_FLASH);
+            app.update_cues();
+            assert_eq!((app.sim.track_flash, app.sim.lit_leds()), (None, cars), "the cars are as they were");
+            app.update_playback(); // Starts the loop again
+            app.update_cues();
+            assert_eq!((app.sim.current_index, app.sim.track_flash), (0, None));
+        }
+    }
+
+    #[test]
+    fn looping_restarts_from_scratch_after_the_pause() {
+        let mut app = app(vec![vec![row(1.0, 10), row(2.0, 10), row(3.0, 10)]]);
+        app.sim.trail_length = 2;
+        app.looping = true;
+        app.loop_pause = Duration::from_secs(5);
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_secs(1));
+        app.update_playback();
+        assert!(app.race_complete);
+        let finished: Vec<_> = app.sim.cars.iter().map(|car| car.trail.clone()).collect();
+
+        app.clock.advance_wall(Duration::from_secs(4));
+        app.update_playback();
+        assert!(app.race_complete, "still showing the final state");
+        for _ in 0..100 {
+            app.clock.advance_wall(Duration::from_secs(10));
+            app.update_playback(); // Restarts
+            assert_eq!((app.sim.current_index, app.sim.sim_elapsed_ms), (0, 0));
+            assert!(app.race_started && !app.race_complete);
+            app.clock.advance_wall(Duration::from_secs(1));
+            app.update_playback();
+            assert!(app.race_complete);
+            assert_eq!(app.sim.sim_elapsed_ms, 30, "no drift between loops");
+            let trails: Vec<_> = app.sim.cars.iter().map(|car| car.trail.clone()).collect();
+            assert_eq!(trails, finished);
+        }
+    }
+
+    #[test]
+    fn driver_metadata_names_and_colors_drivers() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-metadata.toml", std::process::id()));
+        let entry = |driver: &str, secondary: &str| {
+            format!("[[driver]]\ndriver = \"{driver}\"\nname = \"Name {driver}\"\nteam = \"Team\"\ncode = \"C{driver}\"\nprimary = \"#102030\"\n{secondary}\n")
+        };
+        std::fs::write(&path, entry("driver0", "") + &entry("driver2", "secondary = \"#ffffff\"")).unwrap();
+        let mut app = app(vec![vec![row(1.0, 10)], vec![row(2.0, 10)], vec![row(3.0, 10)]]);
+        app.driver_table = DriverTable::read(&path).unwrap();
+        app.apply_driver_info();
+
+        let names: Vec<_> = app.sim.drivers.iter().map(|driver| driver.name.as_str()).collect();
+        let codes: Vec<_> = app.sim.drivers.iter().map(|driver| driver.code.as_str()).collect();
+        assert_eq!((names, codes), (vec!["Name driver0", "Driver 1", "Name driver2"], vec!["Cdriver0", "D1", "Cdriver2"]));
+        assert_eq!(app.sim.drivers.iter().map(|driver| driver.team.as_deref()).collect::<Vec<_>>(), [Some("Team"), None, Some("Team")]);
+        assert_eq!(app.sim.drivers[0].color, egui::Color32::from_rgb(0x10, 0x20, 0x30));
+        assert_eq!(app.sim.drivers[1].color, Palette::Default.colors(&keys(&app))[1]);
+        assert_eq!(app.data_issues.len(), 1);
+        assert!(app.data_issues[0].message.contains("driver1"));
+        assert_eq!(app.legend_groups(), [(Some("Team".to_string()), vec![0, 2]), (None, vec![1])]);
+
+        app.set_palette(Palette::HighContrast);
+        assert_eq!(app.sim.drivers[2].color, egui::Color32::from_rgb(0x10, 0x20, 0x30));
+    }
+
+    #[test]
+    fn picked_colors_and_the_spotlight_reach_the_leds() {
+        let mut app = app(vec![vec![row(1.0, 10)], vec![row(2.0, 10)]]);
+        app.sim.advance();
+        let white = egui::Color32::WHITE;
+        assert!(app.set_driver_color("Driver 1", white));
+        assert!(!app.set_driver_color("Nobody", white), "kept for a race that has them");
+        app.set_palette(Palette::HighContrast);
+        assert_eq!(app.sim.lit_leds(), [(1, app.sim.drivers[0].color), (2, white)]);
+        assert_eq!(app.sim.led_colors()[2], [255, 255, 255], "the strip gets it too");
+
+        app.sim.spotlight = Some(1);
+        assert_eq!(app.sim.lit_leds(), [(1, app.sim.drivers[0].color.gamma_multiply(crate::sim::SPOTLIGHT_DIM)), (2, white)]);
+        let settings = app.settings();
+        assert_eq!(settings.color_overrides.get("Driver 1"), Some(&[255, 255, 255]));
+
+        app.reset_driver_colors();
+        assert_eq!(app.sim.drivers.iter().map(|driver| driver.color).collect::<Vec<_>>(), Palette::HighContrast.colors(&keys(&app)));
+        app.apply_settings(settings);
+        assert_eq!(app.sim.drivers[1].color, white);
+    }
+
+    #[test]
+    fn trail_length_changes_show_on_the_next_frame() {
+        let mut app = app(vec![[0.0, 1.0, 2.0, 3.0, 4.0].map(|x| row(x, 10)).into()]);
+        while app.sim.advance() {}
+        let lit = |app: &PlotApp| app.sim.lit_leds().iter().map(|&(led, _)| led).collect::<Vec<_>>();
+        assert_eq!(lit(&app), [4], "only the current LED without a trail");
+        app.sim.trail_length = 2;
+        assert_eq!(lit(&app), [2, 3, 4]);
+        let colors = app.sim.lit_leds();
+        assert!(colors[0].1.a() < colors[1].1.a() && colors[1].1.a() < colors[2].1.a(), "fades with age");
+        app.sim.trail_length = 0;
+        assert_eq!(lit(&app), [4]);
+    }
+
+    #[test]
+    fn cars_on_the_same_led_mix_their_colors() {
+        let mut app = app(vec![vec![row(1.0, 10), row(2.0, 10)], vec![row(1.0, 10), row(3.0, 10)], vec![row(3.0, 10)]]);
+        app.sim.trail_length = 1;
+        app.sim.advance();
+        let (a, b) = (app.sim.drivers[0].color, app.sim.drivers[1].color);
+        assert_eq!(app.sim.lit_leds(), [(1, render::mix(&[a, b])), (3, app.sim.drivers[2].color)]);
+        assert_ne!(render::mix(&[a, b]), a);
+        // A comparison pane shows its driver on their own, even while hidden
+        app.sim.drivers[0].visible = false;
+        assert_eq!(app.sim.lit_leds_of(|dataset_idx| dataset_idx == 0), [(1, a)]);
+        app.sim.drivers[0].visible = true;
+
+        // Car 1 joins car 2, which has run out of data on LED 3, and leaves a trail shared with car 0
+        app.sim.advance();
+        let lit = app.sim.lit_leds();
+        assert_eq!(lit.iter().filter(|(led, _)| *led == 3).count(), 1);
+        assert_eq!(lit.last(), Some(&(3, render::mix(&[app.sim.drivers[1].color, app.sim.drivers[2].color]))));
+        assert_eq!(lit[0].0, 1); // The shared trail, oldest first
+    }
+
+    #[test]
+    fn session_round_trip_restores_position() {
+        let rows = || vec![row(1.0, 10), row(2.0, 10), row(3.0, 10), row(4.0, 10)];
+        let mut saved = app(vec![rows(), rows()]);
+        saved.sim.advance();
+        saved.sim.advance();
+        saved.set_paused(true);
+        saved.sim.drivers[1].visible = false;
+        saved.speed = 4.0;
+        let path = std::env::temp_dir().join(format!("f1-led-{}-session.json", std::process::id()));
+        saved.save_session(&path).unwrap();
+
+        let mut restored = app(vec![rows(), rows()]);
+        restored.load_session(&path).unwrap();
+        assert_eq!(restored.sim.current_index, 2);
+        assert_eq!(current_leds(&restored), current_leds(&saved));
+        assert!(restored.race_started && restored.paused);
+        assert_eq!(visible(&restored), [true, false]);
+        assert_eq!(restored.speed, 4.0);
+    }
+
+    #[test]
+    fn a_session_in_another_race_waits_for_it_to_load() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-session-race", std::process::id()));
+        std::fs::create_dir_all(dir.join("monza")).unwrap();
+        let rows = "date,x_led,y_led,time_delta\n2023-09-03T13:00:00Z,0,0,0\n2023-09-03T13:00:01Z,1,0,1000\n2023-09-03T13:00:02Z,2,0,1000\n";
+        std::fs::write(dir.join("monza").join("time_delta_sainz_start.csv"), rows).unwrap();
+        let mut app = app(vec![vec![row(4.0, 10)]]);
+        let session = Session { data_dir: Some(dir.clone()), race: Some("monza".to_string()), current_index: 2, race_started: true, paused: true, ..Default::default() };
+        app.apply_session(session);
+        assert_eq!((app.sim.current_index, app.selected_race.as_deref()), (0, Some("monza")), "nothing applied to the race there was");
+
+        loaded(&mut app);
+        assert_eq!((keys(&app), app.sim.current_index, current_leds(&app)), (vec!["sainz".to_string()], 2, vec![Some(1)]));
+        assert!(app.race_started && app.paused && app.startup.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn old_session_without_version_loads_with_defaults() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-old-session.json", std::process::id()));
+        std::fs::write(&path, r#"{"current_index": 1, "visible": {"Nobody": false}, "zoom": 2.0}"#).unwrap();
+        let mut app = app(vec![vec![row(1.0, 10), row(2.0, 10)]]);
+        app.load_session(&path).unwrap();
+        assert_eq!(app.sim.current_index, 1);
+        assert_eq!(visible(&app), [true]);
+        assert_eq!(app.speed, 1.0);
+    }
+
+    #[test]
+    fn settings_ignore_unknown_drivers_and_reset_to_defaults() {
+        let mut app = app(vec![vec![row(1.0, 10)], vec![row(2.0, 10)]]);
+        let mut settings = app.settings();
+        settings.speed = 3.0;
+        settings.trail_length = 4;
+        settings.visible = [("Driver 1".to_string(), false), ("Nobody".to_string(), false)].into();
+        app.apply_settings(settings.clone());
+        assert_eq!(visible(&app), [true, false]);
+        assert_eq!((app.speed, app.sim.trail_length), (3.0, 4));
+
+        app.apply_settings(app.defaults.clone());
+        assert_eq!(visible(&app), [true, true]);
+        assert_eq!((app.speed, app.sim.trail_length), (1.0, 0));
+    }
+
+    #[test]
+    fn a_driver_offset_moves_the_driver_at_once_and_persists() {
+        let rows = || (0..4).map(|x| row(x as f64, 1000)).collect::<Vec<_>>();
+        let mut app = app(vec![rows(), rows()]);
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(3500)); // The first row is due a second in
+        app.update_playback();
+        assert_eq!(current_leds(&app), [Some(2), Some(2)]);
+
+        app.set_driver_offset(1, 1000);
+        assert_eq!(current_leds(&app), [Some(2), Some(1)], "moved back a row straight away");
+        assert_eq!(app.clock.now_ms(), 3500.0, "playback carries on from the same moment");
+        assert!(app.race_started && !app.paused);
+        assert_eq!(app.settings().driver_offsets, [("driver1".to_string(), 1.0)].into());
+        let resolved: Vec<_> = app.resolved_drivers().into_iter().map(|(key, dataset, offset_ms)| crate::export::ResolvedDriver::new(key, &dataset, offset_ms)).collect();
+        assert_eq!(resolved[1].origin - resolved[0].origin, chrono::Duration::seconds(1), "the export has the corrected dates");
+
+        app.set_driver_offset(1, -500);
+        assert_eq!(current_leds(&app), [Some(2), Some(3)]);
+        app.set_driver_offset(1, 0);
+        assert!(app.driver_offsets.is_empty());
+        let mut settings = app.settings();
+        settings.driver_offsets.insert("driver0".to_string(), -2.0);
+        app.apply_settings(settings);
+        assert_eq!((app.sim.manual_offset_ms(0), current_leds(&app)), (-2000, vec![Some(3), Some(2)]));
+    }
+
+    #[test]
+    fn the_reference_race_keeps_time_from_its_own_first_row() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-reference", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A year earlier and at another time of day than the loaded race
+        let rows = "date,x_led,y_led,time_delta\n2022-08-28T13:00:00Z,0,0,1000\n2022-08-28T13:00:01Z,1,0,1000\n2022-08-28T13:00:02Z,2,0,1000\n2022-08-28T13:00:03Z,3,0,1000\n";
+        std::fs::write(dir.join("time_delta_max_start.csv"), rows).unwrap();
+        let mut app = app(vec![vec![row(4.0, 1000), row(3.0, 1000), row(2.0, 1000), row(1.0, 1000)]]);
+        app.reference_path = Some(dir.clone());
+        app.load_reference();
+        assert!(app.reference.is_none(), "read in the background");
+        await_reference(&mut app);
+        assert_eq!(app.reference.as_ref().unwrap().name, format!("f1-led-{}-reference", std::process::id()));
+
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(3500));
+        app.update_playback();
+        app.sync_reference();
+        let reference = &app.reference.as_ref().unwrap().sim;
+        assert_eq!((current_leds(&app), reference.cars[0].trail.front().copied()), (vec![Some(2)], Some(2)));
+
+        app.seek(1);
+        app.sync_reference();
+        assert_eq!(app.reference.as_ref().unwrap().sim.cars[0].trail.front().copied(), Some(0), "follows playback back");
+
+        app.reference_path = Some(dir.join("missing"));
+        app.load_reference();
+        await_reference(&mut app);
+        assert!(app.reference.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn brightness_and_gamma_reach_the_output_and_persist() {
+        let mut app = app(vec![vec![row(1.0, 10)]]);
+        app.sim.advance();
+        let mut settings = app.settings();
+        (settings.brightness, settings.gamma) = (50.0, 2.2);
+        app.apply_settings(settings);
+        app.sim.drivers[0].color = egui::Color32::from_rgb(255, 128, 0);
+        assert_eq!(app.sim.led_colors()[1], [128, 28, 0]);
+        assert_eq!(app.sim.led_colors()[0], [0, 0, 0], "unlit LEDs stay off");
+        let settings = app.settings();
+        assert_eq!((settings.brightness, settings.gamma), (50.0, 2.2));
+    }
+
+    #[test]
+    fn led_size_and_unlit_edge_persist_and_reach_the_track_style() {
+        let mut app = app(vec![vec![row(1.0, 10)]]);
+        let mut settings = app.settings();
+        (settings.led_size, settings.unlit_outline) = (LedSize::Spacing(0.5), Some([40, 40, 40]));
+        app.apply_settings(settings);
+        let style = app.track_style();
+        assert_eq!((style.led_size, style.unlit_outline), (LedSize::Spacing(0.5), Some(egui::Color32::from_gray(40))));
+        assert_eq!(style.spacing.average, 1.0, "the test LEDs are one apart");
+        let settings = app.settings();
+        assert_eq!((settings.led_size, settings.unlit_outline), (LedSize::Spacing(0.5), Some([40, 40, 40])));
+    }
+
+    #[test]
+    fn stepping_back_matches_playing_forward() {
+        let rows = || (1..=6).map(|x| row(x as f64 % 5.0, 10)).collect::<Vec<_>>();
+        let mut stepped = app(vec![rows()]);
+        stepped.sim.trail_length = 2;
+        stepped.set_paused(true);
+        for _ in 0..6 {
+            stepped.step(true);
+        }
+        assert!(stepped.race_complete && stepped.can_step());
+        stepped.step(true);
+        assert_eq!(stepped.sim.current_index, 6);
+
+        stepped.step(false);
+        stepped.step(false);
+        let mut fresh = app(vec![rows()]);
+        fresh.sim.trail_length = 2;
+        for _ in 0..4 {
+            fresh.sim.advance();
+        }
+        assert_eq!(stepped.sim.current_index, 4);
+        assert_eq!(stepped.sim.cars[0].trail, fresh.sim.cars[0].trail);
+        assert!(stepped.paused && !stepped.race_complete);
+
+        for _ in 0..5 {
+            stepped.step(false);
+        }
+        assert_eq!(stepped.sim.current_index, 0);
+        assert!(stepped.sim.cars[0].trail.is_empty());
+    }
+
+    #[test]
+    fn jump_to_time_picks_the_nearest_row_and_clamps() {
+        let rows = |n: usize| (0..n).map(|i| row(1.0, if i == 0 { 0 } else { 100 })).collect::<Vec<_>>();
+        let mut app = app(vec![rows(2), rows(4)]);
+        app.jump_to_time("15:04:22.240").unwrap();
+        assert_eq!(app.sim.current_index, 3);
+        app.jump_to_time("15:04:22.260").unwrap();
+        assert_eq!(app.sim.current_index, 4);
+        assert!(app.jump_to_time("15:04:21").unwrap().contains("before the first record"));
+        assert_eq!(app.sim.current_index, 0);
+        assert!(app.jump_to_time("15:05").unwrap().contains("after the last record"));
+        assert_eq!(app.sim.current_index, 4);
+        assert!(app.jump_to_time("soon").unwrap_err().contains("not a time"));
+    }
+
+    #[test]
+    fn start_at_opens_part_way_through_and_clamps_to_the_data() {
+        let rows = (0..10).map(|i| row(1.0, if i == 0 { 0 } else { 100 })).collect();
+        let mut app = app(vec![rows]);
+        app.start_at(RaceTime::Elapsed(420));
+        assert_eq!((app.sim.current_index, app.race_elapsed_ms()), (5, Some(400)));
+        assert!(app.race_started && !app.paused, "playing on with autostart");
+
+        let time = |text: &str| RaceTime::TimeOfDay(NaiveTime::parse_from_str(text, "%H:%M:%S%.f").unwrap());
+        app.start_at(time("15:04:22.710"));
+        assert_eq!(app.sim.current_index, 8);
+        app.start_at(time("15:04:00.000"));
+        assert_eq!(app.sim.current_index, 0);
+        app.start_at(RaceTime::Elapsed(60_000));
+        assert_eq!(app.sim.current_index, 10);
+        assert!(app.race_complete);
+    }
+
+    #[test]
+    fn offsets_and_bookmarks_seek_by_simulated_time() {
+        let rows = (0..10).map(|i| row(1.0, if i == 0 { 0 } else { 100 })).collect();
+        let mut app = app(vec![rows]);
+        app.jump_to_time("+0.45s").unwrap();
+        assert_eq!((app.sim.current_index, app.sim.sim_elapsed_ms), (5, 400));
+        app.bookmark_label = "pit stop".to_string();
+        app.add_bookmark();
+        app.jump_to_time("-1s").unwrap();
+        assert_eq!(app.sim.current_index, 1);
+        assert!(app.jump_to_time("+ten").is_err());
+
+        app.seek(app.sim.index_at_sim_ms(app.bookmarks[0].sim_ms));
+        assert_eq!(app.sim.sim_elapsed_ms, 400);
+        assert_eq!(app.bookmarks[0].label, "pit stop");
+    }
+
+    #[test]
+    fn clip_frames_sample_playback_at_the_frame_rate() {
+        let rows = (0..5).map(|i| row(i as f64, if i == 0 { 0 } else { 100 })).collect();
+        let mut app = app(vec![rows]);
+        app.seek(3);
+        let frames = app.sample_frames(0, 400, 5, Simulation::lit_leds);
+        let car_leds: Vec<_> = frames.iter().map(|lit| lit.last().unwrap().0).collect();
+        assert_eq!(car_leds, [0, 2, 4]);
+        assert_eq!((app.sim.current_index, app.sim.sim_elapsed_ms), (3, 200));
+        let mut copy = app.sim.clone();
+        assert_eq!(std::thread::spawn(move || sample_clip(&mut copy, 0, 400, 5, 1.0, Simulation::lit_leds)).join().unwrap(), frames, "the same on a copy on another thread");
+        // Twice the speed covers the range in half the frames
+        app.speed = 2.0;
+        assert_eq!(app.sample_frames(0, 400, 5, Simulation::lit_leds).len(), 2);
+        assert_eq!(clip_frame_count(100, 0, 10, 1.0), 1);
+    }
+
+    #[test]
+    fn a_race_clip_of_a_driver_moves_the_camera_after_them_and_leaves_the_view_as_it_was() {
+        let rows = (0..5).map(|i| row(i as f64, 1000)).collect();
+        let mut app = app(vec![rows, vec![row(2.0, 1000)]]);
+        let whole = app.race_clip([50, 10], 1, None);
+        assert_eq!((whole.frames.len(), whole.cameras.len()), (6, 0));
+
+        let clip = app.race_clip([50, 10], 1, Some(0));
+        assert_eq!(clip.frames, whole.frames);
+        assert!(clip.cameras.iter().all(|camera| camera.zoom == FOLLOW_ZOOM));
+        let xs: Vec<_> = clip.cameras.iter().map(|camera| camera.focus.x).collect();
+        assert_eq!(xs[0], xs[1], "on the car at the start");
+        assert!(xs.windows(2).skip(1).all(|pair| pair[0] < pair[1]), "eases after it: {xs:?}");
+        assert_eq!((app.following, app.zoom, app.focus, app.sim.current_index), (None, 1.0, FITTED_FOCUS, 0));
+    }
+
+    #[test]
+    fn repaints_wait_for_the_next_row_and_idle_otherwise() {
+        let rows = (0..3).map(|i| row(1.0, if i == 0 { 0 } else { 400 })).collect();
+        let mut app = app(vec![rows]);
+        app.reset();
+        assert_eq!(app.wake(), (None, "paused"));
+
+        app.start();
+        app.update_playback(); // Shows the first row
+        app.clock.advance_wall(Duration::from_millis(150));
+        assert_eq!(app.wake(), (Some(Duration::from_millis(250)), "next row"));
+        app.speed = 0.01;
+        app.update_playback();
+        assert_eq!(app.wake(), (Some(IDLE_REPAINT), "idle")); // 25 s away
+        app.speed = 1.0;
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_secs(1));
+        assert_eq!(app.wake().0, Some(Duration::ZERO)); // Overdue
+
+        app.set_paused(true);
+        assert_eq!(app.wake(), (None, "paused"), "nothing to redraw until input");
+        app.skipped = Some((1000, app.clock.wall_now() + SKIP_NOTICE));
+        assert_eq!(app.wake(), (Some(IDLE_REPAINT), "idle"), "until the skip notice is gone");
+    }
+
+    #[test]
+    fn long_gaps_are_skipped_after_the_longest_wait() {
+        let rows = [row(0.0, 0), row(1.0, 100), row(2.0, 10_000), row(3.0, 100), row(4.0, 1000)].into();
+        let mut app = app(vec![rows]);
+        (app.skip_gaps, app.max_gap) = (true, Duration::from_millis(500));
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(100));
+        app.update_playback();
+        assert_eq!(app.sim.current_index, 2);
+        assert_eq!(app.wake().0, Some(Duration::from_millis(500)), "wakes up to skip");
+        app.clock.advance_wall(Duration::from_millis(499));
+        app.update_playback();
+        assert_eq!((app.sim.current_index, app.skipped), (2, None));
+
+        app.clock.advance_wall(Duration::from_millis(1));
+        app.update_playback();
+        assert_eq!((app.sim.current_index, app.sim.sim_elapsed_ms), (3, 10_100), "the race clock jumps forward");
+        assert_eq!(app.skipped.map(|(ms, _)| format_skipped(ms)), Some("9.5s".to_string()));
+        app.clock.advance_wall(Duration::from_millis(100));
+        app.update_playback();
+        assert_eq!(app.sim.current_index, 4, "short steps keep their timing");
+
+        app.speed = 2.0;
+        let delays: Vec<_> = app.led_frames().iter().map(|frame| frame.delay_ms).collect();
+        assert_eq!(delays, [0, 50, 500, 50, 500]);
+        assert_eq!(format_skipped(102_000), "1m42s");
+    }
+
+    #[test]
+    fn moving_cars_are_placed_by_the_time_until_the_next_row() {
+        let rows = [row(0.0, 0), row(2.0, 400), row(2.0, 400)].into();
+        let mut app = app(vec![rows]);
+        app.start();
+        app.update_playback(); // Shows the first row
+        app.clock.advance_wall(Duration::from_millis(300));
+        assert_eq!(app.moving_cars(&|_| true), [(0, 2, 0.75, app.sim.drivers[0].color)]);
+        assert!(app.moving_cars(&|_| false).is_empty());
+        assert_eq!(app.wake().0, Some(Duration::from_millis(100))); // Without smooth motion, until the next row
+
+        app.smooth = true;
+        assert_eq!(app.wake(), (Some(app.tick), "smooth motion"));
+        app.sim.advance(); // The next row stays on LED 2
+        assert!(app.moving_cars(&|_| true).is_empty());
+        app.set_paused(true);
+        assert_eq!(app.step_fraction(), None);
+    }
+
+    #[test]
+    fn the_clock_cycles_between_time_of_day_and_time_since_lights_out() {
+        let mut app = app(vec![(0..5).map(|x| row(x as f64, 1000)).collect()]);
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(2500));
+        app.update_playback();
+        let date = app.race_date().unwrap();
+        assert_eq!(app.clock_text(), Some(format!("{} UTC", date.format("%H:%M:%S%.3f"))));
+        app.clock_mode = app.clock_mode.next();
+        assert_eq!(app.clock_text(), Some(date.with_timezone(&chrono::Local).format("%H:%M:%S%.3f").to_string()));
+
+        app.clock_mode = app.clock_mode.next();
+        assert_eq!(app.clock_text().as_deref(), Some("+00:01.500"), "from the first row, between rows");
+        app.set_paused(true);
+        app.clock.advance_wall(Duration::from_secs(1));
+        app.update_playback();
+        assert_eq!(app.clock_text().as_deref(), Some("+00:01.500"), "held while paused");
+        app.set_paused(false);
+        app.speed = 2.0;
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(250));
+        app.update_playback();
+        assert_eq!(app.clock_text().as_deref(), Some("+00:02.000"));
+        app.seek(4);
+        assert_eq!(app.clock_text().as_deref(), Some("+00:03.000"));
+        assert_eq!(app.clock_mode.next(), ClockMode::Utc);
+    }
+
+    #[test]
+    fn a_state_dump_has_the_cars_and_both_ways_of_working_out_the_leds() {
+        let mut app = app(vec![vec![row(1.0, 10), row(2.0, 10)], vec![row(3.0, 20)]]);
+        app.sim.trail_length = 1;
+        while app.sim.advance() {}
+        app.sim.drivers[1].visible = false;
+        let snapshot = app.snapshot();
+        assert_eq!((snapshot.current_index, snapshot.moments, snapshot.leds), (2, 2, 5));
+        assert_eq!(snapshot.lit_leds, snapshot.leds_at);
+        assert_eq!(snapshot.lit_leds.iter().map(|lit| lit.led).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!((snapshot.cars[0].trail.clone(), snapshot.cars[1].visible), (vec![2, 1], false));
+
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-dump", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = snapshot.write(&dir).unwrap();
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("state-"));
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!((json["cars"][1]["key"].as_str(), json["lit_leds"][0]["led"].as_u64()), (Some("driver1"), Some(1)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_completed_lap_flashes_the_start_finish_led() {
+        let mut app = app(vec![[0.0, 1.0, 2.0, 3.0, 0.0, 0.0].map(|x| row(x, 100)).into()]);
+        (app.sim.start_finish_led, app.sim.lap_debounce_leds) = (Some(0), 2);
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(400));
+        app.update_playback();
+        app.update_lap_flash();
+        assert_eq!((app.sim.completed_laps(), app.sim.flash_led), (0, None), "the first crossing only starts lap one");
+
+        app.clock.advance_wall(Duration::from_millis(100));
+        app.update_playback();
+        app.update_lap_flash();
+        assert_eq!((app.sim.completed_laps(), app.sim.flash_led), (1, Some(0)));
+        assert_eq!(app.sim.lit_leds().last(), Some(&(0, egui::Color32::WHITE)));
+        assert_eq!(app.wake(), (Some(LAP_FLASH.min(app.tick)), "lap flash"));
+
+        app.clock.advance_wall(LAP_FLASH);
+        app.update_playback();
+        app.update_lap_flash();
+        assert_eq!((app.sim.completed_laps(), app.sim.flash_led), (1, None), "a car sitting on the line counts once");
+    }
+
+    #[test]
+    fn lap_starts_come_from_the_leader_and_become_bookmarks() {
+        let start = "2023-08-27T15:04:22Z".parse::<DateTime<Utc>>().unwrap();
+        let lap = |leds: &[f64]| {
+            let rows = leds.iter().enumerate().map(|(i, &x_led)| RunRace {
+                date: start + chrono::Duration::milliseconds(100 * i as i64),
+                ..row(x_led, 100)
+            });
+            rows.collect::<Vec<_>>()
+        };
+        let leader = lap(&[1.0, 0.0, 1.0, 2.0, 3.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.0]);
+        let behind = lap(&[2.0, 1.0, 0.0, 1.0, 2.0, 3.0, 4.0, 0.0]);
+        let mut app = app(Vec::new());
+        app.sim.start_finish_led = Some(0);
+        app.sim.lap_debounce_leds = 2;
+        let mut race = LoadedRace::default();
+        let led_index = Arc::clone(&app.sim.led_index);
+        for rows in [leader, behind] {
+            race.drivers.push(Driver::new(Dataset::from_rows(&rows, &led_index, 0), egui::Color32::WHITE));
+        }
+        app.set_race(race);
+        assert!(app.lap_starts.is_empty() && app.wake() == (Some(PROGRESS_REPAINT), "lap starts"), "found on another thread");
+        await_lap_starts(&mut app);
+        // The first row of each dataset has its delay counted too
+        assert_eq!(app.lap_starts, [200, 600, 1100]);
+        assert_eq!((app.sim.current_index, app.total_laps()), (0, 2));
+
+        app.seek(7);
+        assert_eq!(app.sim.leader_lap(), 2);
+        app.add_lap_bookmarks();
+        app.add_lap_bookmarks();
+        let labels: Vec<_> = app.bookmarks.iter().map(|bookmark| (bookmark.sim_ms, bookmark.label.as_str())).collect();
+        assert_eq!(labels, [(200, "Lap 1"), (600, "Lap 2"), (1100, "Finish")]);
+    }
+
+    #[test]
+    fn loading_and_exporting_a_race_log_no_moves() {
+        let mut app = app(vec![(0..10).map(|i| row((i % 5) as f64, 100)).collect()]);
+        (app.sim.start_finish_led, app.sim.lap_debounce_leds) = (Some(0), 2);
+        let mut race = LoadedRace::default();
+        race.drivers.push(Driver::new(app.sim.drivers[0].records.clone(), egui::Color32::WHITE));
+        app.set_race(race);
+        await_lap_starts(&mut app);
+        assert_eq!(app.lap_starts.len(), 2);
+        app.led_frames();
+        sample_clip(&mut app.sim.clone(), 0, 1000, 10, 1.0, Simulation::lit_leds);
+        app.activity.poll(app.sim.session_start(), &[]);
+        assert!(app.activity.entries().is_empty(), "{:?}", app.activity.text(|_| true));
+
+        app.step(true);
+        app.activity.poll(app.sim.session_start(), &[]);
+        assert_eq!(app.activity.entries().len(), 1, "playing still logs");
+    }
+
+    #[test]
+    fn interpolation_lights_the_led_between_two_rows() {
+        let rows = [row(0.0, 0), row(0.0, 400), row(4.0, 400)].into();
+        let mut app = app(vec![rows]);
+        app.sim.trail_length = 1;
+        app.seek(2);
+        app.update_playback();
+        app.clock.advance_wall(Duration::from_millis(200));
+        app.interpolate_cars();
+        assert!(app.sim.interpolated.is_empty(), "off by default");
+
+        app.interpolate = true;
+        app.interpolate_cars();
+        assert_eq!(app.sim.interpolated, [Some(2)]);
+        let leds: Vec<_> = app.sim.lit_leds().iter().map(|&(led, _)| led).collect();
+        assert_eq!(leds, [0, 2]); // Oldest first
+        app.sim.advance();
+        assert!(app.sim.interpolated.is_empty());
+        assert_eq!(app.sim.lit_leds().last().unwrap().0, 4);
+    }
+
+    #[test]
+    fn races_with_other_drivers_get_a_new_recording_and_log_header() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-app-new-drivers", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (record_path, log_path) = (dir.join("race.jsonl"), dir.join("events.csv"));
+        let mut app = app(vec![vec![row(1.0, 0), row(2.0, 100)]]);
+        (app.record_path, app.event_log_path) = (Some(record_path.clone()), Some(log_path.clone()));
+        let race = |app: &PlotApp, key: &str| {
+            let drivers = app.sim.drivers.iter().map(|driver| Driver { key: key.to_string(), ..driver.clone() }).collect();
+            LoadedRace { drivers, ..Default::default() }
+        };
+        for key in ["driver0", "driver0", "driver9"] {
+            app.set_race(race(&app, key));
+            app.log_events();
+            app.sim.advance();
+            app.record_frame();
+            app.log_events();
+        }
+        (app.recorder, app.event_log) = (None, None); // Flushes
+
+        let headers = std::fs::read_to_string(&record_path).unwrap().lines().filter(|line| line.starts_with('{')).count();
+        assert_eq!((headers, Recording::read(&record_path).unwrap().events.len()), (1, 1), "the same drivers go on into the same recording");
+        let recording = Recording::read(&recording::numbered(&record_path, 2)).unwrap();
+        assert_eq!((recording.header.drivers[0].key.as_str(), recording.events.len()), ("driver9", 1));
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert_eq!(log, "sim_ms,index,driver0\n0,1,1\n0,1,1\nsim_ms,index,driver9\n0,1,1\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recorded_leds_replay_onto_the_cars() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-main-recording.jsonl", std::process::id()));
+        let rows = [row(1.0, 0), row(3.0, 100)].into();
+        let mut live = app(vec![rows, Vec::new()]);
+        live.record_path = Some(path.clone());
+        live.start_recording();
+        live.sim.advance();
+        live.record_frame();
+        live.sim.drivers[0].visible = false;
+        live.record_frame();
+        let reloaded = LoadedRace { drivers: live.sim.drivers.clone(), ..Default::default() };
+        live.set_race(reloaded);
+        live.sim.advance();
+        live.record_frame();
+        live.recorder = None; // Flushes
+        let recording = Recording::read(&path).unwrap();
+        assert_eq!(recording.header.drivers[1].code, "D1");
+        let events: Vec<_> = recording.events.iter().map(|&Event(_, driver, led)| (driver, led)).collect();
+        assert_eq!(events, [(0, Some(1)), (0, None), (0, Some(1))], "a reload goes on into the same recording");
+
+        let mut app = app(vec![Vec::new()]);
+        app.sim.trail_length = 1;
+        app.replay = Some(Replay::new(vec![Event(0, 0, Some(2)), Event(0, 0, Some(4)), Event(3_600_000, 0, None)]));
+        app.apply_replay();
+        assert_eq!(current_leds(&app), [Some(4)]);
+        assert_eq!(app.sim.lit_leds().len(), 2);
+        assert!(app.wake().0.is_some_and(|wait| wait <= IDLE_REPAINT));
+        app.reset();
+        app.apply_replay();
+        assert_eq!(current_leds(&app), [None]);
+        app.start();
+        app.apply_replay();
+        assert_eq!(current_leds(&app), [Some(4)]);
+    }
+
+    #[test]
+    fn car_speed_skips_samples_with_the_same_timestamp() {
+        let mut app = app(vec![vec![row(0.0, 0), row(3.0, 500), row(3.0, 250), row(1.0, 250), row(2.0, 0)]]);
+        app.sim.advance();
+        assert_eq!(app.car_speed(0), None);
+        app.sim.advance();
+        assert_eq!(app.car_speed(0), Some(6.0));
+        app.sim.advance();
+        assert_eq!(app.car_speed(0), Some(0.0), "standing still between its last two rows");
+        app.sim.advance(); // Its last two rows share a timestamp and are shown together
+        assert_eq!(app.car_speed(0), None);
+
+        app.seek(2);
+        assert_eq!(app.car_speed(0), Some(6.0));
+    }
+
+    #[test]
+    fn the_step_inspector_shows_the_file_row_behind_the_last_step() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-inspect", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_albon_start.csv");
+        let rows = "date,x_led,y_led,time_delta\r\n2023-08-27T12:01:00Z,0.2,0,0\r\n2023-08-27T12:01:01Z,3.9,0.1,1000\r\n";
+        std::fs::write(&path, rows).unwrap();
+        let mut app = app(Vec::new());
+        app.dataset_paths = vec![path.clone()];
+        reload(&mut app);
+        app.start();
+        app.set_paused(true);
+        // The rows are read again on another thread
+        let update_stepped = |app: &mut PlotApp| {
+            app.update_stepped();
+            while app.stepped_reads.is_some() {
+                std::thread::sleep(Duration::from_millis(1));
+                app.update_stepped();
+            }
+        };
+        update_stepped(&mut app);
+        assert!(app.stepped.as_ref().unwrap().1.is_empty());
+        app.step(true);
+        app.update_stepped();
+        assert_eq!(app.stepped.as_ref().unwrap().1[0].line, 2, "listed before the file is read");
+        update_stepped(&mut app);
+        let first = &app.stepped.as_ref().unwrap().1[0];
+        assert_eq!((first.line, first.position, first.led), (2, Some((0.2, 0.0)), Some(0)));
+
+        app.step(true);
+        update_stepped(&mut app);
+        let (index, rows) = app.stepped.as_ref().unwrap();
+        assert_eq!((*index, rows.len()), (2, 1));
+        assert_eq!((rows[0].dataset_idx, rows[0].line, rows[0].position, rows[0].led), (0, 3, Some((3.9, 0.1)), Some(4)));
+        assert_eq!(rows[0].date, Some("2023-08-27T12:01:01Z".parse().unwrap()));
+        assert!(app.race_complete, "stepping past the end does nothing");
+        app.step(true);
+        assert_eq!(app.sim.current_index, 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_strict_start_with_data_problems_lists_them_instead_of_playing() {
+        let mut app = app(Vec::new());
+        let path = std::env::temp_dir().join(format!("f1-led-{}-strict-missing.csv", std::process::id()));
+        let race = PendingRace::start(vec![path], Vec::new(), app.sim.led_index.clone(), app.read_options);
+        app.startup = Some(Startup { race, strict: true, autostart: true, settings: None, session: None, start_at: None, race_dir: None });
+        app.startup.as_mut().unwrap().race.wait();
+        app.finish_loading();
+        let (error, issues) = app.startup_failed.as_ref().unwrap();
+        assert_eq!((error.as_str(), issues.len()), ("Data problems found and --strict is set", 1));
+        assert!(issues[0].contains("strict-missing.csv"), "{issues:?}");
+    }
+
+    #[test]
+    fn a_start_time_past_the_race_is_shown_instead_of_playing() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-trim-past", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_albon_start.csv");
+        std::fs::write(&path, "date,x_led,y_led,time_delta\n2023-08-27T12:01:00Z,1,0,0\n2023-08-27T12:01:01Z,2,0,1000\n").unwrap();
+        let mut app = app(Vec::new());
+        app.trim_times = (Some(RaceTime::Elapsed(60_000)), None);
+        let race = PendingRace::start(vec![path], Vec::new(), app.sim.led_index.clone(), app.read_options);
+        app.startup = Some(Startup { race, strict: false, autostart: true, settings: None, session: None, start_at: None, race_dir: None });
+        app.startup.as_mut().unwrap().race.wait();
+        app.finish_loading();
+        let (error, issues) = app.startup_failed.as_ref().unwrap();
+        assert!(error.starts_with("start_time ") && issues.is_empty(), "{error}");
+        assert!(app.sim.drivers.is_empty(), "not played");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_new_snap_distance_matches_the_race_again_and_is_kept_in_the_settings() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-snap", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_albon_start.csv");
+        // Half a LED off the line, then far above it
+        let rows = "date,x_led,y_led,time_delta\n2023-08-27T12:01:00Z,1,0.5,0\n2023-08-27T12:01:01Z,2,40,1000\n";
+        std::fs::write(&path, rows).unwrap();
+        let mut loading = app(Vec::new());
+        let mut app = app(Vec::new());
+        app.dataset_paths = vec![path.clone()];
+        reload(&mut app);
+        app.seek(2);
+        assert_eq!((current_leds(&app), app.sim.off_map_cars()), (vec![Some(2)], 0));
+
+        // Matched again on other threads
+        let snap = |app: &mut PlotApp| {
+            while app.snapping.is_some() {
+                std::thread::sleep(Duration::from_millis(1));
+                app.finish_snapping();
+            }
+        };
+        app.set_snap_distance(Some(1.0));
+        assert_eq!((app.snap_distance, app.snap_target(), app.wake().1), (None, Some(1.0), "snapping"));
+        snap(&mut app);
+        assert_eq!(app.status.as_deref(), Some("Snapping within 1.00: 1 of 2 rows off the map"));
+        assert_eq!((app.sim.current_index, current_leds(&app), app.sim.off_map_cars()), (2, vec![None], 1));
+        assert_eq!(app.settings().snap_distance, Some(1.0));
+        let mut settings = app.settings();
+        settings.snap_distance = None;
+        app.apply_settings(settings);
+        snap(&mut app);
+        assert_eq!((app.snap_distance, app.sim.off_map_cars()), (None, 0));
+
+        // Saved settings read while the race loads match it with their distance as it is read
+        let race = PendingRace::start(vec![path.clone()], Vec::new(), loading.sim.led_index.clone(), loading.read_options);
+        loading.startup = Some(Startup { race, strict: false, autostart: false, settings: None, session: None, start_at: None, race_dir: None });
+        let mut settings = loading.settings();
+        settings.snap_distance = Some(1.0);
+        loading.restore_settings(settings);
+        loading.startup.as_mut().unwrap().race.wait();
+        loading.finish_loading();
+        assert!(loading.snapping.is_none(), "not matched again");
+        assert_eq!((loading.snap_distance, loading.sim.drivers[0].records.get(1).unwrap().on_led()), (Some(1.0), None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reloading_keeps_the_position_and_keeps_the_old_data_on_errors() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-reload", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_albon_start.csv");
+        let write = |xs: [f64; 4]| {
+            let rows: String = xs
+                .iter()
+                .enumerate()
+                .map(|(i, x)| format!("0,0,0,2023-08-27T12:01:0{i}.000000+00:00,U{i},{x},0,1000\n"))
+                .collect();
+            std::fs::write(&path, format!("x,y,z,date,designator,x_led,y_led,time_delta\n{rows}")).unwrap();
+        };
+        write([0.0, 1.0, 2.0, 3.0]);
+        let mut live = app(Vec::new());
+        live.dataset_paths = vec![path.clone()];
+        reload(&mut live);
+        live.seek(3);
+        live.sim.drivers[0].visible = false;
+
+        write([4.0, 3.0, 1.0, 0.0]);
+        live.reload_datasets("Reloaded".to_string());
+        assert_eq!((live.wake().1, current_leds(&live)), ("reloading", vec![Some(2)]), "the old data plays on while it is read");
+        assert_eq!(reloaded(&mut live), "Reloaded");
+        assert_eq!(live.sim.current_index, 3);
+        assert_eq!(current_leds(&live), [Some(1)]);
+        assert_eq!(visible(&live), [false]);
+
+        // A loaded driver's file is read again on its own
+        write([0.0, 2.0, 3.0, 4.0]);
+        let finish = |live: &mut PlotApp| {
+            live.reload_files(vec![path.clone()]);
+            while !live.reloads.is_empty() {
+                std::thread::sleep(Duration::from_millis(10));
+                live.finish_reloads();
+            }
+        };
+        finish(&mut live);
+        assert_eq!(live.status.as_deref(), Some("Reloaded time_delta_albon_start.csv (4 rows)"));
+        assert_eq!((live.sim.current_index, current_leds(&live)), (3, vec![Some(3)]));
+
+        std::fs::remove_file(&path).unwrap();
+        finish(&mut live);
+        assert!(live.status.as_deref().unwrap().starts_with("Kept the previous data"));
+        assert_eq!((live.sim.drivers[0].records.len(), current_leds(&live)), (4, vec![Some(3)]));
+        let status = reload(&mut live);
+        assert!(status.starts_with("Kept the previous data") && status.contains("time_delta_albon_start.csv"), "{status}");
+        assert_eq!((live.sim.drivers[0].records.len(), live.sim.current_index), (4, 3));
+        assert_eq!([0, 999, 4211, 1234567].map(format_count), ["0", "999", "4,211", "1,234,567"]);
+    }
+
+    #[test]
+    fn a_trimmed_race_plays_only_its_window_and_stays_trimmed_when_reloaded() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-trim", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_albon_start.csv");
+        let write = |xs: [f64; 5]| {
+            let rows: String = xs
+                .iter()
+                .enumerate()
+                .map(|(i, x)| format!("0,0,0,2023-08-27T12:01:0{i}.000000+00:00,U{i},{x},0,1000\n"))
+                .collect();
+            std::fs::write(&path, format!("x,y,z,date,designator,x_led,y_led,time_delta\n{rows}")).unwrap();
+        };
+        write([0.0, 1.0, 2.0, 3.0, 4.0]);
+        let mut app = app(Vec::new());
+        app.dataset_paths = vec![path.clone()];
+        app.trim_times = (Some(RaceTime::Elapsed(1_000)), Some(RaceTime::Elapsed(3_000)));
+        let mut race = loader::load_datasets(&app.dataset_paths, &app.sim.led_index, app.read_options, app.palette, &app.driver_codes);
+        app.trim_race(&mut race).unwrap();
+        app.reload_race(race);
+        assert_eq!(app.sim.moments(), 3);
+        app.seek(1);
+        assert_eq!((current_leds(&app), app.sim.sim_elapsed_ms), (vec![Some(1)], 1000), "a second after the row before the window");
+
+        write([4.0, 3.0, 2.0, 1.0, 0.0]);
+        reload(&mut app);
+        assert_eq!(app.sim.moments(), 3);
+        app.seek(3);
+        assert_eq!(current_leds(&app), [Some(1)]);
+
+        app.trim_times = (Some(RaceTime::Elapsed(3_000)), Some(RaceTime::Elapsed(1_000)));
+        let mut race = loader::load_datasets(&app.dataset_paths, &app.sim.led_index, app.read_options, app.palette, &app.driver_codes);
+        assert!(app.trim_race(&mut race).unwrap_err().contains("is not before"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cars_wait_on_the_grid_until_the_start_lights_go_out() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-app-grid.csv", std::process::id()));
+        std::fs::write(&path, "driver,led\ndriver1,3\n").unwrap();
+        let mut app = app(vec![vec![row(1.0, 100), row(2.0, 100)], vec![row(0.0, 100)]]);
+        (app.grid, app.start_lights) = (Grid::read(&path, 5).unwrap().0, Duration::from_secs(2));
+        assert_eq!(app.grid_leds(), [(1, 3)], "only the driver on the grid is parked");
+
+        app.start();
+        app.clock.advance_wall(Duration::from_millis(1500));
+        app.update_playback();
+        assert_eq!(app.sim.current_index, 0, "held while the lights are on");
+        assert_eq!(app.wake(), (Some(Duration::from_millis(100)), "start lights")); // The fourth light at 1.6 s
+        app.clock.advance_wall(Duration::from_millis(500));
+        app.update_playback();
+        assert_eq!((app.lights_since, app.sim.current_index, app.race_elapsed_ms()), (None, 1, Some(0)), "lights out is the first row");
+        assert_eq!(current_leds(&app), [Some(1), Some(0)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn excluding_leds_moves_the_grid_and_sectors_with_them() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-app-grid-excluded.csv", std::process::id()));
+        std::fs::write(&path, "driver,led\ndriver1,3\ndriver2,2\n").unwrap();
+        let mut app = app(Vec::new());
+        app.sim.coordinates[2].x_led = 1000.0;
+        app.grid = Grid::read(&path, 5).unwrap().0;
+        app.sectors = Sectors::from_leds(&[vec![0, 1], vec![3, 4]], 5).0;
+        app.sim.start_finish_led = Some(4);
+        app.exclude_outliers();
+        assert_eq!(reloaded(&mut app), "Excluded 1 LEDs");
+        assert_eq!(app.sim.coordinates.len(), 4);
+        assert_eq!(app.sim.start_finish_led, Some(3));
+        assert_eq!(app.grid.led_of("driver1", "", &app.sim.led_index), Some(2), "the slot moves down with its LED");
+        assert_eq!(app.grid.led_of("driver2", "", &app.sim.led_index), None, "the slot on the excluded LED is gone");
+        assert_eq!((0..5).map(|led| app.sectors.sector(led)).collect::<Vec<_>>(), [Some(1), Some(1), Some(2), Some(2), None]);
+        assert!(app.sectors.boundaries.iter().all(|&(from, to)| from < 4 && to < 4));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_race_runs_until_the_longest_dataset_ends_not_the_first() {
+        let mut app = app(vec![vec![row(0.0, 100)], vec![row(1.0, 100), row(2.0, 100), row(3.0, 100)]]);
+        app.start();
+        app.clock.advance_wall(Duration::from_millis(150));
+        app.update_playback();
+        assert!(app.sim.cars[0].rows == 1 && !app.race_complete, "the first dataset is done, the second is not");
+        app.clock.advance_wall(Duration::from_secs(1));
+        app.update_playback();
+        assert!(app.race_complete);
+        assert_eq!(current_leds(&app), [Some(0), Some(3)]);
+    }
+
+    #[test]
+    fn pausing_and_changing_speed_never_reorder_the_moves() {
+        let race = || {
+            app(vec![
+                vec![row(4.0, 100), row(3.0, 2000)],
+                (0..12).map(|i| row((i % 5) as f64, 300)).collect(),
+                (0..8).map(|i| row((i * 2 % 5) as f64, 450)).collect(),
+            ])
+        };
+        let mut straight = race();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        straight.sim.send_transitions(Some(sender));
+        while straight.sim.advance() {}
+        let expected: Vec<_> = receiver.try_iter().collect();
+
+        // Whether to pause or play, the speed to change to if any and how long until the next frame
+        let frames = vec((prop::bool::weighted(0.2), prop::option::weighted(0.2, 0..5usize), 0..400u64), 1..200);
+        fixtures::check(16, frames, |frames| {
+            let mut app = race();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            app.sim.send_transitions(Some(sender));
+            app.start();
+            for (toggle, speed, wall_ms) in frames {
+                if toggle {
+                    app.set_paused(!app.paused);
+                }
+                if let Some(speed) = speed {
+                    app.speed = [0.25, 0.5, 1.0, 2.0, 8.0][speed];
+                }
+                app.clock.advance_wall(Duration::from_millis(wall_ms));
+                app.update_playback();
+            }
+            app.set_paused(false);
+            while !app.race_complete {
+                app.clock.advance_wall(Duration::from_millis(100));
+                app.update_playback();
+            }
+            prop_assert_eq!(receiver.try_iter().collect::<Vec<_>>(), expected.clone());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn the_countdown_holds_a_race_without_a_grid_until_lights_out() {
+        let mut app = app(vec![vec![row(1.0, 100), row(2.0, 100)]]);
+        (app.countdown, app.start_lights) = (true, Duration::from_secs(1));
+        app.start();
+        app.clock.advance_wall(Duration::from_millis(250));
+        app.update_playback();
+        assert_eq!((app.sim.current_index, app.race_elapsed_ms()), (0, Some(0)), "the race clock waits");
+        assert_eq!(app.wake().1, "start lights");
+        app.clock.advance_wall(Duration::from_millis(750));
+        app.update_playback();
+        assert_eq!((app.lights_since.is_none(), app.sim.current_index), (true, 1));
+        assert_eq!(app.wake(), (Some(app.tick), "lights out"));
+    }
+
+    #[test]
+    fn the_playlist_unloads_a_race_before_playing_the_next() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-playlist", std::process::id()));
+        std::fs::create_dir_all(dir.join("spa")).unwrap();
+        let coordinates = dir.join("spa.csv");
+        std::fs::write(&coordinates, "x_led,y_led\n0,0\n1,0\n2,0\n").unwrap();
+        let rows = "date,x_led,y_led,time_delta\n2023-07-30T13:00:00Z,2,0,0\n";
+        std::fs::write(dir.join("spa").join("time_delta_piastri_start.csv"), rows).unwrap();
+        let entry = |title: &str, data_dir: PathBuf| PlaylistEntry { title: title.to_string(), coordinates: coordinates.clone(), data_dir, max_duration: None };
+
+        let mut app = app(vec![vec![row(1.0, 10)]]);
+        app.playlist = Some(Playlist::new(vec![entry("Monza", dir.join("monza")), entry("Spa", dir.join("spa"))]));
+        app.switch_playlist(1);
+        assert!(app.playlist.as_ref().unwrap().is_switching());
+        assert!(app.sim.drivers.is_empty() && app.sim.cars.is_empty(), "the race playing is let go of first");
+
+        app.play_entry(read_playlist_entry(&entry("Spa", dir.join("spa")), app.read_options, app.palette, &BTreeMap::new(), None));
+        assert_eq!((app.sim.coordinates.len(), keys(&app), app.race_started), (3, vec!["piastri".to_string()], true));
+        app.sim.advance();
+        assert_eq!(current_leds(&app), [Some(2)]);
+
+        let missing = read_playlist_entry(&entry("Monza", dir.join("monza")), app.read_options, app.palette, &BTreeMap::new(), None);
+        app.switch_playlist(1);
+        app.play_entry(missing);
+        assert!(app.status.as_deref().is_some_and(|status| status.starts_with("Skipped Monza")));
+        assert_eq!(app.playlist.as_ref().unwrap().entry().title, "Spa", "goes on to the next race");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_setup_screen_opens_the_chosen_files_and_remembers_them() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-setup-app", std::process::id()));
+        std::fs::create_dir_all(dir.join("monza")).unwrap();
+        let coordinates = dir.join("track.csv");
+        std::fs::write(&coordinates, "x_led,y_led\n0,0\n1,0\n2,0\n3,0\n4,0\n5,0\n").unwrap();
+        let rows = "date,x_led,y_led,time_delta\n2023-09-03T13:00:00Z,5,0,0\n2023-09-03T13:00:01Z,4,0,1000\n";
+        std::fs::write(dir.join("monza").join("time_delta_sainz_start.csv"), rows).unwrap();
+
+        let mut app = app(vec![vec![row(1.0, 10)]]);
+        app.open_setup();
+        let setup = app.setup.as_mut().unwrap();
+        assert!(setup.cancellable);
+        setup.set_coordinates(dir.join("missing.csv"));
+        setup.set_data_dir(dir.clone());
+        assert_eq!((setup.race.as_deref(), setup.drivers.clone()), (Some("monza"), Ok(1)));
+        assert!(!setup.is_ready());
+        app.start_setup();
+        assert!(app.setup.is_some(), "nothing to start from yet");
+        assert_eq!(keys(&app), ["driver0"]);
+
+        app.setup.as_mut().unwrap().set_coordinates(coordinates.clone());
+        app.setup.as_mut().unwrap().error = None;
+        app.start_setup();
+        assert!(app.setup.is_none());
+        assert!(app.startup.is_some() && keys(&app) == ["driver0"], "on the loading screen until the race is read");
+        loaded(&mut app);
+        assert_eq!((app.sim.coordinates.len(), keys(&app), app.selected_race.as_deref()), (6, vec!["sainz".to_string()], Some("monza")));
+        assert_eq!(app.races, ["monza"]);
+        app.sim.advance();
+        assert_eq!(current_leds(&app), [Some(5)]);
+        let settings = app.settings();
+        assert_eq!((settings.coordinates, settings.data_dir), (Some(coordinates), Some(dir.clone())));
+
+        app.setup = Some(Setup::new(dir.join("missing.csv"), dir.clone(), None, None));
+        app.start_setup();
+        assert!(app.setup.as_ref().unwrap().error.as_deref().unwrap().contains("missing.csv"));
+        assert_eq!(app.sim.coordinates.len(), 6, "a failed start keeps what was loaded");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flags_follow_the_row_shown_when_seeking_back_and_forth() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-race-events.csv", std::process::id()));
+        std::fs::write(&path, "date,kind,message\n2023-08-27T15:04:23Z,SC,\n2023-08-27T15:04:25Z,GREEN,\n").unwrap();
+        let mut app = app(vec![(0..5).map(|x| row(x as f64, 1000)).collect()]);
+        app.events = RaceEvents::read(&path).unwrap();
+        let tint = events::Flag::SafetyCar.outline_tint().unwrap();
+
+        app.seek(2); // 15:04:23
+        assert_eq!(app.current_outline_color(), tint);
+        app.seek(4);
+        assert_eq!(app.current_outline_color(), app.outline_color);
+        app.seek(3);
+        assert_eq!(app.current_outline_color(), tint);
+        app.seek(1);
+        assert_eq!(app.current_outline_color(), app.outline_color);
+    }
+
+    #[test]
+    fn led_frames_skip_repeats_and_keep_the_total_time() {
+        let mut app = app(vec![vec![row(1.0, 100), row(1.0, 100), row(3.0, 101), row(3.0, 0), row(0.0, 99)]]);
+        app.speed = 2.0;
+        app.seek(2);
+        let frames = app.led_frames();
+        let delays: Vec<_> = frames.iter().map(|frame| frame.delay_ms).collect();
+        assert_eq!(delays, [50, 101, 49]); // 400 ms at 2x
+        let lit: Vec<_> = frames.iter().map(|frame| frame.rgb.chunks(3).position(|rgb| rgb != [0, 0, 0])).collect();
+        assert_eq!(lit, [Some(1), Some(3), Some(0)]);
+        assert!(frames.iter().all(|frame| frame.rgb.len() == 5 * 3));
+        assert_eq!(app.sim.current_index, 2);
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// File name bookmarks are saved under, next to the race data.
+pub const BOOKMARKS_FILE: &str = "bookmarks.json";
+
+/// A moment of the race to come back to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// Simulated time since the race started.
+    pub sim_ms: u64,
+    #[serde(default)]
+    pub label: String,
+}
+
+pub fn read(path: &Path) -> Result<Vec<Bookmark>, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read bookmarks {}: {e}", path.display()))?;
+    let mut bookmarks: Vec<Bookmark> =
+        serde_json::from_str(&text).map_err(|e| format!("invalid bookmarks {}: {e}", path.display()))?;
+    bookmarks.sort_by_key(|bookmark| bookmark.sim_ms);
+    Ok(bookmarks)
+}
+
+pub fn write(path: &Path, bookmarks: &[Bookmark]) -> Result<(), Box<dyn Error>> {
+    let text = serde_json::to_string_pretty(bookmarks)?;
+    fs::write(path, text).map_err(|e| format!("cannot write bookmarks {}: {e}", path.display()))?;
+    Ok(())
+}
+
+/// Formats a simulated time as `m:ss.fff`.
+pub fn format_sim_ms(ms: u64) -> String {
+    format!("{}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
@@ -0,0 +1,249 @@
+use clap::Parser;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::heatmap::Gradient;
+
+/// Replays recorded F1 car positions on a simulated LED circuit.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML config file with the coordinate file, dataset list and playback defaults
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Directory prepended to every relative CSV path
+    #[arg(long, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Subfolder of the data directory to scan for `time_delta_*_start.csv` (or `.tsv`, `.csv.gz`) files
+    #[arg(long, value_name = "NAME")]
+    pub race: Option<String>,
+
+    /// Folder of another race on the same LEDs, played faintly behind this one and lined up by time since each race's first row
+    #[arg(long, value_name = "DIR")]
+    pub reference: Option<PathBuf>,
+
+    /// Playback speed multiplier (1.0 = recorded pace)
+    #[arg(long, value_name = "F64", value_parser = parse_speed)]
+    pub speed: Option<f64>,
+
+    /// Start the race as soon as the window opens
+    #[arg(long)]
+    pub autostart: bool,
+
+    /// Restart the race after the data runs out, forever; combine with --autostart for unattended displays
+    #[arg(long = "loop")]
+    pub looping: bool,
+
+    /// Seconds the final state stays on screen before a loop restarts
+    #[arg(long, value_name = "SECS")]
+    pub loop_pause: Option<f64>,
+
+    /// Frames per second drawn while cars move smoothly between LEDs, 60 by default
+    #[arg(long, value_name = "FPS")]
+    pub tick_rate: Option<f64>,
+
+    /// Never wait more than SECS of wall time for the next row; longer idle stretches are skipped
+    #[arg(long, value_name = "SECS")]
+    pub max_gap: Option<f64>,
+
+    /// Keep the playback state every SECS of race time so seeking back is quick; 0 keeps none
+    #[arg(long, value_name = "SECS")]
+    pub checkpoint_interval: Option<f64>,
+
+    /// Read race data from disk as playback reaches it instead of loading it all up front
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Show rows of a driver at least MS apart, so rows with no time_delta do not jump the car ahead; 0 keeps them as recorded
+    #[arg(long, value_name = "MS")]
+    pub min_step_ms: Option<u32>,
+
+    /// Update each car at most HZ times a second while it stays on one LED; rows moving it to another LED are always kept
+    #[arg(long, value_name = "HZ")]
+    pub max_rate: Option<f64>,
+
+    /// Take a car off the map while it is more than DISTANCE coordinate units from every LED, e.g. in an unmapped pit lane
+    #[arg(long, value_name = "DISTANCE")]
+    pub snap_distance: Option<f64>,
+
+    /// Real-world metres per coordinate unit, to show speeds in km/h and gaps in metres
+    #[arg(long, value_name = "M", alias = "meters-per-unit")]
+    pub metres_per_unit: Option<f64>,
+
+    /// Play race data exactly as exported, without sorting out-of-order rows or dropping repeated dates
+    #[arg(long)]
+    pub no_repair: bool,
+
+    /// Drop rows dated no later than a row above them instead of sorting them into place, listing their lines
+    #[arg(long)]
+    pub drop_out_of_order: bool,
+
+    /// Field separator of the coordinates and race data files, e.g. `;` or `tab`; .tsv files are read as tab separated without it
+    #[arg(long, value_name = "CHAR", value_parser = parse_delimiter)]
+    pub delimiter: Option<char>,
+
+    /// Open the race at TIME: since the first row like `5m30s`, a time of day like `15:04:22.500` or a date; with --autostart it plays from there
+    #[arg(long, value_name = "TIME")]
+    pub start_at: Option<String>,
+
+    /// Leave out every row before TIME, given as for --start-at, so playback, the scrubber and exports cover only what is left
+    #[arg(long, value_name = "TIME")]
+    pub start_time: Option<String>,
+
+    /// Leave out every row after TIME, given as for --start-at
+    #[arg(long, value_name = "TIME")]
+    pub end_time: Option<String>,
+
+    /// Refuse to start if any input file has problems
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Stay quiet when a car completes a lap instead of ringing the terminal bell
+    #[arg(long)]
+    pub no_sound: bool,
+
+    /// Count down with the five red start lights before every start, also for a race without a grid
+    #[arg(long)]
+    pub countdown: bool,
+
+    /// Reload the coordinates and race data files whenever they change on disk
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Window size, in points, instead of the one the window had last time
+    #[arg(long, value_name = "WxH", value_parser = parse_window_size)]
+    pub window_size: Option<[u32; 2]>,
+
+    /// Open fullscreen, e.g. on a projector
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Open without a title bar or borders
+    #[arg(long)]
+    pub borderless: bool,
+
+    /// Open on the monitor N, counted from 0 left to right
+    #[arg(long, value_name = "N")]
+    pub monitor: Option<usize>,
+
+    /// Keep the window above the other windows
+    #[arg(long)]
+    pub always_on_top: bool,
+
+    /// Hide the mouse pointer once it has not moved for SECS seconds
+    #[arg(long, value_name = "SECS")]
+    pub hide_cursor_after: Option<f64>,
+
+    /// Open fullscreen and keep it so: F11 and Escape do not leave it, for a display left running as signage
+    #[arg(long)]
+    pub kiosk: bool,
+
+    /// Record what the track shows to a replay file as it plays
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Write a CSV row to PATH each time playback moves on, with the simulated time, the timeline index and every car's LED
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub event_log: Option<PathBuf>,
+
+    /// Write a line to PATH, or to stdout for -, for every car moving between LEDs, flag change, reload and output error,
+    /// also while exporting without the window
+    #[arg(long, value_name = "PATH")]
+    pub activity_log: Option<PathBuf>,
+
+    /// Read the coordinates and every dataset as they would load, print what each holds and its problems, and exit
+    /// without opening the window; the exit code is 1 if a file cannot be used, or with --strict has any problem
+    #[arg(long, conflicts_with_all = ["replay", "export_frames", "heatmap", "export_gif"])]
+    pub validate: bool,
+
+    /// Write every LED frame of the race to PATH and exit without opening the window,
+    /// as JSON if PATH ends in .json and compact binary otherwise
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub export_frames: Option<PathBuf>,
+
+    /// Write a PNG of how often each LED had a car on it, over every row of every driver, to PATH and exit
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["replay", "export_frames"])]
+    pub heatmap: Option<PathBuf>,
+
+    /// Colors of the heatmap from least to most visited: heat, ice or gray
+    #[arg(long, value_name = "NAME", value_parser = parse_gradient)]
+    pub heatmap_gradient: Option<Gradient>,
+
+    /// Size of the heatmap image in pixels
+    #[arg(long, value_name = "WxH", value_parser = parse_window_size)]
+    pub heatmap_size: Option<[u32; 2]>,
+
+    /// Write a GIF of the whole race to PATH and exit without opening the window
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["replay", "export_frames", "heatmap"])]
+    pub export_gif: Option<PathBuf>,
+
+    /// With --export-gif, treat PATH as a folder and write one GIF per driver into it, named by the driver,
+    /// the camera following them
+    #[arg(long, requires = "export_gif")]
+    pub per_driver: bool,
+
+    /// Frames a second of the GIF
+    #[arg(long, value_name = "FPS")]
+    pub gif_fps: Option<u32>,
+
+    /// Size of the GIF in pixels
+    #[arg(long, value_name = "WxH", value_parser = parse_window_size)]
+    pub gif_size: Option<[u32; 2]>,
+
+    /// LEDs kept lit behind each car
+    #[arg(long, value_name = "N")]
+    pub trail_length: Option<usize>,
+
+    /// Play a file written with --record instead of loading race data
+    #[arg(long, value_name = "PATH", conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+
+    /// Serve the LED colors to WebSocket clients on ADDR, e.g. 0.0.0.0:9001: every LED as JSON on connecting,
+    /// then the ones that change
+    #[arg(long, value_name = "ADDR")]
+    pub serve: Option<SocketAddr>,
+
+    /// Reopen a session saved with the Save button; Save writes back to it
+    #[arg(long, value_name = "PATH")]
+    pub resume: Option<PathBuf>,
+
+    /// Play a made-up race of four cars on a 60 LED oval instead of the configured files, to try things out without data
+    #[arg(long)]
+    pub demo: bool,
+}
+
+fn parse_window_size(s: &str) -> Result<[u32; 2], String> {
+    let parse = |(width, height): (&str, &str)| Some([width.trim().parse().ok()?, height.trim().parse().ok()?]);
+    s.split_once(['x', 'X']).and_then(parse).ok_or_else(|| format!("`{s}` is not a size like 1920x1080"))
+}
+
+fn parse_delimiter(s: &str) -> Result<char, String> {
+    match s {
+        "tab" | "\\t" | "\t" => Ok('\t'),
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("`{s}` is not a single character or `tab`")),
+            }
+        }
+    }
+}
+
+fn parse_gradient(s: &str) -> Result<Gradient, String> {
+    Gradient::ALL.into_iter().find(|gradient| gradient.name() == s).ok_or_else(|| {
+        let names: Vec<_> = Gradient::ALL.iter().map(|gradient| gradient.name()).collect();
+        format!("`{s}` is not one of {}", names.join(", "))
+    })
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s.parse().map_err(|_| format!("`{s}` is not a number"))?;
+    if speed.is_finite() && speed > 0.0 {
+        Ok(speed)
+    } else {
+        Err(format!("speed must be a positive number, got {s}"))
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Simulated race time in milliseconds, driven by the wall clock at the
+/// playback speed. Tests fix the wall clock instead of reading the system one.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationClock {
+    anchor_wall: DateTime<Utc>, // Wall time at which the simulated time was `anchor_ms`
+    anchor_ms: f64,
+    speed: f64, // Simulated milliseconds per wall millisecond, negative to run backwards
+    paused: bool,
+    fixed_wall: Option<DateTime<Utc>>, // Set by tests in place of the system clock
+}
+
+impl SimulationClock {
+    /// A paused clock at 0 that runs at `speed` once resumed.
+    pub fn new(speed: f64) -> Self {
+        Self { anchor_wall: Utc::now(), anchor_ms: 0.0, speed, paused: true, fixed_wall: None }
+    }
+
+    pub fn wall_now(&self) -> DateTime<Utc> {
+        self.fixed_wall.unwrap_or_else(Utc::now)
+    }
+
+    pub fn now_ms(&self) -> f64 {
+        if self.paused {
+            return self.anchor_ms;
+        }
+        let elapsed_us = (self.wall_now() - self.anchor_wall).num_microseconds().unwrap_or(i64::MAX);
+        self.anchor_ms + elapsed_us as f64 / 1000.0 * self.speed
+    }
+
+    /// Holds the simulated time where it is. Does nothing if already paused.
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.anchor();
+            self.paused = true;
+        }
+    }
+
+    /// Runs on from where the clock was paused.
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.anchor_wall = self.wall_now();
+            self.paused = false;
+        }
+    }
+
+    /// Changes the speed from now on, without a jump in simulated time.
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed != self.speed {
+            self.anchor();
+            self.speed = speed;
+        }
+    }
+
+    /// Sets the simulated time, paused or not.
+    pub fn seek(&mut self, ms: f64) {
+        (self.anchor_ms, self.anchor_wall) = (ms, self.wall_now());
+    }
+
+    /// Whether the clock has got to `ms` in the direction it runs.
+    pub fn reached(&self, ms: f64) -> bool {
+        if self.speed < 0.0 {
+            self.now_ms() <= ms
+        } else {
+            self.now_ms() >= ms
+        }
+    }
+
+    /// Wall time until the clock gets to `ms`, zero if it already has.
+    /// `None` if it will not, being paused or running the other way.
+    pub fn until(&self, ms: f64) -> Option<Duration> {
+        if self.reached(ms) {
+            return Some(Duration::ZERO);
+        }
+        let wall_ms = (ms - self.now_ms()) / self.speed;
+        (!self.paused && wall_ms.is_finite() && wall_ms > 0.0).then(|| Duration::from_secs_f64(wall_ms / 1000.0))
+    }
+
+    fn anchor(&mut self) {
+        (self.anchor_ms, self.anchor_wall) = (self.now_ms(), self.wall_now());
+    }
+
+    /// Uses `wall` as the wall clock time from now on.
+    #[cfg(test)]
+    pub fn set_wall(&mut self, wall: DateTime<Utc>) {
+        if self.fixed_wall.is_none() {
+            self.anchor_wall = wall;
+        }
+        self.fixed_wall = Some(wall);
+    }
+
+    #[cfg(test)]
+    pub fn advance_wall(&mut self, by: Duration) {
+        self.set_wall(self.wall_now() + by);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulated_time_follows_the_wall_clock_at_the_speed() {
+        let mut clock = SimulationClock::new(2.0);
+        clock.set_wall("2023-08-27T12:00:00Z".parse().unwrap());
+        clock.advance_wall(Duration::from_millis(500));
+        assert_eq!(clock.now_ms(), 0.0, "starts paused");
+        assert_eq!(clock.until(100.0), None);
+
+        clock.resume();
+        clock.advance_wall(Duration::from_millis(100));
+        assert_eq!(clock.now_ms(), 200.0);
+        assert_eq!(clock.until(300.0), Some(Duration::from_millis(50)));
+        assert!(clock.reached(200.0) && !clock.reached(201.0));
+
+        clock.pause();
+        clock.advance_wall(Duration::from_secs(10));
+        clock.resume();
+        clock.set_speed(0.5);
+        clock.advance_wall(Duration::from_millis(100));
+        assert_eq!(clock.now_ms(), 250.0);
+
+        clock.seek(1000.0);
+        clock.set_speed(-1.0);
+        clock.advance_wall(Duration::from_millis(100));
+        assert_eq!(clock.now_ms(), 900.0);
+        assert!(clock.reached(950.0) && !clock.reached(800.0));
+        assert_eq!(clock.until(800.0), Some(Duration::from_millis(100)));
+        assert_eq!(clock.until(2000.0), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,688 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use crate::cli::Cli;
+use crate::cues::Cue;
+use crate::data;
+use crate::dataset;
+use crate::drivers;
+use crate::events;
+use crate::grid;
+use crate::heatmap::{self, Gradient};
+use crate::mqtt::MqttConfig;
+use crate::output::OutputConfig;
+use crate::palette::Palette;
+use crate::playlist::PlaylistEntry;
+use crate::render::{LedShape, LedSize};
+use crate::sectors;
+use crate::sim::{self, TrailMode};
+use crate::timestamp::{self, RaceTime};
+
+/// Smallest window, in points, so the track view never shrinks to nothing.
+pub const MIN_WINDOW_SIZE: [u32; 2] = [640, 400];
+
+/// Dataset files of a 2023 start, one per driver, taken when the config
+/// lists none of its own.
+pub const DEFAULT_DATASETS: [&str; 20] = [
+    "time_delta_albon_start.csv",
+    "time_delta_alonso_start.csv",
+    "time_delta_bottas_start.csv",
+    "time_delta_gasley_start.csv",
+    "time_delta_guanyu_start.csv",
+    "time_delta_hamilton_start.csv",
+    "time_delta_hulkenberg_start.csv",
+    "time_delta_lawson_start.csv",
+    "time_delta_leclerc_start.csv",
+    "time_delta_magnussen_start.csv",
+    "time_delta_norris_start.csv",
+    "time_delta_ocon_start.csv",
+    "time_delta_perez_start.csv",
+    "time_delta_piastri_start.csv",
+    "time_delta_russell_start.csv",
+    "time_delta_sainz_start.csv",
+    "time_delta_sargeant_start.csv",
+    "time_delta_stroll_start.csv",
+    "time_delta_tsunoda_start.csv",
+    "time_delta_verstappen_start.csv",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data_dir: Option<PathBuf>,
+    pub coordinates: PathBuf,
+    /// Dataset files to load. When empty or left at `DEFAULT_DATASETS`, the
+    /// race folder is scanned instead, and `DEFAULT_DATASETS` only read if
+    /// the folder cannot be listed.
+    pub datasets: Vec<PathBuf>,
+    /// Subfolder of `data_dir` holding one race's dataset files.
+    pub race: Option<String>,
+    /// Folder of another race on the same LEDs, such as last year's start,
+    /// played faintly behind the loaded one. Both are timed from their own
+    /// first row, so they line up by time into the race. Relative to
+    /// `data_dir`. Off when unset.
+    pub reference: Option<PathBuf>,
+    pub speed: f64,
+    pub autostart: bool,
+    /// Restart the race whenever the data runs out.
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    /// Seconds the final state is shown before a loop restarts.
+    pub loop_pause: f64,
+    /// Frames per second drawn while cars move smoothly between LEDs.
+    /// Otherwise the window only redraws when a row is due, and not at all
+    /// while paused.
+    pub tick_rate: f64,
+    /// Longest wall-clock wait, in seconds, for the next row. Longer idle
+    /// stretches, such as a formation lap, are skipped with a notice while
+    /// the race clock jumps forward. Off when unset.
+    pub max_gap: Option<f64>,
+    /// Seconds of simulated time between the copies of the playback state
+    /// kept so that seeking back does not replay from the start. 0 keeps
+    /// none, saving their memory on short races.
+    pub checkpoint_interval: f64,
+    /// Keep race data on disk and read it as playback needs it.
+    pub stream: bool,
+    /// Shortest time, in milliseconds, between two rows of a driver. Rows
+    /// closer together, such as runs with a `time_delta` of 0, are shown
+    /// this far apart instead of all at once. 0 shows them as recorded.
+    pub min_step_ms: u32,
+    /// Most rows per second kept of a car that stays on one LED, thinned
+    /// out when the race is loaded. Rows moving a car to another LED are
+    /// always kept. Off when unset; streamed race data is never thinned.
+    pub max_rate: Option<f64>,
+    /// Furthest a row's position may be from the nearest LED, in coordinate
+    /// units, for the car to be shown there. Rows further away, such as in a
+    /// pit lane the coordinates leave out, take the car off the map until it
+    /// is back. Off when unset: every row lights its nearest LED.
+    pub snap_distance: Option<f64>,
+    /// Sort rows that are out of date order and drop all but the last of
+    /// rows sharing a date, reporting how many. Off plays files as exported.
+    pub repair: bool,
+    /// Drop rows dated no later than a row above them instead, such as the
+    /// rows of a badly merged file, listing the lines dropped. Takes
+    /// precedence over `repair`.
+    pub drop_out_of_order: bool,
+    /// Field separator of the coordinates and race data files, such as
+    /// `"\t"` or `";"`. Without one, `.tsv` files are read as tab separated
+    /// and everything else as comma separated.
+    pub delimiter: Option<char>,
+    /// Where playback opens: a time since the first row such as `"5m30s"`, a
+    /// time of day such as `"15:04:22.500"` or a date. Times outside the
+    /// data open at its start or end.
+    pub start_at: Option<String>,
+    /// Rows before this time are left out when the race loads, as if the
+    /// files did not have them, given as for `start_at`.
+    pub start_time: Option<String>,
+    /// Rows after this time are left out the same way.
+    pub end_time: Option<String>,
+    /// Treat any data problem as a fatal error.
+    pub strict: bool,
+    /// Reload the coordinates and dataset files when they change on disk,
+    /// keeping the playback position.
+    pub watch: bool,
+    /// Row index in the coordinates file of the LED on the start/finish line.
+    pub start_finish_led: Option<usize>,
+    /// Alternatively, a point on the start/finish line; the nearest LED is used.
+    pub start_finish: Option<[f64; 2]>,
+    /// Distinct LEDs a car must pass between two crossings for a lap to count.
+    pub lap_debounce_leds: usize,
+    /// Ring the terminal bell when a car completes a lap. The start/finish
+    /// LED flashes white either way.
+    pub sound: bool,
+    /// Moments of the race that pulse the whole track white, of
+    /// `lights_out`, `lead_change`, `final_lap` and `finish`. None by
+    /// default; they can also be turned on in the Settings menu.
+    pub cues: BTreeSet<Cue>,
+    /// Ring the terminal bell with each cue as well.
+    pub cue_sound: bool,
+    /// LEDs kept lit behind each car, fading with age.
+    pub trail_length: usize,
+    /// `fade`, or `speed_gradient` to shift each LED of the trail from the
+    /// car's color towards orange-red the slower the car went on from it,
+    /// so braking points show.
+    pub trail_mode: TrailMode,
+    /// Rows in a row a car must show on one LED to be taken as stopped,
+    /// retired or crashed: it is drawn dimmed and marked in the legend
+    /// until it moves again. 0 never marks a car.
+    pub stopped_after: usize,
+    /// Short codes drawn next to each car, keyed by the driver part of the
+    /// file name (`verstappen` for `time_delta_verstappen_start.csv`).
+    pub driver_codes: BTreeMap<String, String>,
+    /// Date simulated time 0 stands for, in any format the `date` column
+    /// takes. When unset, the earliest start among the loaded datasets, so
+    /// drivers whose files start at different moments line up by date.
+    pub session_start: Option<String>,
+    /// Seconds added to a driver's dates, for files whose clock is off,
+    /// keyed like `driver_codes`. Offsets dialed in on the Alignment panel
+    /// are kept for the next run, these take precedence over them.
+    pub driver_offsets: BTreeMap<String, f64>,
+    /// Driver metadata file (`.toml` or `.csv`) with names, teams, codes and
+    /// colors. When unset, `drivers.toml` or `drivers.csv` in `data_dir` is
+    /// used if there is one.
+    pub drivers: Option<PathBuf>,
+    /// Race control events file with `date,kind,message` columns, shown as
+    /// flags in the top bar. When unset, `events.csv` in the race folder is
+    /// used if there is one.
+    pub events: Option<PathBuf>,
+    /// Starting grid file with a `driver` column and either `led` or
+    /// `x_led,y_led` columns. Drivers on it are parked on their slots
+    /// until the start, the others stay unlit until their first row. When
+    /// unset, `grid.csv` in the race folder is used if there is one.
+    pub grid: Option<PathBuf>,
+    /// Seconds the five red lights take to come on before lights out, the
+    /// moment the race clock starts from, for a race with a grid or with
+    /// `countdown`. 0 starts straight away.
+    pub start_lights: f64,
+    /// Run the start lights over the track before every start, not only
+    /// for a race with a grid.
+    pub countdown: bool,
+    /// Sectors file with `first_led,last_led,sector` columns, giving each
+    /// stretch of the track outline its own shade and a line across the
+    /// track where each begins. When unset,
+    /// `sectors.csv` next to the coordinates file is used if there is one.
+    pub sectors: Option<PathBuf>,
+    /// Sectors given here instead, each the list of LED ids in it, e.g.
+    /// `[[0, 1, 2], [3, 4]]`, numbered from 1, over any `sectors.csv` next
+    /// to the coordinates file. LEDs in none are in no sector.
+    pub sector_leds: Vec<Vec<usize>>,
+    /// Draw the driver codes on the track.
+    pub labels: bool,
+    /// Draw each car's driver code on top of its current LED, in black or
+    /// white, whichever reads better on the car's color.
+    pub led_codes: bool,
+    /// Fan cars that share an LED out in a tight cluster of dots around it,
+    /// so a packed start grid shows every car instead of one mixed color.
+    pub stack_offset: bool,
+    /// Fit the track into the view without stretching it.
+    pub keep_aspect: bool,
+    /// `square`, or `circle` to draw the LEDs as dots like a real strip.
+    pub led_shape: LedShape,
+    /// `{ points = 20 }` for a fixed width, or `{ spacing = 0.8 }` for that
+    /// fraction of the distance between neighbouring LEDs, never so large
+    /// that the closest two overlap.
+    pub led_size: LedSize,
+    /// RGB color of an edge around unlit LEDs, so the track shows with
+    /// every LED off. No edge when absent.
+    pub unlit_outline: Option<[u8; 3]>,
+    /// Draw a line through the LEDs in strip order underneath them.
+    pub outline: bool,
+    /// RGB color of that line.
+    pub outline_color: [u8; 3],
+    /// Move a marker for each car smoothly between the LEDs of two rows.
+    /// Off by default, since LED hardware can only show the rows themselves.
+    pub smooth: bool,
+    /// Light the LED nearest to where each car is between two rows, also on
+    /// the LED output, instead of holding it on the last row's LED.
+    pub interpolate: bool,
+    /// Window size in points, `[width, height]`; when unset the window
+    /// opens at the size it had when last closed.
+    pub window_size: Option<[u32; 2]>,
+    /// Open the window fullscreen. F11 goes in and out of it, Escape leaves it.
+    pub fullscreen: bool,
+    /// Open the window without a title bar or borders.
+    pub borderless: bool,
+    /// Monitor to open the window on, counted from 0 left to right. The
+    /// monitors are taken to be side by side and as large as the one the
+    /// window first opens on.
+    pub monitor: Option<usize>,
+    /// Keep the window above the other windows.
+    pub always_on_top: bool,
+    /// Seconds the mouse pointer has to stay still before it is hidden;
+    /// shown all the time when unset.
+    pub hide_cursor_after: Option<f64>,
+    /// Open fullscreen and stay so, whatever F11 or Escape do.
+    pub kiosk: bool,
+    /// Colors of the image `--heatmap` writes, `heat`, `ice` or `gray`.
+    pub heatmap_gradient: Gradient,
+    /// Size of that image in pixels, `[width, height]`.
+    pub heatmap_size: [u32; 2],
+    /// Frames a second of the animation `--export-gif` writes.
+    pub gif_fps: u32,
+    /// Size of that animation in pixels, `[width, height]`.
+    pub gif_size: [u32; 2],
+    /// Real-world metres per coordinate unit; when set, speeds are shown in
+    /// km/h and gaps in metres, otherwise in coordinate units.
+    #[serde(alias = "meters_per_unit")]
+    pub metres_per_unit: Option<f64>,
+    /// Driver colors; when unset the palette last picked in the app is used.
+    pub palette: Option<Palette>,
+    /// Replay file to record what the track shows to, rewritten whenever a
+    /// race is loaded.
+    pub record: Option<PathBuf>,
+    /// CSV file a row is written to each time playback moves on a moment,
+    /// rewritten whenever a race is loaded.
+    pub event_log: Option<PathBuf>,
+    /// File the activity log is written to as it goes, a line per car
+    /// moving between LEDs, flag change, reload and output error; `-` for
+    /// stdout.
+    pub activity_log: Option<PathBuf>,
+    /// Folder screenshots are written to.
+    pub screenshot_dir: PathBuf,
+    /// Network output to an Art-Net node or raw UDP receiver, off when absent.
+    pub output: Option<OutputConfig>,
+    /// LED colors published to an MQTT broker, off when absent.
+    pub mqtt: Option<MqttConfig>,
+    /// Address to serve the LED colors on to WebSocket clients, e.g.
+    /// `0.0.0.0:9001`, off when absent. Each client is sent every LED as
+    /// JSON on connecting and then the ones that change.
+    pub serve: Option<SocketAddr>,
+    /// Races played one after the other and then from the first again, each
+    /// a `[[playlist]]` table with its `title`, `coordinates`, `data_dir`
+    /// and optional `max_duration` in seconds. Replaces the race above when
+    /// there are any.
+    pub playlist: Vec<PlaylistEntry>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: None,
+            coordinates: PathBuf::from("led_coords.csv"),
+            datasets: DEFAULT_DATASETS.iter().map(PathBuf::from).collect(),
+            race: None,
+            reference: None,
+            speed: 1.0,
+            autostart: false,
+            looping: false,
+            loop_pause: 5.0,
+            tick_rate: 60.0,
+            max_gap: None,
+            checkpoint_interval: sim::DEFAULT_CHECKPOINT_INTERVAL_MS as f64 / 1000.0,
+            stream: false,
+            min_step_ms: dataset::DEFAULT_MIN_STEP_MS,
+            max_rate: None,
+            snap_distance: None,
+            repair: true,
+            drop_out_of_order: false,
+            delimiter: None,
+            start_at: None,
+            start_time: None,
+            end_time: None,
+            strict: false,
+            watch: false,
+            start_finish_led: None,
+            start_finish: None,
+            lap_debounce_leds: 10,
+            sound: true,
+            cues: BTreeSet::new(),
+            cue_sound: false,
+            trail_length: 10,
+            trail_mode: TrailMode::Fade,
+            stopped_after: 20,
+            driver_codes: [
+                ("albon", "ALB"),
+                ("alonso", "ALO"),
+                ("bottas", "BOT"),
+                ("gasley", "GAS"),
+                ("guanyu", "ZHO"),
+                ("hamilton", "HAM"),
+                ("hulkenberg", "HUL"),
+                ("lawson", "LAW"),
+                ("leclerc", "LEC"),
+                ("magnussen", "MAG"),
+                ("norris", "NOR"),
+                ("ocon", "OCO"),
+                ("perez", "PER"),
+                ("piastri", "PIA"),
+                ("russell", "RUS"),
+                ("sainz", "SAI"),
+                ("sargeant", "SAR"),
+                ("stroll", "STR"),
+                ("tsunoda", "TSU"),
+                ("verstappen", "VER"),
+            ]
+            .iter()
+            .map(|&(driver, code)| (driver.to_string(), code.to_string()))
+            .collect(),
+            session_start: None,
+            driver_offsets: BTreeMap::new(),
+            drivers: None,
+            events: None,
+            grid: None,
+            start_lights: 4.0,
+            countdown: false,
+            sectors: None,
+            sector_leds: Vec::new(),
+            labels: false,
+            led_codes: false,
+            stack_offset: false,
+            keep_aspect: false,
+            led_shape: LedShape::Square,
+            led_size: LedSize::default(),
+            unlit_outline: None,
+            outline: true,
+            outline_color: [64, 64, 64],
+            smooth: false,
+            interpolate: false,
+            window_size: None,
+            heatmap_gradient: Gradient::default(),
+            heatmap_size: heatmap::DEFAULT_SIZE,
+            gif_fps: 10,
+            gif_size: [640, 480],
+            fullscreen: false,
+            borderless: false,
+            monitor: None,
+            always_on_top: false,
+            hide_cursor_after: None,
+            kiosk: false,
+            metres_per_unit: None,
+            palette: None,
+            record: None,
+            event_log: None,
+            activity_log: None,
+            screenshot_dir: PathBuf::from("."),
+            output: None,
+            mqtt: None,
+            serve: None,
+            playlist: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config named on the command line (or the defaults) and
+    /// applies the command line overrides on top of it.
+    pub fn from_cli(cli: &Cli) -> Result<Self, Box<dyn Error>> {
+        let mut config = match &cli.config {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("cannot read config {}: {e}", path.display()))?;
+                toml::from_str(&text)
+                    .map_err(|e| format!("invalid config {}: {e}", path.display()))?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(data_dir) = &cli.data_dir {
+            config.data_dir = Some(data_dir.clone());
+        }
+        if let Some(race) = &cli.race {
+            config.race = Some(race.clone());
+        }
+        if let Some(reference) = &cli.reference {
+            config.reference = Some(reference.clone());
+        }
+        if let Some(speed) = cli.speed {
+            config.speed = speed;
+        }
+        config.autostart |= cli.autostart;
+        config.looping |= cli.looping;
+        if let Some(pause) = cli.loop_pause {
+            config.loop_pause = pause;
+        }
+        if let Some(rate) = cli.tick_rate {
+            config.tick_rate = rate;
+        }
+        if let Some(gap) = cli.max_gap {
+            config.max_gap = Some(gap);
+        }
+        if let Some(interval) = cli.checkpoint_interval {
+            config.checkpoint_interval = interval;
+        }
+        config.stream |= cli.stream;
+        if let Some(step) = cli.min_step_ms {
+            config.min_step_ms = step;
+        }
+        if let Some(rate) = cli.max_rate {
+            config.max_rate = Some(rate);
+        }
+        if let Some(distance) = cli.snap_distance {
+            config.snap_distance = Some(distance);
+        }
+        if let Some(metres) = cli.metres_per_unit {
+            config.metres_per_unit = Some(metres);
+        }
+        config.repair &= !cli.no_repair;
+        config.sound &= !cli.no_sound;
+        config.countdown |= cli.countdown;
+        config.drop_out_of_order |= cli.drop_out_of_order;
+        if let Some(delimiter) = cli.delimiter {
+            config.delimiter = Some(delimiter);
+        }
+        if let Some(start_at) = &cli.start_at {
+            config.start_at = Some(start_at.clone());
+        }
+        if let Some(start_time) = &cli.start_time {
+            config.start_time = Some(start_time.clone());
+        }
+        if let Some(end_time) = &cli.end_time {
+            config.end_time = Some(end_time.clone());
+        }
+        config.strict |= cli.strict;
+        config.watch |= cli.watch;
+        if let Some(size) = cli.window_size {
+            config.window_size = Some(size);
+        }
+        if let Some(gradient) = cli.heatmap_gradient {
+            config.heatmap_gradient = gradient;
+        }
+        if let Some(size) = cli.heatmap_size {
+            config.heatmap_size = size;
+        }
+        if let Some(fps) = cli.gif_fps {
+            config.gif_fps = fps;
+        }
+        if let Some(size) = cli.gif_size {
+            config.gif_size = size;
+        }
+        if let Some(length) = cli.trail_length {
+            config.trail_length = length;
+        }
+        config.fullscreen |= cli.fullscreen;
+        config.borderless |= cli.borderless;
+        if let Some(monitor) = cli.monitor {
+            config.monitor = Some(monitor);
+        }
+        config.always_on_top |= cli.always_on_top;
+        if let Some(seconds) = cli.hide_cursor_after {
+            config.hide_cursor_after = Some(seconds);
+        }
+        config.kiosk |= cli.kiosk;
+        if let Some(record) = &cli.record {
+            config.record = Some(record.clone());
+        }
+        if let Some(event_log) = &cli.event_log {
+            config.event_log = Some(event_log.clone());
+        }
+        if let Some(activity_log) = &cli.activity_log {
+            config.activity_log = Some(activity_log.clone());
+        }
+        if let Some(serve) = cli.serve {
+            config.serve = Some(serve);
+        }
+
+        if !(config.speed.is_finite() && config.speed > 0.0) {
+            return Err(format!("speed must be a positive number, got {}", config.speed).into());
+        }
+        if !(config.loop_pause.is_finite() && config.loop_pause >= 0.0) {
+            return Err(format!("loop_pause must be zero or more seconds, got {}", config.loop_pause).into());
+        }
+        if !(config.start_lights.is_finite() && config.start_lights >= 0.0) {
+            return Err(format!("start_lights must be zero or more seconds, got {}", config.start_lights).into());
+        }
+        if !(config.tick_rate.is_finite() && config.tick_rate > 0.0) {
+            return Err(format!("tick_rate must be a positive number of frames per second, got {}", config.tick_rate).into());
+        }
+        if let Some(gap) = config.max_gap.filter(|gap| !(gap.is_finite() && *gap > 0.0)) {
+            return Err(format!("max_gap must be a positive number of seconds, got {gap}").into());
+        }
+        if !(config.checkpoint_interval.is_finite() && config.checkpoint_interval >= 0.0) {
+            return Err(format!("checkpoint_interval must be zero or more seconds, got {}", config.checkpoint_interval).into());
+        }
+        if let Some(rate) = config.max_rate.filter(|rate| !(rate.is_finite() && *rate > 0.0)) {
+            return Err(format!("max_rate must be a positive number of rows per second, got {rate}").into());
+        }
+        if let Some(distance) = config.snap_distance.filter(|distance| !(distance.is_finite() && *distance >= 0.0)) {
+            return Err(format!("snap_distance must be zero or more, got {distance}").into());
+        }
+        if let Some(delimiter) = config.delimiter.filter(|delimiter| !delimiter.is_ascii() || matches!(delimiter, '"' | '\n' | '\r')) {
+            return Err(format!("delimiter must be a single ASCII character other than a quote or line break, got {delimiter:?}").into());
+        }
+        if config.heatmap_size.contains(&0) {
+            return Err(format!("heatmap_size must not be empty, got {}x{}", config.heatmap_size[0], config.heatmap_size[1]).into());
+        }
+        if config.gif_size.contains(&0) {
+            return Err(format!("gif_size must not be empty, got {}x{}", config.gif_size[0], config.gif_size[1]).into());
+        }
+        if !(1..=100).contains(&config.gif_fps) {
+            return Err(format!("gif_fps must be between 1 and 100, got {}", config.gif_fps).into());
+        }
+        if let Some([width, height]) = config.window_size {
+            let [min_width, min_height] = MIN_WINDOW_SIZE;
+            if width < min_width || height < min_height {
+                return Err(format!("window_size must be at least {min_width}x{min_height}, got {width}x{height}").into());
+            }
+        }
+        if let Some(seconds) = config.hide_cursor_after.filter(|seconds| !(seconds.is_finite() && *seconds > 0.0)) {
+            return Err(format!("hide_cursor_after must be a positive number of seconds, got {seconds}").into());
+        }
+        if let Some(text) = &config.session_start {
+            timestamp::parse_timestamp(text).map_err(|e| format!("invalid session_start: {e}"))?;
+        }
+        if let Some(text) = &config.start_at {
+            timestamp::parse_race_time(text).map_err(|e| format!("invalid start_at: {e}"))?;
+        }
+        let trim_time = |name: &str, text: &Option<String>| text.as_deref().map(timestamp::parse_race_time).transpose().map_err(|e| format!("invalid {name}: {e}"));
+        match (trim_time("start_time", &config.start_time)?, trim_time("end_time", &config.end_time)?) {
+            (Some(RaceTime::Elapsed(start)), Some(RaceTime::Elapsed(end))) if start >= end => {
+                return Err(format!("start_time {} must be before end_time {}", config.start_time.as_deref().unwrap_or_default(), config.end_time.as_deref().unwrap_or_default()).into());
+            }
+            (Some(RaceTime::Date(start)), Some(RaceTime::Date(end))) if start >= end => {
+                return Err(format!("start_time {start} must be before end_time {end}").into());
+            }
+            _ => {}
+        }
+        if let Some((driver, offset)) = config.driver_offsets.iter().find(|(_, offset)| !offset.is_finite()) {
+            return Err(format!("driver_offsets.{driver} must be a number of seconds, got {offset}").into());
+        }
+        if let Some(scale) = config.metres_per_unit.filter(|scale| !(scale.is_finite() && *scale > 0.0)) {
+            return Err(format!("metres_per_unit must be a positive number, got {scale}").into());
+        }
+        if let Some(mqtt) = &config.mqtt {
+            mqtt.validate()?;
+        }
+        if let Some(entry) = config.playlist.iter().find(|entry| entry.max_duration.is_some_and(|seconds| !(seconds.is_finite() && seconds > 0.0))) {
+            return Err(format!("max_duration of {} must be a positive number of seconds", entry.title).into());
+        }
+        Ok(config)
+    }
+
+    /// The configured session start, checked when the config was loaded.
+    pub fn session_start(&self) -> Option<DateTime<Utc>> {
+        timestamp::parse_timestamp(self.session_start.as_deref()?).ok()
+    }
+
+    /// Where playback opens, checked when the config was loaded.
+    pub fn start_at(&self) -> Option<RaceTime> {
+        timestamp::parse_race_time(self.start_at.as_deref()?).ok()
+    }
+
+    /// What the race is trimmed to, `start_time` and `end_time`, checked
+    /// when the config was loaded.
+    pub fn trim(&self) -> (Option<RaceTime>, Option<RaceTime>) {
+        let parse = |text: &Option<String>| timestamp::parse_race_time(text.as_deref()?).ok();
+        (parse(&self.start_time), parse(&self.end_time))
+    }
+
+    /// The directory races and relative paths are looked up in.
+    pub fn data_root(&self) -> PathBuf {
+        self.data_dir.clone().unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// The folder to scan for datasets, if the dataset list comes from a scan
+    /// rather than from `datasets`.
+    pub fn scan_dir(&self) -> Option<PathBuf> {
+        match &self.race {
+            Some(race) => Some(self.data_root().join(race)),
+            None if self.datasets.is_empty() || self.uses_default_datasets() => Some(self.data_root()),
+            None => None,
+        }
+    }
+
+    /// Whether `datasets` is left at `DEFAULT_DATASETS`.
+    pub fn uses_default_datasets(&self) -> bool {
+        self.datasets.iter().map(|path| path.to_str()).eq(DEFAULT_DATASETS.iter().map(|&name| Some(name)))
+    }
+
+    /// The driver metadata file to read, if any.
+    pub fn drivers_path(&self) -> Option<PathBuf> {
+        match &self.drivers {
+            Some(path) => Some(self.resolve(path)),
+            None => drivers::DEFAULT_FILES.iter().map(|name| self.data_root().join(name)).find(|path| path.is_file()),
+        }
+    }
+
+    /// The race control events file to read, if any.
+    pub fn events_path(&self) -> Option<PathBuf> {
+        match &self.events {
+            Some(path) => Some(self.resolve(path)),
+            None => self.scan_dir().map(|dir| dir.join(events::DEFAULT_FILE)).filter(|path| path.is_file()),
+        }
+    }
+
+    /// The starting grid file to read, if any.
+    pub fn grid_path(&self) -> Option<PathBuf> {
+        match &self.grid {
+            Some(path) => Some(self.resolve(path)),
+            None => self.scan_dir().map(|dir| dir.join(grid::DEFAULT_FILE)).filter(|path| path.is_file()),
+        }
+    }
+
+    /// Reads the demo race written to `dir` with `data::Demo::write` instead
+    /// of the configured files, counting laps at its start/finish LED.
+    pub fn use_demo(&mut self, dir: &Path, coordinates: PathBuf) {
+        self.data_dir = Some(dir.to_path_buf());
+        self.coordinates = coordinates;
+        self.datasets.clear();
+        (self.race, self.reference, self.drivers, self.events, self.sectors, self.grid) = (None, None, None, None, None, None);
+        self.sector_leds.clear();
+        (self.start_finish_led, self.start_finish) = (Some(data::DEMO_START_FINISH_LED), None);
+    }
+
+    /// The playlist with its paths looked up in `data_dir`.
+    pub fn playlist(&self) -> Vec<PlaylistEntry> {
+        let resolve = |entry: &PlaylistEntry| PlaylistEntry { coordinates: self.resolve(&entry.coordinates), data_dir: self.resolve(&entry.data_dir), ..entry.clone() };
+        self.playlist.iter().map(resolve).collect()
+    }
+
+    /// The folder of the reference race, if one is set.
+    pub fn reference_path(&self) -> Option<PathBuf> {
+        self.reference.as_deref().map(|path| self.resolve(path))
+    }
+
+    /// The shortest gap `max_rate` leaves between rows on one LED, 0 when unset.
+    pub fn downsample_ms(&self) -> u32 {
+        self.max_rate.map_or(0, |rate| (1000.0 / rate).round() as u32)
+    }
+
+    /// The configured field separator as a byte, checked to be ASCII by `from_cli`.
+    pub fn delimiter(&self) -> Option<u8> {
+        self.delimiter.map(|delimiter| delimiter as u8)
+    }
+
+    /// The sectors file to read, if any.
+    pub fn sectors_path(&self) -> Option<PathBuf> {
+        match &self.sectors {
+            Some(path) => Some(self.resolve(path)),
+            None if !self.sector_leds.is_empty() => None,
+            None => {
+                let coordinates = self.resolve(&self.coordinates);
+                coordinates.parent().map(|dir| dir.join(sectors::DEFAULT_FILE)).filter(|path| path.is_file())
+            }
+        }
+    }
+
+    /// Resolves a CSV path against `data_dir`; absolute paths are kept as is.
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        match &self.data_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+}
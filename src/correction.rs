@@ -0,0 +1,72 @@
+use eframe::egui::Color32;
+
+/// Gamma values the settings accept.
+pub const GAMMA_RANGE: std::ops::RangeInclusive<f32> = 0.5..=3.0;
+
+/// Brightness and gamma applied to every lit LED before it is painted or
+/// sent to the hardware, through a lookup table built once per setting.
+#[derive(Debug, Clone)]
+pub struct ColorCorrection {
+    brightness: f32, // 0.0 for off to 1.0 for full
+    gamma: f32,
+    lut: [u8; 256],
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self::new(1.0, 1.0)
+    }
+}
+
+impl ColorCorrection {
+    /// `brightness` from 0.0 to 1.0 scales every channel after the gamma
+    /// curve; both are clamped to what makes sense. Black stays black.
+    pub fn new(brightness: f32, gamma: f32) -> Self {
+        let brightness = if brightness.is_finite() { brightness.clamp(0.0, 1.0) } else { 1.0 };
+        let gamma = if gamma.is_finite() { gamma.clamp(*GAMMA_RANGE.start(), *GAMMA_RANGE.end()) } else { 1.0 };
+        let mut lut = [0; 256];
+        for (value, out) in lut.iter_mut().enumerate() {
+            *out = (255.0 * brightness * (value as f32 / 255.0).powf(gamma)).round() as u8;
+        }
+        Self { brightness, gamma, lut }
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    /// Corrects the RGB of a premultiplied color, keeping its alpha. LEDs
+    /// are drawn over black, so this is the color they end up showing.
+    pub fn apply(&self, color: Color32) -> Color32 {
+        let [r, g, b, a] = color.to_array();
+        Color32::from_rgba_premultiplied(self.lut[r as usize], self.lut[g as usize], self.lut[b as usize], a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_lookup_table_keeps_black_and_ends_at_the_brightness() {
+        for gamma in [0.5, 1.0, 2.2, 3.0] {
+            let full = ColorCorrection::new(1.0, gamma);
+            assert_eq!((full.lut[0], full.lut[255]), (0, 255), "gamma {gamma}");
+            let half = ColorCorrection::new(0.5, gamma);
+            assert_eq!((half.lut[0], half.lut[255]), (0, 128), "gamma {gamma}");
+            assert!(ColorCorrection::new(0.0, gamma).lut.iter().all(|&out| out == 0));
+        }
+        let identity = ColorCorrection::default();
+        assert!(identity.lut.iter().enumerate().all(|(value, &out)| out as usize == value));
+        assert_eq!(ColorCorrection::new(1.0, 2.2).lut[128], 56);
+        assert_eq!(ColorCorrection::new(1.0, 2.2).apply(Color32::from_rgba_premultiplied(255, 128, 0, 200)).to_array(), [255, 56, 0, 200]);
+
+        let clamped = ColorCorrection::new(1.5, f32::NAN);
+        assert_eq!((clamped.brightness(), clamped.gamma()), (1.0, 1.0));
+        assert_eq!(ColorCorrection::new(1.0, 10.0).gamma(), 3.0);
+    }
+}
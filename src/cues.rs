@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use crate::progress;
+use crate::sim::Simulation;
+
+/// Simulated time a car must stay in front before the lead counts as
+/// changed, so cars swapping places over one LED do not cue again and again.
+pub const LEAD_DEBOUNCE_MS: u64 = 3000;
+
+/// A moment of the race the display can call attention to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cue {
+    LightsOut,
+    LeadChange,
+    FinalLap,
+    Finish,
+}
+
+impl Cue {
+    pub const ALL: [Cue; 4] = [Cue::LightsOut, Cue::LeadChange, Cue::FinalLap, Cue::Finish];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Cue::LightsOut => "Lights out",
+            Cue::LeadChange => "Lead change",
+            Cue::FinalLap => "Final lap",
+            Cue::Finish => "Finish",
+        }
+    }
+}
+
+/// Watches playback for the moments a `Cue` marks, each given once as
+/// playback reaches it. Going back to the start gives them all again, so
+/// every loop of a race is cued.
+#[derive(Debug, Default, Clone)]
+pub struct CueDetector {
+    index: usize, // `current_index` when last looked at
+    leader: Option<usize>, // Dataset in front, as last cued
+    challenger: Option<(usize, u64)>, // Dataset in front of `leader` and the simulated time it got there
+    final_lap: bool, // Cued already
+    finished: bool, // Cued already
+}
+
+impl CueDetector {
+    /// The cues for what `sim` showed since the last call, in the order they
+    /// happen. `total_laps` is the laps of the race, if known, and
+    /// `chequered_ms` the simulated time of the chequered flag, if there is
+    /// one; without either the race finishes when the data ends.
+    ///
+    /// The leader is ranked as the leaderboard ranks them, by laps and then
+    /// distance. The first car in front leads without a cue, and another
+    /// takes over only after `LEAD_DEBOUNCE_MS` in front.
+    pub fn observe(&mut self, sim: &Simulation, total_laps: Option<u32>, chequered_ms: Option<u64>) -> Vec<Cue> {
+        let mut cues = Vec::new();
+        if sim.current_index < self.index {
+            *self = Self::default();
+        }
+        if self.index == 0 && sim.current_index > 0 {
+            cues.push(Cue::LightsOut);
+        }
+        self.index = sim.current_index;
+
+        let now_ms = sim.sim_elapsed_ms;
+        let cars: Vec<_> = sim.cars.iter().map(|car| (&car.progress, car.laps.laps, now_ms)).collect();
+        let front = progress::gaps_to_leader(&cars).first().map(|gap| gap.dataset_idx).filter(|&idx| sim.cars[idx].progress.led().is_some());
+        match (self.leader, front) {
+            (None, Some(front)) => self.leader = Some(front),
+            (Some(leader), Some(front)) if front != leader => {
+                let since_ms = match self.challenger {
+                    Some((challenger, since_ms)) if challenger == front => since_ms,
+                    _ => {
+                        self.challenger = Some((front, now_ms));
+                        now_ms
+                    }
+                };
+                if now_ms - since_ms >= LEAD_DEBOUNCE_MS {
+                    (self.leader, self.challenger) = (Some(front), None);
+                    cues.push(Cue::LeadChange);
+                }
+            }
+            _ => self.challenger = None,
+        }
+
+        if let Some(total_laps) = total_laps.filter(|&laps| laps > 0) {
+            if !self.final_lap && sim.leader_lap() >= total_laps {
+                self.final_lap = true;
+                cues.push(Cue::FinalLap);
+            }
+        }
+        let finished = match (chequered_ms, total_laps.filter(|&laps| laps > 0)) {
+            (Some(chequered_ms), _) => sim.current_index > 0 && now_ms >= chequered_ms,
+            (None, Some(total_laps)) => sim.leader_lap() > total_laps,
+            (None, None) => sim.current_index > 0 && sim.is_finished(),
+        };
+        if !self.finished && finished {
+            self.finished = true;
+            cues.push(Cue::Finish);
+        }
+        cues
+    }
+
+    /// Takes up where `sim` is without cueing, after a seek, so what was
+    /// jumped over is not cued.
+    pub fn sync(&mut self, sim: &Simulation, total_laps: Option<u32>, chequered_ms: Option<u64>) {
+        *self = Self::default();
+        self.observe(sim, total_laps, chequered_ms);
+        self.challenger = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::fixtures;
+
+    /// The cues `detector` gives playing `sim` to the end, with the simulated time of each.
+    fn played(detector: &mut CueDetector, sim: &mut Simulation, total_laps: Option<u32>, chequered_ms: Option<u64>) -> Vec<(u64, Cue)> {
+        let mut cues = Vec::new();
+        while sim.advance() {
+            cues.extend(detector.observe(sim, total_laps, chequered_ms).into_iter().map(|cue| (sim.sim_elapsed_ms, cue)));
+        }
+        cues
+    }
+
+    #[test]
+    fn cues_each_moment_once_and_a_lead_change_only_once_it_holds() {
+        // A row a second, one for each LED in `leds`, the first shown at 1 s
+        let rows = |leds: &[usize]| -> Vec<_> { leds.iter().enumerate().map(|(row, &led)| fixtures::row(row as i64 * 1000, (led as f64, 0.0), 1000)).collect() };
+        let race = [
+            rows(&(0..13).collect::<Vec<_>>()),
+            // Ahead at 2 s and 4 s for a second each, then from 6 s for good
+            rows(&[0, 2, 2, 4, 4, 6, 7, 9, 10, 11, 12, 13, 14]),
+        ];
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(20), None, &race);
+        (sim.start_finish_led, sim.lap_debounce_leds) = (Some(0), 2);
+
+        let mut detector = CueDetector::default();
+        let cues = played(&mut detector, &mut sim, None, None);
+        assert_eq!(cues, [(1_000, Cue::LightsOut), (9_000, Cue::LeadChange), (13_000, Cue::Finish)], "no lead change for the swapping");
+
+        // Looping back, both on the first and last lap of a race of one, with a chequered flag before the end
+        sim.reset();
+        let cues = played(&mut detector, &mut sim, Some(1), Some(5_000));
+        assert_eq!(cues, [(1_000, Cue::LightsOut), (1_000, Cue::FinalLap), (5_000, Cue::Finish), (9_000, Cue::LeadChange)], "every cue again from the start");
+
+        // Seeking over the race cues nothing
+        sim.seek(0);
+        detector.sync(&sim, None, None);
+        sim.seek(sim.moments());
+        detector.sync(&sim, None, None);
+        assert_eq!(detector.observe(&sim, None, None), []);
+    }
+}
@@ -0,0 +1,1199 @@
+use chrono::{DateTime, Utc};
+use csv::{Position, ReaderBuilder, StringRecord, Trim};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::source;
+use crate::timestamp;
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct LedCoordinate {
+    pub x_led: f64,
+    pub y_led: f64,
+    /// Position on the physical strip, from an optional `index` or `id`
+    /// column. Without one, `output::strip_positions` falls back to the
+    /// designator and then to the row, which `read_coordinates` puts here
+    /// so the LEDs after a row it merges or drops keep their places.
+    #[serde(default, alias = "id")]
+    pub index: Option<usize>,
+    #[serde(default)]
+    pub designator: Option<String>,
+    /// Strip or segment the LED belongs to, from an optional `segment` column.
+    #[serde(default)]
+    pub segment: Option<String>,
+    /// What part of the circuit the LED marks, from an optional `class`
+    /// column. A blank or unknown class is track.
+    #[serde(default, deserialize_with = "blank_is_track")]
+    pub class: LedClass,
+}
+
+impl LedCoordinate {
+    /// The number in the designator, 1 for `U1`, if it has one.
+    pub fn designator_number(&self) -> Option<usize> {
+        let designator = self.designator.as_deref()?;
+        designator.trim_start_matches(|c: char| !c.is_ascii_digit()).parse().ok()
+    }
+}
+
+/// Part of the circuit an LED marks. Pit lane LEDs are drawn dim and put a
+/// PIT tag on the leaderboard for the cars on them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedClass {
+    #[default]
+    Track,
+    #[serde(alias = "pit", alias = "pit_lane")]
+    Pitlane,
+    Grid,
+}
+
+impl LedClass {
+    /// The class a `class` field names, `None` for one that is not known.
+    fn named(name: &str) -> Option<Self> {
+        Self::deserialize(serde::de::value::StrDeserializer::<serde::de::value::Error>::new(name)).ok()
+    }
+}
+
+fn blank_is_track<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<LedClass, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.and_then(|name| LedClass::named(&name)).unwrap_or_default())
+}
+
+#[derive(Debug)]
+pub struct RunRace {
+    pub date: DateTime<Utc>,
+    pub x_led: f64,
+    pub y_led: f64,
+    pub time_delta: u64, // New field to hold the time delta
+    pub line: u64, // Line of the file the row is on, 0 for rows not read from a file
+    pub telemetry: Telemetry,
+}
+
+/// What the car was doing at a row, from the optional `speed`, `gear` and
+/// `throttle` columns of a race data file, as in OpenF1 `car_data`.
+/// Values a row leaves out are `None`, as are speeds below zero and
+/// throttles outside 0 to 100.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Telemetry {
+    pub speed: Option<f64>, // km/h
+    pub gear: Option<u8>, // 0 for neutral
+    pub throttle: Option<f64>, // Percent, 0 to 100
+}
+
+impl Telemetry {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A row exactly as it appears in a race data file, before validation.
+#[derive(Deserialize)]
+struct RawRunRace {
+    date: String,
+    x_led: f64,
+    y_led: f64,
+    time_delta: Option<i64>, // Allow for missing values
+    speed: Option<f64>,
+    gear: Option<u8>,
+    throttle: Option<f64>,
+}
+
+/// A problem found in an input file. Rows with problems are dropped unless
+/// the issue says otherwise; `--strict` turns any issue into a hard error.
+#[derive(Debug, Clone)]
+pub struct DataIssue {
+    pub file: PathBuf,
+    /// 1-based line number, or `None` for problems with the whole file.
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for DataIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} line {}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// The good rows of a file together with everything wrong with the rest.
+#[derive(Debug)]
+pub struct Validated<T> {
+    pub records: Vec<T>,
+    pub issues: Vec<DataIssue>,
+}
+
+/// Ends the issue about a row dated before the one above it, which stays where it is.
+const OUT_OF_ORDER: &str = "is earlier than the previous row (kept)";
+
+/// Highest strip position an LED may have, from its index or designator,
+/// so a stray id cannot make an output frame for billions of LEDs.
+pub const MAX_STRIP_POSITION: usize = u16::MAX as usize;
+
+/// Ends the issue about an LED far outside the others, which is kept until excluded.
+pub const OUTLIER: &str = "is far from the other LEDs (kept)";
+
+/// Interquartile ranges an LED may lie from the median point before it is an outlier.
+const OUTLIER_IQRS: f64 = 5.0;
+
+/// Column layouts assumed for coordinate files without a header row, by field count.
+const COORDINATE_LAYOUTS: &[&[&str]] = &[&["x_led", "y_led", "designator"], &["x_led", "y_led"]];
+
+/// Column layouts assumed for race data files without a header row, by field count.
+const RACE_DATA_LAYOUTS: &[&[&str]] = &[
+    &["x", "y", "z", "date", "designator", "x_led", "y_led", "time_delta"],
+    &["date", "x_led", "y_led", "time_delta"],
+    &["date", "x_led", "y_led"],
+];
+
+/// Whether `contents`, of a file named like `file_path`, start like a file
+/// `read_coordinates` reads: with a header row naming `x_led` and `y_led`
+/// but no `date`, or without a header, with a position first in a line of
+/// as many fields as a coordinates layout. `.gz` contents are decompressed.
+pub fn looks_like_coordinates(file_path: &Path, contents: &[u8], delimiter: Option<u8>) -> bool {
+    let reader: Box<dyn Read + '_> = if is_gzip(file_path) { Box::new(GzDecoder::new(contents)) } else { Box::new(contents) };
+    let mut rdr = ReaderBuilder::new().has_headers(false).flexible(true).trim(Trim::All).delimiter(self::delimiter(file_path, delimiter)).from_reader(reader);
+    let Some(Ok(first)) = rdr.records().next() else {
+        return false;
+    };
+    let named = |column: &str| first.iter().any(|field| field == column);
+    if named("x_led") && named("y_led") {
+        return !named("date");
+    }
+    COORDINATE_LAYOUTS.iter().any(|layout| layout.len() == first.len()) && first.iter().take(2).all(|field| field.parse::<f64>().is_ok())
+}
+
+/// The field separator of `file_path`: `configured` if set, otherwise a
+/// tab for `.tsv` files, compressed or not, and a comma for anything else.
+pub fn delimiter(file_path: &Path, configured: Option<u8>) -> u8 {
+    let name = file_path.file_name().map(|name| name.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    configured.unwrap_or(if name.ends_with(".tsv") || name.ends_with(".tsv.gz") { b'\t' } else { b',' })
+}
+
+/// Reads the LED coordinates file. Header detection works as for race data:
+/// the first line is the header only if it names `x_led` and `y_led`.
+/// Fields are separated by `delimiter`, or as `delimiter` guesses when `None`.
+///
+/// An LED at exactly the position of one above it is merged into that one.
+/// LEDs far outside the others, see `outliers`, are kept and reported. A
+/// file with fewer than two distinct positions is refused, as it has no
+/// extent to draw the track in.
+pub fn read_coordinates(file_path: impl AsRef<Path>, delimiter: Option<u8>) -> Result<Validated<LedCoordinate>, Box<dyn Error>> {
+    let file_path = file_path.as_ref();
+    let delimiter = self::delimiter(file_path, delimiter);
+    let mut records = Vec::new();
+    let mut lines = Vec::new();
+    let mut issues = Vec::new();
+    // An LED's index is its identity on the strip, a second LED with it would never light
+    let mut indices: HashMap<usize, Option<u64>> = HashMap::new();
+    let mut row = 0;
+    let (_, read_issues) = read_rows(file_path, delimiter, &["x_led", "y_led"], COORDINATE_LAYOUTS, |headers, record, line| {
+        row += 1;
+        match record.deserialize::<LedCoordinate>(Some(headers)) {
+            Ok(coord) if coord.index.or_else(|| coord.designator_number()?.checked_sub(1)).is_some_and(|position| position > MAX_STRIP_POSITION) => {
+                issues.push(issue(file_path, line, format!("strip position is past the last one, {MAX_STRIP_POSITION}")));
+            }
+            Ok(mut coord) if coord.x_led.is_finite() && coord.y_led.is_finite() => {
+                let class = headers.iter().position(|header| header == "class").and_then(|column| record.get(column));
+                if let Some(class) = class.filter(|class| !class.is_empty() && LedClass::named(class).is_none()) {
+                    issues.push(issue(file_path, line, format!("unknown class `{class}`, taken as track")));
+                }
+                if let Some(index) = coord.index {
+                    match indices.entry(index) {
+                        Entry::Occupied(first) => {
+                            let first = first.get().map_or(String::new(), |first| format!(" on line {first}"));
+                            issues.push(issue(file_path, line, format!("index {index} is already used{first}")));
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(line);
+                        }
+                    }
+                }
+                if coord.index.is_none() && coord.designator_number().and_then(|number| number.checked_sub(1)).is_none() {
+                    coord.index = Some(row - 1);
+                }
+                records.push(coord);
+                lines.push(line);
+            }
+            Ok(coord) => issues.push(issue(
+                file_path,
+                line,
+                format!("non-finite coordinate ({}, {})", coord.x_led, coord.y_led),
+            )),
+            Err(e) => issues.push(issue(file_path, line, describe_deserialize_error(headers, record, e))),
+        }
+    })?;
+    // Keep the first of LEDs sharing a position, drawn on top of each other they light as one
+    let mut firsts: HashMap<(u64, u64), Option<u64>> = HashMap::new();
+    let mut merged = Vec::with_capacity(records.len());
+    for (coord, line) in records.into_iter().zip(lines) {
+        match firsts.entry((coord.x_led.to_bits(), coord.y_led.to_bits())) {
+            Entry::Occupied(first) => {
+                let first = first.get().map_or(String::new(), |first| format!(" on line {first}"));
+                issues.push(issue(file_path, line, format!("same position as the LED{first}, merged into it")));
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(line);
+                merged.push((coord, line));
+            }
+        }
+    }
+    if merged.len() < 2 {
+        return Err("fewer than 2 distinct LED positions, the track cannot be drawn".into());
+    }
+    let (records, lines): (Vec<LedCoordinate>, Vec<Option<u64>>) = merged.into_iter().unzip();
+    for led in outliers(&records) {
+        let coord = &records[led];
+        issues.push(issue(file_path, lines[led], format!("LED at ({}, {}) {OUTLIER}", coord.x_led, coord.y_led)));
+    }
+    Ok(Validated { records, issues: merge_issues(issues, read_issues) })
+}
+
+/// The LEDs far outside the cluster of the others, such as a row left at
+/// (0, 0) by mistake: further from the median point along either axis than
+/// `OUTLIER_IQRS` times the interquartile range of the wider axis. Fewer
+/// than four LEDs have no outliers.
+pub fn outliers(coordinates: &[LedCoordinate]) -> Vec<usize> {
+    if coordinates.len() < 4 {
+        return Vec::new();
+    }
+    // Median and interquartile range of one axis
+    let spread = |value: fn(&LedCoordinate) -> f64| {
+        let mut values: Vec<f64> = coordinates.iter().map(value).collect();
+        values.sort_by(f64::total_cmp);
+        let quartile = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+        (quartile(0.5), quartile(0.75) - quartile(0.25))
+    };
+    let ((median_x, iqr_x), (median_y, iqr_y)) = (spread(|coord| coord.x_led), spread(|coord| coord.y_led));
+    let limit = OUTLIER_IQRS * iqr_x.max(iqr_y);
+    (0..coordinates.len())
+        .filter(|&led| (coordinates[led].x_led - median_x).abs() > limit || (coordinates[led].y_led - median_y).abs() > limit)
+        .collect()
+}
+
+/// Reads a race data file. Columns are matched by name, so their order does
+/// not matter; files without a header row must use one of the known layouts.
+/// The first line is the header only if it names the `date`, `x_led` and
+/// `y_led` columns, otherwise it is the first data row and is kept. Fields
+/// are separated as for `read_coordinates`.
+///
+/// Files ending in `.parquet` are read as Parquet, see `parquet_data`, in
+/// builds with the `parquet` feature; their lines are row numbers.
+///
+/// A file without a `time_delta` column, or with 0 on every row, gets each
+/// row's delay worked out from the date of the row before, the first
+/// row's left at 0.
+pub fn read_race_data(file_path: impl AsRef<Path>, delimiter: Option<u8>) -> Result<Validated<RunRace>, Box<dyn Error>> {
+    let mut data = if is_parquet(file_path.as_ref()) {
+        read_parquet_race_data(file_path.as_ref())?
+    } else {
+        let mut records = Vec::new();
+        let (_, issues) = scan_race_data(file_path, delimiter, |run_race, _| records.push(run_race))?;
+        Validated { records, issues }
+    };
+    if data.records.iter().all(|record| record.time_delta == 0) {
+        fill_delays(&mut data.records);
+    }
+    Ok(data)
+}
+
+/// Sets each row's `time_delta` after the first to the time since the row
+/// before, 0 for a row dated before it.
+fn fill_delays(records: &mut [RunRace]) {
+    for i in 1..records.len() {
+        records[i].time_delta = (records[i].date - records[i - 1].date).num_milliseconds().max(0) as u64;
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet_race_data(file_path: &Path) -> Result<Validated<RunRace>, Box<dyn Error>> {
+    let (mut records, mut issues) = (Vec::new(), Vec::new());
+    let mut last_date = None;
+    crate::parquet_data::read_rows(file_path, |run_race, line| match run_race {
+        Ok(run_race) => {
+            issues.extend(out_of_order(file_path, Some(line), run_race.date, &mut last_date));
+            records.push(run_race);
+        }
+        Err(message) => issues.push(issue(file_path, Some(line), message)),
+    })?;
+    Ok(Validated { records, issues })
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet_race_data(_file_path: &Path) -> Result<Validated<RunRace>, Box<dyn Error>> {
+    Err("Parquet files can only be read by a build with the parquet feature".into())
+}
+
+/// Whether `file_path` is read as Parquet, going by its extension.
+pub fn is_parquet(file_path: &Path) -> bool {
+    file_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("parquet"))
+}
+
+/// Puts rows read with `read_race_data` in date order: a row dated before
+/// the rows above it moves into place, and of rows sharing a date only the
+/// last in the file is kept. Each `time_delta` after the first row's is
+/// worked out again from the dates, and the first row's from the date the
+/// file started at, its first row's date minus its delay. The issues about
+/// rows out of order are replaced by one saying how many rows were moved
+/// and dropped.
+pub fn repair_race_data(file_path: &Path, data: &mut Validated<RunRace>) {
+    let records = &mut data.records;
+    let started = records.first().map(|first| first.date - chrono::Duration::milliseconds(first.time_delta as i64));
+    let mut latest = None;
+    let mut moved = 0;
+    for record in records.iter() {
+        if latest.is_some_and(|latest| record.date < latest) {
+            moved += 1;
+        } else {
+            latest = Some(record.date);
+        }
+    }
+    let before = records.len();
+    records.sort_by_key(|record| record.date);
+    records.reverse();
+    records.dedup_by_key(|record| record.date); // Keeps the last of each date in the file
+    records.reverse();
+    let dropped = before - records.len();
+    if let (Some(first), Some(started)) = (records.first_mut(), started) {
+        first.time_delta = (first.date - started).num_milliseconds().max(0) as u64;
+    }
+    for i in 1..records.len() {
+        records[i].time_delta = (records[i].date - records[i - 1].date).num_milliseconds() as u64;
+    }
+
+    data.issues.retain(|issue| !is_out_of_order(issue));
+    let mut repairs = Vec::new();
+    if moved > 0 {
+        repairs.push(format!("moved {moved} {} into date order", rows(moved)));
+    }
+    if dropped > 0 {
+        repairs.push(format!("dropped {dropped} {} sharing a date with a later row", rows(dropped)));
+    }
+    if !repairs.is_empty() {
+        data.issues.insert(0, issue(file_path, None, repairs.join(", ")));
+    }
+}
+
+/// Whether `issue` is about a row dated before the row above it.
+pub fn is_out_of_order(issue: &DataIssue) -> bool {
+    issue.message.ends_with(OUT_OF_ORDER)
+}
+
+/// Checks that every row is dated after all the rows above it. Otherwise
+/// returns the index of each row that is not, so dropping them leaves the
+/// dates strictly increasing.
+pub fn validate_monotonic(records: &[RunRace]) -> Result<(), Vec<usize>> {
+    let mut latest: Option<DateTime<Utc>> = None;
+    let mut offenders = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        if latest.is_some_and(|latest| record.date <= latest) {
+            offenders.push(i);
+        } else {
+            latest = Some(record.date);
+        }
+    }
+    if offenders.is_empty() {
+        Ok(())
+    } else {
+        Err(offenders)
+    }
+}
+
+/// Drops the rows read with `read_race_data` that `validate_monotonic`
+/// finds, instead of sorting them into place like `repair_race_data`. The
+/// `time_delta` of each row after a dropped one is worked out again from
+/// the dates. The issues about rows out of order are replaced by one
+/// naming the lines dropped.
+pub fn drop_out_of_order(file_path: &Path, data: &mut Validated<RunRace>) {
+    let Err(offenders) = validate_monotonic(&data.records) else {
+        return;
+    };
+    let lines: Vec<String> = offenders.iter().map(|&i| data.records[i].line.to_string()).collect();
+    let mut previous: Option<DateTime<Utc>> = None;
+    let (mut i, mut after_dropped) = (0, false);
+    data.records.retain_mut(|record| {
+        let keep = offenders.binary_search(&i).is_err();
+        i += 1;
+        if !keep {
+            after_dropped = true;
+            return false;
+        }
+        if let Some(previous) = previous.filter(|_| after_dropped) {
+            record.time_delta = (record.date - previous).num_milliseconds() as u64;
+        }
+        (previous, after_dropped) = (Some(record.date), false);
+        true
+    });
+    data.issues.retain(|issue| !is_out_of_order(issue));
+    let shown = lines.len().min(MAX_LINES_LISTED);
+    let more = if lines.len() > shown { format!(" and {} more", lines.len() - shown) } else { String::new() };
+    let message = format!("dropped {} {} dated no later than a row above, on line {}{more}", lines.len(), rows(lines.len()), lines[..shown].join(", "));
+    data.issues.insert(0, issue(file_path, None, message));
+}
+
+/// Most lines `drop_out_of_order` names before saying how many more there are.
+const MAX_LINES_LISTED: usize = 10;
+
+fn rows(count: usize) -> &'static str {
+    if count == 1 {
+        "row"
+    } else {
+        "rows"
+    }
+}
+
+/// Reads a race data file like `read_race_data`, but hands each good row to
+/// `row` together with where it starts in the file instead of keeping it.
+/// Returns the header the rows were matched with, which `RaceRows`
+/// needs to read them again, and the problems found.
+pub fn scan_race_data(
+    file_path: impl AsRef<Path>,
+    delimiter: Option<u8>,
+    mut row: impl FnMut(RunRace, &Position),
+) -> Result<(StringRecord, Vec<DataIssue>), Box<dyn Error>> {
+    let file_path = file_path.as_ref();
+    let delimiter = self::delimiter(file_path, delimiter);
+    let mut issues = Vec::new();
+    let mut last_date: Option<DateTime<Utc>> = None;
+    let (headers, read_issues) = read_rows(file_path, delimiter, &["date", "x_led", "y_led"], RACE_DATA_LAYOUTS, |headers, record, line| {
+        match parse_race_row(headers, record) {
+            Ok(run_race) => {
+                issues.extend(out_of_order(file_path, line, run_race.date, &mut last_date));
+                row(run_race, record.position().unwrap_or(&Position::new()));
+            }
+            Err(message) => issues.push(issue(file_path, line, message)),
+        }
+    })?;
+    Ok((headers, merge_issues(issues, read_issues)))
+}
+
+/// The issue for a row dated before the row above it, whose date is
+/// `last_date`, which then moves on to `date`. Out-of-order rows are kept
+/// but reported.
+fn out_of_order(file_path: &Path, line: Option<u64>, date: DateTime<Utc>, last_date: &mut Option<DateTime<Utc>>) -> Option<DataIssue> {
+    let before = last_date.replace(date);
+    before.is_some_and(|last| date < last).then(|| issue(file_path, line, format!("date {date} {OUT_OF_ORDER}")))
+}
+
+/// The good row on `line` of a race data file, as `read_race_data` reads
+/// it, or `None` if that line has no such row.
+pub fn read_race_line(file_path: &Path, delimiter: Option<u8>, line: u64) -> Result<Option<RunRace>, Box<dyn Error>> {
+    if is_parquet(file_path) {
+        return Ok(read_race_data(file_path, delimiter)?.records.into_iter().find(|run_race| run_race.line == line));
+    }
+    let mut found = None;
+    scan_race_data(file_path, delimiter, |run_race, _| {
+        if run_race.line == line {
+            found = Some(run_race);
+        }
+    })?;
+    Ok(found)
+}
+
+/// The good rows of a race data file, read again from positions
+/// `scan_race_data` reported with the header it returned. Rows with
+/// problems are skipped silently since the scan already reported them.
+///
+/// A read that starts at or after where the last one stopped carries on
+/// with the same reader for `.gz` files, which cannot seek, instead of
+/// decompressing everything before it again.
+pub struct RaceRows {
+    path: PathBuf,
+    delimiter: u8, // The one the scan used
+    headers: StringRecord,
+    reader: Option<(u64, csv::Reader<CountLines>)>, // Byte the last reader started at, and the reader
+}
+
+impl RaceRows {
+    pub fn new(path: impl Into<PathBuf>, delimiter: u8, headers: StringRecord) -> Self {
+        RaceRows { path: path.into(), delimiter, headers, reader: None }
+    }
+
+    /// The same rows, read with a reader of its own.
+    pub fn reopen(&self) -> Self {
+        RaceRows::new(self.path.clone(), self.delimiter, self.headers.clone())
+    }
+
+    /// Calls `row` with each good row from `start` on, stopping as soon as
+    /// it returns `false`. Fails if the file cannot be read to the end.
+    pub fn read(&mut self, start: &Position, mut row: impl FnMut(RunRace) -> bool) -> Result<(), Box<dyn Error>> {
+        let carries_on = is_gzip(&self.path) && self.reader.as_ref().is_some_and(|(base, rdr)| base + rdr.position().byte() <= start.byte());
+        if !carries_on {
+            self.reader = Some((start.byte(), csv_reader(&self.path, self.delimiter, start)?));
+        }
+        let (base, rdr) = self.reader.as_mut().expect("opened above");
+        while let Some(result) = next_record(rdr) {
+            let record = match result {
+                Ok(record) => record,
+                Err(e) if e.is_io_error() => {
+                    self.reader = None; // Start over next time
+                    return Err(e.into());
+                }
+                Err(_) => continue,
+            };
+            if record.position().is_some_and(|position| *base + position.byte() < start.byte()) {
+                continue; // Before the start, from carrying on
+            }
+            if let Ok(run_race) = parse_race_row(&self.headers, &record) {
+                if !row(run_race) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A CSV reader of `file_path` from `start`, counting the lines it reads
+/// to put each record on its line.
+fn csv_reader(file_path: &Path, delimiter: u8, start: &Position) -> io::Result<csv::Reader<CountLines>> {
+    let lines = LineStarts { line: start.line(), fresh: true, counting: start.byte() == 0, ..LineStarts::default() };
+    let reader = CountLines { inner: open_at(file_path, start.byte())?, lines };
+    Ok(ReaderBuilder::new().has_headers(false).flexible(true).trim(Trim::All).delimiter(delimiter).from_reader(reader))
+}
+
+/// Where the lines with something on them start in what a CSV reader has
+/// read. The reader's own line count is one short for every record after
+/// the first in files with `\r\n` line endings, and misses their blank
+/// lines; it also puts such a record's position on the line ends before it.
+#[derive(Default)]
+struct LineStarts {
+    read: u64, // Bytes read so far
+    line: u64, // Line the last byte read is on
+    fresh: bool, // Nothing but line ends read on `line` yet
+    counting: bool, // Off until the first record of a reader started on one, whose line it was given
+    ahead: VecDeque<(u64, u64)>, // Offset and line of the first byte of each line read but not yet passed
+}
+
+impl LineStarts {
+    /// Line of the record at `offset` from where the reader started: that
+    /// of the first line start from there. Offsets are asked about in
+    /// order, as the records come.
+    fn line_at(&mut self, offset: u64) -> u64 {
+        while self.ahead.front().is_some_and(|&(start, _)| start < offset) {
+            self.ahead.pop_front();
+        }
+        self.ahead.front().map_or(self.line, |&(_, line)| line)
+    }
+
+    fn note(&mut self, byte: u8) {
+        match byte {
+            b'\n' if self.counting => (self.line, self.fresh) = (self.line + 1, true),
+            b'\n' | b'\r' => {}
+            _ if self.fresh => {
+                self.ahead.push_back((self.read, self.line));
+                (self.fresh, self.counting) = (false, true);
+            }
+            _ => {}
+        }
+        self.read += 1;
+    }
+}
+
+/// Notes the lines in what passes through to the CSV reader.
+struct CountLines {
+    inner: Box<dyn Read + Send>,
+    lines: LineStarts,
+}
+
+impl Read for CountLines {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for &byte in &buf[..n] {
+            self.lines.note(byte);
+        }
+        Ok(n)
+    }
+}
+
+/// The next record of `rdr`, put on its line, or `None` at the end.
+fn next_record(rdr: &mut csv::Reader<CountLines>) -> Option<csv::Result<StringRecord>> {
+    let mut record = StringRecord::new();
+    match rdr.read_record(&mut record) {
+        Ok(false) => None,
+        Ok(true) => {
+            if let Some(position) = record.position() {
+                let mut position = position.clone();
+                position.set_line(rdr.get_mut().lines.line_at(position.byte()));
+                record.set_position(Some(position));
+            }
+            Some(Ok(record))
+        }
+        Err(e) => Some(Err(e)),
+    }
+}
+
+/// Calls `row` with the header and each data record of a CSV file.
+///
+/// The first line is taken as the header if it names every `required` column;
+/// otherwise the file is treated as headerless, the first line is data, and
+/// the column names come from the entry in `layouts` with a matching field
+/// count. Lines later in the file that repeat the header are skipped.
+/// Returns the lines that could not be read as CSV at all.
+///
+/// Files ending in `.gz` are decompressed while they are read. Returns the
+/// header used along with the problems.
+fn read_rows(
+    file_path: &Path,
+    delimiter: u8,
+    required: &[&str],
+    layouts: &[&[&str]],
+    mut row: impl FnMut(&StringRecord, &StringRecord, Option<u64>),
+) -> Result<(StringRecord, Vec<DataIssue>), Box<dyn Error>> {
+    let mut rdr = csv_reader(file_path, delimiter, &Position::new())?;
+
+    let first = match next_record(&mut rdr) {
+        Some(first) => first?,
+        None => return Ok((StringRecord::new(), Vec::new())), // Empty file
+    };
+    let is_header = required.iter().all(|column| first.iter().any(|field| field == *column));
+    // A first line without a single number is a header, just not one with the columns needed
+    if !is_header && first.iter().all(|field| field.parse::<f64>().is_err()) {
+        let missing: Vec<&str> = required.iter().copied().filter(|column| !first.iter().any(|field| field == *column)).collect();
+        let columns: Vec<&str> = first.iter().collect();
+        return Err(format!("line 1: the header row has no {} column (it has {})", missing.join(" or "), columns.join(", ")).into());
+    }
+    let headers = if is_header {
+        first.clone()
+    } else {
+        let layout = layouts
+            .iter()
+            .find(|layout| layout.len() == first.len())
+            .ok_or_else(|| {
+                format!(
+                    "no header row with columns {} and {} fields does not match a known layout",
+                    required.join(", "),
+                    first.len()
+                )
+            })?;
+        let headers = StringRecord::from(layout.to_vec());
+        let line = first.position().map(Position::line);
+        row(&headers, &first, line);
+        headers
+    };
+
+    let mut issues = Vec::new();
+    while let Some(result) = next_record(&mut rdr) {
+        match read_record(result, &mut rdr.get_mut().lines) {
+            Ok((record, _)) if is_header && record == headers => {} // Stray repeated header
+            Ok((record, line)) => row(&headers, &record, line),
+            Err((line, message)) => issues.push(issue(file_path, line, message)),
+        }
+    }
+    Ok((headers, issues))
+}
+
+/// Opens a file from the current `source` at `byte`, counted in the
+/// decompressed stream for `.gz` files.
+fn open_at(file_path: &Path, byte: u64) -> io::Result<Box<dyn Read + Send>> {
+    let source = source::current();
+    if is_gzip(file_path) {
+        // Compressed streams cannot seek, so read up to the position
+        let mut reader = GzDecoder::new(source.open_at(file_path, 0)?);
+        io::copy(&mut (&mut reader).take(byte), &mut io::sink())?;
+        Ok(Box::new(reader))
+    } else {
+        source.open_at(file_path, byte)
+    }
+}
+
+fn is_gzip(file_path: &Path) -> bool {
+    file_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Combines row and read issues into one list in line order.
+fn merge_issues(mut issues: Vec<DataIssue>, read_issues: Vec<DataIssue>) -> Vec<DataIssue> {
+    issues.extend(read_issues);
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+fn parse_race_row(headers: &StringRecord, record: &StringRecord) -> Result<RunRace, String> {
+    let raw: RawRunRace = record.deserialize(Some(headers)).map_err(|e| describe_deserialize_error(headers, record, e))?;
+    let run_race = validate_row(raw)?;
+    Ok(RunRace { line: record.position().map_or(0, Position::line), ..run_race })
+}
+
+fn validate_row(raw: RawRunRace) -> Result<RunRace, String> {
+    let telemetry = Telemetry {
+        speed: raw.speed.filter(|speed| speed.is_finite() && *speed >= 0.0),
+        gear: raw.gear,
+        throttle: raw.throttle.filter(|throttle| (0.0..=100.0).contains(throttle)),
+    };
+    Ok(RunRace { telemetry, ..race_row(timestamp::parse_timestamp(&raw.date)?, raw.x_led, raw.y_led, raw.time_delta)? })
+}
+
+/// A row from values read out of a file, checked as for CSV rows. Its line is 0.
+pub(crate) fn race_row(date: DateTime<Utc>, x_led: f64, y_led: f64, time_delta: Option<i64>) -> Result<RunRace, String> {
+    if !(x_led.is_finite() && y_led.is_finite()) {
+        return Err(format!("non-finite coordinate ({x_led}, {y_led})"));
+    }
+    let time_delta = time_delta.unwrap_or(0); // Default to 0 if missing
+    if time_delta < 0 {
+        return Err(format!("negative time_delta {time_delta}"));
+    }
+    Ok(RunRace {
+        date,
+        x_led,
+        y_led,
+        time_delta: time_delta as u64,
+        line: 0,
+        telemetry: Telemetry::default(),
+    })
+}
+
+/// Unwraps a record from `next_record`, or returns its line number, counted
+/// by `lines`, and a description of why it could not be read.
+fn read_record(result: csv::Result<StringRecord>, lines: &mut LineStarts) -> Result<(StringRecord, Option<u64>), (Option<u64>, String)> {
+    match result {
+        Ok(record) => {
+            let line = record.position().map(Position::line);
+            Ok((record, line))
+        }
+        Err(e) => Err((e.position().map(|position| lines.line_at(position.byte())), e.to_string())),
+    }
+}
+
+/// Names the column and quotes the value that could not be read, when known.
+fn describe_deserialize_error(headers: &StringRecord, record: &StringRecord, e: csv::Error) -> String {
+    match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => {
+            let field = err.field().map(|field| field as usize);
+            match (field.and_then(|field| headers.get(field)), field.and_then(|field| record.get(field))) {
+                (Some(column), Some(value)) => format!("column {column}: {} in {value:?}", err.kind()),
+                (Some(column), None) => format!("column {column}: {}", err.kind()),
+                _ => err.kind().to_string(),
+            }
+        }
+        _ => e.to_string(),
+    }
+}
+
+fn issue(file_path: &Path, line: Option<u64>, message: String) -> DataIssue {
+    DataIssue {
+        file: file_path.to_path_buf(),
+        line,
+        message,
+    }
+}
+
+/// LEDs around the oval of the demo race.
+pub const DEMO_LEDS: usize = 60;
+
+/// Where the demo cars start and finish their laps.
+pub const DEMO_START_FINISH_LED: usize = 0;
+
+/// Drivers of the demo race, as the keys of their file names.
+const DEMO_DRIVERS: [&str; 4] = ["ash", "birch", "cedar", "elm"];
+
+/// Simulated time the demo race lasts, and how often its cars send a row.
+const DEMO_LENGTH_MS: i64 = 60_000;
+const DEMO_ROW_MS: i64 = 250;
+
+/// The demo race as the files it would be read from, so it goes through
+/// the same loaders as a real one.
+#[derive(Debug, Clone)]
+pub struct Demo {
+    pub coordinates: String, // led_coords.csv
+    pub datasets: Vec<(String, String)>, // File name and contents of each dataset
+}
+
+impl Demo {
+    /// Writes the files into `dir`, returning where the coordinates went.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let coordinates = dir.join("led_coords.csv");
+        std::fs::write(&coordinates, &self.coordinates)?;
+        for (name, contents) in &self.datasets {
+            std::fs::write(dir.join(name), contents)?;
+        }
+        Ok(coordinates)
+    }
+}
+
+/// A made-up race for trying the simulator without any data: four cars
+/// lapping an oval of `DEMO_LEDS` LEDs for a minute from
+/// `DEMO_START_FINISH_LED`. Each car is a little slower than the one ahead of
+/// it on the grid and its pace wavers, so they spread out and swap LEDs.
+/// The same every time.
+pub fn demo() -> Demo {
+    let point = |fraction: f64| {
+        let angle = fraction * std::f64::consts::TAU;
+        (500.0 + 400.0 * angle.cos(), 300.0 + 200.0 * angle.sin())
+    };
+    let mut coordinates = String::from("x_led,y_led\n");
+    for led in 0..DEMO_LEDS {
+        let (x, y) = point(led as f64 / DEMO_LEDS as f64);
+        coordinates += &format!("{x:.0},{y:.0}\n");
+    }
+    let start = DateTime::from_timestamp(1_714_917_600, 0).unwrap_or_default(); // 2024-05-05 14:00 UTC
+    let datasets = DEMO_DRIVERS
+        .iter()
+        .enumerate()
+        .map(|(grid, driver)| {
+            let lap_ms = 20_000.0 + 600.0 * grid as f64;
+            let mut rows = String::from("date,x_led,y_led,time_delta\n");
+            for t_ms in (0..=DEMO_LENGTH_MS).step_by(DEMO_ROW_MS as usize) {
+                let t = t_ms as f64;
+                let fraction = t / lap_ms - 1.5 * grid as f64 / DEMO_LEDS as f64 + 0.004 * (t / 3000.0 + grid as f64).sin();
+                let (x, y) = point(fraction);
+                let date = (start + chrono::Duration::milliseconds(t_ms)).format("%Y-%m-%dT%H:%M:%S%.3fZ");
+                let time_delta = if t_ms == 0 { 0 } else { DEMO_ROW_MS };
+                rows += &format!("{date},{x:.1},{y:.1},{time_delta}\n");
+            }
+            (format!("time_delta_{driver}_start.csv"), rows)
+        })
+        .collect();
+    Demo { coordinates, datasets }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Writes `contents` to a uniquely named file in the temp directory.
+    fn fixture(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn xs(records: &[RunRace]) -> Vec<f64> {
+        records.iter().map(|r| r.x_led).collect()
+    }
+
+    const HEADER: &str = "x,y,z,date,designator,x_led,y_led,time_delta\n";
+    const ROWS: &str = "\
+1183,5254,0,2023-08-27T12:11:11.114000+00:00,U53,1,5212,240
+1192,5273,0,2023-08-27T12:11:11.314000+00:00,U53,2,5212,200
+1201,5294,0,2023-08-27T12:11:11.534000+00:00,U53,3,5212,220
+";
+
+    #[test]
+    fn keeps_first_row_after_header() {
+        let path = fixture("with-header.csv", &format!("{HEADER}{ROWS}"));
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0]);
+        assert_eq!(data.records[0].time_delta, 240);
+        assert!(data.issues.is_empty());
+    }
+
+    #[test]
+    fn reads_headerless_file_by_layout() {
+        let path = fixture("no-header.csv", ROWS);
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0]);
+
+        let path = fixture("no-header-short.csv", "2023-08-27T12:11:11.114Z,7,8,100\n2023-08-27T12:11:11.214Z,9,8,100\n");
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [7.0, 9.0]);
+    }
+
+    #[test]
+    fn skips_repeated_header_row() {
+        let (first, rest) = ROWS.split_at(ROWS.find('\n').unwrap() + 1);
+        let path = fixture("dup-header.csv", &format!("{HEADER}{first}{HEADER}{rest}"));
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0]);
+        assert!(data.issues.is_empty());
+    }
+
+    #[test]
+    fn matches_columns_by_name() {
+        let path = fixture(
+            "reordered.csv",
+            "time_delta,y_led,x_led,date\n240,5212,1,2023-08-27T12:11:11.114Z\n200,5212,2,2023-08-27T12:11:11.314Z\n",
+        );
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [1.0, 2.0]);
+        assert_eq!(data.records[1].time_delta, 200);
+    }
+
+    #[test]
+    fn tsv_files_are_tab_separated_and_others_take_the_configured_delimiter() {
+        let tsv = fixture("tabs.tsv", &format!("{HEADER}{ROWS}").replace(',', "\t"));
+        assert_eq!(xs(&read_race_data(&tsv, None).unwrap().records), [1.0, 2.0, 3.0]);
+        let coords = read_coordinates(fixture("coords.tsv", "x_led\ty_led\n1\t2\n3\t4\n"), None).unwrap();
+        assert_eq!(coords.records.iter().map(|coord| coord.y_led).collect::<Vec<_>>(), [2.0, 4.0]);
+        assert!(coords.issues.is_empty());
+
+        let semicolons = fixture("semicolons.csv", &format!("{HEADER}{ROWS}").replace(',', ";"));
+        assert_eq!(xs(&read_race_data(&semicolons, Some(b';')).unwrap().records), [1.0, 2.0, 3.0]);
+        assert_eq!(delimiter(Path::new("race.TSV.gz"), None), b'\t');
+        assert_eq!(delimiter(Path::new("race.tsv"), Some(b',')), b',');
+    }
+
+    #[test]
+    fn header_only_and_empty_files_have_no_records() {
+        for (name, contents) in [("header-only.csv", HEADER), ("empty.csv", "")] {
+            let data = read_race_data(fixture(name, contents), None).unwrap();
+            assert!(data.records.is_empty(), "{name}");
+            assert!(data.issues.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn bad_first_data_row_is_reported_not_taken_as_header() {
+        let path = fixture("bad-first-row.csv", &format!("{HEADER}1,2,3,yesterday,U1,0,0,0\n{ROWS}"));
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0]);
+        assert_eq!(data.issues.len(), 1);
+        assert_eq!(data.issues[0].line, Some(2));
+    }
+
+    #[test]
+    fn speed_gear_and_throttle_columns_come_through_when_present() {
+        let path = fixture(
+            "telemetry.csv",
+            "date,x_led,y_led,time_delta,speed,gear,throttle\n\
+             2023-08-27T12:11:11.100Z,1,0,100,287.5,7,100\n2023-08-27T12:11:11.300Z,2,0,200,,0,\n\
+             2023-08-27T12:11:11.500Z,3,0,200,-4,8,104\n",
+        );
+        let data = read_race_data(&path, None).unwrap();
+        assert!(data.issues.is_empty());
+        let telemetry: Vec<_> = data.records.iter().map(|row| row.telemetry).collect();
+        assert_eq!(
+            telemetry,
+            [
+                Telemetry { speed: Some(287.5), gear: Some(7), throttle: Some(100.0) },
+                Telemetry { speed: None, gear: Some(0), throttle: None },
+                Telemetry { speed: None, gear: Some(8), throttle: None },
+            ],
+            "blank and impossible values are left out"
+        );
+        let plain = read_race_data(fixture("no-telemetry.csv", &format!("{HEADER}{ROWS}")), None).unwrap();
+        assert!(plain.records.iter().all(|row| row.telemetry.is_empty()));
+    }
+
+    #[test]
+    fn repair_sorts_shuffled_rows_and_works_out_the_delays_again() {
+        let path = fixture(
+            "shuffled.csv",
+            "date,x_led,y_led,time_delta
+\
+             2023-08-27T12:11:11.100Z,1,0,100\n2023-08-27T12:11:11.500Z,4,0,400\n2023-08-27T12:11:11.300Z,2,0,0\n\
+             2023-08-27T12:11:11.400Z,3,0,0\n2023-08-27T12:11:11.700Z,5,0,300\n",
+        );
+        let mut data = read_race_data(&path, None).unwrap();
+        assert_eq!(data.issues.iter().map(|issue| issue.line).collect::<Vec<_>>(), [Some(4)]);
+        repair_race_data(&path, &mut data);
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0, 4.0, 5.0]);
+        let delays: Vec<u64> = data.records.iter().map(|r| r.time_delta).collect();
+        assert_eq!(delays, [100, 200, 100, 100, 200]);
+        let issues: Vec<_> = data.issues.iter().map(|issue| (issue.line, issue.message.as_str())).collect();
+        assert_eq!(issues, [(None, "moved 2 rows into date order")]);
+
+        // A row sorted to the top is delayed from where the file started, not by its own old delay
+        let path = fixture("shuffled-first.csv", "date,x_led,y_led,time_delta\n2023-08-27T12:11:11.300Z,3,0,300\n2023-08-27T12:11:11.100Z,1,0,0\n2023-08-27T12:11:11.500Z,5,0,200\n");
+        let mut data = read_race_data(&path, None).unwrap();
+        repair_race_data(&path, &mut data);
+        assert_eq!(xs(&data.records), [1.0, 3.0, 5.0]);
+        assert_eq!(data.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [100, 200, 200]);
+        let path = fixture("shuffled-early.csv", "date,x_led,y_led,time_delta\n2023-08-27T12:11:11.300Z,3,0,300\n2023-08-27T12:11:10.900Z,0,0,0\n");
+        let mut data = read_race_data(&path, None).unwrap();
+        repair_race_data(&path, &mut data);
+        assert_eq!(data.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [0, 400], "dated before the file started");
+    }
+
+    #[test]
+    fn delays_missing_from_every_row_come_from_the_dates() {
+        let path = fixture(
+            "no-delays.csv",
+            "date,x_led,y_led\n2023-08-27T12:11:11.100Z,1,0\n2023-08-27T12:11:11.350Z,2,0\n2023-08-27T12:11:11.300Z,3,0\n2023-08-27T12:11:12Z,4,0\n",
+        );
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(data.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [0, 250, 0, 700]);
+        let zeros = read_race_data(fixture("zero-delays.csv", "date,x_led,y_led,time_delta\n2023-08-27T12:11:11.100Z,1,0,0\n2023-08-27T12:11:11.300Z,2,0,0\n"), None).unwrap();
+        assert_eq!(zeros.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [0, 200]);
+        let some = read_race_data(fixture("some-delays.csv", "date,x_led,y_led,time_delta\n2023-08-27T12:11:11.100Z,1,0,0\n2023-08-27T12:11:11.300Z,2,0,150\n"), None).unwrap();
+        assert_eq!(some.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [0, 150], "delays the file gives are kept");
+    }
+
+    #[test]
+    fn rows_not_after_every_row_above_break_monotony_and_can_be_dropped() {
+        let path = fixture(
+            "merged.csv",
+            "date,x_led,y_led,time_delta
+\
+             2023-08-27T12:11:11.100Z,1,0,100\n2023-08-27T12:11:11.500Z,2,0,400\n2023-08-27T12:11:11.300Z,8,0,0\n\
+             2023-08-27T12:11:11.500Z,9,0,200\n2023-08-27T12:11:11.700Z,3,0,200\n",
+        );
+        let mut data = read_race_data(&path, None).unwrap();
+        assert_eq!(validate_monotonic(&data.records), Err(vec![2, 3]), "the repeated date counts, the row after the jump back does not");
+        drop_out_of_order(&path, &mut data);
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0]);
+        assert_eq!(data.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [100, 400, 200]);
+        assert_eq!(validate_monotonic(&data.records), Ok(()));
+        let issues: Vec<_> = data.issues.iter().map(|issue| (issue.line, issue.message.as_str())).collect();
+        assert_eq!(issues, [(None, "dropped 2 rows dated no later than a row above, on line 4, 5")]);
+    }
+
+    #[test]
+    fn repair_keeps_the_last_of_rows_sharing_a_date() {
+        let path = fixture(
+            "duplicated.csv",
+            "date,x_led,y_led,time_delta
+\
+             2023-08-27T12:11:11.100Z,1,0,100\n2023-08-27T12:11:11.300Z,2,0,200\n2023-08-27T12:11:11.300Z,3,0,0\n\
+             2023-08-27T12:11:11.500Z,4,0,200\n2023-08-27T12:11:11.300Z,9,0,0\n",
+        );
+        let mut data = read_race_data(&path, None).unwrap();
+        repair_race_data(&path, &mut data);
+        assert_eq!(xs(&data.records), [1.0, 9.0, 4.0]);
+        assert_eq!(data.records.iter().map(|r| r.time_delta).collect::<Vec<_>>(), [100, 200, 200]);
+        assert_eq!(data.issues.len(), 1);
+        assert_eq!(data.issues[0].message, "moved 1 row into date order, dropped 2 rows sharing a date with a later row");
+
+        // Rows already in order with distinct dates are left alone
+        let mut clean = read_race_data(fixture("in-order.csv", &format!("{HEADER}{ROWS}")), None).unwrap();
+        repair_race_data(&path, &mut clean);
+        assert_eq!(xs(&clean.records), [1.0, 2.0, 3.0]);
+        assert_eq!(clean.records[0].time_delta, 240);
+        assert!(clean.issues.is_empty());
+    }
+
+    #[test]
+    fn wrong_column_names_and_values_say_where_they_are() {
+        let e = read_coordinates(fixture("coords-misnamed.csv", "x,y_led,designator\n1,2,U1\n"), None).unwrap_err();
+        assert_eq!(e.to_string(), "line 1: the header row has no x_led column (it has x, y_led, designator)");
+        let e = read_race_data(fixture("race-misnamed.csv", "time,x_led,y\n2023-08-27T12:11:11.114Z,1,2\n"), None).unwrap_err();
+        assert_eq!(e.to_string(), "line 1: the header row has no date or y_led column (it has time, x_led, y)");
+
+        let data = read_coordinates(fixture("coords-text.csv", "x_led,y_led\n1,2\n3,north\n5,6\n"), None).unwrap();
+        assert_eq!(data.records.len(), 2);
+        assert_eq!(data.issues[0].to_string().split_once(" line ").unwrap().1, "3: column y_led: invalid float literal in \"north\"");
+    }
+
+    #[test]
+    fn coordinates_keep_every_row_with_or_without_header() {
+        let rows = "6413,33,U1\n710,2755,U68\n";
+        let data = read_coordinates(fixture("coords.csv", &format!("x_led,y_led,designator\n{rows}")), None).unwrap();
+        assert_eq!(data.records.len(), 2);
+        assert_eq!(data.records[0].designator.as_deref(), Some("U1"));
+        let data = read_coordinates(fixture("coords-no-header.csv", rows), None).unwrap();
+        assert_eq!(data.records.len(), 2);
+        assert_eq!(data.records[0].x_led, 6413.0);
+    }
+
+    #[test]
+    fn coordinates_take_an_id_column_as_the_index_and_report_repeats() {
+        let data = read_coordinates(fixture("coords-id.csv", "id,x_led,y_led
+1,6413,33
+0,710,2755
+1,0,0
+"), None).unwrap();
+        let indices: Vec<_> = data.records.iter().map(|coord| coord.index).collect();
+        assert_eq!(indices, [Some(1), Some(0), Some(1)]);
+        assert_eq!(data.issues.len(), 1);
+        assert_eq!(data.issues[0].line, Some(4));
+        assert!(data.issues[0].message.contains("line 2"), "{}", data.issues[0].message);
+
+        let data = read_coordinates(fixture("coords-far-id.csv", "id,x_led,y_led\n0,0,0\n4000000000,1,0\n1,2,0\n"), None).unwrap();
+        assert_eq!(data.records.iter().map(|coord| coord.index).collect::<Vec<_>>(), [Some(0), Some(1)]);
+        assert_eq!((data.issues.len(), data.issues[0].line), (1, Some(3)), "left out");
+        let data = read_coordinates(fixture("coords-far-designator.csv", "x_led,y_led,designator\n0,0,U1\n1,0,U70000\n2,0,U2\n"), None).unwrap();
+        assert_eq!((data.records.len(), data.issues.len()), (2, 1));
+    }
+
+    #[test]
+    fn leds_keep_their_strip_positions_past_merged_and_dropped_rows() {
+        let data = read_coordinates(fixture("coords-gaps.csv", "x_led,y_led\n0,0\n1,0\n1,0\nx,0\n2,0\n3,0\n"), None).unwrap();
+        assert_eq!(data.issues.len(), 2, "{:?}", data.issues);
+        assert_eq!(crate::output::strip_positions(&data.records), [0, 1, 4, 5]);
+        let designated = read_coordinates(fixture("coords-designated.csv", "x_led,y_led,designator\n0,0,U2\n0,0,U3\n1,0,U1\n"), None).unwrap();
+        assert_eq!(crate::output::strip_positions(&designated.records), [1, 0], "designators still number the LEDs");
+    }
+
+    #[test]
+    fn coordinates_merge_repeated_positions_and_report_outliers() {
+        // Around a circle centred on (2000, 1500), one LED twice and one left at (0, 0)
+        let mut rows = String::from("x_led,y_led\n");
+        for led in 0..12 {
+            let angle = std::f64::consts::TAU * led as f64 / 12.0;
+            rows += &format!("{:.1},{:.1}\n", 2000.0 + 100.0 * angle.cos(), 1500.0 + 100.0 * angle.sin());
+        }
+        rows += "2100.0,1500.0\n0,0\n";
+        let data = read_coordinates(fixture("coords-repeats.csv", &rows), None).unwrap();
+        assert_eq!(data.records.len(), 13);
+        let issues: Vec<_> = data.issues.iter().map(|issue| (issue.line, issue.message.as_str())).collect();
+        assert_eq!(issues, [(Some(14), "same position as the LED on line 2, merged into it"), (Some(15), "LED at (0, 0) is far from the other LEDs (kept)")]);
+        assert_eq!(outliers(&data.records), [12]);
+
+        let e = read_coordinates(fixture("coords-one-point.csv", "x_led,y_led\n3,4\n3,4\n"), None).unwrap_err();
+        assert_eq!(e.to_string(), "fewer than 2 distinct LED positions, the track cannot be drawn");
+    }
+
+    #[test]
+    fn coordinates_read_an_optional_class_column() {
+        let data = read_coordinates(fixture("coords-class.csv", "x_led,y_led,class
+0,0,track
+1,0,
+2,0,pitlane
+3,0,pit
+4,0,grid
+5,0,garage
+"), None).unwrap();
+        let classes: Vec<_> = data.records.iter().map(|coord| coord.class).collect();
+        assert_eq!(classes, [LedClass::Track, LedClass::Track, LedClass::Pitlane, LedClass::Pitlane, LedClass::Grid, LedClass::Track]);
+        assert_eq!(data.issues.len(), 1, "an unknown class is reported");
+        assert!(data.issues[0].message.contains("unknown class `garage`"), "{:?}", data.issues);
+        assert_eq!(read_coordinates(fixture("coords-classless.csv", "x_led,y_led\n0,0\n1,0\n"), None).unwrap().records[0].class, LedClass::Track);
+    }
+
+    #[test]
+    fn rows_know_their_line_and_can_be_read_again_by_it() {
+        let path = fixture("race-crlf.csv", "date,x_led,y_led\r\n2023-08-27T12:11:11Z,1,2\r\nbad\r\n2023-08-27T12:11:12Z,3,4\r\n");
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(data.records.iter().map(|row| row.line).collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(data.issues[0].line, Some(3));
+        let row = read_race_line(&path, None, 4).unwrap().unwrap();
+        assert_eq!((row.x_led, row.y_led), (3.0, 4.0));
+        assert!(read_race_line(&path, None, 3).unwrap().is_none());
+    }
+
+    #[test]
+    fn lines_count_the_blank_lines_and_the_lines_inside_quotes() {
+        for (name, end) in [("race-lines-lf.csv", "\n"), ("race-lines-crlf.csv", "\r\n")] {
+            let rows = ["date,x_led,y_led", "2023-08-27T12:11:11Z,1,2", "", "\"2023-08-27", "T12:11:12Z\",3,4", "2023-08-27T12:11:13Z,5,6", ""];
+            let data = read_race_data(fixture(name, &rows.join(end)), None).unwrap();
+            assert_eq!(data.records.iter().map(|row| row.line).collect::<Vec<_>>(), [2, 6], "{name}");
+            assert_eq!(data.issues.iter().map(|issue| issue.line).collect::<Vec<_>>(), [Some(4)], "{name}: the quoted date starts on line 4");
+        }
+    }
+
+    #[test]
+    fn shipped_files_have_one_record_per_data_line() {
+        let data_lines = |path: &str| fs::read_to_string(path).unwrap().lines().skip(1).count();
+        let coords = read_coordinates("led_coords.csv", None).unwrap();
+        assert_eq!(coords.records.len(), data_lines("led_coords.csv"));
+        let race = read_race_data("time_delta_albon.csv", None).unwrap();
+        assert_eq!(race.records.len() + race.issues.len(), data_lines("time_delta_albon.csv"));
+    }
+
+    #[test]
+    fn reads_gzip_compressed_files() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(format!("{HEADER}{ROWS}").as_bytes()).unwrap();
+        let path = std::env::temp_dir().join(format!("f1-led-{}-compressed.csv.gz", std::process::id()));
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let data = read_race_data(&path, None).unwrap();
+        assert_eq!(xs(&data.records), [1.0, 2.0, 3.0]);
+        assert!(data.issues.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_headerless_layout() {
+        let path = fixture("bad-layout.csv", "1,2,3,4,5\n");
+        assert!(read_race_data(&path, None).is_err());
+    }
+}
@@ -0,0 +1,595 @@
+use chrono::{DateTime, Duration, Utc};
+use csv::Position;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::data::{self, DataIssue, RaceRows, RunRace, Telemetry};
+use crate::timestamp::RaceTime;
+use crate::track::LedIndex;
+
+/// Samples per block a streamed dataset reads at a time.
+const BLOCK_SAMPLES: usize = 4096;
+
+/// Blocks a streamed dataset keeps; two so that looking one row back from
+/// the start of a block does not read the file again.
+const CACHED_BLOCKS: usize = 2;
+
+/// Shortest time between two samples of a dataset unless configured, a
+/// fraction of the usual gap between rows.
+pub const DEFAULT_MIN_STEP_MS: u32 = 50;
+
+/// One row reduced to what playback needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Milliseconds since the dataset's `origin`.
+    pub t_ms: u32,
+    /// Nearest LED, an index into the coordinates, or `OFF_MAP`.
+    pub led: u16,
+    /// Line of the file the row is on, 0 for rows not read from a file.
+    pub line: u32,
+}
+
+impl Sample {
+    /// `led` of a row too far from every LED to be matched to one, see
+    /// `LedIndex::snap`.
+    pub const OFF_MAP: u16 = u16::MAX;
+
+    /// The LED the car is on, `None` while it is off the map.
+    pub fn on_led(&self) -> Option<usize> {
+        (self.led != Self::OFF_MAP).then_some(self.led as usize)
+    }
+}
+
+/// Where the samples of a dataset come from.
+pub trait DataSource: Send {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn sample(&self, row: usize) -> Option<Sample>;
+    /// Heap bytes currently held.
+    fn bytes(&self) -> usize;
+    /// A source of the same samples to read from another thread.
+    fn share(&self) -> Box<dyn DataSource>;
+    /// Problems met reading samples since last asked.
+    fn take_issues(&self) -> Vec<DataIssue> {
+        Vec::new()
+    }
+}
+
+/// Every sample in memory, shared by the datasets cloned from one another.
+impl DataSource for Arc<[Sample]> {
+    fn len(&self) -> usize {
+        <[Sample]>::len(self)
+    }
+
+    fn sample(&self, row: usize) -> Option<Sample> {
+        self.get(row).copied()
+    }
+
+    fn bytes(&self) -> usize {
+        <[Sample]>::len(self) * mem::size_of::<Sample>()
+    }
+
+    fn share(&self) -> Box<dyn DataSource> {
+        Box::new(self.clone())
+    }
+}
+
+/// A driver's rows after matching them to LEDs; the raw rows are dropped.
+///
+/// A row less than the minimum step after the one before, such as a run of
+/// rows with a `time_delta` of 0 and the same date, is shown that step
+/// after it instead, so the car moves LED by LED rather than jumping
+/// through them all in one frame. Later rows keep their own times, so the
+/// delay catches up at the next real gap.
+pub struct Dataset {
+    /// The first row's date minus its `time_delta`, so the first sample's
+    /// time is that delay and every later row's delay is the difference
+    /// to the row before, exactly as in the file.
+    pub origin: DateTime<Utc>,
+    /// Samples made from the rows before `from_rows_downsampled` thinned them.
+    pub downsampled_from: Option<usize>,
+    /// Share of the rows on the track, see `LedIndex::on_track`, `None`
+    /// without rows or LEDs to tell.
+    pub on_track: Option<f64>,
+    /// Rows left out for want of an LED to put them on: there are no LEDs
+    /// to match them to, or the nearest is past the LEDs a sample can number.
+    pub unplaced: usize,
+    /// File the rows were read from, to look a row up again by its line.
+    pub path: Option<PathBuf>,
+    source: Box<dyn DataSource>,
+    /// Telemetry of each sample, empty when no row has any. Streamed
+    /// datasets do not keep it.
+    telemetry: Arc<[Telemetry]>,
+}
+
+/// Clones share the samples held in memory; a streamed clone reads the
+/// file by itself, so it can go to another thread.
+impl Clone for Dataset {
+    fn clone(&self) -> Self {
+        Self {
+            origin: self.origin,
+            downsampled_from: self.downsampled_from,
+            on_track: self.on_track,
+            unplaced: self.unplaced,
+            path: self.path.clone(),
+            source: self.source.share(),
+            telemetry: self.telemetry.clone(),
+        }
+    }
+}
+
+impl Dataset {
+    /// Converts rows to samples held in memory, at least `min_step_ms`
+    /// apart. Rows earlier than the origin clamp to it.
+    pub fn from_rows(rows: &[RunRace], led_index: &LedIndex, min_step_ms: u32) -> Self {
+        Self::from_rows_downsampled(rows, led_index, min_step_ms, 0)
+    }
+
+    /// Converts rows like `from_rows`, then drops every sample less than
+    /// `interval_ms` after the last one kept on the same LED. A sample on
+    /// another LED is always kept, so the car moves exactly as before
+    /// while a car standing still is updated less often. 0 keeps them all.
+    pub fn from_rows_downsampled(rows: &[RunRace], led_index: &LedIndex, min_step_ms: u32, interval_ms: u32) -> Self {
+        let origin = rows.first().map_or(DateTime::UNIX_EPOCH, origin_of);
+        let on_track = share(rows.iter().filter(|row| led_index.on_track(row.x_led, row.y_led)).count(), rows.len()).filter(|_| !led_index.is_empty());
+        let mut timing = Timing { origin, min_step_ms, previous: None, unplaced: 0 };
+        let mut samples: Vec<(Sample, Telemetry)> = rows.iter().filter_map(|row| Some((timing.sample(row, led_index)?, row.telemetry))).collect();
+        let mut downsampled_from = None;
+        if interval_ms > 0 {
+            let before = samples.len();
+            let mut kept: Option<Sample> = None;
+            samples.retain(|(sample, _)| {
+                let keep = kept.is_none_or(|kept| sample.led != kept.led || sample.t_ms.saturating_sub(kept.t_ms) >= interval_ms);
+                if keep {
+                    kept = Some(*sample);
+                }
+                keep
+            });
+            downsampled_from = Some(before);
+        }
+        let (samples, mut telemetry): (Vec<Sample>, Vec<Telemetry>) = samples.into_iter().unzip();
+        if telemetry.iter().all(Telemetry::is_empty) {
+            telemetry = Vec::new();
+        }
+        let unplaced = timing.unplaced;
+        Self { origin, downsampled_from, on_track, unplaced, path: None, source: Box::new(Arc::<[Sample]>::from(samples)), telemetry: telemetry.into() }
+    }
+
+    /// Reads samples from `path` as playback needs them instead of holding
+    /// them all. The file is scanned once up front for its problems and for
+    /// where each block of samples starts. Fields are separated by
+    /// `delimiter`, or as `data::delimiter` guesses when `None`.
+    pub fn stream(
+        path: &Path,
+        led_index: Arc<LedIndex>,
+        min_step_ms: u32,
+        delimiter: Option<u8>,
+    ) -> Result<(Self, Vec<DataIssue>), Box<dyn Error>> {
+        let delimiter = data::delimiter(path, delimiter);
+        let mut timing: Option<Timing> = None;
+        let (mut starts, mut len) = (Vec::new(), 0);
+        let (mut rows, mut on_track) = (0, 0);
+        let (headers, issues) = data::scan_race_data(path, Some(delimiter), |row, position| {
+            rows += 1;
+            on_track += led_index.on_track(row.x_led, row.y_led) as usize;
+            let timing = timing.get_or_insert_with(|| Timing { origin: origin_of(&row), min_step_ms, previous: None, unplaced: 0 });
+            let previous = timing.previous;
+            if timing.sample(&row, &led_index).is_some() {
+                if len % BLOCK_SAMPLES == 0 {
+                    starts.push((position.clone(), previous));
+                }
+                len += 1;
+            }
+        })?;
+        let (origin, unplaced) = timing.map_or((DateTime::UNIX_EPOCH, 0), |timing| (timing.origin, timing.unplaced));
+        let on_track = share(on_track, rows).filter(|_| !led_index.is_empty());
+        let source = Streamed {
+            rows: RefCell::new(RaceRows::new(path, delimiter, headers)),
+            path: path.to_path_buf(),
+            led_index,
+            origin,
+            min_step_ms,
+            starts,
+            len,
+            blocks: RefCell::new(VecDeque::new()),
+            failed: Cell::new(false),
+            issues: RefCell::new(Vec::new()),
+        };
+        let dataset = Self {
+            origin,
+            downsampled_from: None,
+            on_track,
+            unplaced,
+            path: Some(path.to_path_buf()),
+            source: Box::new(source),
+            telemetry: Arc::new([]),
+        };
+        Ok((dataset, issues))
+    }
+
+    pub fn len(&self) -> usize {
+        self.source.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, row: usize) -> Option<Sample> {
+        self.source.sample(row)
+    }
+
+    /// What the car was doing at a row, `None` for datasets without telemetry.
+    pub fn telemetry(&self, row: usize) -> Option<Telemetry> {
+        self.telemetry.get(row).copied()
+    }
+
+    pub fn has_telemetry(&self) -> bool {
+        !self.telemetry.is_empty()
+    }
+
+    /// Absolute time of a row.
+    pub fn date(&self, row: usize) -> Option<DateTime<Utc>> {
+        let sample = self.get(row)?;
+        Some(self.origin + Duration::milliseconds(sample.t_ms as i64))
+    }
+
+    /// Delay before a row is shown: the time since the previous row.
+    pub fn step_ms(&self, row: usize) -> Option<u64> {
+        let t_ms = self.get(row)?.t_ms;
+        let previous = row.checked_sub(1).and_then(|prev| self.get(prev)).map_or(0, |sample| sample.t_ms);
+        Some(t_ms.saturating_sub(previous) as u64)
+    }
+
+    /// The first row for which `pred` is false, assuming it is true for
+    /// every row before that and false after, as `slice::partition_point`.
+    pub fn partition_point(&self, pred: impl Fn(&Sample) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.get(mid) {
+                Some(sample) if pred(&sample) => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        low
+    }
+
+    /// Keeps only the rows dated from `from` to `to`, both included, as if
+    /// the file had no others. The first row kept still waits its delay
+    /// from the row before it. A streamed dataset is read into memory, only
+    /// the rows kept.
+    pub fn trim(&mut self, from: DateTime<Utc>, to: DateTime<Utc>) {
+        let samples: Vec<Sample> = (0..self.len()).filter_map(|row| self.get(row)).collect();
+        let date = |sample: &Sample| self.origin + Duration::milliseconds(sample.t_ms as i64);
+        let first = samples.iter().position(|sample| date(sample) >= from).unwrap_or(samples.len());
+        let end = samples.iter().rposition(|sample| date(sample) <= to).map_or(0, |last| last + 1).max(first);
+        let shift_ms = first.checked_sub(1).map_or(0, |before| samples[before].t_ms);
+        self.origin += Duration::milliseconds(shift_ms as i64);
+        let kept: Vec<Sample> = samples[first..end].iter().map(|sample| Sample { t_ms: sample.t_ms - shift_ms, ..*sample }).collect();
+        if !self.telemetry.is_empty() {
+            self.telemetry = self.telemetry[first..end].into();
+        }
+        self.source = Box::new(Arc::<[Sample]>::from(kept));
+    }
+
+    /// Heap bytes held by the samples and their telemetry.
+    pub fn bytes(&self) -> usize {
+        self.source.bytes() + self.telemetry.len() * mem::size_of::<Telemetry>()
+    }
+
+    /// Problems met reading a streamed dataset's file again since last
+    /// asked, such as the file being cut short after the scan.
+    pub fn take_issues(&self) -> Vec<DataIssue> {
+        self.source.take_issues()
+    }
+}
+
+/// First and last dates of the rows a race is trimmed to.
+pub type TrimWindow = (DateTime<Utc>, DateTime<Utc>);
+
+/// The dates `start` and `end` put the race in `datasets` between, each
+/// taken from its first row as `--start-at` is, and cut to the rows there
+/// are. An error if nothing is left between them.
+pub fn trim_range<'a>(datasets: impl Iterator<Item = &'a Dataset> + Clone, start: Option<RaceTime>, end: Option<RaceTime>) -> Result<TrimWindow, String> {
+    let first = datasets.clone().filter_map(|dataset| dataset.date(0)).min().ok_or("no rows to trim")?;
+    let last = datasets.filter_map(|dataset| dataset.date(dataset.len().checked_sub(1)?)).max().ok_or("no rows to trim")?;
+    let from = start.map_or(first, |start| start.resolve(first, last)).max(first);
+    let to = end.map_or(last, |end| end.resolve(first, last)).min(last);
+    if from >= to {
+        let format = |date: DateTime<Utc>| date.format("%Y-%m-%d %H:%M:%S%.3f");
+        return Err(format!("start_time {} is not before end_time {}, the rows run from {} to {}", format(from), format(to), format(first), format(last)));
+    }
+    Ok((from, to))
+}
+
+/// `count` of `total` as a fraction, `None` of nothing.
+fn share(count: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| count as f64 / total as f64)
+}
+
+fn origin_of(first: &RunRace) -> DateTime<Utc> {
+    first.date - Duration::milliseconds(first.time_delta as i64)
+}
+
+/// Turns rows into samples in file order, keeping them `min_step_ms` apart.
+struct Timing {
+    origin: DateTime<Utc>,
+    min_step_ms: u32,
+    previous: Option<u32>, // Time of the last sample made
+    unplaced: usize, // Rows given no sample, see `Dataset::unplaced`
+}
+
+impl Timing {
+    /// The sample of `row`, `None` if it has no LED to be put on.
+    fn sample(&mut self, row: &RunRace, led_index: &LedIndex) -> Option<Sample> {
+        let led = match led_index.snap(row.x_led, row.y_led) {
+            _ if led_index.is_empty() => None,
+            Some(led) => u16::try_from(led).ok().filter(|&led| led != Sample::OFF_MAP),
+            None => Some(Sample::OFF_MAP),
+        };
+        let Some(led) = led else {
+            self.unplaced += 1;
+            return None;
+        };
+        let mut t_ms = (row.date - self.origin).num_milliseconds().clamp(0, u32::MAX as i64) as u32;
+        if let Some(previous) = self.previous {
+            t_ms = t_ms.max(previous.saturating_add(self.min_step_ms));
+        }
+        self.previous = Some(t_ms);
+        Some(Sample { t_ms, led, line: row.line.min(u32::MAX as u64) as u32 })
+    }
+}
+
+/// Samples read from disk a block at a time, keeping the blocks used last.
+struct Streamed {
+    path: PathBuf,
+    rows: RefCell<RaceRows>,
+    led_index: Arc<LedIndex>,
+    origin: DateTime<Utc>,
+    min_step_ms: u32,
+    /// Where in the file each block's first sample is, and the time of the sample before it.
+    starts: Vec<(Position, Option<u32>)>,
+    len: usize,
+    /// Most recently used first.
+    blocks: RefCell<VecDeque<(usize, Vec<Sample>)>>,
+    failed: Cell<bool>, // A read failed already, so later ones are not reported again
+    issues: RefCell<Vec<DataIssue>>, // Not yet taken
+}
+
+impl Streamed {
+    /// The samples of `block`, as many as could be read if the file fails.
+    fn read_block(&self, block: usize) -> Vec<Sample> {
+        let mut samples = Vec::with_capacity(BLOCK_SAMPLES);
+        let (start, previous) = &self.starts[block];
+        let mut timing = Timing { origin: self.origin, min_step_ms: self.min_step_ms, previous: *previous, unplaced: 0 };
+        let result = self.rows.borrow_mut().read(start, |row| {
+            samples.extend(timing.sample(&row, &self.led_index));
+            samples.len() < BLOCK_SAMPLES
+        });
+        if let Err(e) = result {
+            if !self.failed.replace(true) {
+                let line = samples.last().map_or(start.line(), |sample| sample.line as u64);
+                let message = format!("cannot read past line {line} again: {e}");
+                self.issues.borrow_mut().push(DataIssue { file: self.path.clone(), line: None, message });
+            }
+        }
+        samples
+    }
+}
+
+impl DataSource for Streamed {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn sample(&self, row: usize) -> Option<Sample> {
+        if row >= self.len {
+            return None;
+        }
+        let (block, offset) = (row / BLOCK_SAMPLES, row % BLOCK_SAMPLES);
+        let mut blocks = self.blocks.borrow_mut();
+        match blocks.iter().position(|(cached, _)| *cached == block) {
+            Some(0) => {}
+            Some(i) => {
+                let entry = blocks.remove(i).unwrap();
+                blocks.push_front(entry);
+            }
+            None => {
+                blocks.push_front((block, self.read_block(block)));
+                blocks.truncate(CACHED_BLOCKS);
+            }
+        }
+        blocks[0].1.get(offset).copied()
+    }
+
+    fn bytes(&self) -> usize {
+        let blocks: usize = self.blocks.borrow().iter().map(|(_, samples)| samples.capacity()).sum();
+        blocks * mem::size_of::<Sample>() + self.starts.capacity() * mem::size_of::<(Position, Option<u32>)>()
+    }
+
+    fn share(&self) -> Box<dyn DataSource> {
+        Box::new(Streamed {
+            path: self.path.clone(),
+            rows: RefCell::new(self.rows.borrow().reopen()),
+            led_index: self.led_index.clone(),
+            origin: self.origin,
+            min_step_ms: self.min_step_ms,
+            starts: self.starts.clone(),
+            len: self.len,
+            blocks: RefCell::new(VecDeque::new()),
+            failed: Cell::new(false),
+            issues: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn take_issues(&self) -> Vec<DataIssue> {
+        mem::take(&mut self.issues.borrow_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LedCoordinate;
+
+    #[test]
+    fn samples_keep_delays_and_dates() {
+        let leds = [LedCoordinate::default(), LedCoordinate { x_led: 10.0, ..Default::default() }];
+        let start = "2023-08-27T12:11:11.114Z".parse::<DateTime<Utc>>().unwrap();
+        let rows: Vec<RunRace> = [(0, 240, 1.0), (200, 200, 9.0), (420, 220, 8.0)]
+            .iter()
+            .map(|&(ms, time_delta, x_led)| RunRace {
+                date: start + Duration::milliseconds(ms),
+                x_led,
+                y_led: 0.0,
+                time_delta,
+                line: 0,
+                telemetry: Default::default(),
+            })
+            .collect();
+        let dataset = Dataset::from_rows(&rows, &LedIndex::new(&leds), 0);
+        let steps: Vec<_> = (0..3).filter_map(|row| dataset.step_ms(row)).collect();
+        assert_eq!(steps, [240, 200, 220]);
+        assert_eq!(dataset.date(0), Some(start));
+        assert_eq!(dataset.date(2), Some(rows[2].date));
+        let leds: Vec<_> = (0..3).filter_map(|row| dataset.get(row)).map(|sample| sample.led).collect();
+        assert_eq!(leds, [0, 1, 1]);
+        assert_eq!(dataset.unplaced, 0);
+        assert_eq!(Dataset::from_rows(&rows, &LedIndex::new(&[]), 0).unplaced, 3, "no LEDs to put the rows on");
+
+        // The last LED is one more than a sample can number
+        let many: Vec<LedCoordinate> = (0..=Sample::OFF_MAP).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let dataset = Dataset::from_rows(&rows[..1], &LedIndex::new(&many), 0);
+        assert_eq!((dataset.len(), dataset.unplaced), (1, 0));
+        let far = [RunRace { date: start, x_led: Sample::OFF_MAP as f64, y_led: 0.0, time_delta: 0, line: 0, telemetry: Default::default() }];
+        assert_eq!(Dataset::from_rows(&far, &LedIndex::new(&many), 0).unplaced, 1);
+    }
+
+    #[test]
+    fn streamed_samples_match_the_in_memory_ones() {
+        let coordinates = data::read_coordinates("led_coords.csv", None).unwrap().records;
+        let led_index = Arc::new(LedIndex::new(&coordinates));
+        let path = Path::new("time_delta_albon.csv");
+        let rows = data::read_race_data(path, None).unwrap().records;
+        let in_memory = Dataset::from_rows(&rows, &led_index, DEFAULT_MIN_STEP_MS);
+        let (streamed, _) = Dataset::stream(path, led_index, DEFAULT_MIN_STEP_MS, None).unwrap();
+
+        assert_eq!(streamed.len(), in_memory.len());
+        assert!(streamed.len() > BLOCK_SAMPLES * CACHED_BLOCKS);
+        assert_eq!(streamed.origin, in_memory.origin);
+        for row in (0..in_memory.len()).chain((0..in_memory.len()).rev().step_by(997)) {
+            assert_eq!(streamed.get(row), in_memory.get(row), "row {row}");
+        }
+        assert_eq!(streamed.get(streamed.len()), None);
+        let shared = std::thread::spawn({
+            let (streamed, in_memory) = (streamed.clone(), in_memory.clone());
+            move || (0..streamed.len()).step_by(1013).all(|row| streamed.get(row) == in_memory.get(row))
+        });
+        assert!(shared.join().unwrap(), "clones read the same samples on another thread");
+        assert_eq!(in_memory.get(0).map(|sample| sample.line), Some(2), "the line after the header");
+        assert!(streamed.bytes() < in_memory.bytes());
+    }
+
+    #[test]
+    fn streamed_gzip_files_read_on_and_report_being_cut_short() {
+        let coordinates = data::read_coordinates("led_coords.csv", None).unwrap().records;
+        let led_index = Arc::new(LedIndex::new(&coordinates));
+        let text = std::fs::read("time_delta_albon.csv").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, &text).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-streamed-gz", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (whole, cut) = (dir.join("time_delta_albon.csv.gz"), dir.join("time_delta_cut.csv.gz"));
+        std::fs::write(&whole, &compressed).unwrap();
+        std::fs::write(&cut, &compressed[..compressed.len() * 3 / 4]).unwrap();
+
+        let rows = data::read_race_data("time_delta_albon.csv", None).unwrap().records;
+        let in_memory = Dataset::from_rows(&rows, &led_index, DEFAULT_MIN_STEP_MS);
+        let (streamed, _) = Dataset::stream(&whole, led_index.clone(), DEFAULT_MIN_STEP_MS, None).unwrap();
+        assert_eq!(streamed.len(), in_memory.len());
+        for row in (0..in_memory.len()).chain([0, in_memory.len() - 1, BLOCK_SAMPLES * 2 + 5]) {
+            assert_eq!(streamed.get(row), in_memory.get(row), "row {row}");
+        }
+        assert!(streamed.take_issues().is_empty());
+
+        let (streamed, issues) = Dataset::stream(&cut, led_index, DEFAULT_MIN_STEP_MS, None).unwrap();
+        assert!(!issues.is_empty(), "the scan finds the end missing");
+        assert!(streamed.len() > BLOCK_SAMPLES && streamed.len() < in_memory.len());
+        for row in 0..streamed.len() {
+            assert_eq!(streamed.get(row), in_memory.get(row), "row {row}");
+        }
+        let issues = streamed.take_issues();
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert!(issues[0].message.starts_with("cannot read past line"));
+        streamed.get(0);
+        streamed.get(streamed.len() - 1);
+        assert!(streamed.take_issues().is_empty(), "reported once");
+    }
+
+    #[test]
+    fn rows_without_a_delay_are_spread_out_by_the_minimum_step() {
+        let leds: Vec<LedCoordinate> = (0..6).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let start = "2023-08-27T12:11:11.114Z".parse::<DateTime<Utc>>().unwrap();
+        // Three rows sharing a date after the first, then the next row a second later
+        let rows: Vec<RunRace> = [(0, 240, 0.0), (0, 0, 1.0), (0, 0, 2.0), (0, 0, 3.0), (1000, 1000, 4.0), (1010, 10, 5.0)]
+            .iter()
+            .map(|&(ms, time_delta, x_led)| RunRace { date: start + Duration::milliseconds(ms), x_led, y_led: 0.0, time_delta, line: 0, telemetry: Default::default() })
+            .collect();
+        let led_index = LedIndex::new(&leds);
+        let steps = |dataset: &Dataset| (0..dataset.len()).filter_map(|row| dataset.step_ms(row)).collect::<Vec<_>>();
+        assert_eq!(steps(&Dataset::from_rows(&rows, &led_index, 0)), [240, 0, 0, 0, 1000, 10]);
+        let spread = Dataset::from_rows(&rows, &led_index, 50);
+        assert_eq!(steps(&spread), [240, 50, 50, 50, 850, 50]);
+        assert_eq!(spread.date(4), Some(rows[4].date), "back on time after the gap");
+    }
+
+    #[test]
+    fn downsampling_thins_a_car_standing_still_but_keeps_every_move() {
+        let leds: Vec<LedCoordinate> = (0..3).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let start = "2023-08-27T12:11:11.114Z".parse::<DateTime<Utc>>().unwrap();
+        // Four rows a second on LED 0, a quick hop over LEDs 1 and 2, then standing on LED 2
+        let rows: Vec<RunRace> = [(0, 0.0), (250, 0.0), (500, 0.0), (750, 0.0), (1000, 0.0), (1100, 1.0), (1200, 2.0), (1450, 2.0), (2200, 2.0)]
+            .iter()
+            .map(|&(ms, x_led)| RunRace { date: start + Duration::milliseconds(ms), x_led, y_led: 0.0, time_delta: 0, line: 0, telemetry: Default::default() })
+            .collect();
+        let led_index = LedIndex::new(&leds);
+        let dataset = Dataset::from_rows_downsampled(&rows, &led_index, 0, 500);
+        let kept: Vec<(u32, u16)> = (0..dataset.len()).filter_map(|row| dataset.get(row)).map(|sample| (sample.t_ms, sample.led)).collect();
+        assert_eq!(kept, [(0, 0), (500, 0), (1000, 0), (1100, 1), (1200, 2), (2200, 2)]);
+        assert_eq!(dataset.downsampled_from, Some(9));
+        assert_eq!(Dataset::from_rows(&rows, &led_index, 0).downsampled_from, None);
+    }
+
+    #[test]
+    fn trimming_keeps_the_rows_in_the_window_with_their_dates_and_delays() {
+        let leds: Vec<LedCoordinate> = (0..5).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let led_index = LedIndex::new(&leds);
+        let start = "2023-08-27T12:11:11.114Z".parse::<DateTime<Utc>>().unwrap();
+        // A row a second, one at each LED
+        let rows: Vec<RunRace> = (0..5)
+            .map(|row| RunRace { date: start + Duration::seconds(row), x_led: row as f64, y_led: 0.0, time_delta: 1000, line: 0, telemetry: Default::default() })
+            .collect();
+        let datasets = [Dataset::from_rows(&rows, &led_index, 0)];
+
+        let (from, to) = trim_range(datasets.iter(), Some(RaceTime::Elapsed(1_500)), Some(RaceTime::Elapsed(3_000))).unwrap();
+        assert_eq!((from, to), (start + Duration::milliseconds(1_500), start + Duration::seconds(3)));
+        let mut dataset = Dataset::from_rows(&rows, &led_index, 0);
+        dataset.trim(from, to);
+        let leds: Vec<_> = (0..dataset.len()).filter_map(|row| dataset.get(row)).map(|sample| sample.led).collect();
+        assert_eq!(leds, [2, 3]);
+        assert_eq!((dataset.date(0), dataset.step_ms(0)), (Some(rows[2].date), Some(1000)), "still a second after the row before");
+
+        assert_eq!(trim_range(datasets.iter(), None, Some(RaceTime::Elapsed(60_000))), Ok((start, rows[4].date)), "cut to the rows there are");
+        let error = trim_range(datasets.iter(), Some(RaceTime::Elapsed(3_000)), Some(RaceTime::Elapsed(1_000))).unwrap_err();
+        assert!(error.contains("is not before end_time"), "{error}");
+        assert!(trim_range(datasets.iter(), Some(RaceTime::Elapsed(60_000)), None).is_err(), "starts after the last row");
+    }
+}
@@ -0,0 +1,44 @@
+// File dialogs, which only the native build has. In the browser every
+// dialog is cancelled straight away. Call them from the UI thread, which some
+// platforms need for their dialogs, and which waits while one is open.
+
+use std::path::{Path, PathBuf};
+
+/// Asks where to save a file called `file_name` of the type `filter`
+/// describes, `None` if cancelled.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(filter: &str, extensions: &[&str], file_name: &str) -> Option<PathBuf> {
+    rfd::FileDialog::new().add_filter(filter, extensions).set_file_name(file_name).save_file()
+}
+
+/// Asks for a file of the type `filter` describes, starting in `dir`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_file(filter: &str, extensions: &[&str], dir: &Path) -> Option<PathBuf> {
+    rfd::FileDialog::new().add_filter(filter, extensions).set_directory(dir).pick_file()
+}
+
+/// Asks for a folder, starting in `dir` if given.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_folder(dir: Option<&Path>) -> Option<PathBuf> {
+    let dialog = rfd::FileDialog::new();
+    match dir {
+        Some(dir) => dialog.set_directory(dir),
+        None => dialog,
+    }
+    .pick_folder()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(_filter: &str, _extensions: &[&str], _file_name: &str) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn pick_file(_filter: &str, _extensions: &[&str], _dir: &Path) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn pick_folder(_dir: Option<&Path>) -> Option<PathBuf> {
+    None
+}
@@ -0,0 +1,257 @@
+use csv::{ReaderBuilder, Trim};
+use eframe::egui::Color32;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dataset::Dataset;
+use crate::palette::Palette;
+
+/// File names looked for in the data directory when no metadata file is configured.
+pub const DEFAULT_FILES: [&str; 2] = ["drivers.toml", "drivers.csv"];
+
+/// What the metadata file says about one driver.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DriverInfo {
+    /// Driver key, the driver part of the dataset file name (`verstappen`).
+    pub driver: String,
+    /// Display name; the one derived from the file name is used when absent.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub team: String,
+    #[serde(default)]
+    pub number: Option<u32>,
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Color of the car's LED, `#rrggbb`.
+    #[serde(deserialize_with = "hex_color")]
+    pub primary: Color32,
+    /// Color of the car's trail, so teammates sharing a primary color can be told apart.
+    #[serde(default, deserialize_with = "optional_hex_color")]
+    pub secondary: Option<Color32>,
+}
+
+/// The driver metadata file, by driver key.
+#[derive(Debug, Default, Clone)]
+pub struct DriverTable {
+    pub path: PathBuf,
+    drivers: BTreeMap<String, DriverInfo>,
+}
+
+#[derive(Deserialize)]
+struct DriversFile {
+    driver: Vec<DriverInfo>,
+}
+
+impl DriverTable {
+    /// Reads a `.toml` file with one `[[driver]]` table per driver, or a CSV
+    /// file with a header row naming the same fields.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let drivers: Vec<DriverInfo> = if path.extension().is_some_and(|extension| extension == "toml") {
+            let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+            let file: DriversFile = toml::from_str(&text).map_err(|e| format!("invalid {}: {e}", path.display()))?;
+            file.driver
+        } else {
+            let mut rdr = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+            rdr.deserialize().collect::<Result<_, _>>().map_err(|e| format!("invalid {}: {e}", path.display()))?
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            drivers: drivers.into_iter().map(|info| (info.driver.to_lowercase(), info)).collect(),
+        })
+    }
+
+    pub fn get(&self, driver_key: &str) -> Option<&DriverInfo> {
+        self.drivers.get(&driver_key.to_lowercase())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drivers.is_empty()
+    }
+}
+
+/// One driver of the loaded race: who it is, its rows and how its car is lit.
+#[derive(Clone)]
+pub struct Driver {
+    pub key: String, // Driver part of the dataset file name, used to look up codes and team colors
+    pub name: String, // Shown in the legend and panels
+    pub code: String, // Short code drawn as a label on the track
+    pub team: Option<String>, // From the metadata file, when it has the driver
+    pub records: Dataset, // Rows already matched to LEDs; replace them through the simulation so it plays them again
+    pub color: Color32, // Color of the car's LED
+    pub trail_color: Option<Color32>, // Second color for the trail behind the car, if it has one
+    pub visible: bool, // Whether the car is lit
+}
+
+impl Driver {
+    /// A visible driver with `records` in `color`, without a name until one is given.
+    pub fn new(records: Dataset, color: Color32) -> Self {
+        Self { key: String::new(), name: String::new(), code: String::new(), team: None, records, color, trail_color: None, visible: true }
+    }
+
+    /// Takes the name, code and team the metadata file gives for the driver.
+    pub fn apply(&mut self, info: &DriverInfo) {
+        if let Some(name) = &info.name {
+            self.name = name.clone();
+        }
+        if let Some(code) = &info.code {
+            self.code = code.clone();
+        }
+        self.team = Some(info.team.clone());
+    }
+}
+
+/// Colors picked for drivers by name, over the palette and the metadata
+/// file, kept for later races with drivers of the same names.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ColorOverrides {
+    picked: BTreeMap<String, Color32>,
+}
+
+impl ColorOverrides {
+    /// Shows the driver named `name` in `color` whatever the palette and metadata say.
+    pub fn set_driver_color(&mut self, name: &str, color: Color32) {
+        self.picked.insert(name.to_string(), color);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Color32> {
+        self.picked.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.picked.is_empty()
+    }
+
+    /// Gives every driver back the color of the palette or metadata.
+    pub fn clear(&mut self) {
+        self.picked.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Color32)> {
+        self.picked.iter().map(|(name, &color)| (name.as_str(), color))
+    }
+
+    /// Colors each of `drivers` from `palette`, or from `table` for drivers
+    /// in the metadata file, or with the color picked here.
+    pub fn recolor(&self, palette: Palette, drivers: &mut [Driver], table: &DriverTable) {
+        let keys: Vec<String> = drivers.iter().map(|driver| driver.key.clone()).collect();
+        for (driver, color) in drivers.iter_mut().zip(palette.colors(&keys)) {
+            driver.color = color;
+            if let Some(info) = table.get(&driver.key) {
+                driver.color = info.primary;
+            }
+            if let Some(picked) = self.get(&driver.name) {
+                driver.color = picked;
+            }
+        }
+    }
+}
+
+impl FromIterator<(String, Color32)> for ColorOverrides {
+    fn from_iter<I: IntoIterator<Item = (String, Color32)>>(iter: I) -> Self {
+        Self { picked: iter.into_iter().collect() }
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Color32, String> {
+    let hex = text.trim().trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+    let value = value.ok_or_else(|| format!("`{text}` is not a #rrggbb color"))?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok(Color32::from_rgb(r, g, b))
+}
+
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color32, D::Error> {
+    parse_hex(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn optional_hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color32>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(text) if !text.trim().is_empty() => parse_hex(&text).map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_and_toml_files_read_the_same() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-drivers", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("drivers.csv");
+        fs::write(
+            &csv,
+            "driver,name,team,number,code,primary,secondary\n\
+             Verstappen,Max Verstappen,Red Bull Racing,1,VER,#3671C6,\n\
+             perez,Sergio Perez,Red Bull Racing,11,PER,#3671c6,#FFD700\n",
+        )
+        .unwrap();
+        let toml = dir.join("drivers.toml");
+        fs::write(
+            &toml,
+            r##"
+            [[driver]]
+            driver = "verstappen"
+            name = "Max Verstappen"
+            team = "Red Bull Racing"
+            number = 1
+            code = "VER"
+            primary = "#3671C6"
+
+            [[driver]]
+            driver = "perez"
+            name = "Sergio Perez"
+            team = "Red Bull Racing"
+            number = 11
+            code = "PER"
+            primary = "#3671C6"
+            secondary = "#FFD700"
+            "##,
+        )
+        .unwrap();
+
+        for path in [&csv, &toml] {
+            let table = DriverTable::read(path).unwrap();
+            let max = table.get("verstappen").unwrap();
+            assert_eq!((max.number, max.code.as_deref()), (Some(1), Some("VER")));
+            assert_eq!(max.primary, Color32::from_rgb(0x36, 0x71, 0xC6));
+            assert_eq!(max.secondary, None);
+            assert_eq!(table.get("perez").unwrap().secondary, Some(Color32::from_rgb(0xFF, 0xD7, 0x00)));
+            assert!(table.get("hamilton").is_none());
+        }
+
+        fs::write(&csv, "driver,team,primary\nalbon,Williams,blue\n").unwrap();
+        assert!(DriverTable::read(&csv).unwrap_err().to_string().contains("#rrggbb"));
+    }
+
+    #[test]
+    fn picked_colors_go_over_the_palette_and_metadata_by_name() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-picked-colors", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("drivers.csv");
+        fs::write(&path, "driver,name,team,primary\nperez,Checo,Red Bull Racing,#3671C6\n").unwrap();
+        let table = DriverTable::read(&path).unwrap();
+        let records = Dataset::from_rows(&[], &crate::track::LedIndex::new(&[]), 0);
+        let driver = |key: &str, name: &str| Driver { key: key.to_string(), name: name.to_string(), ..Driver::new(records.clone(), Color32::BLACK) };
+        let mut drivers = [driver("albon", "Alex Albon"), driver("perez", "Checo"), driver("sainz", "Carlos Sainz")];
+        let palette = Palette::Default.colors(&["albon".to_string(), "perez".to_string(), "sainz".to_string()]);
+        let colors = |drivers: &[Driver]| drivers.iter().map(|driver| driver.color).collect::<Vec<_>>();
+
+        let mut overrides = ColorOverrides::default();
+        overrides.recolor(Palette::Default, &mut drivers, &table);
+        assert_eq!(colors(&drivers), [palette[0], Color32::from_rgb(0x36, 0x71, 0xC6), palette[2]]);
+        overrides.set_driver_color("Checo", Color32::WHITE);
+        overrides.set_driver_color("Carlos Sainz", Color32::RED);
+        overrides.set_driver_color("Nobody", Color32::BLUE);
+        overrides.recolor(Palette::Default, &mut drivers, &table);
+        assert_eq!(colors(&drivers), [palette[0], Color32::WHITE, Color32::RED]);
+        assert_eq!(overrides.iter().count(), 3, "kept for races that have them");
+        overrides.clear();
+        assert!(overrides.is_empty());
+    }
+}
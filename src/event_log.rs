@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::sim::Simulation;
+
+/// Longest rows wait in memory before they are written out, so a crash
+/// loses no more of the log than this.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes a CSV row each time playback moves on a moment of the timeline:
+/// its simulated time, the `current_index` it brought playback to and the
+/// LED each car is on, blank for a hidden car or one off the map. The LEDs
+/// are worked out from the datasets as `Simulation::leds_at` does, so a
+/// race logs the same rows however fast or smoothly it played.
+pub struct EventLog {
+    out: BufWriter<File>,
+    logged: usize, // Moments of the timeline logged, or skipped by seeking back
+    rows: Vec<usize>, // Rows of each dataset due by the last moment logged
+    flushed: Instant,
+    keys: Vec<String>,
+}
+
+impl EventLog {
+    /// Starts the log at `path` over, with a column for each car named by
+    /// its dataset's key in `keys`.
+    pub fn create(path: &Path, keys: &[String]) -> Result<Self, Box<dyn Error>> {
+        Self::open(path, keys, File::create(path))
+    }
+
+    /// Goes on at the end of the log at `path` for other cars, after a new
+    /// header row with their `keys`.
+    pub fn append(path: &Path, keys: &[String]) -> Result<Self, Box<dyn Error>> {
+        Self::open(path, keys, OpenOptions::new().append(true).create(true).open(path))
+    }
+
+    fn open(path: &Path, keys: &[String], file: std::io::Result<File>) -> Result<Self, Box<dyn Error>> {
+        let file = file.map_err(|e| format!("cannot write event log {}: {e}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        write!(out, "sim_ms,index")?;
+        for key in keys {
+            write!(out, ",{key}")?;
+        }
+        writeln!(out)?;
+        out.flush()?;
+        Ok(Self { out, logged: 0, rows: vec![0; keys.len()], flushed: Instant::now(), keys: keys.to_vec() })
+    }
+
+    /// The dataset keys the log has a column for.
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Writes a row for every moment `sim` moved on since the last call. A
+    /// seek back logs nothing, the moments played again after it are.
+    pub fn log(&mut self, sim: &Simulation) -> Result<(), Box<dyn Error>> {
+        self.log_at(sim, Instant::now())
+    }
+
+    fn log_at(&mut self, sim: &Simulation, now: Instant) -> Result<(), Box<dyn Error>> {
+        if sim.current_index < self.logged {
+            self.rows.fill(0);
+            self.catch_up(sim, sim.current_index);
+            self.logged = sim.current_index;
+        }
+        for index in self.logged + 1..=sim.current_index {
+            let sim_ms = self.catch_up(sim, index);
+            write!(self.out, "{sim_ms},{index}")?;
+            for (dataset_idx, &rows) in self.rows.iter().enumerate() {
+                let driver = sim.drivers.get(dataset_idx).filter(|driver| driver.visible);
+                let sample = rows.checked_sub(1).and_then(|row| driver?.records.get(row));
+                match sample.and_then(|sample| sample.on_led()) {
+                    Some(led) => write!(self.out, ",{led}")?,
+                    None => write!(self.out, ",")?,
+                }
+            }
+            writeln!(self.out)?;
+        }
+        self.logged = sim.current_index;
+        if now.saturating_duration_since(self.flushed) >= FLUSH_INTERVAL {
+            self.out.flush()?;
+            self.flushed = now;
+        }
+        Ok(())
+    }
+
+    /// Counts the rows of each dataset due once the first `index` moments
+    /// are shown, on from those already counted, and gives the simulated
+    /// time of the last of those moments.
+    fn catch_up(&mut self, sim: &Simulation, index: usize) -> u64 {
+        let Some(due_ms) = index.checked_sub(1).and_then(|last| sim.moment_ms(last)) else {
+            return 0;
+        };
+        for (dataset_idx, rows) in self.rows.iter_mut().enumerate() {
+            while sim.row_ms(dataset_idx, *rows).is_some_and(|ms| ms <= due_ms) {
+                *rows += 1;
+            }
+        }
+        due_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::fixtures;
+
+    #[test]
+    fn logs_each_moment_played_with_the_led_of_every_car() {
+        let rows = |rows: &[(i64, f64)]| -> Vec<_> { rows.iter().map(|&(s, x_led)| fixtures::row(s * 1000, (x_led, 0.0), 1000)).collect() };
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(4), None, &[rows(&[(0, 0.0), (1, 1.0), (3, 3.0)]), rows(&[(0, 2.0), (2, 3.0)])]);
+        let path = std::env::temp_dir().join(format!("f1-led-{}-event-log.csv", std::process::id()));
+        let mut log = EventLog::create(&path, &["albon".to_string(), "sainz".to_string()]).unwrap();
+        let now = Instant::now();
+
+        sim.advance();
+        log.log_at(&sim, now).unwrap();
+        sim.seek(3);
+        log.log_at(&sim, now).unwrap();
+        sim.seek(1); // Nothing logged going back
+        log.log_at(&sim, now).unwrap();
+        sim.drivers[1].visible = false;
+        sim.seek(2);
+        log.log_at(&sim, now).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "sim_ms,index,albon,sainz\n", "not flushed yet");
+
+        log.log_at(&sim, now + FLUSH_INTERVAL).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "sim_ms,index,albon,sainz\n1000,1,0,2\n2000,2,1,2\n3000,3,1,3\n2000,2,1,\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
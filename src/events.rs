@@ -0,0 +1,162 @@
+use chrono::{DateTime, Utc};
+use csv::{ReaderBuilder, Trim};
+use eframe::egui::Color32;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::Path;
+
+use crate::timestamp;
+
+/// File name looked for next to the datasets when no events file is configured.
+pub const DEFAULT_FILE: &str = "events.csv";
+
+/// The track state race control can declare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Green,
+    Yellow,
+    SafetyCar,
+    VirtualSafetyCar,
+    Red,
+    Chequered,
+}
+
+impl Flag {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind.to_uppercase().as_str() {
+            "GREEN" => Some(Flag::Green),
+            "YELLOW" => Some(Flag::Yellow),
+            "SC" => Some(Flag::SafetyCar),
+            "VSC" => Some(Flag::VirtualSafetyCar),
+            "RED" => Some(Flag::Red),
+            "CHEQUERED" => Some(Flag::Chequered),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Flag::Green => "GREEN FLAG",
+            Flag::Yellow => "YELLOW FLAG",
+            Flag::SafetyCar => "SAFETY CAR",
+            Flag::VirtualSafetyCar => "VIRTUAL SAFETY CAR",
+            Flag::Red => "RED FLAG",
+            Flag::Chequered => "CHEQUERED FLAG",
+        }
+    }
+
+    /// Background and text color of the banner.
+    pub fn colors(self) -> (Color32, Color32) {
+        match self {
+            Flag::Green => (Color32::from_rgb(0, 150, 60), Color32::WHITE),
+            Flag::Yellow | Flag::SafetyCar | Flag::VirtualSafetyCar => (Color32::from_rgb(250, 210, 0), Color32::BLACK),
+            Flag::Red => (Color32::from_rgb(215, 25, 30), Color32::WHITE),
+            Flag::Chequered => (Color32::WHITE, Color32::BLACK),
+        }
+    }
+
+    /// Color of the track outline while this flag is out, if it changes.
+    pub fn outline_tint(self) -> Option<Color32> {
+        match self {
+            Flag::Yellow | Flag::SafetyCar | Flag::VirtualSafetyCar => Some(Color32::from_rgb(160, 135, 0)),
+            Flag::Red => Some(Color32::from_rgb(150, 20, 20)),
+            Flag::Green | Flag::Chequered => None,
+        }
+    }
+}
+
+/// One row of the events file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaceEvent {
+    pub date: DateTime<Utc>,
+    /// `None` for kinds that are not a track state, shown as plain messages.
+    pub flag: Option<Flag>,
+    pub kind: String,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct Row {
+    date: String,
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// Race control events in date order.
+#[derive(Debug, Default, Clone)]
+pub struct RaceEvents {
+    events: Vec<RaceEvent>,
+}
+
+impl RaceEvents {
+    /// Reads a CSV file with `date,kind,message` columns, in any order of dates.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        let mut events = Vec::new();
+        for (i, row) in rdr.deserialize::<Row>().enumerate() {
+            let invalid = |e: &dyn std::fmt::Display| format!("invalid {} line {}: {e}", path.display(), i + 2);
+            let row = row.map_err(|e| invalid(&e))?;
+            let date = timestamp::parse_timestamp(&row.date).map_err(|e| invalid(&e))?;
+            events.push(RaceEvent { date, flag: Flag::parse(&row.kind), kind: row.kind, message: row.message });
+        }
+        events.sort_by_key(|event| event.date);
+        Ok(Self { events })
+    }
+
+    /// The events up to and including `date`.
+    fn until(&self, date: DateTime<Utc>) -> &[RaceEvent] {
+        &self.events[..self.events.partition_point(|event| event.date <= date)]
+    }
+
+    /// The last track state declared at or before `date`.
+    pub fn flag_at(&self, date: DateTime<Utc>) -> Option<(Flag, &RaceEvent)> {
+        self.until(date).iter().rev().find_map(|event| Some((event.flag?, event)))
+    }
+
+    /// When the chequered flag is first shown, if it is.
+    pub fn chequered(&self) -> Option<DateTime<Utc>> {
+        self.events.iter().find(|event| event.flag == Some(Flag::Chequered)).map(|event| event.date)
+    }
+
+    /// The last event of any kind at or before `date`.
+    pub fn latest_at(&self, date: DateTime<Utc>) -> Option<&RaceEvent> {
+        self.until(date).last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn events_are_sorted_and_unknown_kinds_are_messages() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-events.csv", std::process::id()));
+        fs::write(
+            &path,
+            "date,kind,message\n\
+             2023-08-27T13:20:00Z,SC,Safety car deployed\n\
+             2023-08-27T13:00:00Z,green,\n\
+             2023-08-27T13:25:00Z,PENALTY,5 seconds for car 23\n\
+             2023-08-27T13:30:00Z,RED,Rain\n",
+        )
+        .unwrap();
+        let events = RaceEvents::read(&path).unwrap();
+        let at = |time: &str| format!("2023-08-27T{time}Z").parse::<DateTime<Utc>>().unwrap();
+
+        assert!(events.flag_at(at("12:59:59")).is_none());
+        assert_eq!(events.flag_at(at("13:00:00")).unwrap().0, Flag::Green);
+        let penalty = events.latest_at(at("13:26:00")).unwrap();
+        assert_eq!((penalty.flag, penalty.kind.as_str()), (None, "PENALTY"));
+        let (flag, _) = events.flag_at(at("13:26:00")).unwrap();
+        assert_eq!((flag, flag.outline_tint().is_some()), (Flag::SafetyCar, true));
+        assert_eq!(events.flag_at(at("14:00:00")).unwrap().1.message, "Rain");
+
+        fs::write(&path, "date,kind,message\nlater,SC,\n").unwrap();
+        assert!(RaceEvents::read(&path).unwrap_err().to_string().contains("line 2"));
+    }
+}
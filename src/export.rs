@@ -0,0 +1,395 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use eframe::egui::Color32;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+
+use crate::data::{LedCoordinate, Telemetry};
+use crate::dataset::{Dataset, Sample};
+use crate::dialog;
+use crate::loader;
+use crate::render::{self, Backdrop, Camera, LedShape, LedSize, TrackStyle};
+use crate::track::Spacing;
+
+/// One driver's rows after matching them to LEDs.
+pub struct ResolvedDriver {
+    /// Driver key, used in the file name.
+    pub key: String,
+    pub origin: DateTime<Utc>,
+    pub samples: Vec<Sample>,
+    /// Telemetry of each sample, empty when the driver has none.
+    pub telemetry: Vec<Telemetry>,
+}
+
+impl ResolvedDriver {
+    /// Every sample of `dataset`, its origin moved by the driver's manual offset.
+    pub fn new(key: String, dataset: &Dataset, offset_ms: i64) -> Self {
+        Self {
+            key,
+            origin: dataset.origin + Duration::milliseconds(offset_ms),
+            samples: (0..dataset.len()).filter_map(|row| dataset.get(row)).collect(),
+            telemetry: (0..dataset.len()).map_while(|row| dataset.telemetry(row)).collect(),
+        }
+    }
+}
+
+/// What a finished export reports: a message for the status line, or why
+/// it failed.
+pub type ExportResult = Result<String, String>;
+
+/// The writing after a file dialog, on a background thread so large
+/// exports do not hold up the window. The dialog itself is asked on the
+/// UI thread, and no export is made if it is cancelled.
+pub struct Export {
+    receiver: Receiver<ExportResult>,
+    /// Frames written so far, for exports that report progress.
+    pub done: Arc<AtomicUsize>,
+    /// Frames in all, 0 for exports without progress.
+    pub total: usize,
+    cancel: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipFormat {
+    Gif,
+    Apng,
+}
+
+impl ClipFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ClipFormat::Gif => "gif",
+            ClipFormat::Apng => "png",
+        }
+    }
+
+    /// Rough bytes per pixel of one frame, for the estimate shown before
+    /// exporting. Both formats compress the flat track view well.
+    fn bytes_per_pixel(self) -> f64 {
+        match self {
+            ClipFormat::Gif => 0.1,
+            ClipFormat::Apng => 0.15,
+        }
+    }
+}
+
+/// An animation of the track view, everything needed to draw its frames.
+pub struct Clip {
+    pub size: [u32; 2],
+    pub fps: u32,
+    pub format: ClipFormat,
+    pub coordinates: Vec<LedCoordinate>,
+    pub keep_aspect: bool,
+    pub shape: LedShape,
+    pub led_size: LedSize,
+    pub spacing: Spacing,
+    pub unlit_outline: Option<Color32>,
+    pub outline: Vec<Vec<usize>>,
+    pub outline_color: Color32,
+    pub outline_shades: Vec<f32>,
+    pub sector_lines: Vec<(usize, usize)>,
+    pub background: Color32,
+    pub backdrop: Backdrop, // What `background` was picked as, for the unlit LEDs
+    /// The lit LEDs of each frame.
+    pub frames: Vec<Vec<(usize, Color32)>>,
+    /// Where the camera is in each frame, empty to show the whole track throughout.
+    pub cameras: Vec<Camera>,
+}
+
+impl Clip {
+    /// A rough size of the encoded file.
+    pub fn estimated_bytes(size: [u32; 2], frames: usize, format: ClipFormat) -> u64 {
+        (size[0] as f64 * size[1] as f64 * frames as f64 * format.bytes_per_pixel()) as u64
+    }
+
+    fn render(&self, frame: &[(usize, Color32)], camera: Camera) -> RgbaImage {
+        let style = TrackStyle {
+            keep_aspect: self.keep_aspect,
+            shape: self.shape,
+            led_size: self.led_size,
+            spacing: self.spacing,
+            unlit_outline: self.unlit_outline,
+            outline: &self.outline,
+            outline_color: self.outline_color,
+            outline_shades: &self.outline_shades,
+            sector_lines: &self.sector_lines,
+            backdrop: self.backdrop,
+        };
+        render::render_zoomed(self.size, &self.coordinates, &style, frame, self.background, camera)
+    }
+
+    /// Encodes every frame to `path`, counting them in `done`. Stops and
+    /// removes the partial file if `cancel` is set. Returns `false` if cancelled.
+    pub fn write(&self, path: &Path, done: &AtomicUsize, cancel: &AtomicBool) -> Result<bool, Box<dyn Error>> {
+        let file = BufWriter::new(File::create(path)?);
+        let cancelled = match self.format {
+            ClipFormat::Gif => {
+                let mut encoder = GifEncoder::new_with_speed(file, 10);
+                encoder.set_repeat(Repeat::Infinite)?;
+                self.each_frame(done, cancel, |image| {
+                    let delay = Delay::from_numer_denom_ms(1000, self.fps);
+                    Ok(encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?)
+                })?
+            }
+            ClipFormat::Apng => {
+                let mut encoder = png::Encoder::new(file, self.size[0], self.size[1]);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(self.frames.len() as u32, 0)?;
+                encoder.set_frame_delay(1, self.fps as u16)?;
+                let mut writer = encoder.write_header()?;
+                let cancelled = self.each_frame(done, cancel, |image| Ok(writer.write_image_data(image.as_raw())?))?;
+                if !cancelled {
+                    writer.finish()?;
+                }
+                cancelled
+            }
+        };
+        if cancelled {
+            fs::remove_file(path)?;
+        }
+        Ok(!cancelled)
+    }
+
+    /// Renders the frames in order for `encode`; `true` if cancelled first.
+    fn each_frame(
+        &self,
+        done: &AtomicUsize,
+        cancel: &AtomicBool,
+        mut encode: impl FnMut(RgbaImage) -> Result<(), Box<dyn Error>>,
+    ) -> Result<bool, Box<dyn Error>> {
+        for (idx, frame) in self.frames.iter().enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(true);
+            }
+            encode(self.render(frame, self.cameras.get(idx).copied().unwrap_or(Camera::FITTED)))?;
+            done.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(false)
+    }
+}
+
+impl Export {
+    /// Asks where to save `image` and writes it as a PNG.
+    pub fn png(image: RgbaImage, file_name: String) -> Option<Self> {
+        let path = dialog::save_file("PNG image", &["png"], &file_name)?;
+        Some(Self::spawn(move || {
+            image.save(&path).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+            Ok(format!("Saved {}", path.display()))
+        }))
+    }
+
+    /// Asks where to save `text` and writes it there.
+    pub fn text(text: String, file_name: String) -> Option<Self> {
+        Self::document(text, "Text", &["log", "txt"], file_name)
+    }
+
+    /// Asks where to save `text`, as a file of one of the `filter` kinds.
+    pub fn document(text: String, filter: &'static str, extensions: &'static [&'static str], file_name: String) -> Option<Self> {
+        let path = dialog::save_file(filter, extensions, &file_name)?;
+        Some(Self::spawn(move || {
+            fs::write(&path, text).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+            Ok(format!("Saved {}", path.display()))
+        }))
+    }
+
+    /// Asks for a folder and writes one resolved CSV per driver into it,
+    /// from each driver's key, dataset and manual offset.
+    pub fn resolved(drivers: Vec<(String, Dataset, i64)>, leds: Vec<(f64, f64)>) -> Option<Self> {
+        let dir = dialog::pick_folder(None)?;
+        Some(Self::spawn(move || {
+            let drivers: Vec<ResolvedDriver> = drivers.into_iter().map(|(key, dataset, offset_ms)| ResolvedDriver::new(key, &dataset, offset_ms)).collect();
+            let paths = write_resolved(&dir, &drivers, &leds).map_err(|e| e.to_string())?;
+            Ok(format!("Exported {} files to {}", paths.len(), dir.display()))
+        }))
+    }
+
+    /// Asks where to save `clip` and encodes it with the `total` frames
+    /// `frames` makes, made on the export thread too, reporting progress.
+    pub fn clip(mut clip: Clip, total: usize, frames: impl FnOnce() -> Vec<Vec<(usize, Color32)>> + Send + 'static) -> Option<Self> {
+        let extension = clip.format.extension();
+        let path = dialog::save_file("Animation", &[extension], &format!("clip.{extension}"))?;
+        let (done, cancel) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicBool::new(false)));
+        let (thread_done, thread_cancel) = (done.clone(), cancel.clone());
+        let mut export = Self::spawn(move || {
+            clip.frames = frames();
+            let finished = clip
+                .write(&path, &thread_done, &thread_cancel)
+                .map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+            Ok(if finished { format!("Saved {}", path.display()) } else { "Export cancelled".to_string() })
+        });
+        (export.done, export.total, export.cancel) = (done, total, cancel);
+        Some(export)
+    }
+
+    fn spawn(job: impl FnOnce() -> ExportResult + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        loader::spawn(move || {
+            let _ = sender.send(job());
+        });
+        Self {
+            receiver,
+            done: Arc::new(AtomicUsize::new(0)),
+            total: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Asks the export to stop after the frame it is on.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// The result once the export has finished.
+    pub fn poll(&self) -> Option<ExportResult> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("export stopped".to_string())),
+        }
+    }
+}
+
+/// A telemetry value of a row as written to the CSV, `None` for a blank.
+type TelemetryValue = fn(&Telemetry) -> Option<String>;
+
+/// Telemetry columns of the resolved CSV, each with its value in a row.
+const TELEMETRY_COLUMNS: [(&str, TelemetryValue); 3] = [
+    ("speed", |t| t.speed.map(|speed| speed.to_string())),
+    ("gear", |t| t.gear.map(|gear| gear.to_string())),
+    ("throttle", |t| t.throttle.map(|throttle| throttle.to_string())),
+];
+
+/// Writes `resolved_<driver>.csv` for each driver into `dir`, one row per
+/// sample with its date, the matched LED and that LED's position, left
+/// blank while the car is off the map. The `speed`, `gear` and `throttle`
+/// columns follow for a driver whose telemetry has them, blank where a row
+/// does not. Returns the files written.
+pub fn write_resolved(dir: &Path, drivers: &[ResolvedDriver], leds: &[(f64, f64)]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = Vec::new();
+    for driver in drivers {
+        let path = dir.join(format!("resolved_{}.csv", driver.key));
+        let columns: Vec<_> = TELEMETRY_COLUMNS.iter().filter(|(_, value)| driver.telemetry.iter().any(|t| value(t).is_some())).collect();
+        let write = || -> std::io::Result<()> {
+            let mut out = BufWriter::new(File::create(&path)?);
+            write!(out, "date,led,x_led,y_led")?;
+            for (name, _) in &columns {
+                write!(out, ",{name}")?;
+            }
+            writeln!(out)?;
+            for (row, sample) in driver.samples.iter().enumerate() {
+                let date = driver.origin + Duration::milliseconds(sample.t_ms as i64);
+                let date = date.to_rfc3339_opts(SecondsFormat::Millis, true);
+                match sample.on_led() {
+                    Some(led) => {
+                        let (x, y) = leds[led];
+                        write!(out, "{date},{led},{x},{y}")?;
+                    }
+                    None => write!(out, "{date},,,")?, // Off the map
+                }
+                let telemetry = driver.telemetry.get(row).copied().unwrap_or_default();
+                for (_, value) in &columns {
+                    write!(out, ",{}", value(&telemetry).unwrap_or_default())?;
+                }
+                writeln!(out)?;
+            }
+            out.flush()
+        };
+        write().map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn resolved_csv_has_dates_leds_and_positions() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-export", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let driver = ResolvedDriver {
+            key: "albon".to_string(),
+            origin: "2023-08-27T12:11:11Z".parse().unwrap(),
+            samples: vec![Sample { t_ms: 240, led: 1, line: 2 }, Sample { t_ms: 1000, led: 0, line: 3 }],
+            telemetry: Vec::new(),
+        };
+        let paths = write_resolved(&dir, &[driver], &[(6413.0, 33.0), (710.0, 2755.5)]).unwrap();
+        assert_eq!(paths, [dir.join("resolved_albon.csv")]);
+        assert_eq!(
+            fs::read_to_string(&paths[0]).unwrap(),
+            "date,led,x_led,y_led\n2023-08-27T12:11:11.240Z,1,710,2755.5\n2023-08-27T12:11:12.000Z,0,6413,33\n"
+        );
+
+        let telemetry = |speed, gear| Telemetry { speed, gear, throttle: None };
+        let driver = ResolvedDriver {
+            key: "sainz".to_string(),
+            origin: "2023-08-27T12:11:11Z".parse().unwrap(),
+            samples: vec![Sample { t_ms: 0, led: 0, line: 2 }, Sample { t_ms: 500, led: Sample::OFF_MAP, line: 3 }],
+            telemetry: vec![telemetry(Some(301.5), Some(8)), telemetry(None, Some(7))],
+        };
+        let paths = write_resolved(&dir, &[driver], &[(6413.0, 33.0)]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&paths[0]).unwrap(),
+            "date,led,x_led,y_led,speed,gear\n2023-08-27T12:11:11.000Z,0,6413,33,301.5,8\n2023-08-27T12:11:11.500Z,,,,,7\n",
+            "only the telemetry columns the driver has"
+        );
+        let _ = fs::remove_file(dir.join("resolved_x.csv"));
+        let empty = ResolvedDriver { key: "x".to_string(), origin: Utc::now(), samples: Vec::new(), telemetry: Vec::new() };
+        let error = write_resolved(&dir.join("missing"), &[empty], &[]).unwrap_err();
+        assert!(error.to_string().starts_with("cannot write"));
+    }
+
+    #[test]
+    fn clips_encode_every_frame_and_cancel_cleanly() {
+        let coordinates = [(0.0, 0.0), (1.0, 1.0)].map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let clip = |format| Clip {
+            size: [40, 30],
+            fps: 10,
+            format,
+            coordinates: coordinates.to_vec(),
+            keep_aspect: false,
+            shape: LedShape::Circle,
+            led_size: LedSize::Spacing(0.5),
+            spacing: Spacing::of(&coordinates),
+            unlit_outline: Some(Color32::DARK_GRAY),
+            outline: Vec::new(),
+            outline_color: Color32::GRAY,
+            outline_shades: Vec::new(),
+            sector_lines: Vec::new(),
+            background: Color32::from_gray(27),
+            backdrop: Backdrop::Theme,
+            frames: vec![vec![(0, Color32::RED)], vec![(1, Color32::BLUE)], Vec::new()],
+            cameras: Vec::new(),
+        };
+        for format in [ClipFormat::Gif, ClipFormat::Apng] {
+            let path = std::env::temp_dir().join(format!("f1-led-{}-clip.{}", std::process::id(), format.extension()));
+            let done = AtomicUsize::new(0);
+            assert!(clip(format).write(&path, &done, &AtomicBool::new(false)).unwrap());
+            assert_eq!(done.into_inner(), 3);
+            let frames = match format {
+                ClipFormat::Gif => {
+                    use image::AnimationDecoder;
+                    let decoder = image::codecs::gif::GifDecoder::new(std::io::BufReader::new(File::open(&path).unwrap())).unwrap();
+                    decoder.into_frames().count() as u32
+                }
+                ClipFormat::Apng => {
+                    let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+                    decoder.read_info().unwrap().info().animation_control().unwrap().num_frames
+                }
+            };
+            assert_eq!(frames, 3, "{format:?}");
+
+            assert!(!clip(format).write(&path, &AtomicUsize::new(0), &AtomicBool::new(true)).unwrap());
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"F1LF";
+const BINARY_VERSION: u8 = 1;
+
+/// What the LEDs show after waiting `delay_ms` since the previous frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedFrame {
+    pub delay_ms: u32,
+    /// Three bytes per LED, in strip order.
+    pub rgb: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct JsonFrames<'a> {
+    leds: usize,
+    frames: Vec<JsonFrame<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFrame<'a> {
+    delay_ms: u32,
+    rgb: Vec<&'a [u8]>,
+}
+
+/// Writes `frames` to `path` for hardware that replays a race on its own.
+///
+/// A `.json` file is `{"leds": N, "frames": [{"delay_ms": 240, "rgb": [[r, g, b], ...]}, ...]}`.
+/// Any other file is the same in little-endian binary: the magic `F1LF`, a
+/// format version byte, the LED count as `u16` and the frame count as `u32`,
+/// then each frame as a `u32` delay followed by three bytes per LED. LEDs
+/// are in strip order in both.
+pub fn write(path: &Path, frames: &[LedFrame]) -> Result<(), Box<dyn Error>> {
+    let file = File::create(path).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    if path.extension().is_some_and(|extension| extension == "json") {
+        write_json(&mut out, frames)?;
+    } else {
+        write_binary(&mut out, frames)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_json(out: &mut impl Write, frames: &[LedFrame]) -> Result<(), Box<dyn Error>> {
+    let json = JsonFrames {
+        leds: frames.first().map_or(0, |frame| frame.rgb.len() / 3),
+        frames: frames
+            .iter()
+            .map(|frame| JsonFrame { delay_ms: frame.delay_ms, rgb: frame.rgb.chunks(3).collect() })
+            .collect(),
+    };
+    serde_json::to_writer(out, &json)?;
+    Ok(())
+}
+
+fn write_binary(out: &mut impl Write, frames: &[LedFrame]) -> Result<(), Box<dyn Error>> {
+    let leds = frames.first().map_or(0, |frame| frame.rgb.len() / 3);
+    let leds = u16::try_from(leds).map_err(|_| format!("{leds} LEDs do not fit the binary format"))?;
+    out.write_all(MAGIC)?;
+    out.write_all(&[BINARY_VERSION])?;
+    out.write_all(&leds.to_le_bytes())?;
+    out.write_all(&(frames.len() as u32).to_le_bytes())?;
+    for frame in frames {
+        out.write_all(&frame.delay_ms.to_le_bytes())?;
+        out.write_all(&frame.rgb)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_and_binary_hold_the_same_frames() {
+        let frames = [
+            LedFrame { delay_ms: 240, rgb: vec![255, 0, 0, 0, 0, 0] },
+            LedFrame { delay_ms: 1000, rgb: vec![0, 0, 0, 1, 2, 3] },
+        ];
+        let mut json = Vec::new();
+        write_json(&mut json, &frames).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"{"leds":2,"frames":[{"delay_ms":240,"rgb":[[255,0,0],[0,0,0]]},{"delay_ms":1000,"rgb":[[0,0,0],[1,2,3]]}]}"#
+        );
+
+        let mut binary = Vec::new();
+        write_binary(&mut binary, &frames).unwrap();
+        assert_eq!(&binary[..11], b"F1LF\x01\x02\x00\x02\x00\x00\x00");
+        assert_eq!(&binary[11..21], [240, 0, 0, 0, 255, 0, 0, 0, 0, 0]);
+        assert_eq!(binary.len(), 11 + 2 * (4 + 6));
+    }
+}
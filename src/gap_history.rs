@@ -0,0 +1,102 @@
+/// Simulated time each point of the chart stands for. A later arrival in
+/// the same stretch replaces the point, so a long race stays cheap to draw.
+pub const SAMPLE_MS: u64 = 1000;
+
+/// Each driver's gap to the leader over simulated time, measured at every
+/// LED like a timing loop: the first car to arrive on an LED for the nth
+/// time leads there, and every other car is as far behind as it arrives
+/// later for its own nth time.
+#[derive(Debug, Default, Clone)]
+pub struct GapHistory {
+    first_arrivals: Vec<Vec<u64>>, // Per LED, simulated time of its first, second... arrival by any car
+    visits: Vec<Vec<u32>>, // Per driver and LED, times the driver arrived on it
+    series: Vec<Vec<[f64; 2]>>, // Per driver, points of simulated seconds and seconds behind
+    arrivals: Vec<(usize, usize, u64)>, // Every arrival so far as driver, LED and simulated time, to rewind by
+}
+
+impl GapHistory {
+    pub fn new(drivers: usize, leds: usize) -> Self {
+        Self {
+            first_arrivals: vec![Vec::new(); leds],
+            visits: vec![vec![0; leds]; drivers],
+            series: vec![Vec::new(); drivers],
+            arrivals: Vec::new(),
+        }
+    }
+
+    /// Arrivals noted so far, to go back to with `rewound`.
+    pub fn arrivals(&self) -> usize {
+        self.arrivals.len()
+    }
+
+    /// The history as it was after its first `arrivals` arrivals, worked
+    /// out again from them rather than by replaying the race.
+    pub fn rewound(&self, arrivals: usize) -> Self {
+        let mut gaps = Self::new(self.visits.len(), self.first_arrivals.len());
+        for &(driver, led, sim_ms) in &self.arrivals[..arrivals.min(self.arrivals.len())] {
+            gaps.arrive(driver, led, sim_ms);
+        }
+        gaps
+    }
+
+    /// Notes that `driver` arrived on `led` at `sim_ms`, arrivals coming in
+    /// race order.
+    pub fn arrive(&mut self, driver: usize, led: usize, sim_ms: u64) {
+        let (Some(visits), Some(first_arrivals)) = (self.visits.get_mut(driver).and_then(|visits| visits.get_mut(led)), self.first_arrivals.get_mut(led))
+        else {
+            return;
+        };
+        let nth = *visits as usize;
+        *visits += 1;
+        self.arrivals.push((driver, led, sim_ms));
+        let gap_ms = match first_arrivals.get(nth) {
+            Some(&leader_ms) => sim_ms.saturating_sub(leader_ms),
+            None => {
+                first_arrivals.push(sim_ms);
+                0
+            }
+        };
+        let point = [sim_ms as f64 / 1000.0, gap_ms as f64 / 1000.0];
+        let series = &mut self.series[driver];
+        match series.last_mut() {
+            Some(last) if (last[0] * 1000.0).round() as u64 / SAMPLE_MS == sim_ms / SAMPLE_MS => *last = point,
+            _ => series.push(point),
+        }
+    }
+
+    /// The gap of `driver` over time, as simulated seconds and seconds behind.
+    pub fn series(&self, driver: usize) -> &[[f64; 2]] {
+        self.series.get(driver).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_car_on_an_led_each_time_round_leads_there() {
+        let mut gaps = GapHistory::new(2, 3);
+        for (driver, led, sim_ms) in [(0, 0, 0), (1, 0, 500), (0, 1, 2000), (0, 2, 3000), (1, 1, 3500), (0, 0, 4000), (1, 2, 4500), (1, 0, 9000)] {
+            gaps.arrive(driver, led, sim_ms);
+        }
+        assert_eq!(gaps.series(0), [[0.0, 0.0], [2.0, 0.0], [3.0, 0.0], [4.0, 0.0]]);
+        // Behind on every LED, the second time round on LED 0 five seconds down
+        assert_eq!(gaps.series(1), [[0.5, 0.5], [3.5, 1.5], [4.5, 1.5], [9.0, 5.0]]);
+
+        // A later arrival in the same second replaces the point
+        gaps.arrive(1, 1, 9500);
+        assert_eq!(gaps.series(1).last(), Some(&[9.5, 0.0]), "first on LED 1 for the second time");
+        assert_eq!(gaps.series(1).len(), 4);
+        assert!(gaps.series(5).is_empty());
+
+        let rewound = gaps.rewound(5);
+        assert_eq!(rewound.series(0), [[0.0, 0.0], [2.0, 0.0], [3.0, 0.0]]);
+        assert_eq!(rewound.series(1), [[0.5, 0.5], [3.5, 1.5]]);
+        let mut replayed = rewound.clone();
+        for (driver, led, sim_ms) in [(0, 0, 4000), (1, 2, 4500), (1, 0, 9000), (1, 1, 9500)] {
+            replayed.arrive(driver, led, sim_ms);
+        }
+        assert_eq!((replayed.series(0), replayed.series(1)), (gaps.series(0), gaps.series(1)), "rewinding forgets the later arrivals");
+    }
+}
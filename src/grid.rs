@@ -0,0 +1,204 @@
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::data::DataIssue;
+use crate::track::LedIndex;
+
+/// File name looked for in the race folder when no grid file is configured.
+pub const DEFAULT_FILE: &str = "grid.csv";
+
+/// Red lights that come on one by one before lights out.
+pub const START_LIGHTS: usize = 5;
+
+/// How long each of the lights takes to fade in as it comes on.
+pub const LIGHT_FADE: Duration = Duration::from_millis(120);
+
+#[derive(Deserialize)]
+struct Row {
+    driver: String,
+    led: Option<usize>,
+    x_led: Option<f64>,
+    y_led: Option<f64>,
+}
+
+/// Where a driver lines up: an LED of the coordinates file, or a point
+/// matched to its nearest LED.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Led(usize),
+    At(f64, f64),
+}
+
+/// The starting grid, where each driver is parked before lights out.
+#[derive(Debug, Default, Clone)]
+pub struct Grid {
+    pub path: Option<PathBuf>,
+    slots: Vec<(String, Slot)>, // Driver, lowercased, and slot
+}
+
+impl Grid {
+    /// Reads a CSV file with a `driver` column, the driver's key or name,
+    /// and either an `led` column or `x_led,y_led` columns. Rows that cannot
+    /// be used, LEDs past the last of `led_count` and drivers given a second
+    /// slot come back as issues.
+    pub fn read(path: &Path, led_count: usize) -> Result<(Self, Vec<DataIssue>), Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        let issue = |line: u64, message: String| DataIssue { file: path.to_path_buf(), line: Some(line), message };
+        let mut slots: Vec<(String, Slot)> = Vec::new();
+        let mut issues = Vec::new();
+        for (i, row) in rdr.deserialize::<Row>().enumerate() {
+            let line = i as u64 + 2;
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    issues.push(issue(line, e.to_string()));
+                    continue;
+                }
+            };
+            let slot = match (row.led, row.x_led, row.y_led) {
+                (Some(led), _, _) if led >= led_count => {
+                    issues.push(issue(line, format!("LED {led} is out of range ({led_count} LEDs)")));
+                    continue;
+                }
+                (Some(led), _, _) => Slot::Led(led),
+                (None, Some(x), Some(y)) => Slot::At(x, y),
+                _ => {
+                    issues.push(issue(line, format!("no led or x_led,y_led for {}", row.driver)));
+                    continue;
+                }
+            };
+            let driver = row.driver.to_lowercase();
+            if slots.iter().any(|(other, _)| *other == driver) {
+                issues.push(issue(line, format!("{} is already on the grid", row.driver)));
+                continue;
+            }
+            slots.push((driver, slot));
+        }
+        Ok((Self { path: Some(path.to_path_buf()), slots }, issues))
+    }
+
+    /// The LED the driver with file key `key` or name `name` lines up on,
+    /// if the grid has them.
+    pub fn led_of(&self, key: &str, name: &str, led_index: &LedIndex) -> Option<usize> {
+        let (key, name) = (key.to_lowercase(), name.to_lowercase());
+        let &(_, slot) = self.slots.iter().find(|(driver, _)| *driver == key || *driver == name)?;
+        match slot {
+            Slot::Led(led) => Some(led),
+            Slot::At(x, y) => led_index.nearest(x, y),
+        }
+    }
+
+    /// Moves the slots on LEDs to the LED `moved_to` gives for each, after
+    /// the LEDs changed, and leaves out those it gives none for. The drivers
+    /// left off the grid come back.
+    pub fn remap(&mut self, moved_to: impl Fn(usize) -> Option<usize>) -> Vec<String> {
+        let mut dropped = Vec::new();
+        self.slots.retain_mut(|(driver, slot)| match *slot {
+            Slot::Led(led) => match moved_to(led) {
+                Some(led) => {
+                    *slot = Slot::Led(led);
+                    true
+                }
+                None => {
+                    dropped.push(driver.clone());
+                    false
+                }
+            },
+            Slot::At(..) => true,
+        });
+        dropped
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// How many of the `START_LIGHTS` are on `elapsed` into a start sequence of
+/// `duration`: one more each fifth of it, all of them until lights out.
+pub fn lights_on(elapsed: Duration, duration: Duration) -> usize {
+    if duration.is_zero() || elapsed >= duration {
+        return 0;
+    }
+    let fraction = elapsed.as_secs_f64() / duration.as_secs_f64();
+    ((fraction * START_LIGHTS as f64) as usize + 1).min(START_LIGHTS)
+}
+
+/// How bright `light` of the `START_LIGHTS` is `elapsed` into a start
+/// sequence of `duration`, from 0.0 for off to 1.0, fading in over
+/// `LIGHT_FADE` as it comes on. Every light is off at lights out.
+pub fn light_level(light: usize, elapsed: Duration, duration: Duration) -> f32 {
+    if light >= lights_on(elapsed, duration) {
+        return 0.0;
+    }
+    let on_at = duration / START_LIGHTS as u32 * light as u32;
+    (elapsed.saturating_sub(on_at).as_secs_f32() / LIGHT_FADE.as_secs_f32()).min(1.0)
+}
+
+/// Whether one of the lights is still fading in `elapsed` into a start
+/// sequence of `duration`.
+pub fn fading(elapsed: Duration, duration: Duration) -> bool {
+    (0..lights_on(elapsed, duration)).any(|light| light_level(light, elapsed, duration) < 1.0)
+}
+
+/// How long after `elapsed` the next of the lights comes on, or they go out.
+pub fn until_lights_change(elapsed: Duration, duration: Duration) -> Duration {
+    let step = duration / START_LIGHTS as u32;
+    if step.is_zero() {
+        return Duration::ZERO;
+    }
+    let steps_passed = (elapsed.as_nanos() / step.as_nanos()) as u32;
+    (step * (steps_passed + 1)).saturating_sub(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LedCoordinate;
+    use std::fs;
+
+    #[test]
+    fn slots_are_leds_or_the_nearest_led_to_a_point() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-grid.csv", std::process::id()));
+        fs::write(&path, "driver,led,x_led,y_led\nverstappen,3,,\nLeclerc,,0.9,0.1\nsainz,9,,\nalbon,,,\nverstappen,1,,\n").unwrap();
+        let (grid, issues) = Grid::read(&path, 4).unwrap();
+        let messages: Vec<_> = issues.iter().map(|issue| (issue.line, issue.message.as_str())).collect();
+        assert_eq!(
+            messages,
+            [(Some(4), "LED 9 is out of range (4 LEDs)"), (Some(5), "no led or x_led,y_led for albon"), (Some(6), "verstappen is already on the grid")]
+        );
+
+        let coordinates: Vec<_> = (0..4).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let led_index = LedIndex::new(&coordinates);
+        assert_eq!(grid.led_of("verstappen", "Max Verstappen", &led_index), Some(3));
+        assert_eq!(grid.led_of("charles", "leclerc", &led_index), Some(1), "by name, case aside");
+        assert_eq!(grid.led_of("sainz", "Carlos Sainz", &led_index), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_lights_come_on_one_by_one_then_go_out() {
+        let duration = Duration::from_secs(5);
+        let on: Vec<_> = [0, 999, 1000, 4999, 5000].iter().map(|&ms| lights_on(Duration::from_millis(ms), duration)).collect();
+        assert_eq!(on, [1, 1, 2, 5, 0]);
+        assert_eq!(lights_on(Duration::ZERO, Duration::ZERO), 0);
+        assert_eq!(until_lights_change(Duration::from_millis(2500), duration), Duration::from_millis(500));
+        assert_eq!(until_lights_change(Duration::from_millis(3000), duration), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn each_light_fades_in_as_it_comes_on() {
+        let duration = Duration::from_secs(5);
+        let level = |light, ms| light_level(light, Duration::from_millis(ms), duration);
+        assert_eq!([level(1, 999), level(1, 1000), level(1, 1060), level(1, 1120), level(1, 4999), level(1, 5000)], [0.0, 0.0, 0.5, 1.0, 1.0, 0.0]);
+        assert!(fading(Duration::from_millis(2050), duration));
+        assert!(!fading(Duration::from_millis(2500), duration));
+        assert!(!fading(Duration::from_millis(5000), duration), "all out");
+    }
+}
@@ -0,0 +1,108 @@
+use eframe::egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::Dataset;
+
+/// Size of the heatmap image unless configured, in pixels.
+pub const DEFAULT_SIZE: [u32; 2] = [1600, 1000];
+
+/// Colors from the least visited LED to the most visited one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gradient {
+    /// Dark red through orange and yellow to white.
+    #[default]
+    Heat,
+    /// Dark blue through cyan to white.
+    Ice,
+    Gray,
+}
+
+impl Gradient {
+    pub const ALL: [Gradient; 3] = [Gradient::Heat, Gradient::Ice, Gradient::Gray];
+
+    /// The name used on the command line and in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Gradient::Heat => "heat",
+            Gradient::Ice => "ice",
+            Gradient::Gray => "gray",
+        }
+    }
+
+    fn stops(self) -> &'static [[u8; 3]] {
+        match self {
+            Gradient::Heat => &[[80, 0, 0], [200, 30, 0], [255, 140, 0], [255, 230, 40], [255, 255, 255]],
+            Gradient::Ice => &[[0, 0, 80], [0, 60, 200], [0, 200, 230], [255, 255, 255]],
+            Gradient::Gray => &[[40, 40, 40], [255, 255, 255]],
+        }
+    }
+
+    /// The color a fraction `t` of the way along, clamped to 0.0..=1.0.
+    pub fn color(self, t: f32) -> Color32 {
+        let stops = self.stops();
+        let scaled = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (scaled.floor() as usize).min(stops.len() - 2);
+        let (from, to, f) = (stops[i], stops[i + 1], scaled - i as f32);
+        let channel = |c: usize| (from[c] as f32 + (to[c] as f32 - from[c] as f32) * f).round() as u8;
+        Color32::from_rgb(channel(0), channel(1), channel(2))
+    }
+}
+
+/// How many rows of all `datasets` put a car on each of `led_count` LEDs.
+pub fn visit_counts<'a>(datasets: impl IntoIterator<Item = &'a Dataset>, led_count: usize) -> Vec<u64> {
+    let mut counts = vec![0; led_count];
+    for dataset in datasets {
+        for sample in (0..dataset.len()).filter_map(|row| dataset.get(row)) {
+            if let Some(count) = counts.get_mut(sample.led as usize) {
+                *count += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Each visited LED in the color of its count relative to the busiest
+/// LED, ready to paint over the unlit track. LEDs never visited stay unlit.
+pub fn lit_leds(counts: &[u64], gradient: Gradient) -> Vec<(usize, Color32)> {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    counts
+        .iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(led, &count)| (led, gradient.color(count as f32 / max)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{LedCoordinate, RunRace};
+    use crate::track::LedIndex;
+    use chrono::{DateTime, Duration, Utc};
+
+    #[test]
+    fn counts_every_row_and_colors_by_the_busiest_led() {
+        let leds: Vec<LedCoordinate> = (0..4).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let led_index = LedIndex::new(&leds);
+        let start = "2023-08-27T12:11:11.114Z".parse::<DateTime<Utc>>().unwrap();
+        let dataset = |xs: &[f64]| {
+            let rows: Vec<RunRace> = xs
+                .iter()
+                .enumerate()
+                .map(|(i, &x_led)| RunRace { date: start + Duration::seconds(i as i64), x_led, y_led: 0.0, time_delta: 1000, line: 0, telemetry: Default::default() })
+                .collect();
+            Dataset::from_rows(&rows, &led_index, 0)
+        };
+        let counts = visit_counts(&[dataset(&[0.0, 1.0, 1.0, 2.0]), dataset(&[1.0, 2.0])], leds.len());
+        assert_eq!(counts, [1, 3, 2, 0]);
+
+        let lit = lit_leds(&counts, Gradient::Gray);
+        assert_eq!(lit.iter().map(|&(led, _)| led).collect::<Vec<_>>(), [0, 1, 2], "unvisited LEDs stay unlit");
+        assert_eq!(lit[1].1, Color32::WHITE);
+        assert!(lit[0].1.r() < lit[2].1.r());
+        assert_eq!(Gradient::Heat.color(0.0), Color32::from_rgb(80, 0, 0));
+        assert_eq!(Gradient::Heat.color(2.0), Color32::WHITE);
+        assert_eq!(Gradient::Heat.color(0.125), Color32::from_rgb(140, 15, 0));
+    }
+}
@@ -0,0 +1,22 @@
+use eframe::egui::{Rect, Vec2};
+
+/// Places labels of the given sizes next to their anchor rects, moving a
+/// label up or down by its own height until it no longer overlaps one that
+/// was placed before it. Labels are placed in order, so earlier ones win.
+pub fn place_labels(labels: &[(Rect, Vec2)]) -> Vec<Rect> {
+    let mut placed: Vec<Rect> = Vec::with_capacity(labels.len());
+    for &(anchor, size) in labels {
+        let origin = Rect::from_min_size(anchor.right_center() + Vec2::new(4.0, -size.y / 2.0), size);
+        // Try the natural spot first, then alternate below and above it
+        let rect = (0..=placed.len() * 2)
+            .map(|attempt| {
+                let steps = attempt.div_ceil(2) as f32;
+                let direction = if attempt % 2 == 1 { 1.0 } else { -1.0 };
+                origin.translate(Vec2::new(0.0, direction * steps * size.y))
+            })
+            .find(|candidate| !placed.iter().any(|other| other.shrink(0.5).intersects(*candidate)))
+            .unwrap_or(origin);
+        placed.push(rect);
+    }
+    placed
+}
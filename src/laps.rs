@@ -0,0 +1,44 @@
+/// Counts completed laps for one car from the sequence of LEDs it occupies.
+///
+/// A crossing is an arrival on the start/finish LED from a different LED. The
+/// first crossing starts lap one; every later crossing completes a lap, but
+/// only once the car has passed at least `min_leds_between` other LEDs since
+/// the previous one, so a car lingering or jittering on the line is counted once.
+#[derive(Debug, Default, Clone)]
+pub struct LapCounter {
+    pub laps: u32,
+    started: bool,
+    last_led: Option<usize>,
+    leds_since_crossing: usize,
+}
+
+impl LapCounter {
+    /// The lap the car is on: 0 until it first crosses the line.
+    pub fn lap(&self) -> u32 {
+        if self.started {
+            self.laps + 1
+        } else {
+            0
+        }
+    }
+
+    pub fn observe(&mut self, led: usize, line_led: usize, min_leds_between: usize) {
+        if self.last_led == Some(led) {
+            return;
+        }
+        self.last_led = Some(led);
+
+        if led != line_led {
+            self.leds_since_crossing += 1;
+            return;
+        }
+        if !self.started {
+            self.started = true;
+        } else if self.leds_since_crossing >= min_leds_between {
+            self.laps += 1;
+        } else {
+            return;
+        }
+        self.leds_since_crossing = 0;
+    }
+}
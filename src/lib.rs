@@ -0,0 +1,51 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+pub mod activity;
+pub mod app;
+pub mod bookmarks;
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod correction;
+pub mod cues;
+pub mod data;
+pub mod dataset;
+pub mod dialog;
+pub mod drivers;
+pub mod event_log;
+pub mod events;
+pub mod export;
+pub mod frames;
+pub mod gap_history;
+pub mod grid;
+pub mod heatmap;
+pub mod labels;
+pub mod laps;
+pub mod loader;
+pub mod mqtt;
+pub mod output;
+pub mod palette;
+#[cfg(feature = "parquet")]
+pub mod parquet_data;
+pub mod perf;
+pub mod playlist;
+pub mod progress;
+pub mod recording;
+pub mod render;
+pub mod results;
+pub mod sectors;
+pub mod serve;
+pub mod session;
+pub mod settings;
+pub mod setup;
+pub mod sim;
+pub mod snapshot;
+#[cfg(feature = "sound")]
+pub mod sound;
+pub mod source;
+pub mod timestamp;
+pub mod track;
+pub mod units;
+pub mod validate;
+pub mod watch;
+pub mod window;
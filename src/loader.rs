@@ -0,0 +1,516 @@
+use eframe::egui::Color32;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::data::{self, read_race_data, repair_race_data, DataIssue};
+use crate::dataset::{self, Dataset};
+use crate::drivers::Driver;
+use crate::palette::Palette;
+use crate::source;
+use crate::track::LedIndex;
+
+/// A dataset that was read and matched to LEDs, with the problems found in its file.
+type ReadResult = Result<(Dataset, Vec<DataIssue>), String>;
+
+pub const DATASET_PREFIX: &str = "time_delta_";
+pub const DATASET_SUFFIX: &str = "_start.csv";
+
+/// Also taken as a dataset, the rows tab separated.
+pub const TSV_DATASET_SUFFIX: &str = "_start.tsv";
+
+/// Also taken as a dataset, read as Parquet by builds with the `parquet`
+/// feature and skipped with a note by others.
+const PARQUET_DATASET_SUFFIX: &str = "_start.parquet";
+
+/// Share of a dataset's rows on the track below which it is warned about.
+const MIN_ON_TRACK: f64 = 0.5;
+
+/// How dataset files are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Keep the rows on disk until playback needs them. Parquet files are
+    /// always read whole.
+    pub stream: bool,
+    /// Shortest time between two rows of a dataset, see `Dataset`.
+    pub min_step_ms: u32,
+    /// Put rows in date order and drop repeated dates, see
+    /// `data::repair_race_data`. Streamed files with rows out of date order
+    /// are read whole to be repaired.
+    pub repair: bool,
+    /// Drop rows out of date order instead of sorting them, see
+    /// `data::drop_out_of_order`. Streamed files with such rows are read
+    /// whole to drop them.
+    pub drop_out_of_order: bool,
+    /// Field separator, guessed from each file name when `None`.
+    pub delimiter: Option<u8>,
+    /// Thin out rows of a car standing on one LED to one per this many
+    /// milliseconds, see `Dataset::from_rows_downsampled`. 0 keeps every
+    /// row, as do streamed files.
+    pub downsample_ms: u32,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self { stream: false, min_step_ms: dataset::DEFAULT_MIN_STEP_MS, repair: true, drop_out_of_order: false, delimiter: None, downsample_ms: 0 }
+    }
+}
+
+/// Datasets ready to hand to `PlotApp`, one entry per driver.
+#[derive(Default)]
+pub struct LoadedRace {
+    /// Each driver's rows, named by file name and configured codes and
+    /// colored from the palette.
+    pub drivers: Vec<Driver>,
+    /// Problems found while loading, including files that were skipped.
+    pub issues: Vec<DataIssue>,
+}
+
+impl LoadedRace {
+    /// The driver key of each dataset.
+    pub fn keys(&self) -> Vec<String> {
+        self.drivers.iter().map(|driver| driver.key.clone()).collect()
+    }
+}
+
+/// Finds every `time_delta_<driver>_start.csv` in `dir`, sorted by driver name
+/// so the legend order is stable between runs.
+pub fn scan_datasets(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = source::current().files(dir)?;
+    paths.retain(|path| is_dataset_file(path));
+    // By driver, then by path for the same driver in two formats, so the order does not depend on the folder's
+    paths.sort_by(|a, b| driver_name(a).cmp(&driver_name(b)).then_with(|| a.cmp(b)));
+    Ok(paths)
+}
+
+/// Lists the subfolders of `data_dir` that contain at least one dataset file.
+pub fn list_races(data_dir: &Path) -> Vec<String> {
+    let Ok(dirs) = source::current().dirs(data_dir) else {
+        return Vec::new();
+    };
+    let mut races: Vec<String> = dirs
+        .into_iter()
+        .filter(|path| scan_datasets(path).is_ok_and(|files| !files.is_empty()))
+        .filter_map(|path| path.file_name()?.to_str().map(str::to_string))
+        .collect();
+    races.sort();
+    races
+}
+
+/// Reads each dataset and matches its rows to LEDs, skipping files that
+/// fail to parse, as `options` say. Colors come from `palette` and codes are looked up in `codes` by
+/// driver key. The files are read in parallel as for `PendingRace`, and
+/// this returns once all of them are done.
+pub fn load_datasets(
+    paths: &[PathBuf],
+    led_index: &Arc<LedIndex>,
+    options: ReadOptions,
+    palette: Palette,
+    codes: &BTreeMap<String, String>,
+) -> LoadedRace {
+    let mut pending = PendingRace::start(paths.to_vec(), Vec::new(), led_index.clone(), options);
+    pending.wait();
+    pending.finish(palette, codes)
+}
+
+/// The dataset files in `dir`, or an issue saying why there are none.
+pub fn race_dir_paths(dir: &Path) -> (Vec<PathBuf>, Vec<DataIssue>) {
+    match scan_datasets(dir) {
+        Ok(paths) if paths.is_empty() => {
+            (paths, vec![file_issue(dir, format!("no {DATASET_PREFIX}*{DATASET_SUFFIX}, {TSV_DATASET_SUFFIX} or {PARQUET_DATASET_SUFFIX} files"))])
+        }
+        Ok(paths) => (paths, Vec::new()),
+        Err(e) => (Vec::new(), vec![file_issue(dir, format!("cannot read folder: {e}"))]),
+    }
+}
+
+/// Runs `job` on a thread of its own. Browsers have no threads, so there
+/// it runs straight away and whatever waits for it finds it done.
+pub fn spawn(job: impl FnOnce() + Send + 'static) {
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::spawn(job);
+    #[cfg(target_arch = "wasm32")]
+    job();
+}
+
+/// Where a file being read in the background has got to.
+pub enum FileProgress {
+    Loading,
+    Loaded(usize),
+    Failed(String),
+}
+
+/// Datasets being read on background threads, one per core. The threads
+/// are never joined, so dropping this mid-load does not wait for them.
+pub struct PendingRace {
+    pub paths: Vec<PathBuf>,
+    pub progress: Vec<FileProgress>,
+    results: Vec<Option<ReadResult>>,
+    receiver: Receiver<(usize, ReadResult)>,
+    issues: Vec<DataIssue>,
+    options: ReadOptions,
+    started: Instant,
+}
+
+impl PendingRace {
+    /// Starts reading `paths`. `issues` are problems already found, such as
+    /// an unreadable race folder, to report along with the file problems.
+    pub fn start(paths: Vec<PathBuf>, issues: Vec<DataIssue>, led_index: Arc<LedIndex>, options: ReadOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        // As many readers as cores, each taking the next file until none are left
+        let next = Arc::new(AtomicUsize::new(0));
+        let shared_paths: Arc<[PathBuf]> = paths.clone().into();
+        let readers = thread::available_parallelism().map_or(1, |cores| cores.get()).min(paths.len());
+        for _ in 0..readers {
+            let (sender, next, paths, led_index) = (sender.clone(), next.clone(), shared_paths.clone(), led_index.clone());
+            spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(i) else {
+                        break;
+                    };
+                    let _ = sender.send((i, read_dataset(path, &led_index, options)));
+                }
+            });
+        }
+        Self {
+            progress: paths.iter().map(|_| FileProgress::Loading).collect(),
+            results: paths.iter().map(|_| None).collect(),
+            paths,
+            receiver,
+            issues,
+            options,
+            started: Instant::now(),
+        }
+    }
+
+    /// Starts reading the same files over, matched to `led_index` instead.
+    /// The readers already going finish on their own, unheard.
+    pub fn restart(&mut self, led_index: Arc<LedIndex>) {
+        *self = Self::start(std::mem::take(&mut self.paths), std::mem::take(&mut self.issues), led_index, self.options);
+    }
+
+    /// Collects finished files; `true` once every file has arrived.
+    pub fn poll(&mut self) -> bool {
+        loop {
+            match self.receiver.try_recv() {
+                Ok((i, result)) => self.arrive(i, result),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.stop();
+                    break;
+                }
+            }
+        }
+        self.is_done()
+    }
+
+    /// Blocks until every file has arrived.
+    pub fn wait(&mut self) {
+        while !self.is_done() {
+            match self.receiver.recv() {
+                Ok((i, result)) => self.arrive(i, result),
+                Err(_) => self.stop(),
+            }
+        }
+    }
+
+    /// Time since reading started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn is_done(&self) -> bool {
+        self.results.iter().all(Option::is_some)
+    }
+
+    fn arrive(&mut self, i: usize, result: ReadResult) {
+        self.progress[i] = match &result {
+            Ok((dataset, _)) => FileProgress::Loaded(dataset.len()),
+            Err(e) => FileProgress::Failed(e.clone()),
+        };
+        self.results[i] = Some(result);
+    }
+
+    /// Every thread is done; any file without a result had its reader panic.
+    fn stop(&mut self) {
+        for (progress, result) in self.progress.iter_mut().zip(&mut self.results) {
+            if result.is_none() {
+                *progress = FileProgress::Failed("reader stopped".to_string());
+                *result = Some(Err("reader stopped".to_string()));
+            }
+        }
+    }
+
+    /// The loaded race, once `poll` has returned `true`.
+    pub fn finish(self, palette: Palette, codes: &BTreeMap<String, String>) -> LoadedRace {
+        let results = self
+            .results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("not loaded".to_string())))
+            .collect();
+        let mut race = assemble(&self.paths, results, palette, codes);
+        race.issues.splice(0..0, self.issues);
+        race
+    }
+}
+
+/// One dataset being read again on a background thread.
+pub struct PendingDataset {
+    pub path: PathBuf,
+    receiver: Receiver<ReadResult>,
+}
+
+impl PendingDataset {
+    pub fn start(path: PathBuf, led_index: Arc<LedIndex>, options: ReadOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread_path = path.clone();
+        spawn(move || {
+            let _ = sender.send(read_dataset(&thread_path, &led_index, options));
+        });
+        Self { path, receiver }
+    }
+
+    /// The dataset and its problems once read, or why it could not be.
+    pub fn poll(&self) -> Option<ReadResult> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("reader stopped".to_string())),
+        }
+    }
+}
+
+/// Reads one dataset file and matches its rows to LEDs, as `load_datasets`
+/// does for each.
+pub fn read_dataset(path: &Path, led_index: &Arc<LedIndex>, options: ReadOptions) -> ReadResult {
+    let mut streamed_out_of_order = false;
+    if options.stream && !data::is_parquet(path) {
+        let (dataset, mut issues) = Dataset::stream(path, led_index.clone(), options.min_step_ms, options.delimiter).map_err(|e| e.to_string())?;
+        // Rows out of order are only put right with every row at hand
+        streamed_out_of_order = (options.repair || options.drop_out_of_order) && issues.iter().any(data::is_out_of_order);
+        if !streamed_out_of_order {
+            issues.extend(unplaced_issue(path, &dataset).into_iter().chain(off_track_issue(path, &dataset)));
+            return Ok((dataset, issues));
+        }
+    }
+    let mut data = read_race_data(path, options.delimiter).map_err(|e| e.to_string())?;
+    if streamed_out_of_order {
+        data.issues.push(file_issue(path, "read whole rather than streamed, to put its rows in date order".to_string()));
+    }
+    if options.drop_out_of_order {
+        data::drop_out_of_order(path, &mut data);
+    } else if options.repair {
+        repair_race_data(path, &mut data);
+    }
+    // After matching and repair, so only clean rows are compared
+    let mut dataset = Dataset::from_rows_downsampled(&data.records, led_index, options.min_step_ms, options.downsample_ms);
+    dataset.path = Some(path.to_path_buf());
+    data.issues.extend(unplaced_issue(path, &dataset).into_iter().chain(off_track_issue(path, &dataset)));
+    Ok((dataset, data.issues))
+}
+
+/// An issue for the rows of a dataset left out with no LED to put them on.
+fn unplaced_issue(path: &Path, dataset: &Dataset) -> Option<DataIssue> {
+    let rows = Some(dataset.unplaced).filter(|&rows| rows > 0)?;
+    let message = if rows == 1 { "left out 1 row with no LED to put it on".to_string() } else { format!("left out {rows} rows with no LED to put them on") };
+    Some(file_issue(path, message))
+}
+
+/// A warning for a dataset with less than `MIN_ON_TRACK` of its rows on the
+/// track, most likely written in other coordinates than the LEDs.
+fn off_track_issue(path: &Path, dataset: &Dataset) -> Option<DataIssue> {
+    let on_track = dataset.on_track.filter(|&share| share < MIN_ON_TRACK)?;
+    let message = format!("only {:.0}% of the rows are on the track, are the positions in the same coordinates as the LEDs?", on_track * 100.0);
+    Some(file_issue(path, message))
+}
+
+/// Puts read results together in file order, skipping files that failed.
+fn assemble(
+    paths: &[PathBuf],
+    results: Vec<ReadResult>,
+    palette: Palette,
+    codes: &BTreeMap<String, String>,
+) -> LoadedRace {
+    let mut race = LoadedRace::default();
+    for (path, result) in paths.iter().zip(results) {
+        match result {
+            Ok((dataset, issues)) => {
+                race.drivers.push(Driver {
+                    key: driver_key(path).to_string(),
+                    name: driver_name(path),
+                    code: driver_code(path, codes),
+                    ..Driver::new(dataset, Color32::BLACK)
+                });
+                race.issues.extend(issues);
+            }
+            Err(e) => race.issues.push(file_issue(path, format!("skipped: {e}"))),
+        }
+    }
+    let colors = palette.colors(&race.keys());
+    for (driver, color) in race.drivers.iter_mut().zip(colors) {
+        driver.color = color;
+    }
+    race
+}
+
+fn file_issue(path: &Path, message: String) -> DataIssue {
+    DataIssue {
+        file: path.to_path_buf(),
+        line: None,
+        message,
+    }
+}
+
+/// Whether `path` is named like a dataset file, compressed or not.
+pub fn is_dataset_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.strip_suffix(".gz").unwrap_or(name))
+        .is_some_and(|name| name.starts_with(DATASET_PREFIX) && [DATASET_SUFFIX, TSV_DATASET_SUFFIX, PARQUET_DATASET_SUFFIX].iter().any(|suffix| name.ends_with(suffix)))
+}
+
+/// The driver part of a dataset file name,
+/// e.g. `time_delta_verstappen_start.csv` or `.csv.gz` gives `verstappen`.
+pub fn driver_key(file_path: &Path) -> &str {
+    let file_name = file_path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+    let file_name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+    let stem = Path::new(file_name).file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = stem.strip_prefix(DATASET_PREFIX).unwrap_or(stem);
+    let name = name.strip_suffix("_start").unwrap_or(name);
+    if name.is_empty() {
+        stem
+    } else {
+        name
+    }
+}
+
+/// Derives a display name from a dataset file name,
+/// e.g. `time_delta_verstappen_start.csv` becomes `Verstappen`.
+pub fn driver_name(file_path: &Path) -> String {
+    let mut chars = driver_key(file_path).chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The configured code for a driver, or the first three letters of the
+/// driver name in capitals when none is configured.
+fn driver_code(file_path: &Path, codes: &BTreeMap<String, String>) -> String {
+    let key = driver_key(file_path);
+    match codes.get(key) {
+        Some(code) => code.clone(),
+        None => key.chars().take(3).collect::<String>().to_uppercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn background_loading_keeps_file_order() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-pending", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rows = "date,x_led,y_led,time_delta\n2023-08-27T12:11:11.114Z,1,2,100\n";
+        let paths: Vec<PathBuf> = ["time_delta_zed_start.csv", "time_delta_bad_start.csv", "time_delta_amy_start.csv.gz", "time_delta_tab_start.tsv"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        fs::write(&paths[0], rows).unwrap();
+        fs::write(&paths[1], "1,2,3,4,5\n").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, rows.as_bytes()).unwrap();
+        fs::write(&paths[2], encoder.finish().unwrap()).unwrap();
+        fs::write(&paths[3], rows.replace(',', "\t")).unwrap();
+        assert_eq!(scan_datasets(&dir).unwrap().len(), 4);
+
+        let led_index = LedIndex::new(&[crate::data::LedCoordinate::default(), crate::data::LedCoordinate { x_led: 2.0, y_led: 2.0, ..Default::default() }]);
+        let mut pending = PendingRace::start(paths, Vec::new(), Arc::new(led_index), ReadOptions::default());
+        while !pending.poll() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(matches!(pending.progress[1], FileProgress::Failed(_)));
+        let race = pending.finish(Palette::Default, &BTreeMap::new());
+        assert_eq!(race.drivers.iter().map(|driver| driver.name.as_str()).collect::<Vec<_>>(), ["Zed", "Amy", "Tab"]);
+        assert_eq!(race.drivers.iter().map(|driver| driver.code.as_str()).collect::<Vec<_>>(), ["ZED", "AMY", "TAB"]);
+        assert_eq!(race.issues.len(), 1);
+    }
+
+    #[test]
+    fn a_dataset_mostly_off_the_track_is_warned_about_streamed_or_not() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-off-track", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_far_start.csv");
+        // One row in the LEDs' box, three in other coordinates, one of those close enough to snap
+        fs::write(&path, "date,x_led,y_led,time_delta
+2023-08-27T12:11:11Z,5,5,100
+2023-08-27T12:11:12Z,5000,5000,100
+2023-08-27T12:11:13Z,-900,3,100
+2023-08-27T12:11:14Z,11,10,100
+").unwrap();
+        let leds = [(0.0, 0.0), (10.0, 10.0)].map(|(x_led, y_led)| crate::data::LedCoordinate { x_led, y_led, ..Default::default() });
+        for stream in [false, true] {
+            let options = ReadOptions { stream, ..Default::default() };
+            let (dataset, issues) = read_dataset(&path, &Arc::new(LedIndex::new(&leds)), options).unwrap();
+            assert_eq!(dataset.on_track, Some(0.25));
+            assert_eq!(issues.len(), 1, "stream {stream}");
+            assert!(issues[0].message.starts_with("only 25% of the rows are on the track"), "{}", issues[0].message);
+
+            let led_index = LedIndex::new(&leds).with_snap_distance(Some(2.0));
+            let (dataset, issues) = read_dataset(&path, &Arc::new(led_index), options).unwrap();
+            assert_eq!((dataset.on_track, issues.len()), (Some(0.5), 0), "within the snap distance of an LED");
+        }
+    }
+
+    #[test]
+    fn streamed_files_out_of_date_order_are_read_whole_and_repaired() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-streamed-repair", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("time_delta_shuffled_start.csv");
+        fs::write(&path, "date,x_led,y_led,time_delta\n2023-08-27T12:11:11Z,0,0,100\n2023-08-27T12:11:13Z,2,0,2000\n2023-08-27T12:11:12Z,1,0,0\n").unwrap();
+        let leds: Vec<_> = (0..3).map(|x| crate::data::LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let led_index = Arc::new(LedIndex::new(&leds));
+        let leds_of = |dataset: &Dataset| (0..dataset.len()).map(|row| dataset.get(row).unwrap().led).collect::<Vec<_>>();
+
+        let options = ReadOptions { stream: true, ..Default::default() };
+        let (dataset, issues) = read_dataset(&path, &led_index, options).unwrap();
+        assert_eq!(leds_of(&dataset), [0, 1, 2]);
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(messages, ["moved 1 row into date order", "read whole rather than streamed, to put its rows in date order"]);
+
+        let (dataset, issues) = read_dataset(&path, &led_index, ReadOptions { repair: false, ..options }).unwrap();
+        assert_eq!((leds_of(&dataset), issues.len()), (vec![0, 2, 1], 1), "streamed as exported");
+    }
+
+    #[test]
+    fn a_scan_sorts_by_driver_and_says_when_the_folder_has_none() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-scan", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["time_delta_verstappen_start.tsv", "time_delta_verstappen_start.csv", "time_delta_albon_start.csv", "led_coords.csv", "time_delta_albon.csv"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let (paths, issues) = race_dir_paths(&dir);
+        let names: Vec<_> = paths.iter().map(|path| (driver_name(path), path.file_name().unwrap().to_str().unwrap())).collect();
+        assert_eq!(
+            names,
+            [("Albon".to_string(), "time_delta_albon_start.csv"), ("Verstappen".to_string(), "time_delta_verstappen_start.csv"), ("Verstappen".to_string(), "time_delta_verstappen_start.tsv")]
+        );
+        assert!(issues.is_empty());
+
+        let empty = dir.join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        let (paths, issues) = race_dir_paths(&empty);
+        assert!(paths.is_empty());
+        assert!(issues[0].message.starts_with("no time_delta_*_start.csv"), "{}", issues[0].message);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+// Browsers cannot open TCP connections, so the web build has the config
+// and the messages but `Mqtt::start` always fails there
+#![cfg_attr(target_arch = "wasm32", allow(unused_imports, dead_code))]
+
+use base64::Engine;
+#[cfg(not(target_arch = "wasm32"))]
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use web_time::Instant;
+
+/// Port used when the broker address has none.
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// First wait before connecting again after the broker went away, doubled
+/// on every failure up to `MAX_BACKOFF`.
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Messages waiting in the client for the connection, beyond which new ones are dropped.
+const QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttMode {
+    /// `<topic_prefix>/led/<index>` with an `RRGGBB` payload for every LED
+    /// that changed, by its position on the strip.
+    #[default]
+    Incremental,
+    /// `<topic_prefix>/frame` with the RGB bytes of every LED in strip order,
+    /// every frame.
+    Frame,
+}
+
+/// How a full frame is put in the message.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FramePayload {
+    #[default]
+    Binary,
+    Base64,
+}
+
+/// The `[mqtt]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// `mqtt://host:port`, `host:port` or `host` of the broker.
+    pub broker: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 0 for at most once, 1 for at least once, 2 for exactly once.
+    pub qos: u8,
+    pub mode: MqttMode,
+    pub payload: FramePayload,
+    pub topic_prefix: String,
+    /// Frames published per second, independent of the window's frame rate.
+    pub fps: f64,
+    /// Print what would be published instead of connecting to the broker.
+    pub dry_run: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: format!("mqtt://localhost:{DEFAULT_PORT}"),
+            client_id: "f1-led-circuit".to_string(),
+            username: None,
+            password: None,
+            qos: 0,
+            mode: MqttMode::Incremental,
+            payload: FramePayload::Binary,
+            topic_prefix: "f1".to_string(),
+            fps: 10.0,
+            dry_run: false,
+        }
+    }
+}
+
+impl MqttConfig {
+    /// Host and port of the broker, or why the address does not make sense.
+    pub fn broker_address(&self) -> Result<(String, u16), String> {
+        let address = self.broker.strip_prefix("mqtt://").or_else(|| self.broker.strip_prefix("tcp://")).unwrap_or(&self.broker);
+        let address = address.trim_end_matches('/');
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("`{port}` is not a port in broker `{}`", self.broker))?),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() || host.contains('/') {
+            return Err(format!("broker `{}` is not an address like mqtt://host:1883", self.broker));
+        }
+        Ok((host.to_string(), port))
+    }
+
+    /// Checks the settings `Mqtt::start` cannot do without.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.qos > 2 {
+            return Err(format!("mqtt.qos must be 0, 1 or 2, got {}", self.qos));
+        }
+        if !(self.fps.is_finite() && self.fps > 0.0) {
+            return Err(format!("mqtt.fps must be a positive number, got {}", self.fps));
+        }
+        self.broker_address().map(|_| ()).map_err(|e| format!("invalid mqtt.broker: {e}"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn qos_level(qos: u8) -> QoS {
+    match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// One message to publish.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// What to publish for `frame`, the RGB bytes of every LED in strip order,
+/// given the frame published before it. Without one every LED is sent.
+pub fn messages(config: &MqttConfig, previous: Option<&[u8]>, frame: &[u8]) -> Vec<Message> {
+    let prefix = &config.topic_prefix;
+    match config.mode {
+        MqttMode::Incremental => frame
+            .chunks(3)
+            .enumerate()
+            .filter(|&(led, rgb)| previous.and_then(|previous| previous.get(led * 3..led * 3 + 3)) != Some(rgb))
+            .map(|(led, rgb)| Message {
+                topic: format!("{prefix}/led/{led}"),
+                payload: rgb.iter().map(|channel| format!("{channel:02X}")).collect::<String>().into_bytes(),
+            })
+            .collect(),
+        MqttMode::Frame => vec![Message {
+            topic: format!("{prefix}/frame"),
+            payload: match config.payload {
+                FramePayload::Binary => frame.to_vec(),
+                FramePayload::Base64 => base64::engine::general_purpose::STANDARD.encode(frame).into_bytes(),
+            },
+        }],
+    }
+}
+
+/// What the MQTT threads last reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MqttStatus {
+    Connecting,
+    Connected,
+    DryRun,
+    Error(String),
+}
+
+/// Handle to the publishing thread. Frames are handed over without blocking;
+/// if the thread is still busy with the previous one the new frame is dropped.
+pub struct Mqtt {
+    pub config: MqttConfig,
+    frames: SyncSender<Vec<u8>>,
+    statuses: Receiver<MqttStatus>,
+    pub status: MqttStatus,
+}
+
+impl Mqtt {
+    /// Starts publishing, and connecting to the broker unless this is a dry run.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start(config: MqttConfig) -> io::Result<Self> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+        config.validate().map_err(invalid)?;
+        let (frames, frame_rx) = mpsc::sync_channel(1);
+        let (status_tx, statuses) = mpsc::channel();
+        let thread_config = config.clone();
+        let client = if config.dry_run {
+            let _ = status_tx.send(MqttStatus::DryRun);
+            None
+        } else {
+            let (host, port) = config.broker_address().map_err(invalid)?;
+            let mut options = MqttOptions::new(&config.client_id, host, port);
+            options.set_keep_alive(Duration::from_secs(10));
+            if let Some(username) = &config.username {
+                options.set_credentials(username, config.password.clone().unwrap_or_default());
+            }
+            let (client, connection) = Client::new(options, QUEUE_CAPACITY);
+            let connected = Arc::new(AtomicBool::new(false));
+            let (thread_connected, thread_status, broker) = (connected.clone(), status_tx.clone(), config.broker.clone());
+            thread::Builder::new()
+                .name("mqtt-connection".to_string())
+                .spawn(move || keep_connected(connection, &broker, &thread_connected, &thread_status))?;
+            Some((client, connected))
+        };
+        let qos = qos_level(config.qos);
+        thread::Builder::new()
+            .name("mqtt-publish".to_string())
+            .spawn(move || publish(&thread_config, qos, client, &frame_rx))?;
+        Ok(Self {
+            config,
+            frames,
+            statuses,
+            status: MqttStatus::Connecting,
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn start(_config: MqttConfig) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "browsers cannot connect to an MQTT broker over TCP"))
+    }
+
+    /// Hands the RGB bytes of every LED, in strip order, to the publishing thread.
+    pub fn send(&mut self, frame: Vec<u8>) {
+        match self.frames.try_send(frame) {
+            Ok(()) | Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => self.status = MqttStatus::Error("publisher stopped".to_string()),
+        }
+    }
+
+    /// Picks up status changes reported by the MQTT threads.
+    pub fn poll_status(&mut self) -> &MqttStatus {
+        while let Ok(status) = self.statuses.try_recv() {
+            self.status = status;
+        }
+        &self.status
+    }
+}
+
+/// Drives the connection, which reconnects as it is polled, waiting longer
+/// after each failure. Ends once the client is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+fn keep_connected(mut connection: Connection, broker: &str, connected: &AtomicBool, statuses: &Sender<MqttStatus>) {
+    let mut backoff = MIN_BACKOFF;
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                connected.store(true, Ordering::Relaxed);
+                backoff = MIN_BACKOFF;
+                let _ = statuses.send(MqttStatus::Connected);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                connected.store(false, Ordering::Relaxed);
+                let _ = statuses.send(MqttStatus::Error(format!("{broker}: {e}, retrying in {:.1}s", backoff.as_secs_f64())));
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Publishes the latest frame at the configured rate, or prints it on a
+/// dry run. After the connection drops every LED is sent again.
+#[cfg(not(target_arch = "wasm32"))]
+fn publish(config: &MqttConfig, qos: QoS, client: Option<(Client, Arc<AtomicBool>)>, frames: &Receiver<Vec<u8>>) {
+    let interval = Duration::from_secs_f64(1.0 / config.fps.max(0.1));
+    let mut frame: Option<Vec<u8>> = None;
+    let mut published: Option<Vec<u8>> = None;
+    loop {
+        let started = Instant::now();
+        // Only the most recent frame matters
+        loop {
+            match frames.try_recv() {
+                Ok(latest) => frame = Some(latest),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if let Some(frame) = &frame {
+            match &client {
+                None => {
+                    for message in messages(config, published.as_deref(), frame) {
+                        println!("mqtt: {} {}", message.topic, describe_payload(&message.payload));
+                    }
+                    published = Some(frame.clone());
+                }
+                Some((client, connected)) if connected.load(Ordering::Relaxed) => {
+                    let sent = messages(config, published.as_deref(), frame)
+                        .into_iter()
+                        .all(|message| client.try_publish(message.topic, qos, false, message.payload).is_ok());
+                    published = sent.then(|| frame.clone());
+                }
+                Some(_) => published = None,
+            }
+        }
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+/// The payload as text if it is, otherwise its length.
+fn describe_payload(payload: &[u8]) -> String {
+    match std::str::from_utf8(payload) {
+        Ok(text) => text.to_string(),
+        Err(_) => format!("<{} bytes>", payload.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_updates_only_carry_the_leds_that_changed() {
+        let config = MqttConfig::default();
+        let first = messages(&config, None, &[255, 0, 16, 0, 0, 0]);
+        assert_eq!(
+            first,
+            [
+                Message { topic: "f1/led/0".to_string(), payload: b"FF0010".to_vec() },
+                Message { topic: "f1/led/1".to_string(), payload: b"000000".to_vec() },
+            ]
+        );
+        let next = messages(&config, Some(&[255, 0, 16, 0, 0, 0]), &[255, 0, 16, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(next.iter().map(|message| message.topic.as_str()).collect::<Vec<_>>(), ["f1/led/1", "f1/led/2"]);
+
+        let frame = MqttConfig { mode: MqttMode::Frame, payload: FramePayload::Base64, topic_prefix: "wall".to_string(), ..Default::default() };
+        assert_eq!(messages(&frame, Some(&[1, 2, 3]), &[1, 2, 3]), [Message { topic: "wall/frame".to_string(), payload: b"AQID".to_vec() }]);
+    }
+
+    #[test]
+    fn broker_addresses_take_a_scheme_and_a_default_port() {
+        let address = |broker: &str| MqttConfig { broker: broker.to_string(), ..Default::default() }.broker_address();
+        assert_eq!(address("mqtt://broker.local:1884"), Ok(("broker.local".to_string(), 1884)));
+        assert_eq!(address("10.0.0.5"), Ok(("10.0.0.5".to_string(), DEFAULT_PORT)));
+        assert!(address("mqtt://host:port").is_err());
+        assert!(address("mqtt://").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use serde::Deserialize;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::thread;
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::data::{LedCoordinate, MAX_STRIP_POSITION};
+
+/// LEDs that fit in one DMX universe at three channels each.
+pub const LEDS_PER_UNIVERSE: usize = 170;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    /// ArtDmx packets, one per universe of 170 LEDs.
+    #[default]
+    ArtNet,
+    /// One datagram with the RGB bytes of every LED in strip order.
+    RawUdp,
+}
+
+/// The `[output]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub mode: OutputMode,
+    /// `host:port` of the Art-Net node or UDP receiver.
+    pub target: String,
+    /// Frames sent per second, independent of the window's frame rate.
+    pub fps: f64,
+    /// Art-Net universe of the first 170 LEDs; later LEDs continue in the next universes.
+    pub universe: u16,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            mode: OutputMode::ArtNet,
+            target: "127.0.0.1:6454".to_string(),
+            fps: 30.0,
+            universe: 0,
+        }
+    }
+}
+
+/// What the sender thread last reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputStatus {
+    Waiting,
+    Sending,
+    Error(String),
+}
+
+/// Handle to the sender thread. Frames are handed over without blocking;
+/// if the thread is still busy with the previous one the new frame is dropped.
+pub struct Output {
+    pub config: OutputConfig,
+    frames: SyncSender<Vec<u8>>,
+    statuses: Receiver<OutputStatus>,
+    pub status: OutputStatus,
+}
+
+impl Output {
+    pub fn start(config: OutputConfig) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        let (frames, frame_rx) = mpsc::sync_channel(1);
+        let (status_tx, statuses) = mpsc::channel();
+        let thread_config = config.clone();
+        thread::Builder::new()
+            .name("led-output".to_string())
+            .spawn(move || run(socket, thread_config, frame_rx, status_tx))?;
+        Ok(Self {
+            config,
+            frames,
+            statuses,
+            status: OutputStatus::Waiting,
+        })
+    }
+
+    /// Hands the RGB bytes of every LED, in strip order, to the sender thread.
+    pub fn send(&mut self, frame: Vec<u8>) {
+        match self.frames.try_send(frame) {
+            Ok(()) | Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => self.status = OutputStatus::Error("sender stopped".to_string()),
+        }
+    }
+
+    /// Picks up status changes reported by the sender thread.
+    pub fn poll_status(&mut self) -> &OutputStatus {
+        while let Ok(status) = self.statuses.try_recv() {
+            self.status = status;
+        }
+        &self.status
+    }
+}
+
+fn run(socket: UdpSocket, config: OutputConfig, frames: Receiver<Vec<u8>>, statuses: mpsc::Sender<OutputStatus>) {
+    let interval = Duration::from_secs_f64(1.0 / config.fps.max(1.0));
+    let mut target: Option<SocketAddr> = None;
+    let mut frame: Option<Vec<u8>> = None;
+    let mut sequence = 0u8;
+    let mut status = OutputStatus::Waiting;
+    loop {
+        let started = Instant::now();
+        // Only the most recent frame matters
+        loop {
+            match frames.try_recv() {
+                Ok(latest) => frame = Some(latest),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if let Some(frame) = &frame {
+            sequence = sequence % 255 + 1; // 0 means "no sequencing" to Art-Net receivers
+            let result = resolve(&config.target, &mut target).and_then(|addr| match config.mode {
+                OutputMode::ArtNet => artnet_packets(frame, config.universe, sequence)
+                    .iter()
+                    .try_for_each(|packet| socket.send_to(packet, addr).map(drop)),
+                OutputMode::RawUdp => socket.send_to(frame, addr).map(drop),
+            });
+            let new_status = match result {
+                Ok(()) => OutputStatus::Sending,
+                Err(e) => OutputStatus::Error(format!("{}: {e}", config.target)),
+            };
+            if new_status != status {
+                status = new_status;
+                if statuses.send(status.clone()).is_err() {
+                    return;
+                }
+            }
+        }
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+/// Looks the target up once and keeps the address for later frames.
+fn resolve(target: &str, cached: &mut Option<SocketAddr>) -> io::Result<SocketAddr> {
+    if let Some(addr) = cached {
+        return Ok(*addr);
+    }
+    let addr = target
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+    *cached = Some(addr);
+    Ok(addr)
+}
+
+/// Splits an RGB frame into ArtDmx packets, 170 LEDs per universe starting at `universe`.
+pub fn artnet_packets(frame: &[u8], universe: u16, sequence: u8) -> Vec<Vec<u8>> {
+    frame
+        .chunks(LEDS_PER_UNIVERSE * 3)
+        .enumerate()
+        .map(|(i, channels)| {
+            let universe = universe.wrapping_add(i as u16) & 0x7fff;
+            let length = channels.len() + channels.len() % 2; // DMX length must be even
+            let mut packet = Vec::with_capacity(18 + length);
+            packet.extend_from_slice(b"Art-Net\0");
+            packet.extend_from_slice(&0x5000u16.to_le_bytes()); // OpDmx
+            packet.extend_from_slice(&14u16.to_be_bytes()); // Protocol version
+            packet.push(sequence);
+            packet.push(0); // Physical port
+            packet.extend_from_slice(&universe.to_le_bytes());
+            packet.extend_from_slice(&(length as u16).to_be_bytes());
+            packet.extend_from_slice(channels);
+            packet.resize(18 + length, 0);
+            packet
+        })
+        .collect()
+}
+
+/// Position of each LED on the physical strip: the `index` column if the
+/// coordinates file has one, otherwise the number in its designator (`U1`
+/// is the first LED), otherwise its row in the file.
+pub fn strip_positions(coordinates: &[LedCoordinate]) -> Vec<usize> {
+    coordinates
+        .iter()
+        .enumerate()
+        .map(|(row, coord)| {
+            coord
+                .index
+                .or_else(|| coord.designator_number()?.checked_sub(1))
+                .unwrap_or(row)
+        })
+        .collect()
+}
+
+/// Packs LED colors into RGB bytes ordered by strip position. Strip
+/// positions no LED maps to stay dark, and LEDs past `MAX_STRIP_POSITION`
+/// are left out.
+pub fn strip_frame(colors: &[[u8; 3]], positions: &[usize]) -> Vec<u8> {
+    let len = positions.iter().copied().filter(|&position| position <= MAX_STRIP_POSITION).max().and_then(|max| max.checked_add(1)).unwrap_or(0);
+    let mut frame = vec![0; len * 3];
+    for (rgb, &position) in colors.iter().zip(positions).filter(|&(_, &position)| position <= MAX_STRIP_POSITION) {
+        frame[position * 3..position * 3 + 3].copy_from_slice(rgb);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_frames_across_universes() {
+        let frame = vec![7; 171 * 3];
+        let packets = artnet_packets(&frame, 5, 1);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(&packets[0][..8], b"Art-Net\0");
+        assert_eq!(&packets[0][14..18], [5, 0, 0x01, 0xfe]); // Universe 5, 510 channels
+        assert_eq!(&packets[1][14..18], [6, 0, 0, 4]); // Universe 6, 3 channels padded to 4
+        assert_eq!(packets[1].len(), 18 + 4);
+        assert_eq!(&packets[1][18..], [7, 7, 7, 0]);
+    }
+
+    #[test]
+    fn strip_order_comes_from_index_then_designator() {
+        let coord = |index: Option<usize>, designator: Option<&str>| LedCoordinate {
+            index,
+            designator: designator.map(str::to_string),
+            ..Default::default()
+        };
+        let coordinates = [coord(None, Some("U3")), coord(Some(0), Some("U9")), coord(None, None)];
+        let positions = strip_positions(&coordinates);
+        assert_eq!(positions, [2, 0, 2]);
+        let frame = strip_frame(&[[1, 1, 1], [2, 2, 2], [3, 3, 3]], &positions);
+        assert_eq!(frame, [2, 2, 2, 0, 0, 0, 3, 3, 3]);
+        assert_eq!(strip_frame(&[[1, 1, 1], [2, 2, 2]], &[1, usize::MAX]), [0, 0, 0, 1, 1, 1], "no frame for a stray position");
+    }
+
+    #[test]
+    fn sends_frames_from_the_background_thread() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let config = OutputConfig {
+            mode: OutputMode::RawUdp,
+            target: receiver.local_addr().unwrap().to_string(),
+            fps: 100.0,
+            ..Default::default()
+        };
+        let mut output = Output::start(config).unwrap();
+        output.send(vec![1, 2, 3]);
+        let mut buf = [0; 16];
+        let (len, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], [1, 2, 3]);
+    }
+}
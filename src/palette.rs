@@ -0,0 +1,349 @@
+use eframe::egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// A named set of driver colors, chosen in the settings menu.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    #[default]
+    Default,
+    HighContrast,
+    ColorBlindSafe,
+    TeamColors,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Default,
+        Palette::HighContrast,
+        Palette::ColorBlindSafe,
+        Palette::TeamColors,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Default => "Default",
+            Palette::HighContrast => "High contrast",
+            Palette::ColorBlindSafe => "Color-blind safe",
+            Palette::TeamColors => "Team colors",
+        }
+    }
+
+    /// Colors for the drivers in order, given their driver keys
+    /// (`verstappen`, `hamilton`, ...). The first 20 drivers always get
+    /// distinct colors from the palette; any more get distinct colors from
+    /// `generate_colors`.
+    pub fn colors(self, drivers: &[String]) -> Vec<Color32> {
+        match self {
+            Palette::Default => extend(&DEFAULT, drivers.len()),
+            Palette::HighContrast => extend(&HIGH_CONTRAST, drivers.len()),
+            Palette::ColorBlindSafe => extend(&color_blind_safe(), drivers.len()),
+            Palette::TeamColors => {
+                let fallback = extend(&DEFAULT, drivers.len());
+                drivers
+                    .iter()
+                    .zip(fallback)
+                    .map(|(driver, fallback)| team_color(driver).unwrap_or(fallback))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Generated colors tried for one palette before colors may repeat.
+const MAX_CANDIDATES: usize = 1 << 16;
+
+/// The first `n` of `colors`, followed by generated colors not already in it if `n` is larger.
+fn extend(colors: &[Color32], n: usize) -> Vec<Color32> {
+    let mut extended: Vec<Color32> = colors.iter().copied().take(n).collect();
+    add_distinct(&mut extended, n);
+    extended
+}
+
+/// `n` distinct colors, each a golden angle around the hue circle from the
+/// one before so that neighbours stay far apart however many there are.
+/// Brightness steps the same way, never dim enough to pass for an unlit LED.
+/// Past the `MAX_CANDIDATES` tried, colors repeat rather than run out.
+pub fn generate_colors(n: usize) -> Vec<Color32> {
+    let mut colors = Vec::with_capacity(n);
+    add_distinct(&mut colors, n);
+    colors
+}
+
+/// Adds generated colors to `colors` until it has `n`, skipping those it
+/// has. Once `MAX_CANDIDATES` are tried they are taken over again in
+/// order, repeats and all.
+fn add_distinct(colors: &mut Vec<Color32>, n: usize) {
+    let mut candidates = (0..MAX_CANDIDATES).map(golden_color);
+    while colors.len() < n {
+        match candidates.next() {
+            Some(color) if colors.contains(&color) => {}
+            Some(color) => colors.push(color),
+            None => colors.extend((0..n - colors.len()).map(|i| golden_color(i % MAX_CANDIDATES))),
+        }
+    }
+}
+
+/// The `i`th color of `generate_colors` before repeats are skipped.
+fn golden_color(i: usize) -> Color32 {
+    const GOLDEN_RATIO: f32 = 0.618_034;
+    let hue = (i as f32 * GOLDEN_RATIO).fract() * 6.0;
+    let value = 1.0 - 0.35 * (i as f32 * GOLDEN_RATIO * GOLDEN_RATIO).fract();
+    hsv(hue, 0.75, value)
+}
+
+/// `hue` in sextants, 0 to 6.
+fn hsv(hue: f32, saturation: f32, value: f32) -> Color32 {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let [r, g, b] = match hue as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    let channel = |c: f32| ((c + value - chroma) * 255.0).round() as u8;
+    Color32::from_rgb(channel(r), channel(g), channel(b))
+}
+
+const DEFAULT: [Color32; 20] = [
+    Color32::from_rgb(255, 0, 0),     // Red
+    Color32::from_rgb(0, 255, 0),     // Green
+    Color32::from_rgb(0, 0, 255),     // Blue
+    Color32::from_rgb(255, 255, 0),   // Yellow
+    Color32::from_rgb(255, 0, 255),   // Magenta
+    Color32::from_rgb(0, 255, 255),   // Cyan
+    Color32::from_rgb(128, 0, 0),     // Maroon
+    Color32::from_rgb(0, 128, 0),     // Dark Green
+    Color32::from_rgb(0, 0, 128),     // Navy
+    Color32::from_rgb(128, 128, 0),   // Olive
+    Color32::from_rgb(128, 0, 128),   // Purple
+    Color32::from_rgb(0, 128, 128),   // Teal
+    Color32::from_rgb(192, 192, 192), // Silver
+    Color32::from_rgb(255, 165, 0),   // Orange
+    Color32::from_rgb(255, 20, 147),  // Deep Pink
+    Color32::from_rgb(75, 0, 130),    // Indigo
+    Color32::from_rgb(255, 215, 0),   // Gold
+    Color32::from_rgb(0, 191, 255),   // Deep Sky Blue
+    Color32::from_rgb(255, 105, 180), // Hot Pink
+    Color32::from_rgb(139, 69, 19),   // Saddle Brown
+];
+
+// Kelly's colors of maximum contrast, without black (the unlit LED color) and olive green
+const HIGH_CONTRAST: [Color32; 20] = [
+    Color32::from_rgb(0xF2, 0xF3, 0xF4), // White
+    Color32::from_rgb(0xF3, 0xC3, 0x00), // Yellow
+    Color32::from_rgb(0x87, 0x56, 0x92), // Purple
+    Color32::from_rgb(0xF3, 0x84, 0x00), // Orange
+    Color32::from_rgb(0xA1, 0xCA, 0xF1), // Light Blue
+    Color32::from_rgb(0xBE, 0x00, 0x32), // Red
+    Color32::from_rgb(0xC2, 0xB2, 0x80), // Buff
+    Color32::from_rgb(0x84, 0x84, 0x82), // Gray
+    Color32::from_rgb(0x00, 0x88, 0x56), // Green
+    Color32::from_rgb(0xE6, 0x8F, 0xAC), // Purplish Pink
+    Color32::from_rgb(0x00, 0x67, 0xA5), // Blue
+    Color32::from_rgb(0xF9, 0x93, 0x79), // Yellowish Pink
+    Color32::from_rgb(0x60, 0x4E, 0x97), // Violet
+    Color32::from_rgb(0xF6, 0xA6, 0x00), // Orange Yellow
+    Color32::from_rgb(0xB3, 0x44, 0x6C), // Purplish Red
+    Color32::from_rgb(0xDC, 0xD3, 0x00), // Greenish Yellow
+    Color32::from_rgb(0x88, 0x2D, 0x17), // Reddish Brown
+    Color32::from_rgb(0x8D, 0xB6, 0x00), // Yellow Green
+    Color32::from_rgb(0x65, 0x45, 0x22), // Yellowish Brown
+    Color32::from_rgb(0xE2, 0x58, 0x22), // Reddish Orange
+];
+
+// Okabe-Ito without black
+const OKABE_ITO: [Color32; 7] = [
+    Color32::from_rgb(0xE6, 0x9F, 0x00), // Orange
+    Color32::from_rgb(0x56, 0xB4, 0xE9), // Sky Blue
+    Color32::from_rgb(0x00, 0x9E, 0x73), // Bluish Green
+    Color32::from_rgb(0xF0, 0xE4, 0x42), // Yellow
+    Color32::from_rgb(0x00, 0x72, 0xB2), // Blue
+    Color32::from_rgb(0xD5, 0x5E, 0x00), // Vermillion
+    Color32::from_rgb(0xCC, 0x79, 0xA7), // Reddish Purple
+];
+
+/// Okabe-Ito extended to 20 colors, generated once: each added color is
+/// the one from an sRGB grid farthest, in OKLab, from every color so far,
+/// as seen with normal vision and with each of the simulated color vision
+/// deficiencies. Colors darker than `MIN_LIGHTNESS` are left out since they
+/// are hard to tell from an unlit LED.
+fn color_blind_safe() -> Vec<Color32> {
+    static COLORS: OnceLock<Vec<Color32>> = OnceLock::new();
+    COLORS
+        .get_or_init(|| {
+            let grid = (0..16u32).flat_map(|r| (0..16u32).flat_map(move |g| (0..16u32).map(move |b| [r, g, b])));
+            let candidates: Vec<Color32> = grid
+                .map(|rgb| rgb.map(|c| (c * 17) as u8))
+                .map(|[r, g, b]| Color32::from_rgb(r, g, b))
+                .filter(|&color| oklab(color)[0] >= MIN_LIGHTNESS)
+                .collect();
+            let seen: Vec<_> = candidates.iter().map(|&color| views(color)).collect();
+            let seeds: Vec<_> = OKABE_ITO.iter().map(|&color| views(color)).collect();
+            let mut nearest: Vec<f32> = seen
+                .iter()
+                .map(|candidate| seeds.iter().map(|seed| view_distance(candidate, seed)).fold(f32::MAX, f32::min))
+                .collect();
+            let mut colors = OKABE_ITO.to_vec();
+            while colors.len() < 20 {
+                let best = (0..candidates.len()).max_by(|&a, &b| nearest[a].total_cmp(&nearest[b])).unwrap();
+                colors.push(candidates[best]);
+                for (nearest, views) in nearest.iter_mut().zip(&seen) {
+                    *nearest = nearest.min(view_distance(views, &seen[best]));
+                }
+            }
+            colors
+        })
+        .clone()
+}
+
+/// OKLab lightness below which a color reads as an unlit LED.
+const MIN_LIGHTNESS: f32 = 0.45;
+
+/// Linear RGB as seen with protanopia, deuteranopia and tritanopia
+/// (Machado, Oliveira and Fernandes 2009, full severity).
+const DEFICIENCIES: [[[f32; 3]; 3]; 3] = [
+    [[0.152286, 1.052583, -0.204868], [0.114503, 0.786281, 0.099216], [-0.003882, -0.048116, 1.051998]],
+    [[0.367322, 0.860646, -0.227968], [0.280085, 0.672501, 0.047413], [-0.011820, 0.042940, 0.968881]],
+    [[1.255528, -0.076749, -0.178779], [-0.078411, 0.930809, 0.147602], [0.004733, 0.691367, 0.303900]],
+];
+
+/// A color in OKLab with normal vision, then with each of `DEFICIENCIES`.
+fn views(color: Color32) -> [[f32; 3]; 4] {
+    let rgb = linear_rgb(color);
+    let simulate = |matrix: &[[f32; 3]; 3]| matrix.map(|row| (row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]).clamp(0.0, 1.0));
+    let [protan, deutan, tritan] = DEFICIENCIES.map(|matrix| linear_to_oklab(simulate(&matrix)));
+    [linear_to_oklab(rgb), protan, deutan, tritan]
+}
+
+/// How far apart two colors are for whoever sees them least apart.
+fn view_distance(a: &[[f32; 3]; 4], b: &[[f32; 3]; 4]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum::<f32>().sqrt())
+        .fold(f32::MAX, f32::min)
+}
+
+fn linear_rgb(color: Color32) -> [f32; 3] {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    [linear(color.r()), linear(color.g()), linear(color.b())]
+}
+
+fn oklab(color: Color32) -> [f32; 3] {
+    linear_to_oklab(linear_rgb(color))
+}
+
+fn linear_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+// 2023 teams, drivers first; the second driver gets the darker shade
+const TEAMS: [(Color32, [&str; 2]); 10] = [
+    (Color32::from_rgb(0x36, 0x71, 0xC6), ["verstappen", "perez"]),    // Red Bull
+    (Color32::from_rgb(0x27, 0xF4, 0xD2), ["hamilton", "russell"]),    // Mercedes
+    (Color32::from_rgb(0xE8, 0x00, 0x2D), ["leclerc", "sainz"]),       // Ferrari
+    (Color32::from_rgb(0xFF, 0x80, 0x00), ["norris", "piastri"]),      // McLaren
+    (Color32::from_rgb(0x22, 0x99, 0x71), ["alonso", "stroll"]),       // Aston Martin
+    (Color32::from_rgb(0xFF, 0x87, 0xBC), ["gasley", "ocon"]),         // Alpine
+    (Color32::from_rgb(0x64, 0xC4, 0xFF), ["albon", "sargeant"]),      // Williams
+    (Color32::from_rgb(0x5E, 0x8F, 0xAA), ["tsunoda", "lawson"]),      // AlphaTauri
+    (Color32::from_rgb(0xC9, 0x2D, 0x4B), ["bottas", "guanyu"]),       // Alfa Romeo
+    (Color32::from_rgb(0xB6, 0xBA, 0xBD), ["hulkenberg", "magnussen"]), // Haas
+];
+
+fn team_color(driver: &str) -> Option<Color32> {
+    TEAMS.iter().find_map(|(color, drivers)| match drivers.iter().position(|d| *d == driver)? {
+        0 => Some(*color),
+        _ => Some(darken(*color)),
+    })
+}
+
+fn darken(color: Color32) -> Color32 {
+    let scale = |c: u8| (c as u16 * 3 / 5) as u8;
+    Color32::from_rgb(scale(color.r()), scale(color.g()), scale(color.b()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_two_drivers_share_a_color() {
+        let drivers: Vec<String> = TEAMS
+            .iter()
+            .flat_map(|(_, drivers)| drivers.iter().map(|d| d.to_string()))
+            .collect();
+        assert_eq!(drivers.len(), 20);
+        for palette in Palette::ALL {
+            let colors = palette.colors(&drivers);
+            assert_eq!(colors.len(), 20);
+            for (i, a) in colors.iter().enumerate() {
+                for b in &colors[i + 1..] {
+                    assert_ne!(a, b, "{} repeats a color", palette.label());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn color_blind_safe_colors_stay_apart_with_every_deficiency() {
+        let min_distance = |colors: &[Color32]| {
+            let views: Vec<_> = colors.iter().map(|&color| super::views(color)).collect();
+            let pairs = views.iter().enumerate().flat_map(|(i, a)| views[i + 1..].iter().map(move |b| view_distance(a, b)));
+            pairs.fold(f32::MAX, f32::min)
+        };
+        let colors = color_blind_safe();
+        assert_eq!(colors.len(), 20);
+        assert_eq!(colors[..7], OKABE_ITO);
+        assert!(colors.iter().all(|&color| oklab(color)[0] >= MIN_LIGHTNESS));
+        let (safe, default) = (min_distance(&colors), min_distance(&DEFAULT));
+        assert!(safe > 0.07 && safe > 2.0 * default, "{safe} vs {default}");
+    }
+
+    #[test]
+    fn generated_colors_are_distinct_and_lit() {
+        let colors = generate_colors(500);
+        assert_eq!(colors.len(), 500);
+        for (i, a) in colors.iter().enumerate() {
+            assert!(!colors[i + 1..].contains(a), "{a:?} repeats");
+            assert!(oklab(*a)[0] >= MIN_LIGHTNESS, "{a:?} is too dark");
+        }
+
+        let drivers: Vec<String> = (0..30).map(|i| format!("driver{i}")).collect();
+        for palette in Palette::ALL {
+            let colors = palette.colors(&drivers);
+            assert_eq!(colors.len(), 30);
+            assert!(colors.iter().enumerate().all(|(i, a)| !colors[i + 1..].contains(a)), "{} repeats a color", palette.label());
+        }
+    }
+
+    #[test]
+    fn colors_already_taken_do_not_run_the_generator_dry() {
+        // Every color generate_colors would add for 60 drivers but 20 is taken already
+        let taken = generate_colors(40);
+        let colors = extend(&taken, 60);
+        assert_eq!(colors[..40], taken);
+        assert!(colors.iter().enumerate().all(|(i, a)| !colors[i + 1..].contains(a)), "still distinct");
+
+        let many = extend(&DEFAULT, 3 * MAX_CANDIDATES);
+        assert_eq!(many.len(), 3 * MAX_CANDIDATES, "colors repeat once there are no new ones");
+    }
+}
@@ -0,0 +1,200 @@
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use parquet::basic::{ConvertedType, LogicalType, TimeUnit, Type as PhysicalType};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use parquet::schema::types::Type;
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+
+use crate::data::{self, RunRace};
+use crate::source;
+
+/// What a column of a race data file holds, as far as reading it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Timestamp(TimeUnit),
+    Float,
+    Integer,
+    Other,
+}
+
+/// The columns a race data file needs, by name and kind, `time_delta` last
+/// as the one that may be left out.
+const COLUMNS: &[(&str, &str)] = &[("date", "timestamp"), ("x_led", "number"), ("y_led", "number"), ("time_delta", "integer")];
+
+/// Where the columns of a race data file are, by their index in the schema.
+struct Columns {
+    date: usize,
+    x_led: usize,
+    y_led: usize,
+    time_delta: Option<usize>,
+    nanos: bool, // The dates are nanoseconds, which come through as plain integers
+}
+
+/// Calls `row` with each row of a Parquet race data file, or why it could
+/// not be read, and its row number counted from 1. `date` must be a
+/// timestamp column, `x_led` and `y_led` float or integer columns and the
+/// optional `time_delta` an integer column; a file that differs is refused
+/// with the columns expected and found.
+pub fn read_rows(file_path: &Path, mut row: impl FnMut(Result<RunRace, String>, u64)) -> Result<(), Box<dyn Error>> {
+    let mut contents = Vec::new();
+    source::current().open_at(file_path, 0)?.read_to_end(&mut contents)?;
+    let reader = SerializedFileReader::new(Bytes::from(contents))?;
+    let fields = reader.metadata().file_metadata().schema().get_fields().to_vec();
+    let kinds: Vec<Kind> = fields.iter().map(|field| kind(field)).collect();
+    let position = |name: &str| fields.iter().position(|field| field.name() == name);
+    let columns = match (position("date"), position("x_led"), position("y_led"), position("time_delta")) {
+        (Some(date), Some(x_led), Some(y_led), time_delta)
+            if matches!(kinds[date], Kind::Timestamp(_))
+                && [x_led, y_led].iter().all(|&i| matches!(kinds[i], Kind::Float | Kind::Integer))
+                && time_delta.is_none_or(|i| kinds[i] == Kind::Integer) =>
+        {
+            Columns { date, x_led, y_led, time_delta, nanos: kinds[date] == Kind::Timestamp(TimeUnit::NANOS) }
+        }
+        _ => return Err(schema_mismatch(&fields).into()),
+    };
+
+    for (i, record) in reader.get_row_iter(None)?.enumerate() {
+        let line = i as u64 + 1;
+        let record = record?;
+        let values: Vec<&Field> = record.get_column_iter().map(|(_, value)| value).collect();
+        row(parse_row(&values, &columns).map(|run_race| RunRace { line, ..run_race }), line);
+    }
+    Ok(())
+}
+
+fn parse_row(values: &[&Field], columns: &Columns) -> Result<RunRace, String> {
+    let date = date(values[columns.date], columns.nanos)?;
+    let (x_led, y_led) = (number("x_led", values[columns.x_led])?, number("y_led", values[columns.y_led])?);
+    let time_delta = columns.time_delta.map(|i| integer("time_delta", values[i])).transpose()?.flatten();
+    data::race_row(date, x_led, y_led, time_delta)
+}
+
+fn kind(field: &Type) -> Kind {
+    if !field.is_primitive() {
+        return Kind::Other;
+    }
+    let info = field.get_basic_info();
+    match (field.get_physical_type(), info.logical_type_ref(), info.converted_type()) {
+        (PhysicalType::INT96, _, _) => Kind::Timestamp(TimeUnit::MILLIS), // Read as milliseconds
+        (PhysicalType::INT64, Some(LogicalType::Timestamp(timestamp)), _) => Kind::Timestamp(timestamp.unit),
+        (PhysicalType::INT64, _, ConvertedType::TIMESTAMP_MILLIS) => Kind::Timestamp(TimeUnit::MILLIS),
+        (PhysicalType::INT64, _, ConvertedType::TIMESTAMP_MICROS) => Kind::Timestamp(TimeUnit::MICROS),
+        (PhysicalType::FLOAT | PhysicalType::DOUBLE, _, _) => Kind::Float,
+        (PhysicalType::INT32 | PhysicalType::INT64, None | Some(LogicalType::Integer { .. }), _) => Kind::Integer,
+        _ => Kind::Other,
+    }
+}
+
+/// Says which columns were expected and which the file has.
+fn schema_mismatch(fields: &[std::sync::Arc<Type>]) -> String {
+    let expected: Vec<String> = COLUMNS.iter().map(|(name, kind)| format!("{name} ({kind})")).collect();
+    let found: Vec<String> = fields.iter().map(|field| format!("{} ({})", field.name(), describe(field))).collect();
+    format!("expected columns {} and optionally {}, found {}", expected[..3].join(", "), expected[3], found.join(", "))
+}
+
+fn describe(field: &Type) -> String {
+    match kind(field) {
+        Kind::Timestamp(_) => "timestamp".to_string(),
+        Kind::Float => "float".to_string(),
+        Kind::Integer => "integer".to_string(),
+        Kind::Other if !field.is_primitive() => "group".to_string(),
+        Kind::Other => {
+            let info = field.get_basic_info();
+            match (info.logical_type_ref(), info.converted_type()) {
+                (Some(LogicalType::String), _) | (None, ConvertedType::UTF8) => "string".to_string(),
+                (Some(logical), _) => format!("{logical:?}").to_lowercase(),
+                (None, _) => field.get_physical_type().to_string().to_lowercase(),
+            }
+        }
+    }
+}
+
+fn date(value: &Field, nanos: bool) -> Result<DateTime<Utc>, String> {
+    let date = match value {
+        Field::TimestampMillis(ms) => DateTime::from_timestamp_millis(*ms),
+        Field::TimestampMicros(us) => DateTime::from_timestamp_micros(*us),
+        Field::Long(ns) if nanos => Some(DateTime::from_timestamp_nanos(*ns)),
+        Field::Null => return Err("column date: empty".to_string()),
+        _ => None,
+    };
+    date.ok_or_else(|| format!("column date: invalid timestamp {value}"))
+}
+
+fn number(column: &str, value: &Field) -> Result<f64, String> {
+    match value {
+        Field::Float(value) => Ok(*value as f64),
+        Field::Double(value) => Ok(*value),
+        _ => integer(column, value)?.map(|value| value as f64).ok_or_else(|| format!("column {column}: empty")),
+    }
+}
+
+/// An integer value, `None` when the row leaves it empty.
+fn integer(column: &str, value: &Field) -> Result<Option<i64>, String> {
+    Ok(Some(match value {
+        Field::Byte(value) => *value as i64,
+        Field::Short(value) => *value as i64,
+        Field::Int(value) => *value as i64,
+        Field::Long(value) => *value,
+        Field::UByte(value) => *value as i64,
+        Field::UShort(value) => *value as i64,
+        Field::UInt(value) => *value as i64,
+        Field::ULong(value) => i64::try_from(*value).map_err(|_| format!("column {column}: {value} is too large"))?,
+        Field::Null => return Ok(None),
+        _ => return Err(format!("column {column}: {value} is not a number")),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::sync::Arc;
+
+    /// The first rows of `time_delta_albon_start.csv`, written as Parquet
+    /// with microsecond timestamps and Snappy compression.
+    const FIXTURE: &str = "testdata/time_delta_albon_start.parquet";
+
+    #[test]
+    fn reads_the_same_rows_as_the_csv_it_was_made_from() {
+        let parquet = data::read_race_data(FIXTURE, None).unwrap();
+        let csv = data::read_race_data("time_delta_albon_start.csv", None).unwrap();
+        assert!(parquet.issues.is_empty());
+        assert_eq!(parquet.records.len(), 50);
+        for (parquet, csv) in parquet.records.iter().zip(&csv.records) {
+            assert_eq!((parquet.date, parquet.x_led, parquet.y_led, parquet.time_delta), (csv.date, csv.x_led, csv.y_led, csv.time_delta));
+        }
+        assert_eq!(parquet.records.iter().map(|row| row.line).take(3).collect::<Vec<_>>(), [1, 2, 3], "rows count from 1");
+        assert_eq!(data::read_race_line(Path::new(FIXTURE), None, 2).unwrap().unwrap().date, csv.records[1].date);
+    }
+
+    #[test]
+    fn a_file_with_other_columns_says_what_was_expected() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-strings.parquet", std::process::id()));
+        let schema = Arc::new(parse_message_type("message race { REQUIRED BYTE_ARRAY date (UTF8); REQUIRED DOUBLE x; REQUIRED DOUBLE y_led; }").unwrap());
+        let mut writer = SerializedFileWriter::new(std::fs::File::create(&path).unwrap(), schema, Arc::new(WriterProperties::default())).unwrap();
+        let mut group = writer.next_row_group().unwrap();
+        let mut column = group.next_column().unwrap().unwrap();
+        column.typed::<ByteArrayType>().write_batch(&[ByteArray::from("2023-08-27T12:11:11Z")], None, None).unwrap();
+        column.close().unwrap();
+        for _ in 0..2 {
+            let mut column = group.next_column().unwrap().unwrap();
+            column.typed::<DoubleType>().write_batch(&[1.0], None, None).unwrap();
+            column.close().unwrap();
+        }
+        group.close().unwrap();
+        writer.close().unwrap();
+
+        let e = data::read_race_data(&path, None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected columns date (timestamp), x_led (number), y_led (number) and optionally time_delta (integer), found date (string), x (float), y_led (float)"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+
+/// How far back the averages reach, short enough that the numbers follow
+/// what is on screen while staying steady enough to read.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// What one frame spent its time on.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub update: Duration, // All of update()
+    pub leds: Duration, // Moving playback on and working out which LEDs are lit
+    pub paint: Duration, // Drawing the track
+    pub rows: u64, // Rows of race data played in
+    pub reason: &'static str, // Why the frame was drawn
+}
+
+/// Frame rate and what frames spend their time on, averaged over the frames
+/// of the last second. The window only redraws when there is something new
+/// to show, so a paused race shows a low frame rate.
+#[derive(Debug, Default, Clone)]
+pub struct FrameStats {
+    frames: VecDeque<(Instant, Frame)>, // Oldest first, each with when it began
+}
+
+impl FrameStats {
+    /// Takes a frame that began at `start`, dropping those more than a
+    /// second older.
+    pub fn record(&mut self, start: Instant, frame: Frame) {
+        self.frames.push_back((start, frame));
+        while self.frames.front().is_some_and(|&(first, _)| start.saturating_duration_since(first) > WINDOW) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Frames per second, once two frames have been seen.
+    pub fn fps(&self) -> Option<f64> {
+        let (first, last) = (self.frames.front()?.0, self.frames.back()?.0);
+        let seconds = last.saturating_duration_since(first).as_secs_f64();
+        (seconds > 0.0).then(|| (self.frames.len() - 1) as f64 / seconds)
+    }
+
+    /// Milliseconds spent in `update()` per frame.
+    pub fn update_ms(&self) -> Option<f64> {
+        self.average(|frame| frame.update.as_secs_f64() * 1000.0)
+    }
+
+    /// Milliseconds spent on the LEDs per frame.
+    pub fn leds_ms(&self) -> Option<f64> {
+        self.average(|frame| frame.leds.as_secs_f64() * 1000.0)
+    }
+
+    /// Milliseconds spent painting the track per frame.
+    pub fn paint_ms(&self) -> Option<f64> {
+        self.average(|frame| frame.paint.as_secs_f64() * 1000.0)
+    }
+
+    /// Rows played in per frame.
+    pub fn rows(&self) -> Option<f64> {
+        self.average(|frame| frame.rows as f64)
+    }
+
+    /// Why the latest frame was drawn.
+    pub fn reason(&self) -> Option<&'static str> {
+        Some(self.frames.back()?.1.reason)
+    }
+
+    fn average(&self, value: impl Fn(&Frame) -> f64) -> Option<f64> {
+        (!self.frames.is_empty()).then(|| self.frames.iter().map(|(_, frame)| value(frame)).sum::<f64>() / self.frames.len() as f64)
+    }
+}
+
+/// How many of something went by per second, `None` when no time passed.
+pub fn per_second(count: u64, elapsed: Duration) -> Option<f64> {
+    let seconds = elapsed.as_secs_f64();
+    (seconds > 0.0).then(|| count as f64 / seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_the_frames_of_the_last_second() {
+        let mut stats = FrameStats::default();
+        let start = Instant::now();
+        let frame = |ms: u64, rows: u64| Frame {
+            update: Duration::from_millis(ms),
+            leds: Duration::from_millis(ms / 2),
+            paint: Duration::from_millis(ms / 4),
+            rows,
+            reason: "next row",
+        };
+        stats.record(start, frame(4, 20));
+        assert_eq!((stats.fps(), stats.update_ms(), stats.reason()), (None, Some(4.0), Some("next row")));
+
+        stats.record(start + Duration::from_millis(20), frame(4, 0));
+        assert!((stats.fps().unwrap() - 50.0).abs() < 1e-9);
+        stats.record(start + Duration::from_millis(50), Frame { reason: "input", ..frame(16, 10) });
+        assert!((stats.fps().unwrap() - 40.0).abs() < 1e-9, "two frames in 50 ms");
+        assert!((stats.update_ms().unwrap() - 8.0).abs() < 1e-9);
+        assert_eq!((stats.leds_ms(), stats.paint_ms(), stats.rows(), stats.reason()), (Some(4.0), Some(2.0), Some(10.0), Some("input")));
+
+        stats.record(start + Duration::from_millis(1010), frame(0, 0));
+        assert!((stats.update_ms().unwrap() - 20.0 / 3.0).abs() < 1e-9, "the first frame is over a second old");
+        assert_eq!(per_second(500, Duration::from_millis(250)), Some(2000.0));
+        assert_eq!(per_second(500, Duration::ZERO), None);
+    }
+}
@@ -0,0 +1,148 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::data::{LedCoordinate, Validated};
+use crate::loader::{self, LoadedRace};
+use crate::track::LedIndex;
+
+/// Shortest time the title card of the next race is up, however fast it loads.
+pub const TITLE_CARD: Duration = Duration::from_secs(3);
+
+/// One race of the `[[playlist]]` in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaylistEntry {
+    /// Shown on the title card while the race loads.
+    pub title: String,
+    /// LED coordinates of the race's track, relative to `data_dir` of the config.
+    pub coordinates: PathBuf,
+    /// Folder with the race's dataset files, relative to `data_dir` of the config.
+    pub data_dir: PathBuf,
+    /// Seconds the race plays before the next one, to the end of its data when unset.
+    pub max_duration: Option<f64>,
+}
+
+/// A race of the playlist read in the background, ready to swap in.
+pub struct LoadedEntry {
+    pub coordinates: Validated<LedCoordinate>,
+    pub led_index: Arc<LedIndex>,
+    pub paths: Vec<PathBuf>, // Dataset files, for watching
+    pub race: LoadedRace,
+}
+
+/// Races played one after the other, starting over after the last. Between
+/// two races the previous one is unloaded and a title card shows while the
+/// next is read on another thread.
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+    pub current: usize, // Entry playing, or loading behind the title card
+    loading: Option<(Instant, Receiver<Result<LoadedEntry, String>>)>, // When the title card went up
+    loaded: Option<Result<LoadedEntry, String>>, // Arrived before the title card was up long enough
+    playing_since: Option<Instant>,
+}
+
+impl Playlist {
+    pub fn new(entries: Vec<PlaylistEntry>) -> Self {
+        Self { entries, current: 0, loading: None, loaded: None, playing_since: None }
+    }
+
+    pub fn entry(&self) -> &PlaylistEntry {
+        &self.entries[self.current]
+    }
+
+    /// The title card is up, for the race being loaded.
+    pub fn is_switching(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Moves `step` entries on, going round at either end, and reads that
+    /// entry with `load` on another thread. Whatever was loading before is
+    /// dropped once it arrives.
+    pub fn go(&mut self, step: isize, now: Instant, load: impl FnOnce(PlaylistEntry) -> Result<LoadedEntry, String> + Send + 'static) {
+        let len = self.entries.len() as isize;
+        self.current = (self.current as isize + step).rem_euclid(len) as usize;
+        let (sender, receiver) = mpsc::channel();
+        let entry = self.entry().clone();
+        loader::spawn(move || {
+            let _ = sender.send(load(entry));
+        });
+        (self.loading, self.loaded, self.playing_since) = (Some((now, receiver)), None, None);
+    }
+
+    /// The entry that finished loading, once the title card has been up for
+    /// `TITLE_CARD`. Playing it starts the clock on its `max_duration`.
+    pub fn poll(&mut self, now: Instant) -> Option<Result<LoadedEntry, String>> {
+        let (shown_at, receiver) = self.loading.as_ref()?;
+        if self.loaded.is_none() {
+            self.loaded = match receiver.try_recv() {
+                Ok(loaded) => Some(loaded),
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) => Some(Err("the loading thread stopped".to_string())),
+            };
+        }
+        if now.saturating_duration_since(*shown_at) < TITLE_CARD {
+            return None;
+        }
+        let loaded = self.loaded.take()?;
+        (self.loading, self.playing_since) = (None, Some(now));
+        Some(loaded)
+    }
+
+    /// The race playing is done: its data ran out, or it has played for its `max_duration`.
+    pub fn is_over(&self, race_complete: bool, now: Instant) -> bool {
+        let Some(since) = self.playing_since else {
+            return false;
+        };
+        let timed_out = self.entry().max_duration.is_some_and(|seconds| now.saturating_duration_since(since).as_secs_f64() >= seconds);
+        race_complete || timed_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, max_duration: Option<f64>) -> PlaylistEntry {
+        PlaylistEntry { title: title.to_string(), coordinates: PathBuf::new(), data_dir: PathBuf::from(title), max_duration }
+    }
+
+    fn loaded(entry: PlaylistEntry) -> Result<LoadedEntry, String> {
+        Err(entry.title)
+    }
+
+    /// What `poll` gives at `now` once the loading thread has sent it.
+    fn arrived(playlist: &mut Playlist, now: Instant) -> Result<LoadedEntry, String> {
+        loop {
+            match playlist.poll(now) {
+                Some(loaded) => return loaded,
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+
+    #[test]
+    fn goes_round_the_entries_behind_a_title_card() {
+        let mut playlist = Playlist::new(vec![entry("monza", None), entry("spa", Some(60.0)), entry("zandvoort", None)]);
+        let start = Instant::now();
+        playlist.go(-1, start, loaded);
+        assert_eq!((playlist.current, playlist.is_switching()), (2, true), "back from the first is the last");
+        assert!(playlist.poll(start + Duration::from_secs(1)).is_none(), "the title card stays up");
+        assert_eq!(arrived(&mut playlist, start + TITLE_CARD).err(), Some("zandvoort".to_string()));
+        assert!(!playlist.is_switching());
+
+        playlist.go(1, start, loaded);
+        assert_eq!(playlist.current, 0);
+        playlist.go(1, start, loaded);
+        assert_eq!(playlist.entry().title, "spa");
+        assert!(!playlist.is_over(true, start), "not over while loading");
+        let playing = start + TITLE_CARD;
+        assert!(arrived(&mut playlist, playing).is_err());
+        assert!(!playlist.is_over(false, playing + Duration::from_secs(59)));
+        assert!(playlist.is_over(false, playing + Duration::from_secs(60)));
+        assert!(playlist.is_over(true, playing));
+    }
+}
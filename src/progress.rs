@@ -0,0 +1,134 @@
+use crate::data::LedCoordinate;
+
+/// How far one car has travelled along the LEDs it has visited.
+#[derive(Debug, Default, Clone)]
+pub struct CarProgress {
+    /// Cumulative distance between consecutive visited LEDs, in coordinate units.
+    pub distance: f64,
+    last_led: Option<usize>,
+    /// `(distance, simulated ms)` recorded each time the car moves to a new LED.
+    history: Vec<(f64, u64)>,
+}
+
+impl CarProgress {
+    pub fn observe(&mut self, coordinates: &[LedCoordinate], led: usize, sim_ms: u64) {
+        if self.last_led == Some(led) {
+            return;
+        }
+        if let Some(last) = self.last_led {
+            let (from, to) = (&coordinates[last], &coordinates[led]);
+            self.distance += (to.x_led - from.x_led).hypot(to.y_led - from.y_led);
+        }
+        self.last_led = Some(led);
+        self.history.push((self.distance, sim_ms));
+    }
+
+    /// A copy without the history, which only grows, for keeping many
+    /// copies of one car along a race. `restore_history` puts it back.
+    pub fn without_history(&self) -> Self {
+        Self { distance: self.distance, last_led: self.last_led, history: Vec::new() }
+    }
+
+    /// The LED the car last moved to.
+    pub fn led(&self) -> Option<usize> {
+        self.last_led
+    }
+
+    /// Simulated time the car moved to `led`.
+    pub fn reached_ms(&self) -> Option<u64> {
+        self.history.last().map(|&(_, ms)| ms)
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Moves the history out, leaving none.
+    pub fn take_history(&mut self) -> Vec<(f64, u64)> {
+        std::mem::take(&mut self.history)
+    }
+
+    /// Sets the history to `history`, taken from a later point of the same race.
+    pub fn restore_history(&mut self, history: &[(f64, u64)]) {
+        self.history = history.to_vec();
+    }
+
+    /// Simulated time at which this car first covered `distance`.
+    fn time_at(&self, distance: f64) -> Option<u64> {
+        let idx = self.history.partition_point(|&(d, _)| d < distance);
+        self.history.get(idx).map(|&(_, ms)| ms)
+    }
+}
+
+/// One row of the gap table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gap {
+    pub dataset_idx: usize,
+    /// Distance behind the leader, in coordinate units.
+    pub distance: f64,
+    /// Seconds since the leader was where this car is now.
+    pub seconds: Option<f64>,
+}
+
+/// Ranks cars by completed laps and then distance travelled, leader first,
+/// with each car's gap to the leader. Laps come first so that a car whose
+/// position jitters between LEDs does not gain places from the extra
+/// distance. Each car is given with its laps and the simulated time its gap
+/// is measured at: the current time, or the moment its data ended so the
+/// gap stays frozen. Cars that are level keep their dataset order.
+pub fn gaps_to_leader(cars: &[(&CarProgress, u32, u64)]) -> Vec<Gap> {
+    let mut order: Vec<usize> = (0..cars.len()).collect();
+    order.sort_by(|&a, &b| {
+        let ((car_a, laps_a, _), (car_b, laps_b, _)) = (cars[a], cars[b]);
+        laps_b.cmp(&laps_a).then(car_b.distance.total_cmp(&car_a.distance))
+    });
+    let Some(&leader) = order.first() else {
+        return Vec::new();
+    };
+    let leader = cars[leader].0;
+    order
+        .into_iter()
+        .map(|idx| {
+            let (car, _, as_of_ms) = cars[idx];
+            Gap {
+                dataset_idx: idx,
+                distance: (leader.distance - car.distance).max(0.0),
+                seconds: leader
+                    .time_at(car.distance)
+                    .map(|ms| as_of_ms.saturating_sub(ms) as f64 / 1000.0),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn driven(coordinates: &[LedCoordinate], leds: &[usize]) -> CarProgress {
+        let mut progress = CarProgress::default();
+        for (ms, &led) in leds.iter().enumerate() {
+            progress.observe(coordinates, led, ms as u64 * 100);
+        }
+        progress
+    }
+
+    #[test]
+    fn order_follows_laps_then_distance() {
+        let coordinates: Vec<LedCoordinate> =
+            (0..4).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect();
+        let slow = driven(&coordinates, &[0, 1]);
+        let fast = driven(&coordinates, &[0, 1, 2, 3]);
+        let jittery = driven(&coordinates, &[0, 1, 0, 1, 0, 1, 2]);
+        let order = |cars: &[(&CarProgress, u32, u64)]| -> Vec<usize> {
+            gaps_to_leader(cars).iter().map(|gap| gap.dataset_idx).collect()
+        };
+
+        assert_eq!(order(&[(&slow, 0, 300), (&fast, 0, 300)]), [1, 0]);
+        // More distance from jitter does not beat a car a lap ahead
+        assert_eq!(order(&[(&jittery, 0, 600), (&fast, 1, 600)]), [1, 0]);
+        let gaps = gaps_to_leader(&[(&fast, 0, 300), (&slow, 0, 300)]);
+        assert_eq!(gaps[1].distance, 2.0);
+        assert_eq!(gaps[1].seconds, Some(0.2)); // The leader passed LED 1 at 100 ms
+    }
+}
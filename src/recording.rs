@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::data::LedCoordinate;
+
+/// Version written to the header of new recordings.
+pub const RECORDING_VERSION: u32 = 1;
+
+/// Largest difference between a recorded LED position and the loaded one
+/// for the two to count as the same layout.
+const POSITION_TOLERANCE: f64 = 1e-6;
+
+/// First line of a recording: what is needed to draw it without the race data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    /// Position of every LED, in the order of the coordinates file.
+    pub leds: Vec<[f64; 2]>,
+    pub drivers: Vec<RecordedDriver>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedDriver {
+    pub key: String,
+    pub name: String,
+    pub code: String,
+    pub color: [u8; 3],
+}
+
+/// A car shown on an LED, or taken off the track by a stop or by hiding it,
+/// at a wall clock offset from the start of the recording. Written as a
+/// `[ms, driver, led]` array, one per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event(pub u64, pub usize, pub Option<usize>);
+
+impl Event {
+    pub fn at_ms(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Writes a JSON lines recording as playback goes.
+pub struct Recorder {
+    out: BufWriter<File>,
+    started: Instant,
+    /// The LED last written for each driver.
+    shown: Vec<Option<usize>>,
+    header: Header,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &Header) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path).map_err(|e| format!("cannot write recording {}: {e}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        serde_json::to_writer(&mut out, header)?;
+        writeln!(out)?;
+        Ok(Self { out, started: Instant::now(), shown: vec![None; header.drivers.len()], header: header.clone() })
+    }
+
+    /// Whether a race with `header` can go on into this recording: the
+    /// same drivers, by key and in the same order, on the same LEDs.
+    pub fn continues(&self, header: &Header) -> bool {
+        let keys = |header: &Header| header.drivers.iter().map(|driver| driver.key.clone()).collect::<Vec<_>>();
+        self.header.leds == header.leds && keys(&self.header) == keys(header)
+    }
+
+    /// Writes an event for every driver whose shown LED changed.
+    pub fn record(&mut self, shown: &[Option<usize>]) -> Result<(), Box<dyn Error>> {
+        self.record_at(self.started.elapsed().as_millis() as u64, shown)
+    }
+
+    fn record_at(&mut self, at_ms: u64, shown: &[Option<usize>]) -> Result<(), Box<dyn Error>> {
+        for (driver, (&led, last)) in shown.iter().zip(&mut self.shown).enumerate() {
+            if led != *last {
+                *last = led;
+                serde_json::to_writer(&mut self.out, &Event(at_ms, driver, led))?;
+                writeln!(self.out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the recording after the first `number - 1` goes: `path` with
+/// `-{number}` before its extension.
+pub fn numbered(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{number}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{number}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Plays events back against the wall clock, as they were recorded.
+pub struct Replay {
+    events: Vec<Event>,
+    next: usize,
+    started: Option<Instant>, // Unset while stopped
+}
+
+impl Replay {
+    /// Starts playing straight away.
+    pub fn new(events: Vec<Event>) -> Self {
+        Self { events, next: 0, started: Some(Instant::now()) }
+    }
+
+    pub fn restart(&mut self) {
+        (self.next, self.started) = (0, Some(Instant::now()));
+    }
+
+    pub fn stop(&mut self) {
+        (self.next, self.started) = (0, None);
+    }
+
+    /// The events that became due since the last call.
+    pub fn due(&mut self) -> &[Event] {
+        match self.started {
+            Some(started) => self.due_at(started.elapsed().as_millis() as u64),
+            None => &[],
+        }
+    }
+
+    pub fn due_at(&mut self, at_ms: u64) -> &[Event] {
+        let from = self.next;
+        self.next += self.events[from..].partition_point(|event| event.at_ms() <= at_ms);
+        &self.events[from..self.next]
+    }
+
+    /// Wall time until the next event, `None` when stopped or at the end.
+    pub fn until_next(&self) -> Option<Duration> {
+        let (started, event) = (self.started?, self.events.get(self.next)?);
+        Some(Duration::from_millis(event.at_ms()).saturating_sub(started.elapsed()))
+    }
+}
+
+/// A recording read back, events in the order they were written.
+pub struct Recording {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("cannot read recording {}: {e}", path.display()))?;
+        let invalid = |line: usize, e: &dyn std::fmt::Display| format!("invalid recording {} line {line}: {e}", path.display());
+        let mut lines = BufReader::new(file).lines();
+        let first = lines.next().ok_or_else(|| invalid(1, &"empty file"))??;
+        let header: Header = serde_json::from_str(&first).map_err(|e| invalid(1, &e))?;
+        if header.version > RECORDING_VERSION {
+            return Err(invalid(1, &format!("written by a newer version ({})", header.version)).into());
+        }
+        let mut events = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: Event = serde_json::from_str(&line).map_err(|e| invalid(i + 2, &e))?;
+            if event.1 >= header.drivers.len() || event.2.is_some_and(|led| led >= header.leds.len()) {
+                return Err(invalid(i + 2, &"driver or LED out of range").into());
+            }
+            events.push(event);
+        }
+        Ok(Self { header, events })
+    }
+
+    /// Whether the recording was made with the LED layout in `coordinates`.
+    pub fn check_layout(&self, coordinates: &[LedCoordinate]) -> Result<(), String> {
+        if self.header.leds.len() != coordinates.len() {
+            return Err(format!(
+                "the recording has {} LEDs but the coordinates file has {}",
+                self.header.leds.len(),
+                coordinates.len()
+            ));
+        }
+        let moved = self.header.leds.iter().zip(coordinates).position(|(&[x, y], coord)| {
+            (x - coord.x_led).abs() > POSITION_TOLERANCE || (y - coord.y_led).abs() > POSITION_TOLERANCE
+        });
+        match moved {
+            Some(led) => Err(format!("LED {led} of the recording is not where the coordinates file puts it")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_round_trip_and_check_the_layout() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-recording.jsonl", std::process::id()));
+        let coordinates = [(0.0, 0.0), (1.0, 2.5)].map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let driver = |key: &str| RecordedDriver {
+            key: key.to_string(),
+            name: key.to_uppercase(),
+            code: String::new(),
+            color: [255, 0, 0],
+        };
+        let header = Header {
+            version: RECORDING_VERSION,
+            leds: coordinates.iter().map(|coord| [coord.x_led, coord.y_led]).collect(),
+            drivers: vec![driver("albon"), driver("alonso")],
+        };
+        let mut recorder = Recorder::create(&path, &header).unwrap();
+        recorder.record_at(0, &[Some(0), None]).unwrap();
+        recorder.record_at(16, &[Some(0), None]).unwrap(); // Nothing changed
+        recorder.record_at(240, &[Some(1), Some(0)]).unwrap();
+        recorder.record_at(500, &[None, Some(0)]).unwrap();
+        drop(recorder);
+
+        let recording = Recording::read(&path).unwrap();
+        assert_eq!(recording.header, header);
+        assert_eq!(
+            recording.events,
+            [Event(0, 0, Some(0)), Event(240, 0, Some(1)), Event(240, 1, Some(0)), Event(500, 0, None)]
+        );
+        assert!(std::fs::read_to_string(&path).unwrap().lines().nth(2) == Some("[240,0,1]"));
+        assert!(recording.check_layout(&coordinates).is_ok());
+        assert!(recording.check_layout(&coordinates[..1]).unwrap_err().contains("has 2 LEDs"));
+        let moved = [LedCoordinate::default(), LedCoordinate { x_led: 1.0, ..Default::default() }];
+        assert!(recording.check_layout(&moved).unwrap_err().starts_with("LED 1"));
+
+        std::fs::write(&path, format!("{}\n[0,2,0]\n", serde_json::to_string(&header).unwrap())).unwrap();
+        assert!(Recording::read(&path).err().unwrap().to_string().contains("line 2"));
+        assert_eq!(numbered(Path::new("out/race.jsonl"), 2), Path::new("out/race-2.jsonl"));
+        assert_eq!(numbered(Path::new("race"), 3), Path::new("race-3"));
+    }
+}
@@ -0,0 +1,739 @@
+use eframe::egui::{self, pos2, vec2, Color32, Pos2, Rect, Vec2};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::data::{LedClass, LedCoordinate};
+use crate::track::Spacing;
+
+/// Size of one LED square, in points, unless configured otherwise.
+pub const LED_SIZE: Vec2 = vec2(20.0, 20.0);
+
+/// Widths offered for a fixed LED size, in points.
+pub const LED_POINTS_RANGE: std::ops::RangeInclusive<f32> = 2.0..=60.0;
+
+/// Fractions of the LED spacing offered for an automatic LED size.
+pub const LED_SPACING_RANGE: std::ops::RangeInclusive<f32> = 0.1..=1.0;
+
+/// Width of the track outline, in points.
+pub const OUTLINE_WIDTH: f32 = 4.0;
+
+/// Width of the lines between sectors, in points.
+pub const SECTOR_LINE_WIDTH: f32 = 2.0;
+
+/// Length of the lines between sectors, in LED sizes.
+const SECTOR_LINE_LENGTH: f32 = 2.5;
+
+/// Width of the edge around unlit LEDs, in points.
+pub const UNLIT_OUTLINE_WIDTH: f32 = 1.0;
+
+/// Fill of an unlit pit lane LED, so the pit lane shows against the track.
+pub const PIT_LANE_UNLIT: Color32 = Color32::from_gray(28);
+
+/// Brightness of a car's color on a pit lane LED.
+pub const PIT_LANE_SHADE: f32 = 0.6;
+
+/// Edge around lit LEDs on a light background, so pale car colors show.
+pub const LIT_EDGE: Color32 = Color32::from_gray(60);
+
+/// The look of the window's widgets.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 2] = [Theme::Dark, Theme::Light];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+
+    pub fn visuals(self) -> egui::Visuals {
+        match self {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        }
+    }
+}
+
+/// What the track is drawn on, picked apart from the theme. The unlit LEDs
+/// are shaded to stand out from it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backdrop {
+    /// The theme's panel color, with black LEDs.
+    #[default]
+    Theme,
+    Black,
+    DarkGray,
+    White,
+}
+
+impl Backdrop {
+    pub const ALL: [Backdrop; 4] = [Backdrop::Theme, Backdrop::Black, Backdrop::DarkGray, Backdrop::White];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Backdrop::Theme => "Theme",
+            Backdrop::Black => "Black",
+            Backdrop::DarkGray => "Dark gray",
+            Backdrop::White => "White",
+        }
+    }
+
+    /// The color drawn behind the track, `panel_fill` being the theme's.
+    pub fn fill(self, panel_fill: Color32) -> Color32 {
+        match self {
+            Backdrop::Theme => panel_fill,
+            Backdrop::Black => Color32::BLACK,
+            Backdrop::DarkGray => Color32::from_gray(64),
+            Backdrop::White => Color32::WHITE,
+        }
+    }
+
+    /// Fill of an unlit LED of `class`, the pit lane a shade apart from the track.
+    pub fn unlit(self, class: LedClass) -> Color32 {
+        let pit_lane = class == LedClass::Pitlane;
+        match self {
+            Backdrop::Theme if pit_lane => PIT_LANE_UNLIT,
+            Backdrop::Theme => Color32::BLACK,
+            Backdrop::Black => Color32::from_gray(if pit_lane { 40 } else { 60 }),
+            Backdrop::DarkGray => Color32::from_gray(if pit_lane { 36 } else { 16 }),
+            Backdrop::White => Color32::from_gray(if pit_lane { 220 } else { 190 }),
+        }
+    }
+}
+
+/// `LIT_EDGE` on a light `fill`, where pale car colors would fade into it.
+pub fn lit_edge(fill: Color32) -> Option<Color32> {
+    (text_on(fill) == Color32::BLACK).then_some(LIT_EDGE)
+}
+
+/// How an LED is drawn inside its box, as wide as `LedSize` says.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedShape {
+    #[default]
+    Square,
+    /// A dot as wide as the box, with anti-aliased edges.
+    Circle,
+}
+
+impl LedShape {
+    pub const ALL: [LedShape; 2] = [LedShape::Square, LedShape::Circle];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LedShape::Square => "Square",
+            LedShape::Circle => "Circle",
+        }
+    }
+
+    /// Paints one LED with its box at `rect`.
+    pub fn paint(self, painter: &egui::Painter, rect: Rect, color: Color32) {
+        match self {
+            LedShape::Square => painter.rect_filled(rect, egui::Rounding::same(0.0), color),
+            LedShape::Circle => painter.circle_filled(rect.center(), rect.width() / 2.0, color),
+        }
+    }
+}
+
+/// How wide the box of each LED is.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedSize {
+    /// A fixed width in points, whatever the size of the view.
+    Points(f32),
+    /// This fraction of the average distance between neighbouring LEDs,
+    /// growing and shrinking with the view. It never gets so large that the
+    /// two closest LEDs overlap.
+    Spacing(f32),
+}
+
+impl Default for LedSize {
+    fn default() -> Self {
+        LedSize::Points(LED_SIZE.x)
+    }
+}
+
+impl LedSize {
+    /// The box of an LED drawn in `shape` through `projection`, at least a point wide.
+    pub fn resolve(self, shape: LedShape, spacing: Spacing, projection: &Projection<'_>) -> Vec2 {
+        let width = match self {
+            LedSize::Points(points) => points,
+            LedSize::Spacing(fraction) => {
+                // Circles touch at the distance between centers, squares side on
+                // a diagonal already at that distance over the square root of two
+                let limit = match shape {
+                    LedShape::Square => spacing.min / std::f64::consts::SQRT_2,
+                    LedShape::Circle => spacing.min,
+                };
+                ((fraction as f64 * spacing.average).min(limit) * projection.scale()) as f32
+            }
+        };
+        Vec2::splat(width.max(1.0))
+    }
+}
+
+/// How the track is drawn, shared by the window, screenshots and exports.
+pub struct TrackStyle<'a> {
+    pub keep_aspect: bool,
+    pub shape: LedShape,
+    pub led_size: LedSize,
+    /// Of the coordinates drawn, for `LedSize::Spacing`.
+    pub spacing: Spacing,
+    /// Edge drawn around unlit LEDs so the track shows with every LED off.
+    pub unlit_outline: Option<Color32>,
+    /// Segments to join with a line, empty for no outline.
+    pub outline: &'a [Vec<usize>],
+    pub outline_color: Color32,
+    /// Brightness factor of the outline leaving each LED, from its sector;
+    /// empty to draw the outline in one shade.
+    pub outline_shades: &'a [f32],
+    /// Pairs of LEDs to draw a line across the track between, where one
+    /// sector ends and the next begins; empty for none.
+    pub sector_lines: &'a [(usize, usize)],
+    /// Picks the fill of the unlit LEDs.
+    pub backdrop: Backdrop,
+}
+
+/// The extent of the LED coordinates.
+pub struct Bounds {
+    min_x: f64,
+    min_y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Bounds {
+    /// An axis along which every LED has the same value, as with a single
+    /// LED or a straight line of them, gets a span of 1.0 around that value
+    /// so the LEDs are drawn in the middle of the view.
+    pub fn of(coordinates: &[LedCoordinate]) -> Self {
+        let (min_x, width) = span(coordinates.iter().map(|coord| coord.x_led));
+        let (min_y, height) = span(coordinates.iter().map(|coord| coord.y_led));
+        Self { min_x, min_y, width, height }
+    }
+}
+
+/// The lowest value and the distance to the highest, at least 1.0 wide.
+fn span(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+    if max - min >= f64::EPSILON {
+        (min, max - min)
+    } else if min.is_finite() {
+        (min - 0.5, 1.0)
+    } else {
+        (0.0, 1.0) // No coordinates
+    }
+}
+
+/// Maps track coordinates onto a screen rect, with y pointing up. Every
+/// pass that draws the track uses the same projection so they line up.
+pub struct Projection<'a> {
+    bounds: &'a Bounds,
+    rect: Rect,
+}
+
+impl<'a> Projection<'a> {
+    /// Fills `rect`, or with `keep_aspect` the largest rect inside it with
+    /// the track's aspect ratio, centered.
+    pub fn new(bounds: &'a Bounds, rect: Rect, keep_aspect: bool) -> Self {
+        let rect = if keep_aspect {
+            let scale = (rect.width() as f64 / bounds.width).min(rect.height() as f64 / bounds.height);
+            let size = vec2((bounds.width * scale) as f32, (bounds.height * scale) as f32);
+            Rect::from_center_size(rect.center(), size)
+        } else {
+            rect
+        };
+        Self { bounds, rect }
+    }
+
+    /// Points per track unit along the axis that is squeezed the most.
+    pub fn scale(&self) -> f64 {
+        (self.rect.width() as f64 / self.bounds.width).min(self.rect.height() as f64 / self.bounds.height)
+    }
+
+    /// Top-left corner of the LED at `coord`.
+    pub fn to_screen(&self, coord: &LedCoordinate) -> Pos2 {
+        let (bounds, rect) = (self.bounds, self.rect);
+        let norm_x = rect.left() + ((coord.x_led - bounds.min_x) / bounds.width) as f32 * rect.width();
+        let norm_y = rect.bottom() - ((coord.y_led - bounds.min_y) / bounds.height) as f32 * rect.height();
+        pos2(norm_x, norm_y)
+    }
+
+    /// Where `pos` is in the projected track, (0, 0) being its top-left
+    /// corner and (1, 1) its bottom-right.
+    pub fn fraction(&self, pos: Pos2) -> Vec2 {
+        (pos - self.rect.min) / self.rect.size()
+    }
+
+    /// The point `fraction` of the way across the projected track, as
+    /// `fraction` gives it.
+    pub fn at_fraction(&self, fraction: Vec2) -> Pos2 {
+        self.rect.min + fraction * self.rect.size()
+    }
+
+    /// The part of the track inside `view`, as fractions of the track with
+    /// (0, 0) its top-left corner and (1, 1) its bottom-right, cut to the track.
+    pub fn visible_fraction(&self, view: Rect) -> Rect {
+        let unit = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        Rect::from_min_max(self.fraction(view.min).to_pos2(), self.fraction(view.max).to_pos2()).intersect(unit)
+    }
+
+    /// The same projection magnified `zoom` times, with the point at
+    /// `focus`, as given by `fraction`, in the middle of the view.
+    pub fn zoomed(self, zoom: f32, focus: Vec2) -> Self {
+        let size = self.rect.size() * zoom;
+        Self { bounds: self.bounds, rect: Rect::from_min_size(self.rect.center() - focus * size, size) }
+    }
+
+    /// The LED whose `led_size` box, as drawn, holds `pos`. Where boxes
+    /// overlap it is the one drawn last, so on top.
+    pub fn led_at(&self, coordinates: &[LedCoordinate], led_size: Vec2, pos: Pos2) -> Option<usize> {
+        coordinates.iter().rposition(|coord| Rect::from_min_size(self.to_screen(coord), led_size).contains(pos))
+    }
+}
+
+/// How far a view is zoomed in and on what, as `Projection::zoomed` takes them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub zoom: f32,
+    pub focus: Vec2,
+}
+
+impl Camera {
+    /// The whole track.
+    pub const FITTED: Camera = Camera { zoom: 1.0, focus: vec2(0.5, 0.5) };
+}
+
+/// The LEDs joined up in strip order, one line per `segment` of the
+/// coordinates file. A track with a single segment is closed back to its
+/// first LED; separate segments stay open so no stray line joins them.
+pub fn outline(coordinates: &[LedCoordinate], strip_positions: &[usize]) -> Vec<Vec<usize>> {
+    let mut segments: BTreeMap<Option<&str>, Vec<usize>> = BTreeMap::new();
+    for (led, coord) in coordinates.iter().enumerate() {
+        segments.entry(coord.segment.as_deref()).or_default().push(led);
+    }
+    let closed = segments.len() == 1;
+    segments
+        .into_values()
+        .map(|mut leds| {
+            leds.sort_by_key(|&led| strip_positions[led]);
+            if closed && leds.len() > 2 {
+                leds.push(leds[0]);
+            }
+            leds
+        })
+        .collect()
+}
+
+/// Splits an outline segment into runs of LEDs whose outline has the same
+/// shade, each run sharing its last LED with the next so the line stays joined.
+pub fn outline_runs<'a>(segment: &'a [usize], shades: &[f32]) -> Vec<(f32, &'a [usize])> {
+    let shade = |led: usize| shades.get(led).copied().unwrap_or(1.0);
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..segment.len() {
+        if i + 1 == segment.len() || shade(segment[i]) != shade(segment[start]) {
+            runs.push((shade(segment[start]), &segment[start..=i]));
+            start = i;
+        }
+    }
+    runs
+}
+
+/// The line across the track halfway between LED centers `from` and
+/// `to`, marking where one sector ends and the next begins, or `None` for
+/// LEDs in the same place.
+pub fn sector_line(from: Pos2, to: Pos2, led_size: Vec2) -> Option<[Pos2; 2]> {
+    let across = (to - from).normalized().rot90();
+    if !across.is_finite() || across == Vec2::ZERO {
+        return None;
+    }
+    let (middle, half) = (from.lerp(to, 0.5), across * led_size.max_elem() * SECTOR_LINE_LENGTH / 2.0);
+    Some([middle - half, middle + half])
+}
+
+/// `color` with its RGB channels scaled by `factor`, saturating at white.
+pub fn shade(color: Color32, factor: f32) -> Color32 {
+    let scale = |c: u8| (c as f32 * factor).round().min(255.0) as u8;
+    Color32::from_rgba_premultiplied(scale(color.r()), scale(color.g()), scale(color.b()), color.a())
+}
+
+/// Fill of an LED of `class`, unlit on `backdrop` or `lit` by a car: pit
+/// lane LEDs are drawn dimmer than the track.
+pub fn led_fill(class: LedClass, lit: Option<Color32>, backdrop: Backdrop) -> Color32 {
+    match (class, lit) {
+        (LedClass::Pitlane, Some(color)) => shade(color, PIT_LANE_SHADE),
+        (_, Some(color)) => color,
+        (_, None) => backdrop.unlit(class),
+    }
+}
+
+/// Rasterizes the track view the way it is painted on screen: the outline,
+/// every LED unlit, then `lit` in order on top, blended over `background`.
+/// Lit LEDs get an edge on a light background, see `lit_edge`.
+pub fn render_leds(
+    size: [u32; 2],
+    coordinates: &[LedCoordinate],
+    style: &TrackStyle<'_>,
+    lit: &[(usize, Color32)],
+    background: Color32,
+) -> RgbaImage {
+    render_zoomed(size, coordinates, style, lit, background, Camera::FITTED)
+}
+
+/// `render_leds` with the track magnified as `camera` has it, as the track
+/// view draws it zoomed in.
+pub fn render_zoomed(
+    size: [u32; 2],
+    coordinates: &[LedCoordinate],
+    style: &TrackStyle<'_>,
+    lit: &[(usize, Color32)],
+    background: Color32,
+    camera: Camera,
+) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(size[0], size[1], Rgba(background.to_array()));
+    let rect = Rect::from_min_size(Pos2::ZERO, vec2(size[0] as f32, size[1] as f32));
+    let bounds = Bounds::of(coordinates);
+    let projection = Projection::new(&bounds, rect, style.keep_aspect).zoomed(camera.zoom, camera.focus);
+    let positions: Vec<Pos2> = coordinates.iter().map(|coord| projection.to_screen(coord)).collect();
+    let led_size = style.led_size.resolve(style.shape, style.spacing, &projection);
+    let fill = |image: &mut RgbaImage, rect, color| match style.shape {
+        LedShape::Square => fill_rect(image, rect, color),
+        LedShape::Circle => fill_circle(image, rect, color),
+    };
+
+    for segment in style.outline {
+        for pair in segment.windows(2) {
+            let (from, to) = (positions[pair[0]] + led_size / 2.0, positions[pair[1]] + led_size / 2.0);
+            let color = shade(style.outline_color, style.outline_shades.get(pair[0]).copied().unwrap_or(1.0));
+            stamp_line(&mut image, from, to, OUTLINE_WIDTH, color);
+        }
+    }
+    for &(from, to) in style.sector_lines {
+        if let Some([start, end]) = sector_line(positions[from] + led_size / 2.0, positions[to] + led_size / 2.0, led_size) {
+            stamp_line(&mut image, start, end, SECTOR_LINE_WIDTH, style.outline_color);
+        }
+    }
+
+    for (&pos, coord) in positions.iter().zip(coordinates) {
+        let rect = Rect::from_min_size(pos, led_size);
+        if let Some(edge) = style.unlit_outline {
+            fill(&mut image, rect, edge);
+            fill(&mut image, rect.shrink(UNLIT_OUTLINE_WIDTH), led_fill(coord.class, None, style.backdrop));
+        } else {
+            fill(&mut image, rect, led_fill(coord.class, None, style.backdrop));
+        }
+    }
+    let edge = lit_edge(background);
+    for &(led, color) in lit {
+        let rect = Rect::from_min_size(positions[led], led_size);
+        let color = led_fill(coordinates[led].class, Some(color), style.backdrop);
+        if let Some(edge) = edge {
+            // A faded trail blends over the unlit fill inside the edge, as without one
+            fill(&mut image, rect, edge);
+            fill(&mut image, rect.shrink(UNLIT_OUTLINE_WIDTH), led_fill(coordinates[led].class, None, style.backdrop));
+            fill(&mut image, rect.shrink(UNLIT_OUTLINE_WIDTH), color);
+        } else {
+            fill(&mut image, rect, color);
+        }
+    }
+    image
+}
+
+/// Stamps squares `width` across from `from` to `to`, close enough to leave no gaps.
+fn stamp_line(image: &mut RgbaImage, from: Pos2, to: Pos2, width: f32, color: Color32) {
+    let steps = (from.distance(to) * 2.0).ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let center = from.lerp(to, step as f32 / steps as f32);
+        fill_rect(image, Rect::from_center_size(center, Vec2::splat(width)), color);
+    }
+}
+
+/// Blends a premultiplied color over the pixels covered by `rect`.
+fn fill_rect(image: &mut RgbaImage, rect: Rect, color: Color32) {
+    let clamp = |v: f32, max: u32| (v.round().max(0.0) as u32).min(max);
+    let (x0, x1) = (clamp(rect.left(), image.width()), clamp(rect.right(), image.width()));
+    let (y0, y1) = (clamp(rect.top(), image.height()), clamp(rect.bottom(), image.height()));
+    for y in y0..y1 {
+        for x in x0..x1 {
+            blend_over(&mut image.get_pixel_mut(x, y).0, color);
+        }
+    }
+}
+
+/// Blends a premultiplied color over the circle inscribed in `rect`, edge
+/// pixels weighted by how much of them the circle covers.
+fn fill_circle(image: &mut RgbaImage, rect: Rect, color: Color32) {
+    let (center, radius) = (rect.center(), rect.width() / 2.0);
+    let clamp = |v: f32, max: u32| (v.max(0.0) as u32).min(max);
+    let (x0, x1) = (clamp(rect.left().floor(), image.width()), clamp(rect.right().ceil(), image.width()));
+    let (y0, y1) = (clamp(rect.top().floor(), image.height()), clamp(rect.bottom().ceil(), image.height()));
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let distance = center.distance(pos2(x as f32 + 0.5, y as f32 + 0.5));
+            let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+            if coverage > 0.0 {
+                blend_over(&mut image.get_pixel_mut(x, y).0, color.gamma_multiply(coverage));
+            }
+        }
+    }
+}
+
+/// Black or white, whichever stands out more on `color`, for text drawn over it.
+pub fn text_on(color: Color32) -> Color32 {
+    let linear = egui::Rgba::from(color);
+    let luminance = 0.2126 * linear.r() + 0.7152 * linear.g() + 0.0722 * linear.b();
+    // Where white and black text contrast about as much with the background
+    if luminance > 0.18 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
+/// Where each of `count` cars stacked on one LED is drawn, from the LED's
+/// center: evenly around a circle of `radius`, the first straight above.
+/// A single car stays in the middle.
+pub fn stack_offsets(count: usize, radius: f32) -> Vec<egui::Vec2> {
+    if count < 2 {
+        return vec![egui::Vec2::ZERO; count];
+    }
+    (0..count)
+        .map(|i| {
+            let angle = std::f32::consts::TAU * i as f32 / count as f32;
+            egui::vec2(angle.sin(), -angle.cos()) * radius
+        })
+        .collect()
+}
+
+/// The average of premultiplied colors, channel by channel.
+pub fn mix(colors: &[Color32]) -> Color32 {
+    let n = colors.len().max(1) as u32;
+    let mut sum = [0u32; 4];
+    for color in colors {
+        for (sum, channel) in sum.iter_mut().zip(color.to_array()) {
+            *sum += channel as u32;
+        }
+    }
+    let [r, g, b, a] = sum.map(|sum| ((sum + n / 2) / n) as u8);
+    Color32::from_rgba_premultiplied(r, g, b, a)
+}
+
+/// Blends a premultiplied color over RGB or RGBA channels in place.
+pub fn blend_over(dst: &mut [u8], color: Color32) {
+    let src = color.to_array();
+    let keep = 255 - src[3] as u32;
+    for (dst, &src) in dst.iter_mut().zip(&src) {
+        *dst = (src as u32 + *dst as u32 * keep / 255).min(255) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixed-size squares with no outlines.
+    fn plain_style() -> TrackStyle<'static> {
+        TrackStyle {
+            keep_aspect: false,
+            shape: LedShape::Square,
+            led_size: LedSize::default(),
+            spacing: Spacing::default(),
+            unlit_outline: None,
+            outline: &[],
+            outline_color: Color32::GRAY,
+            outline_shades: &[],
+            sector_lines: &[],
+            backdrop: Backdrop::Theme,
+        }
+    }
+
+    #[test]
+    fn a_zoomed_view_shows_the_part_of_the_track_around_the_focus() {
+        let bounds = Bounds::of(&[(0.0, 0.0), (4.0, 2.0)].map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() }));
+        let view = Rect::from_min_size(pos2(0.0, 0.0), vec2(400.0, 200.0));
+        let fitted = Projection::new(&bounds, view, false);
+        assert_eq!(fitted.visible_fraction(view), Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)));
+        let zoomed = Projection::new(&bounds, view, false).zoomed(4.0, vec2(0.25, 0.5));
+        assert_eq!(zoomed.visible_fraction(view), Rect::from_min_max(pos2(0.125, 0.375), pos2(0.375, 0.625)));
+        let corner = Projection::new(&bounds, view, false).zoomed(2.0, vec2(0.0, 0.0));
+        assert_eq!(corner.visible_fraction(view), Rect::from_min_max(pos2(0.0, 0.0), pos2(0.25, 0.25)), "cut to the track");
+        let minimap = Projection::new(&bounds, Rect::from_min_size(pos2(10.0, 10.0), vec2(40.0, 40.0)), true);
+        assert_eq!(minimap.at_fraction(vec2(0.5, 1.0)), pos2(30.0, 40.0), "the track is 40 by 20 in the middle of it");
+    }
+
+    #[test]
+    fn sector_lines_cross_the_track_between_their_leds() {
+        let [start, end] = sector_line(pos2(10.0, 50.0), pos2(30.0, 50.0), vec2(4.0, 4.0)).unwrap();
+        assert_eq!((start, end), (pos2(20.0, 55.0), pos2(20.0, 45.0)), "upright, halfway along, two and a half LEDs long");
+        assert_eq!(sector_line(pos2(10.0, 50.0), pos2(10.0, 50.0), vec2(4.0, 4.0)), None);
+
+        let coordinates = [(0.0, 1.0), (1.0, 1.0), (2.0, 1.0), (3.0, 0.0)].map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let background = Color32::from_gray(27);
+        let style = TrackStyle { sector_lines: &[(0, 1)], ..plain_style() };
+        let image = render_leds([120, 40], &coordinates, &style, &[], background);
+        // LED centers 10 down at x 10, 50 and 90, the line at x 30 running below them
+        assert_eq!(image.get_pixel(30, 30).0, Color32::GRAY.to_array());
+        assert_eq!(image.get_pixel(70, 30).0, background.to_array(), "none between LEDs in one sector");
+    }
+
+    #[test]
+    fn text_is_black_on_light_colors_and_white_on_dark_ones() {
+        let on = |colors: [Color32; 3]| colors.map(text_on);
+        assert_eq!(on([Color32::YELLOW, Color32::WHITE, Color32::from_rgb(0, 210, 190)]), [Color32::BLACK; 3]);
+        assert_eq!(on([Color32::from_rgb(0, 30, 160), Color32::from_rgb(128, 0, 128), Color32::from_gray(60)]), [Color32::WHITE; 3]);
+    }
+
+    #[test]
+    fn lit_leds_paint_over_unlit_ones() {
+        let coordinates = [(0.0, 0.0), (2.0, 2.0), (1.0, 1.0), (1.0, 0.4)]
+            .map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let background = Color32::from_gray(27);
+        let outline = [vec![0, 2]];
+        let style = TrackStyle {
+            keep_aspect: false,
+            shape: LedShape::Square,
+            led_size: LedSize::default(),
+            spacing: Spacing::default(),
+            unlit_outline: None,
+            outline: &outline,
+            outline_color: Color32::GRAY,
+            outline_shades: &[],
+            sector_lines: &[],
+            backdrop: Backdrop::Theme,
+        };
+        let image = render_leds([100, 100], &coordinates, &style, &[(2, Color32::RED)], background);
+        // LED 2 covers (50, 50) to (70, 70), LED 3 starts at (50, 80)
+        assert_eq!(image.get_pixel(60, 60).0, Color32::RED.to_array());
+        assert_eq!(image.get_pixel(60, 90).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(40, 80).0, Color32::GRAY.to_array()); // On the line between the centers of LEDs 0 and 2
+        assert_eq!(image.get_pixel(20, 60).0, background.to_array());
+    }
+
+    #[test]
+    fn unlit_leds_show_on_the_backdrop_and_lit_ones_get_an_edge_on_white() {
+        let coordinates = [(0.0, 0.0), (2.0, 2.0), (1.0, 1.0), (0.5, 1.0)].map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let style = TrackStyle { backdrop: Backdrop::White, ..plain_style() };
+        let image = render_leds([100, 100], &coordinates, &style, &[(2, Color32::YELLOW)], Backdrop::White.fill(Color32::BLACK));
+        // LED 3 covers (25, 50) to (45, 70), LED 2 (50, 50) to (70, 70)
+        assert_eq!(image.get_pixel(35, 60).0, Color32::from_gray(190).to_array());
+        assert_eq!(image.get_pixel(50, 60).0, LIT_EDGE.to_array());
+        assert_eq!(image.get_pixel(60, 60).0, Color32::YELLOW.to_array());
+        assert_eq!(image.get_pixel(30, 30).0, [255; 4]);
+
+        assert_eq!(lit_edge(Backdrop::Theme.fill(Color32::from_gray(27))), None, "no edge on a dark background");
+        assert_eq!(Backdrop::Black.unlit(LedClass::Track), Color32::from_gray(60), "not black on black");
+    }
+
+    #[test]
+    fn circles_leave_the_corners_of_the_box_and_soften_the_edge() {
+        let coordinates = [(0.0, 0.0), (2.0, 2.0), (1.0, 1.0)]
+            .map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let background = Color32::from_gray(27);
+        let style = TrackStyle { shape: LedShape::Circle, ..plain_style() };
+        let image = render_leds([100, 100], &coordinates, &style, &[(2, Color32::RED)], background);
+        // LED 2's box covers (50, 50) to (70, 70), the circle is centered at (60, 60)
+        assert_eq!(image.get_pixel(60, 60).0, Color32::RED.to_array());
+        assert_eq!(image.get_pixel(50, 50).0, background.to_array());
+        let [r, g, _, a] = image.get_pixel(52, 53).0; // Partly covered
+        assert!((30..250).contains(&r) && g < 27 && a == 255, "{r} {g}");
+    }
+
+    #[test]
+    fn automatic_sizes_follow_the_spacing_without_overlapping() {
+        // Neighbours 10 units apart, except two LEDs only 4 apart, on a 100 unit wide track
+        let coordinates = [(0.0, 0.0), (10.0, 0.0), (20.0, 0.0), (30.0, 0.0), (34.0, 0.0), (100.0, 100.0)]
+            .map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let spacing = Spacing::of(&coordinates);
+        assert_eq!(spacing.min, 4.0);
+        let bounds = Bounds::of(&coordinates);
+        let projection = Projection::new(&bounds, Rect::from_min_size(Pos2::ZERO, vec2(200.0, 400.0)), false);
+        assert_eq!(projection.scale(), 2.0);
+
+        assert_eq!(LedSize::Points(12.0).resolve(LedShape::Square, spacing, &projection), Vec2::splat(12.0));
+        assert_eq!(LedSize::Spacing(0.1).resolve(LedShape::Circle, spacing, &projection).x, (0.2 * spacing.average) as f32);
+        // The two LEDs 4 apart cap the size, less for squares that could touch corner to corner
+        assert_eq!(LedSize::Spacing(1.0).resolve(LedShape::Circle, spacing, &projection), Vec2::splat(8.0));
+        let square = LedSize::Spacing(1.0).resolve(LedShape::Square, spacing, &projection).x;
+        assert!((square - 8.0 / std::f32::consts::SQRT_2).abs() < 1e-4, "{square}");
+    }
+
+    #[test]
+    fn unlit_leds_get_an_edge_when_asked() {
+        let coordinates = [(0.0, 0.0), (2.0, 2.0), (1.0, 1.0)]
+            .map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let edge = Color32::from_gray(60);
+        let style = TrackStyle { unlit_outline: Some(edge), ..plain_style() };
+        // LED 2 covers (50, 50) to (70, 70)
+        let image = render_leds([100, 100], &coordinates, &style, &[], Color32::from_gray(27));
+        assert_eq!(image.get_pixel(50, 60).0, edge.to_array());
+        assert_eq!(image.get_pixel(60, 60).0, [0, 0, 0, 255]);
+        let image = render_leds([100, 100], &coordinates, &style, &[(2, Color32::RED)], Color32::from_gray(27));
+        assert_eq!(image.get_pixel(50, 60).0, Color32::RED.to_array(), "lit LEDs cover the edge");
+    }
+
+    #[test]
+    fn outline_follows_strip_order_and_keeps_segments_apart() {
+        let coord = |segment: Option<&str>| LedCoordinate { segment: segment.map(str::to_string), ..Default::default() };
+        let track = [coord(None), coord(None), coord(None)];
+        assert_eq!(outline(&track, &[2, 0, 1]), [vec![1, 2, 0, 1]]);
+
+        let split = [coord(Some("pit")), coord(Some("main")), coord(Some("pit")), coord(Some("main")), coord(Some("main"))];
+        assert_eq!(outline(&split, &[0, 1, 2, 3, 4]), [vec![1, 3, 4], vec![0, 2]]);
+
+        let shades = [1.0, 1.0, 0.5, 0.5];
+        let runs: Vec<_> = outline_runs(&[0, 1, 2, 3, 0], &shades).into_iter().map(|(shade, run)| (shade, run.to_vec())).collect();
+        assert_eq!(runs, [(1.0, vec![0, 1, 2]), (0.5, vec![2, 3, 0])]);
+        assert_eq!(outline_runs(&[0, 1, 2], &[]), [(1.0, &[0, 1, 2][..])]);
+    }
+
+    #[test]
+    fn coordinates_on_one_axis_value_are_centered() {
+        let rect = Rect::from_min_size(Pos2::ZERO, vec2(100.0, 100.0));
+        let single = [LedCoordinate { x_led: 3.0, y_led: -7.0, ..Default::default() }];
+        let bounds = Bounds::of(&single);
+        for keep_aspect in [false, true] {
+            assert_eq!(Projection::new(&bounds, rect, keep_aspect).to_screen(&single[0]), pos2(50.0, 50.0));
+        }
+
+        let vertical: Vec<_> = (0..5).map(|y| LedCoordinate { x_led: 5.0, y_led: y as f64, ..Default::default() }).collect();
+        let bounds = Bounds::of(&vertical);
+        let projection = Projection::new(&bounds, rect, false);
+        let positions: Vec<_> = vertical.iter().map(|coord| projection.to_screen(coord)).collect();
+        assert!(positions.iter().all(|pos| pos.x == 50.0), "{positions:?}");
+        assert_eq!((positions[0].y, positions[4].y), (100.0, 0.0));
+
+        let style = TrackStyle { keep_aspect: true, ..plain_style() };
+        let image = render_leds([100, 100], &vertical, &style, &[(2, Color32::RED)], Color32::BLACK);
+        assert_eq!(image.get_pixel(55, 55).0, Color32::RED.to_array());
+    }
+
+    #[test]
+    fn keep_aspect_letterboxes_and_centers() {
+        let coordinates = [(0.0, 0.0), (2.0, 1.0)].map(|(x_led, y_led)| LedCoordinate { x_led, y_led, ..Default::default() });
+        let bounds = Bounds::of(&coordinates);
+        let rect = Rect::from_min_size(Pos2::ZERO, vec2(100.0, 100.0));
+        let projection = Projection::new(&bounds, rect, true);
+        assert_eq!(projection.to_screen(&coordinates[0]), pos2(0.0, 75.0));
+        assert_eq!(projection.to_screen(&coordinates[1]), pos2(100.0, 25.0));
+        assert_eq!(projection.led_at(&coordinates, LED_SIZE, pos2(10.0, 90.0)), Some(0));
+        assert_eq!(projection.led_at(&coordinates, LED_SIZE, pos2(50.0, 50.0)), None);
+
+        // Twice the size, with LED 1 in the middle of the view
+        let focus = projection.fraction(projection.to_screen(&coordinates[1]));
+        assert_eq!(focus, vec2(1.0, 0.0));
+        let zoomed = projection.zoomed(2.0, focus);
+        assert_eq!(zoomed.to_screen(&coordinates[1]), pos2(50.0, 50.0));
+        assert_eq!(zoomed.to_screen(&coordinates[0]), pos2(-150.0, 150.0));
+    }
+}
@@ -0,0 +1,170 @@
+use serde::Serialize;
+use std::error::Error;
+
+use crate::bookmarks::format_sim_ms;
+use crate::drivers::Driver;
+use crate::sim::Simulation;
+
+/// Whether a car's data ran to the chequered flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Finished,
+    Retired,
+}
+
+/// One line of the results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classified {
+    pub dataset_idx: usize,
+    pub laps: u32, // Completed
+    pub led: Option<usize>, // Last LED reached
+    pub elapsed_ms: u64, // From its first row to the last one shown
+    pub rows: usize, // Of its dataset shown
+    pub status: Status,
+    pub retired_at_ms: Option<u64>, // Simulated time of its last row, when retired
+}
+
+/// The finishing order as far as `sim` has played, winner first: by laps
+/// completed, then how far round the lap the last LED reached is, counted
+/// on from the start/finish LED as the coordinates file numbers them, then
+/// who got there first. Cars that are level keep their dataset order.
+///
+/// A car whose data ended before the chequered flag is retired at its last
+/// row. The flag falls at `chequered_ms`, or without one when the winner's
+/// data ends, as the cars behind take it after.
+pub fn classify(sim: &Simulation, chequered_ms: Option<u64>) -> Vec<Classified> {
+    let leds = sim.coordinates.len();
+    let round = |led: usize| sim.start_finish_led.map_or(led, |line| (led + leds - line) % leds);
+    let mut order: Vec<usize> = (0..sim.cars.len()).collect();
+    order.sort_by_key(|&idx| {
+        let car = &sim.cars[idx];
+        let reached_ms = car.progress.reached_ms().unwrap_or(u64::MAX);
+        (std::cmp::Reverse(car.laps.laps), std::cmp::Reverse(car.progress.led().map(round)), reached_ms)
+    });
+    let last_ms = |idx: usize| sim.cars[idx].rows.checked_sub(1).and_then(|row| sim.row_ms(idx, row));
+    let flag_ms = chequered_ms.or_else(|| last_ms(*order.first()?));
+    order
+        .into_iter()
+        .map(|idx| {
+            let car = &sim.cars[idx];
+            let ended = car.rows >= sim.drivers[idx].records.len();
+            let retired_at_ms = last_ms(idx).filter(|&ms| ended && flag_ms.is_some_and(|flag_ms| ms < flag_ms));
+            Classified {
+                dataset_idx: idx,
+                laps: car.laps.laps,
+                led: car.progress.led(),
+                elapsed_ms: last_ms(idx).zip(sim.row_ms(idx, 0)).map_or(0, |(last, first)| last.saturating_sub(first)),
+                rows: car.rows,
+                status: if retired_at_ms.is_some() { Status::Retired } else { Status::Finished },
+                retired_at_ms,
+            }
+        })
+        .collect()
+}
+
+/// A line of the results as exported, each car named by `drivers`.
+#[derive(Serialize)]
+struct Line<'a> {
+    position: usize,
+    driver: &'a str,
+    code: &'a str,
+    laps: u32,
+    last_led: Option<usize>,
+    elapsed: String,
+    rows: usize,
+    status: Status,
+    retired_at: Option<String>,
+}
+
+fn lines<'a>(results: &[Classified], drivers: &'a [Driver]) -> Vec<Line<'a>> {
+    results
+        .iter()
+        .enumerate()
+        .map(|(position, result)| {
+            let driver = &drivers[result.dataset_idx];
+            Line {
+                position: position + 1,
+                driver: &driver.name,
+                code: &driver.code,
+                laps: result.laps,
+                last_led: result.led,
+                elapsed: format_sim_ms(result.elapsed_ms),
+                rows: result.rows,
+                status: result.status,
+                retired_at: result.retired_at_ms.map(format_sim_ms),
+            }
+        })
+        .collect()
+}
+
+pub fn to_csv(results: &[Classified], drivers: &[Driver]) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for line in lines(results, drivers) {
+        writer.serialize(line)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+pub fn to_json(results: &[Classified], drivers: &[Driver]) -> Result<String, Box<dyn Error>> {
+    Ok(serde_json::to_string_pretty(&lines(results, drivers))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LedCoordinate;
+    use crate::sim::fixtures;
+
+    #[test]
+    fn classifies_by_laps_then_led_then_time_with_early_ends_retired() {
+        // Six LEDs round a circle, the start/finish line on LED 0
+        let coordinates: Vec<_> = (0..6)
+            .map(|led| {
+                let angle = std::f64::consts::TAU * led as f64 / 6.0;
+                LedCoordinate { x_led: 10.0 * angle.cos(), y_led: 10.0 * angle.sin(), ..Default::default() }
+            })
+            .collect();
+        // A row a second on from `from_s`, one for each LED in `leds`
+        let rows = |from_s: i64, leds: &[usize]| -> Vec<_> {
+            leds.iter().enumerate().map(|(row, &led)| fixtures::row((from_s + row as i64) * 1000, (coordinates[led].x_led, coordinates[led].y_led), 1000)).collect()
+        };
+        let two_laps_to = |last: usize| -> Vec<usize> { (0..6).chain(0..6).chain(0..=last).collect() };
+        let race = [
+            rows(0, &[0, 1, 2, 3, 4, 5, 0, 1]), // Out after a lap
+            rows(1, &two_laps_to(1)),           // A LED short of the others
+            rows(0, &two_laps_to(2)),           // Wins
+            rows(1, &two_laps_to(2)),           // Level with the winner a second later
+        ];
+        let mut sim = fixtures::simulation(coordinates.clone(), None, &race);
+        (sim.start_finish_led, sim.lap_debounce_leds) = (Some(0), 2);
+        sim.seek(sim.moments());
+
+        let results = classify(&sim, None);
+        let summary: Vec<_> = results.iter().map(|result| (result.dataset_idx, result.laps, result.led, result.elapsed_ms, result.rows, result.status)).collect();
+        assert_eq!(
+            summary,
+            [
+                (2, 2, Some(2), 14_000, 15, Status::Finished),
+                (3, 2, Some(2), 14_000, 15, Status::Finished),
+                (1, 2, Some(1), 13_000, 14, Status::Finished),
+                (0, 1, Some(1), 7_000, 8, Status::Retired),
+            ]
+        );
+        assert_eq!(results[3].retired_at_ms, Some(8_000), "shown a time_delta after its date");
+        assert!(classify(&sim, Some(5_000)).iter().all(|result| result.status == Status::Finished), "out after the flag");
+
+        let drivers: Vec<_> = ["Albon", "Sainz", "Verstappen", "Hamilton"]
+            .iter()
+            .zip(&sim.drivers)
+            .map(|(&name, driver)| Driver { key: name.to_lowercase(), name: name.to_string(), code: name[..3].to_uppercase(), ..driver.clone() })
+            .collect();
+        let csv = to_csv(&results, &drivers).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("position,driver,code,laps,last_led,elapsed,rows,status,retired_at"));
+        assert_eq!(lines.next(), Some("1,Verstappen,VER,2,2,0:14.000,15,finished,"));
+        assert_eq!(lines.nth(2), Some("4,Albon,ALB,1,1,0:07.000,8,retired,0:08.000"));
+        let json: serde_json::Value = serde_json::from_str(&to_json(&results, &drivers).unwrap()).unwrap();
+        assert_eq!((json[3]["driver"].as_str(), json[3]["status"].as_str()), (Some("Albon"), Some("retired")));
+    }
+}
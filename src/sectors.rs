@@ -0,0 +1,230 @@
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::data::DataIssue;
+
+/// Name `sector_leds` issues are reported under, as they come from no file.
+const LISTED: &str = "sector_leds";
+
+/// File name looked for next to the coordinates file when no sectors file is configured.
+pub const DEFAULT_FILE: &str = "sectors.csv";
+
+/// Outline brightness of each sector in turn, so neighbouring sectors differ.
+const SHADES: [f32; 3] = [1.0, 0.65, 1.35];
+
+#[derive(Deserialize)]
+struct Row {
+    first_led: usize,
+    last_led: usize,
+    sector: u32,
+}
+
+/// Which sector, or mini-sector, each LED is in.
+#[derive(Debug, Default, Clone)]
+pub struct Sectors {
+    pub path: Option<PathBuf>,
+    /// LED ids of each sector in turn, when given in the config rather than a file.
+    pub listed: Vec<Vec<usize>>,
+    by_led: Vec<Option<u32>>,
+    /// Outline brightness factor of each LED, empty without sectors.
+    pub shades: Vec<f32>,
+    /// Neighbouring LEDs of the track outline that are in different
+    /// sectors, for a line between them; empty until `with_outline`.
+    pub boundaries: Vec<(usize, usize)>,
+}
+
+impl Sectors {
+    /// Reads a CSV file with `first_led,last_led,sector` columns, each row
+    /// putting the LEDs from `first_led` to `last_led` in the coordinates
+    /// file, both included, in that sector. Rows that cannot be used, LEDs
+    /// given a second sector and LEDs left without one come back as issues.
+    pub fn read(path: &Path, led_count: usize) -> Result<(Self, Vec<DataIssue>), Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        let issue = |line: Option<u64>, message: String| DataIssue { file: path.to_path_buf(), line, message };
+        let mut by_led = vec![None; led_count];
+        let mut issues = Vec::new();
+        for (i, row) in rdr.deserialize::<Row>().enumerate() {
+            let line = Some(i as u64 + 2);
+            let row = match row {
+                Ok(row) if row.first_led > row.last_led => {
+                    issues.push(issue(line, format!("first_led {} is after last_led {}", row.first_led, row.last_led)));
+                    continue;
+                }
+                Ok(row) if row.last_led >= led_count => {
+                    issues.push(issue(line, format!("LED {} is out of range ({led_count} LEDs)", row.last_led)));
+                    continue;
+                }
+                Ok(row) => row,
+                Err(e) => {
+                    issues.push(issue(line, e.to_string()));
+                    continue;
+                }
+            };
+            if let Some(message) = assign(&mut by_led, row.first_led..=row.last_led, row.sector) {
+                issues.push(issue(line, message));
+            }
+        }
+        let unassigned: Vec<usize> = (0..led_count).filter(|&led| by_led[led].is_none()).collect();
+        if !unassigned.is_empty() {
+            let verb = if unassigned.len() == 1 { "is" } else { "are" };
+            issues.push(issue(None, format!("{} {verb} in no sector", describe_leds(&unassigned))));
+        }
+        Ok((Self::new(Some(path.to_path_buf()), Vec::new(), by_led), issues))
+    }
+
+    /// Sectors given as the LED ids in each, numbered from 1 in the order
+    /// listed, as `sector_leds` in the config. LEDs in none are left out
+    /// of every sector without an issue, so only part of the track need be
+    /// marked; LEDs out of range or listed twice are issues.
+    pub fn from_leds(listed: &[Vec<usize>], led_count: usize) -> (Self, Vec<DataIssue>) {
+        let issue = |message: String| DataIssue { file: PathBuf::from(LISTED), line: None, message };
+        let mut by_led = vec![None; led_count];
+        let mut issues = Vec::new();
+        for (sector, leds) in (1..).zip(listed) {
+            let (known, unknown): (Vec<usize>, Vec<usize>) = leds.iter().partition(|&&led| led < led_count);
+            if !unknown.is_empty() {
+                issues.push(issue(format!("sector {sector}: {} out of range ({led_count} LEDs)", describe_leds(&unknown))));
+            }
+            if let Some(message) = assign(&mut by_led, known, sector) {
+                issues.push(issue(format!("sector {sector}: {message}")));
+            }
+        }
+        (Self::new(None, listed.to_vec(), by_led), issues)
+    }
+
+    fn new(path: Option<PathBuf>, listed: Vec<Vec<usize>>, by_led: Vec<Option<u32>>) -> Self {
+        let numbers: Vec<u32> = by_led.iter().flatten().copied().collect::<BTreeSet<_>>().into_iter().collect();
+        let shades = by_led
+            .iter()
+            .map(|sector| sector.map_or(1.0, |sector| SHADES[numbers.binary_search(&sector).unwrap() % SHADES.len()]))
+            .collect();
+        Self { path, listed, by_led, shades, boundaries: Vec::new() }
+    }
+
+    /// Finds the boundaries along `outline`, the LEDs in strip order one
+    /// list per segment as `render::outline` gives them, so only LEDs next
+    /// to each other on a segment are paired and no line crosses a break
+    /// between segments.
+    pub fn with_outline(mut self, outline: &[Vec<usize>]) -> Self {
+        let sector = |led: usize| self.by_led.get(led).copied().flatten();
+        self.boundaries = outline
+            .iter()
+            .flat_map(|segment| segment.windows(2))
+            .map(|pair| (pair[0], pair[1]))
+            .filter(|&(led, next)| led != next && sector(led) != sector(next))
+            .collect();
+        self
+    }
+
+    /// The same sectors on `led_count` LEDs after they changed, `moved`
+    /// giving the new id of each LED by its old one, `None` for those left
+    /// out. The boundaries are found again by `with_outline`.
+    pub fn remap(self, moved: &[Option<usize>], led_count: usize) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        let mut by_led = vec![None; led_count];
+        for (led, sector) in self.by_led.into_iter().enumerate() {
+            if let Some(slot) = moved.get(led).copied().flatten().and_then(|led| by_led.get_mut(led)) {
+                *slot = sector;
+            }
+        }
+        Self::new(self.path, self.listed, by_led)
+    }
+
+    /// The sector `led` is in, if any.
+    pub fn sector(&self, led: usize) -> Option<u32> {
+        self.by_led.get(led).copied().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_led.is_empty()
+    }
+}
+
+/// Puts `leds` in `sector`, leaving any already in one where they are, and
+/// what went wrong for those.
+fn assign(by_led: &mut [Option<u32>], leds: impl IntoIterator<Item = usize>, sector: u32) -> Option<String> {
+    let mut taken = Vec::new();
+    for led in leds {
+        match by_led[led] {
+            Some(first) => taken.push((led, first)),
+            None => by_led[led] = Some(sector),
+        }
+    }
+    let &(_, first) = taken.first()?;
+    let leds: Vec<usize> = taken.iter().map(|&(led, _)| led).collect();
+    let verb = if leds.len() == 1 { "is" } else { "are" };
+    Some(format!("{} {verb} already in sector {first}", describe_leds(&leds)))
+}
+
+/// `LED 4`, or `LEDs 4-6, 9` for several, given in order.
+fn describe_leds(leds: &[usize]) -> String {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &led in leds {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == led => *last = led,
+            _ => runs.push((led, led)),
+        }
+    }
+    let runs: Vec<String> = runs
+        .iter()
+        .map(|&(first, last)| if first == last { first.to_string() } else { format!("{first}-{last}") })
+        .collect();
+    match leds {
+        [_] => format!("LED {}", runs[0]),
+        _ => format!("LEDs {}", runs.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn overlapping_and_missing_leds_are_reported() {
+        let path = std::env::temp_dir().join(format!("f1-led-{}-sectors.csv", std::process::id()));
+        fs::write(&path, "first_led,last_led,sector\n0,3,1\n3,5,2\n7,8,3\n9,20,3\n2,1,4\n").unwrap();
+        let (sectors, issues) = Sectors::read(&path, 10).unwrap();
+        let sectors = sectors.with_outline(&[(0..10).chain([0]).collect()]);
+        let issues: Vec<String> = issues.iter().map(|issue| format!("{:?} {}", issue.line, issue.message)).collect();
+        assert_eq!(
+            issues,
+            [
+                "Some(3) LED 3 is already in sector 1",
+                "Some(5) LED 20 is out of range (10 LEDs)",
+                "Some(6) first_led 2 is after last_led 1",
+                "None LEDs 6, 9 are in no sector",
+            ]
+        );
+        assert_eq!((sectors.sector(3), sectors.sector(4), sectors.sector(6), sectors.sector(8)), (Some(1), Some(2), None, Some(3)));
+        assert_eq!(sectors.shades[..], [1.0, 1.0, 1.0, 1.0, 0.65, 0.65, 1.0, 1.35, 1.35, 1.0]);
+        assert_eq!(sectors.boundaries, [(3, 4), (5, 6), (6, 7), (8, 9), (9, 0)]);
+    }
+
+    #[test]
+    fn sectors_listed_in_the_config_mark_only_their_leds() {
+        let (sectors, issues) = Sectors::from_leds(&[vec![2, 3, 4], vec![7, 8, 4, 12]], 10);
+        let sectors = sectors.with_outline(&[(0..10).chain([0]).collect()]);
+        let issues: Vec<String> = issues.iter().map(ToString::to_string).collect();
+        assert_eq!(issues, ["sector_leds: sector 2: LED 12 out of range (10 LEDs)", "sector_leds: sector 2: LED 4 is already in sector 1"]);
+        assert_eq!((sectors.sector(1), sectors.sector(4), sectors.sector(8)), (None, Some(1), Some(2)));
+        assert_eq!(sectors.boundaries, [(1, 2), (4, 5), (6, 7), (8, 9)], "the LEDs in no sector between them count as one");
+        assert!(Sectors::from_leds(&[vec![0, 1]], 2).0.with_outline(&[vec![0, 1]]).boundaries.is_empty(), "a single sector round the whole track");
+    }
+
+    #[test]
+    fn boundaries_follow_the_strip_within_each_segment() {
+        let (sectors, _) = Sectors::from_leds(&[vec![0, 1, 2], vec![3, 4, 5]], 6);
+        // Two open segments, the first laid out backwards on the strip
+        let sectors = sectors.with_outline(&[vec![2, 0, 3], vec![1, 4, 5]]);
+        assert_eq!(sectors.boundaries, [(0, 3), (1, 4)], "no pair across the break between segments or from the last LED to the first");
+    }
+}
@@ -0,0 +1,381 @@
+// Browsers cannot listen for connections, so the web build has the
+// messages but `LedServer::start` always fails there
+#![cfg_attr(target_arch = "wasm32", allow(unused_imports, dead_code))]
+
+use base64::Engine;
+use serde::Serialize;
+#[cfg(not(target_arch = "wasm32"))]
+use sha1::{Digest, Sha1};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Appended to a client's key before hashing it for the handshake, as RFC 6455 has it.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Longest a client may take over the handshake, or over taking a message.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest handshake request read before the client is turned away.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Messages waiting for a client, beyond which it is dropped as too slow.
+const CLIENT_BACKLOG: usize = 64;
+
+/// Most connections served at once, counting those still in the handshake,
+/// so each has a thread without a flood of them starting threads unchecked.
+pub const MAX_CLIENTS: usize = 32;
+
+#[derive(Debug, Serialize)]
+struct Change {
+    led: usize,
+    rgb: [u8; 3],
+}
+
+/// What a client is sent, the LEDs by their index in the coordinates file.
+#[derive(Debug, Serialize)]
+struct Message {
+    t: u64, // Simulated time of the frame
+    changes: Vec<Change>,
+}
+
+/// The JSON message that takes a client from the `previous` colors of
+/// every LED to `colors` at simulated time `t_ms`, `None` if none changed.
+/// Without previous colors every LED is in it, as sent to a client that
+/// just connected.
+pub fn message(t_ms: u64, previous: Option<&[[u8; 3]]>, colors: &[[u8; 3]]) -> Option<String> {
+    let changes: Vec<Change> = colors
+        .iter()
+        .enumerate()
+        .filter(|&(led, rgb)| previous.and_then(|previous| previous.get(led)) != Some(rgb))
+        .map(|(led, &rgb)| Change { led, rgb })
+        .collect();
+    (previous.is_none() || !changes.is_empty()).then(|| serde_json::to_string(&Message { t: t_ms, changes }).unwrap())
+}
+
+/// `text` as an unmasked WebSocket text frame, the way a server sends it.
+pub fn text_frame(text: &str) -> Vec<u8> {
+    let payload = text.as_bytes();
+    let mut frame = vec![0x81]; // Final fragment, text
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// The `Sec-WebSocket-Key` of an HTTP request asking to be upgraded to a
+/// WebSocket, or why it is turned away: it must be a GET with `Upgrade:
+/// websocket`, `Connection: Upgrade` and `Sec-WebSocket-Version: 13`.
+pub fn upgrade_key(request: &str) -> Result<&str, &'static str> {
+    let mut lines = request.lines();
+    let method = lines.next().and_then(|line| line.split_whitespace().next());
+    if method != Some("GET") {
+        return Err("not a GET request");
+    }
+    let headers: Vec<(&str, &str)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    let header = |name: &str| headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|&(_, value)| value);
+    let has_token = |name: &str, token: &str| header(name).is_some_and(|value| value.split(',').any(|part| part.trim().eq_ignore_ascii_case(token)));
+    if !has_token("Upgrade", "websocket") {
+        return Err("no Upgrade: websocket");
+    }
+    if !has_token("Connection", "upgrade") {
+        return Err("no Connection: Upgrade");
+    }
+    if header("Sec-WebSocket-Version") != Some("13") {
+        return Err("not Sec-WebSocket-Version 13");
+    }
+    header("Sec-WebSocket-Key").filter(|key| !key.is_empty()).ok_or("no Sec-WebSocket-Key")
+}
+
+/// The `Sec-WebSocket-Accept` answer to a client's `Sec-WebSocket-Key`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn accept_key(key: &str) -> String {
+    let mut sha = Sha1::new();
+    sha.update(key.trim().as_bytes());
+    sha.update(HANDSHAKE_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(sha.finalize())
+}
+
+/// What the server threads last reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerStatus {
+    Serving(usize), // Clients connected
+    Error(String),
+}
+
+/// Handed to the thread that sends to the clients, in the order they happen.
+enum Event {
+    Frame(u64, Vec<[u8; 3]>),
+    Client(TcpStream, Slot),
+}
+
+/// One of the `MAX_CLIENTS` connections, given back when dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// Takes a slot if one of `limit` is free.
+    fn take(used: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < limit).then_some(count + 1)).ok()?;
+        Some(Self(used.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Handle to the WebSocket server. Frames are handed over without
+/// blocking; slow clients are dropped by the server threads rather than
+/// holding up playback.
+pub struct LedServer {
+    pub address: SocketAddr, // Listened on, with the port picked if 0 was asked for
+    events: Sender<Event>,
+    sent: Option<Vec<[u8; 3]>>, // Colors last handed over
+    statuses: Receiver<ServerStatus>,
+    pub status: ServerStatus,
+}
+
+impl LedServer {
+    /// Starts listening on `address` for clients, each sent every LED on
+    /// connecting and then the ones that change.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start(address: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let (events, event_rx) = mpsc::channel();
+        let (status_tx, statuses) = mpsc::channel();
+        let clients = events.clone();
+        thread::Builder::new().name("serve-accept".to_string()).spawn(move || accept(&listener, &clients))?;
+        thread::Builder::new().name("serve-broadcast".to_string()).spawn(move || broadcast(&event_rx, &status_tx))?;
+        Ok(Self { address, events, sent: None, statuses, status: ServerStatus::Serving(0) })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn start(_address: SocketAddr) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "browsers cannot listen for WebSocket clients"))
+    }
+
+    /// Hands the RGB of every LED at simulated time `t_ms` to the server
+    /// threads, if any changed since the last call.
+    pub fn send(&mut self, t_ms: u64, colors: Vec<[u8; 3]>) {
+        if self.sent.as_ref() == Some(&colors) {
+            return;
+        }
+        self.sent = Some(colors.clone());
+        if self.events.send(Event::Frame(t_ms, colors)).is_err() {
+            self.status = ServerStatus::Error("server stopped".to_string());
+        }
+    }
+
+    /// Picks up status changes reported by the server threads.
+    pub fn poll_status(&mut self) -> &ServerStatus {
+        while let Ok(status) = self.statuses.try_recv() {
+            self.status = status;
+        }
+        &self.status
+    }
+}
+
+/// Takes each connection through the handshake on a thread of its own, so
+/// one slow to answer does not keep the others waiting, and hands it on.
+/// Past `MAX_CLIENTS` connections are turned away until one closes.
+#[cfg(not(target_arch = "wasm32"))]
+fn accept(listener: &TcpListener, clients: &Sender<Event>) {
+    let used = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let Some(slot) = Slot::take(&used, MAX_CLIENTS) else {
+            let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+            let _ = write!(stream, "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+            continue;
+        };
+        let clients = clients.clone();
+        let _ = thread::Builder::new().name("serve-handshake".to_string()).spawn(move || {
+            if handshake(&mut stream).is_ok() {
+                let _ = clients.send(Event::Client(stream, slot));
+            }
+        });
+    }
+}
+
+/// Reads the client's upgrade request and answers it, or turns away a
+/// request that is not for a WebSocket.
+#[cfg(not(target_arch = "wasm32"))]
+fn handshake(stream: &mut TcpStream) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    let (mut request, mut buf) = (Vec::new(), [0; 1024]);
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_BYTES {
+            return Err(invalid("request too long"));
+        }
+        match stream.read(&mut buf)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => request.extend_from_slice(&buf[..read]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    match upgrade_key(&request) {
+        Ok(key) => {
+            let accept = accept_key(key);
+            write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n")
+        }
+        Err(reason) => {
+            write!(stream, "HTTP/1.1 400 Bad Request\r\nSec-WebSocket-Version: 13\r\nContent-Length: 0\r\n\r\n")?;
+            Err(invalid(reason))
+        }
+    }
+}
+
+/// Sends every frame to the clients as the LEDs that changed, and a new
+/// client every LED. Each client has a thread writing to it; one whose
+/// backlog fills up, or whose connection fails, is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+fn broadcast(events: &Receiver<Event>, statuses: &Sender<ServerStatus>) {
+    let (mut t_ms, mut colors): (u64, Vec<[u8; 3]>) = (0, Vec::new());
+    let mut clients: Vec<SyncSender<Vec<u8>>> = Vec::new();
+    for event in events {
+        let connected = clients.len();
+        match event {
+            Event::Frame(t, frame) => {
+                if let Some(text) = message(t, Some(&colors), &frame) {
+                    let frame = text_frame(&text);
+                    clients.retain(|client| !matches!(client.try_send(frame.clone()), Err(TrySendError::Full(_) | TrySendError::Disconnected(_))));
+                }
+                (t_ms, colors) = (t, frame);
+            }
+            Event::Client(stream, slot) => {
+                let (client, messages) = mpsc::sync_channel(CLIENT_BACKLOG);
+                let snapshot = text_frame(&message(t_ms, None, &colors).unwrap_or_default());
+                if client.try_send(snapshot).is_ok() && thread::Builder::new().name("serve-client".to_string()).spawn(move || write_to(stream, &messages, slot)).is_ok() {
+                    clients.push(client);
+                }
+            }
+        }
+        if clients.len() != connected {
+            let _ = statuses.send(ServerStatus::Serving(clients.len()));
+        }
+    }
+}
+
+/// Writes each message to the client until it goes away or falls behind,
+/// then gives up its slot.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_to(mut stream: TcpStream, messages: &Receiver<Vec<u8>>, _slot: Slot) {
+    if stream.set_write_timeout(Some(CLIENT_TIMEOUT)).is_err() {
+        return;
+    }
+    for message in messages {
+        if stream.write_all(&message).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    /// Reads one unmasked text frame as the server writes them.
+    fn read_text(stream: &mut impl Read) -> String {
+        let mut head = [0; 2];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head[0], 0x81);
+        let len = match head[1] {
+            126 => {
+                let mut len = [0; 2];
+                stream.read_exact(&mut len).unwrap();
+                u16::from_be_bytes(len) as usize
+            }
+            127 => {
+                let mut len = [0; 8];
+                stream.read_exact(&mut len).unwrap();
+                u64::from_be_bytes(len) as usize
+            }
+            len => len as usize,
+        };
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    }
+
+    #[test]
+    fn messages_carry_the_leds_that_changed_and_frames_their_length() {
+        let (red, off) = ([255, 0, 0], [0, 0, 0]);
+        assert_eq!(message(1500, None, &[red, off]).unwrap(), r#"{"t":1500,"changes":[{"led":0,"rgb":[255,0,0]},{"led":1,"rgb":[0,0,0]}]}"#);
+        assert_eq!(message(1600, Some(&[red, off]), &[off, off]).unwrap(), r#"{"t":1600,"changes":[{"led":0,"rgb":[0,0,0]}]}"#);
+        assert_eq!(message(1700, Some(&[off, off]), &[off, off]), None);
+        assert_eq!(text_frame("hi"), [0x81, 2, b'h', b'i']);
+        assert_eq!(text_frame(&"x".repeat(300))[..4], [0x81, 126, 1, 44]);
+        // The example of RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn only_websocket_upgrades_are_accepted_and_only_so_many_at_once() {
+        let upgrade = "GET / HTTP/1.1\r\nupgrade: WebSocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: abc==\r\n\r\n";
+        assert_eq!(upgrade_key(upgrade), Ok("abc=="));
+        assert_eq!(upgrade_key(&upgrade.replace("GET", "POST")), Err("not a GET request"));
+        assert_eq!(upgrade_key(&upgrade.replace("WebSocket", "h2c")), Err("no Upgrade: websocket"));
+        assert_eq!(upgrade_key(&upgrade.replace("keep-alive, Upgrade", "keep-alive")), Err("no Connection: Upgrade"));
+        assert_eq!(upgrade_key(&upgrade.replace("Version: 13", "Version: 8")), Err("not Sec-WebSocket-Version 13"));
+        assert_eq!(upgrade_key(&upgrade.replace("Sec-WebSocket-Key: abc==\r\n", "")), Err("no Sec-WebSocket-Key"));
+
+        let used = Arc::new(AtomicUsize::new(0));
+        let slots: Vec<Slot> = (0..2).map_while(|_| Slot::take(&used, 2)).collect();
+        assert_eq!(slots.len(), 2);
+        assert!(Slot::take(&used, 2).is_none(), "all slots taken");
+        drop(slots);
+        assert!(Slot::take(&used, 2).is_some(), "slots given back");
+    }
+
+    #[test]
+    fn a_new_client_is_sent_every_led_and_then_what_changes() {
+        let mut server = LedServer::start("127.0.0.1:0".parse().unwrap()).unwrap();
+        server.send(2000, vec![[255, 0, 0], [0, 0, 0]]);
+
+        let mut stream = TcpStream::connect(server.address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stream, "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let response: Vec<String> = (&mut reader).lines().map(Result::unwrap).take_while(|line| !line.is_empty()).collect();
+        assert_eq!(response[0], "HTTP/1.1 101 Switching Protocols");
+        assert!(response.contains(&"Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string()));
+
+        let snapshot: serde_json::Value = serde_json::from_str(&read_text(&mut reader)).unwrap();
+        assert_eq!(snapshot, serde_json::json!({"t": 2000, "changes": [{"led": 0, "rgb": [255, 0, 0]}, {"led": 1, "rgb": [0, 0, 0]}]}));
+        server.send(2100, vec![[255, 0, 0], [0, 0, 0]]);
+        server.send(2200, vec![[0, 0, 0], [0, 0, 0]]);
+        assert_eq!(read_text(&mut reader), r#"{"t":2200,"changes":[{"led":0,"rgb":[0,0,0]}]}"#, "nothing sent for the frame without changes");
+        while server.poll_status() != &ServerStatus::Serving(1) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut plain = TcpStream::connect(server.address).unwrap();
+        write!(plain, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut answer = String::new();
+        plain.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 400 Bad Request"), "{answer}");
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version written to new session files. Files from older versions load
+/// with defaults for the fields they lack; unknown fields are ignored.
+pub const SESSION_VERSION: u32 = 1;
+
+/// A saved playback position. Only points at the data, it does not embed it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub version: u32,
+    pub data_dir: Option<PathBuf>,
+    pub race: Option<String>,
+    pub current_index: usize,
+    pub playback_speed: Option<f64>,
+    pub race_started: bool,
+    pub paused: bool,
+    /// Visibility by driver name, so it survives a change in load order.
+    pub visible: BTreeMap<String, bool>,
+}
+
+impl Session {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read session {}: {e}", path.display()))?;
+        let session: Session =
+            serde_json::from_str(&text).map_err(|e| format!("invalid session {}: {e}", path.display()))?;
+        if session.version > SESSION_VERSION {
+            eprintln!(
+                "warning: {} was written by a newer version ({}), loading what is understood",
+                path.display(),
+                session.version
+            );
+        }
+        Ok(session)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).map_err(|e| format!("cannot write session {}: {e}", path.display()))?;
+        Ok(())
+    }
+}
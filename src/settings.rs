@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use crate::cues::Cue;
+use crate::palette::Palette;
+use crate::render::{Backdrop, LedShape, LedSize, Theme};
+use crate::sim::TrailMode;
+use crate::timestamp::ClockMode;
+
+/// Key of the settings in eframe storage.
+pub const SETTINGS_KEY: &str = "settings";
+
+/// UI settings kept between runs in eframe storage. Fields missing from an
+/// older store keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub speed: f64,
+    pub trail_length: usize,
+    pub trail_mode: TrailMode,
+    pub palette: Palette,
+    pub show_labels: bool,
+    pub show_led_codes: bool,
+    pub stack_offset: bool,
+    pub show_frame_stats: bool,
+    pub clock_mode: ClockMode,
+    pub theme: Theme,
+    pub backdrop: Backdrop,
+    pub keep_aspect: bool,
+    pub led_shape: LedShape,
+    pub led_size: LedSize,
+    /// RGB of the edge around unlit LEDs, none when absent.
+    pub unlit_outline: Option<[u8; 3]>,
+    pub show_outline: bool,
+    /// Following a driver from the whole track zooms in on them.
+    pub follow_zoom: bool,
+    /// Furthest a row may be from its LED, unlimited when unset.
+    pub snap_distance: Option<f64>,
+    pub smooth: bool,
+    pub interpolate: bool,
+    /// The telemetry driver's LEDs are brighter the more throttle they show.
+    pub throttle_brightness: bool,
+    /// LED brightness, in percent.
+    pub brightness: f32,
+    pub gamma: f32,
+    /// Moments of the race that pulse the track white.
+    pub cues: BTreeSet<Cue>,
+    pub cue_sound: bool,
+    /// Visibility by driver name; drivers not in the loaded race are ignored.
+    pub visible: BTreeMap<String, bool>,
+    /// RGB picked for a driver by hand, by driver name, over the palette.
+    pub color_overrides: BTreeMap<String, [u8; 3]>,
+    /// Data directory used last time.
+    pub data_dir: Option<PathBuf>,
+    /// Coordinates file used last time, opened when the configured one cannot be.
+    pub coordinates: Option<PathBuf>,
+    /// Seconds added to a driver's dates by hand, keyed like the config's
+    /// `driver_offsets`. Drivers without one are not moved.
+    pub driver_offsets: BTreeMap<String, f64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            trail_length: 10,
+            trail_mode: TrailMode::Fade,
+            palette: Palette::default(),
+            show_labels: false,
+            show_led_codes: false,
+            stack_offset: false,
+            show_frame_stats: false,
+            clock_mode: ClockMode::default(),
+            theme: Theme::default(),
+            backdrop: Backdrop::default(),
+            keep_aspect: false,
+            led_shape: LedShape::Square,
+            led_size: LedSize::default(),
+            unlit_outline: None,
+            show_outline: true,
+            follow_zoom: true,
+            snap_distance: None,
+            smooth: false,
+            interpolate: false,
+            throttle_brightness: false,
+            brightness: 100.0,
+            gamma: 1.0,
+            cues: BTreeSet::new(),
+            cue_sound: false,
+            visible: BTreeMap::new(),
+            color_overrides: BTreeMap::new(),
+            data_dir: None,
+            coordinates: None,
+            driver_offsets: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_older_store_keeps_the_defaults_it_lacks() {
+        let stored = r#"{"speed": 4.0, "palette": "high_contrast", "visible": {"Max Verstappen": false}, "retired_option": true}"#;
+        let settings: Settings = serde_json::from_str(stored).unwrap();
+        assert_eq!((settings.speed, settings.palette), (4.0, Palette::HighContrast));
+        assert_eq!(settings.visible, [("Max Verstappen".to_string(), false)].into());
+        assert_eq!(Settings { speed: 1.0, palette: Palette::default(), visible: BTreeMap::new(), ..settings }, Settings::default());
+    }
+}
@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::data;
+use crate::dialog;
+use crate::loader;
+use crate::source;
+
+/// Race folder of the data folder that dropped dataset files go in.
+pub const DROPPED_RACE: &str = "dropped";
+
+/// Files chosen on the setup screen shown when the coordinates or the race
+/// data cannot be found, with what was found in them.
+pub struct Setup {
+    pub coordinates_path: PathBuf,
+    pub data_dir: PathBuf,
+    pub race: Option<String>, // Subfolder of `data_dir` to load, or the folder itself for none
+    pub races: Vec<String>,
+    pub leds: Result<usize, String>, // LEDs in the coordinates file, or why it does not read
+    pub drivers: Result<usize, String>, // Dataset files in the race folder, or why there are none
+    pub error: Option<String>, // Why the last start failed
+    pub rejected: Vec<String>, // Files last dropped that are neither the coordinates nor a dataset
+    pub cancellable: bool, // Opened over a loaded race, which Cancel goes back to
+    delimiter: Option<u8>,
+}
+
+impl Setup {
+    /// Looks at `coordinates_path` and `data_dir` straight away. Without a
+    /// `race`, the first race is picked if the folder has no datasets of its own.
+    pub fn new(coordinates_path: PathBuf, data_dir: PathBuf, race: Option<String>, delimiter: Option<u8>) -> Self {
+        let mut setup = Self {
+            coordinates_path,
+            data_dir,
+            race,
+            races: Vec::new(),
+            leds: Ok(0),
+            drivers: Ok(0),
+            error: None,
+            rejected: Vec::new(),
+            cancellable: false,
+            delimiter,
+        };
+        setup.check_coordinates();
+        setup.set_data_dir(setup.data_dir.clone());
+        setup
+    }
+
+    /// Both files are there and have something in them.
+    pub fn is_ready(&self) -> bool {
+        matches!(self.leds, Ok(leds) if leds > 0) && matches!(self.drivers, Ok(drivers) if drivers > 0)
+    }
+
+    /// The folder the datasets are read from.
+    pub fn race_dir(&self) -> PathBuf {
+        match &self.race {
+            Some(race) => self.data_dir.join(race),
+            None => self.data_dir.clone(),
+        }
+    }
+
+    pub fn set_coordinates(&mut self, path: PathBuf) {
+        self.coordinates_path = path;
+        self.check_coordinates();
+    }
+
+    pub fn set_data_dir(&mut self, dir: PathBuf) {
+        self.races = loader::list_races(&dir);
+        self.data_dir = dir;
+        let own_datasets = loader::scan_datasets(&self.data_dir).is_ok_and(|paths| !paths.is_empty());
+        if !self.race.as_ref().is_some_and(|race| self.races.contains(race)) {
+            self.race = if own_datasets { None } else { self.races.first().cloned() };
+        }
+        self.check_drivers();
+    }
+
+    pub fn set_race(&mut self, race: Option<String>) {
+        self.race = race;
+        self.check_drivers();
+    }
+
+    /// Takes files dropped on the window, by name and contents: dataset files
+    /// go in the `DROPPED_RACE` folder, which becomes the race, and a file
+    /// named like the coordinates file or starting like one, see
+    /// `data::looks_like_coordinates`, is taken as the coordinates. Any
+    /// other file, such as a grid or drivers file, is left out and listed
+    /// in `rejected`.
+    pub fn add_files(&mut self, files: Vec<(String, Arc<[u8]>)>) {
+        let mut datasets = false;
+        self.rejected.clear();
+        for (name, contents) in files {
+            let Some(name) = Path::new(&name).file_name() else {
+                continue;
+            };
+            if loader::is_dataset_file(Path::new(name)) {
+                source::add(self.data_dir.join(DROPPED_RACE).join(name), contents);
+                datasets = true;
+            } else if self.coordinates_path.file_name() == Some(name) || data::looks_like_coordinates(Path::new(name), &contents, self.delimiter) {
+                let path = self.data_dir.join(name);
+                source::add(&path, contents);
+                self.set_coordinates(path);
+            } else {
+                self.rejected.push(name.to_string_lossy().into_owned());
+            }
+        }
+        if datasets {
+            self.race = Some(DROPPED_RACE.to_string());
+            self.set_data_dir(self.data_dir.clone());
+        }
+    }
+
+    /// Asks for the coordinates file, which takes over if one is picked.
+    pub fn browse_coordinates(&mut self) {
+        let dir = self.coordinates_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(path) = dialog::pick_file("LED coordinates", &["csv", "tsv", "gz"], &dir) {
+            self.set_coordinates(path);
+        }
+    }
+
+    /// Asks for the data folder, which takes over if one is picked.
+    pub fn browse_data_dir(&mut self) {
+        if let Some(dir) = dialog::pick_folder(Some(&self.data_dir)) {
+            self.set_data_dir(dir);
+        }
+    }
+
+    fn check_coordinates(&mut self) {
+        self.leds = data::read_coordinates(&self.coordinates_path, self.delimiter)
+            .map(|coordinates| coordinates.records.len())
+            .map_err(|e| e.to_string());
+    }
+
+    fn check_drivers(&mut self) {
+        let (paths, issues) = loader::race_dir_paths(&self.race_dir());
+        self.drivers = match issues.first() {
+            Some(issue) => Err(issue.message.clone()),
+            None => Ok(paths.len()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn counts_leds_and_drivers_and_picks_a_race() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-setup", std::process::id()));
+        let race = dir.join("zandvoort");
+        fs::create_dir_all(&race).unwrap();
+        let coordinates = dir.join("led_coords.csv");
+        fs::write(&coordinates, "x_led,y_led\n0,0\n1,0\n2,0\n").unwrap();
+        for driver in ["max", "lando"] {
+            fs::write(race.join(format!("time_delta_{driver}_start.csv")), "date,x_led,y_led,time_delta\n").unwrap();
+        }
+
+        let mut setup = Setup::new(dir.join("missing.csv"), dir.clone(), None, None);
+        assert!(setup.leds.is_err());
+        assert_eq!((setup.races.clone(), setup.race.clone(), setup.drivers.clone()), (vec!["zandvoort".to_string()], Some("zandvoort".to_string()), Ok(2)));
+        assert!(!setup.is_ready());
+        setup.set_coordinates(coordinates);
+        assert_eq!(setup.leds, Ok(3));
+        assert!(setup.is_ready());
+
+        setup.set_race(None);
+        assert!(setup.drivers.is_err(), "the data folder itself has no datasets");
+        assert!(!setup.is_ready());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dropped_files_make_a_race_of_their_own() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-setup-dropped", std::process::id()));
+        let mut setup = Setup::new(dir.join("led_coords.csv"), dir.clone(), None, None);
+        assert!(setup.leds.is_err() && setup.drivers.is_err());
+        setup.add_files(vec![
+            ("track.csv".to_string(), b"x_led,y_led\n0,0\n1,0\n".as_slice().into()),
+            ("time_delta_max_start.csv".to_string(), b"date,x_led,y_led,time_delta\n".as_slice().into()),
+        ]);
+        assert_eq!((setup.coordinates_path.clone(), setup.leds.clone()), (dir.join("track.csv"), Ok(2)));
+        assert_eq!((setup.races.clone(), setup.race.clone(), setup.drivers.clone()), (vec![DROPPED_RACE.to_string()], Some(DROPPED_RACE.to_string()), Ok(1)));
+        assert!(setup.is_ready());
+        assert!(setup.rejected.is_empty());
+
+        setup.add_files(vec![
+            ("grid.csv".to_string(), b"position,driver\n1,max\n".as_slice().into()),
+            ("drivers.toml".to_string(), b"[[driver]]\n".as_slice().into()),
+            ("laps.csv".to_string(), b"date,x_led,y_led\n2023-08-27T12:11:11Z,0,0\n".as_slice().into()),
+        ]);
+        assert_eq!(setup.rejected, ["grid.csv", "drivers.toml", "laps.csv"]);
+        assert_eq!(setup.coordinates_path, dir.join("track.csv"), "the coordinates stay");
+        setup.add_files(vec![("track.csv".to_string(), b"x;y\n".as_slice().into())]);
+        assert!(setup.rejected.is_empty() && setup.leds.is_err(), "named like the coordinates, whatever is in it");
+        setup.add_files(vec![("strip.tsv".to_string(), b"0\t0\tU1\n1\t0\tU2\n".as_slice().into())]);
+        assert_eq!((setup.coordinates_path.clone(), setup.leds.clone()), (dir.join("strip.tsv"), Ok(2)), "by its first line");
+    }
+}
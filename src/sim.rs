@@ -0,0 +1,1606 @@
+use chrono::{DateTime, Utc};
+use eframe::egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use crate::correction::ColorCorrection;
+use crate::data::{self, DataIssue, LedClass, LedCoordinate, Telemetry};
+use crate::dataset::{Dataset, Sample};
+use crate::drivers::Driver;
+use crate::gap_history::GapHistory;
+use crate::laps::LapCounter;
+use crate::loader;
+use crate::palette::Palette;
+use crate::progress::CarProgress;
+use crate::render;
+use crate::track::LedIndex;
+
+/// Longest trail that can be shown. Cars remember this many LEDs whatever
+/// the trail length, so a longer trail shows at once.
+pub const MAX_TRAIL_LENGTH: usize = 100;
+
+/// Brightness of every other car while one is in the spotlight.
+pub const SPOTLIGHT_DIM: f32 = 0.3;
+
+/// Simulated time between the checkpoints seeking starts from, unless configured.
+pub const DEFAULT_CHECKPOINT_INTERVAL_MS: u64 = 60_000;
+
+/// Brightness of a car whose LEDs follow its throttle, with the throttle closed.
+pub const THROTTLE_DIM: f32 = 0.2;
+
+/// Brightness of a car taken to have stopped, see `Simulation::stopped_after`.
+pub const STOPPED_DIM: f32 = 0.25;
+
+/// Where a trail slowed down right to a stop is drawn in, see `TrailMode::SpeedGradient`.
+pub const SLOW_TRAIL_COLOR: Color32 = Color32::from_rgb(255, 40, 0);
+
+/// How the LEDs behind a car are colored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailMode {
+    /// In the car's trail color, fading with age.
+    #[default]
+    Fade,
+    /// Fading the same, shifting from the trail color where the car was
+    /// fastest towards `SLOW_TRAIL_COLOR` the slower it went between LEDs,
+    /// so braking zones stand out.
+    SpeedGradient,
+}
+
+impl TrailMode {
+    pub const ALL: [TrailMode; 2] = [TrailMode::Fade, TrailMode::SpeedGradient];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TrailMode::Fade => "Fade",
+            TrailMode::SpeedGradient => "Speed gradient",
+        }
+    }
+}
+
+/// A car moving onto an LED, or off the map for `None`, as playback goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub dataset_idx: usize,
+    pub sim_ms: u64,
+    pub led: Option<usize>,
+}
+
+/// Playback state of one car.
+#[derive(Debug, Default, Clone)]
+pub struct CarState {
+    pub trail: VecDeque<usize>, // Recently visited LEDs, the current one first, up to `MAX_TRAIL_LENGTH` behind it
+    pub trail_ms: VecDeque<u64>, // Simulated time the car reached each LED of `trail`
+    pub laps: LapCounter,
+    pub progress: CarProgress,
+    pub ended_at_ms: Option<u64>, // Simulated time the dataset ran out of rows
+    pub rows: usize, // Rows of the dataset shown so far
+    pub off_map: bool, // The last row shown was too far from every LED, so none is lit
+}
+
+impl CarState {
+    /// A copy without the progress history, see `CarProgress::without_history`.
+    fn without_history(&self) -> Self {
+        Self {
+            trail: self.trail.clone(),
+            trail_ms: self.trail_ms.clone(),
+            laps: self.laps.clone(),
+            progress: self.progress.without_history(),
+            ended_at_ms: self.ended_at_ms,
+            rows: self.rows,
+            off_map: self.off_map,
+        }
+    }
+
+    /// Puts `led`, reached at `sim_ms`, at the front of the trail unless
+    /// the car is on it already. Returns whether it moved.
+    pub fn arrive(&mut self, led: usize, sim_ms: u64) -> bool {
+        if self.trail.front() == Some(&led) {
+            return false;
+        }
+        self.trail.push_front(led);
+        self.trail_ms.push_front(sim_ms);
+        self.trail.truncate(MAX_TRAIL_LENGTH + 1);
+        self.trail_ms.truncate(MAX_TRAIL_LENGTH + 1);
+        true
+    }
+
+    /// Forgets the trail, as when the car goes off the map.
+    pub fn clear_trail(&mut self) {
+        self.trail.clear();
+        self.trail_ms.clear();
+    }
+}
+
+/// The playback state at one moment of the timeline, to seek from.
+#[derive(Clone)]
+struct Checkpoint {
+    index: usize,
+    sim_elapsed_ms: u64,
+    cars: Vec<CarState>, // Without their progress history, see `Checkpoints::histories`
+    history_lens: Vec<usize>, // How much of each car's history is theirs by then
+    gap_arrivals: usize, // How much of `Checkpoints::gaps` is noted by then
+}
+
+/// Moments of the timeline between its marks, found again by merging the
+/// datasets' rows on from the mark before.
+const TIMELINE_MARK_EVERY: usize = 256;
+
+/// Where the timeline stands at one of its moments: when it is, and how
+/// many rows of each dataset are due before it.
+#[derive(Clone)]
+struct Mark {
+    due_ms: u64,
+    rows: Vec<usize>,
+}
+
+/// Every moment a row is due, in order and without repeats. Each
+/// dataset's rows are already in time order, so rather than every moment
+/// this keeps how many there are, the last and a `Mark` every
+/// `TIMELINE_MARK_EVERY` of them, and merges the rows for the rest.
+#[derive(Default, Clone)]
+struct Timeline {
+    len: usize,
+    last_ms: Option<u64>,
+    marks: Vec<Mark>,
+}
+
+/// Copies of the playback state along the race, made in one pass over it
+/// the first time seeking needs them.
+#[derive(Clone)]
+struct Checkpoints {
+    lap_settings: (Option<usize>, usize), // `start_finish_led` and `lap_debounce_leds` they were made with
+    states: Vec<Checkpoint>, // In timeline order, the first at the start
+    histories: Vec<Vec<(f64, u64)>>, // Each car's progress history to the end of the race
+    gaps: Option<GapHistory>, // The gap history to the end of the race, if it was kept while they were made
+}
+
+/// A race played on the LEDs, without a window or a clock: the caller says
+/// how far to go in simulated time and reads back the LEDs.
+///
+/// Every dataset is put on one clock by its dates, so drivers whose files
+/// start at different moments still line up: simulated time 0 is the
+/// session start, and each row is due at its date minus the session start
+/// plus the driver's manual offset. Rows due before 0 are shown at once.
+///
+/// A clone plays on by itself, such as to sample an export on another thread.
+#[derive(Clone)]
+pub struct Simulation {
+    pub coordinates: Vec<LedCoordinate>,
+    pub led_index: Arc<LedIndex>, // Spatial index for matching car positions to LEDs
+    pub drivers: Vec<Driver>, // One per dataset, the race's rows with how each car is lit; set through `set_drivers` and `replace_dataset`
+    pub spotlight: Option<usize>, // Dataset lit at full brightness, the others at `SPOTLIGHT_DIM`
+    pub flash_led: Option<usize>, // LED lit white over every car, such as the start/finish LED after a lap
+    pub track_flash: Option<f32>, // White laid over every LED as strong as this, from 0 to 1, such as for a cue
+    pub throttle_car: Option<usize>, // Dataset lit brighter the more throttle its telemetry shows, down to `THROTTLE_DIM`
+    pub stopped_after: usize, // Rows in a row on one LED after which a car has stopped and is lit at `STOPPED_DIM`, 0 for never
+    pub trail_length: usize, // LEDs kept lit behind each car's current LED
+    pub trail_mode: TrailMode,
+    pub correction: ColorCorrection, // Brightness and gamma of every lit LED
+    pub start_finish_led: Option<usize>, // Index into coordinates of the start/finish LED
+    pub lap_debounce_leds: usize,
+    pub interpolated: Vec<Option<usize>>, // LED leading each dataset's car between rows, when it differs from the current one
+    pub cars: Vec<CarState>, // Playback state of each dataset
+    gap_history: Option<GapHistory>, // Kept while something shows it, replayed from the start when turned on
+    pub current_index: usize, // Moments of the timeline shown so far
+    pub sim_elapsed_ms: u64, // Simulated time since the session start
+    pub rows_applied: u64, // Rows played in since the simulation was made, seeking included
+    configured_start: Option<DateTime<Utc>>, // Session start from the config, else the earliest dataset start
+    manual_offsets_ms: Vec<i64>, // Added to each dataset's dates, for files with a broken clock
+    session_start: DateTime<Utc>, // Date at simulated time 0
+    offsets_ms: Vec<i64>, // Simulated time of each dataset's origin
+    timeline: Timeline, // Every moment a row is due
+    led_states: Vec<Color32>, // Color of every LED as of the last `advance_to`
+    checkpoint_interval_ms: Option<u64>, // Simulated time between checkpoints, none for no checkpoints
+    checkpoints: Option<Checkpoints>, // Made when first needed, dropped with the datasets or alignment they were made for
+    transitions: Option<Sender<Transition>>, // Told of every car that moves while playing, not while seeking
+}
+
+impl Simulation {
+    /// A race of `drivers` at its start, without trails or lap counting.
+    pub fn new(coordinates: Vec<LedCoordinate>, led_index: Arc<LedIndex>, drivers: Vec<Driver>) -> Self {
+        let mut sim = Self {
+            led_states: vec![Color32::BLACK; coordinates.len()],
+            coordinates,
+            led_index,
+            drivers: Vec::new(),
+            spotlight: None,
+            flash_led: None,
+            track_flash: None,
+            throttle_car: None,
+            stopped_after: 0,
+            trail_length: 0,
+            trail_mode: TrailMode::Fade,
+            correction: ColorCorrection::default(),
+            start_finish_led: None,
+            lap_debounce_leds: 0,
+            interpolated: Vec::new(),
+            cars: Vec::new(),
+            gap_history: None,
+            current_index: 0,
+            rows_applied: 0,
+            sim_elapsed_ms: 0,
+            configured_start: None,
+            manual_offsets_ms: Vec::new(),
+            session_start: DateTime::UNIX_EPOCH,
+            offsets_ms: Vec::new(),
+            timeline: Timeline::default(),
+            checkpoint_interval_ms: None,
+            checkpoints: None,
+            transitions: None,
+        };
+        sim.set_drivers(drivers, Vec::new());
+        sim
+    }
+
+    /// Reads the coordinates file and the datasets, colored from the default
+    /// palette. Datasets that do not load are left out and reported with the
+    /// other data problems.
+    pub fn load(coordinates_path: &Path, dataset_paths: &[PathBuf]) -> Result<(Self, Vec<DataIssue>), Box<dyn Error>> {
+        let coordinates = data::read_coordinates(coordinates_path, None)
+            .map_err(|e| format!("cannot read {}: {e}", coordinates_path.display()))?;
+        let led_index = Arc::new(LedIndex::new(&coordinates.records));
+        let race = loader::load_datasets(dataset_paths, &led_index, loader::ReadOptions::default(), Palette::default(), &BTreeMap::new());
+        let mut issues = coordinates.issues;
+        issues.extend(race.issues);
+        Ok((Self::new(coordinates.records, led_index, race.drivers), issues))
+    }
+
+    /// Replaces the race with `drivers`, every one visible and none in the
+    /// spotlight or following its throttle, with a manual offset for each in
+    /// `manual_offsets_ms`; missing ones are 0. Playback goes back to the start.
+    pub fn set_drivers(&mut self, drivers: Vec<Driver>, manual_offsets_ms: Vec<i64>) {
+        (self.drivers, self.manual_offsets_ms, self.spotlight, self.throttle_car) = (drivers, manual_offsets_ms, None, None);
+        self.drivers.iter_mut().for_each(|driver| driver.visible = true);
+        self.align();
+    }
+
+    /// Replaces one driver's dataset, keeping playback at the same
+    /// simulated time: the other cars stay as they are and only this one is
+    /// played again, from its new rows. Replaying the whole race is only
+    /// needed when the new rows move the session start, or for the gap
+    /// history, which every car's arrivals make together.
+    pub fn replace_dataset(&mut self, dataset_idx: usize, dataset: Dataset) {
+        let (session_start, sim_ms, cars) = (self.session_start, self.sim_elapsed_ms, std::mem::take(&mut self.cars));
+        self.drivers[dataset_idx].records = dataset;
+        self.align();
+        let index = self.index_at_sim_ms(sim_ms);
+        if self.session_start != session_start || self.gap_history.is_some() {
+            self.seek(index);
+            return;
+        }
+        let now_ms = index.checked_sub(1).and_then(|last| self.moment_ms(last)).unwrap_or(0);
+        self.cars = cars;
+        self.cars[dataset_idx] = CarState::default();
+        self.current_index = index;
+        self.replay(|sim| {
+            let mut shown_ms = 0;
+            while let Some(row_ms) = sim.row_ms(dataset_idx, sim.cars[dataset_idx].rows) {
+                // Shown once every row before it is, as playing would
+                shown_ms = shown_ms.max(row_ms);
+                if shown_ms > now_ms {
+                    break;
+                }
+                sim.sim_elapsed_ms = shown_ms;
+                sim.track_car(dataset_idx);
+            }
+        });
+        self.sim_elapsed_ms = now_ms;
+        // A car runs out at the first moment after its last row, which the new rows can move for any car
+        for dataset_idx in 0..self.cars.len() {
+            let rows = self.cars[dataset_idx].rows;
+            let last_ms = rows.checked_sub(1).and_then(|last| self.row_ms(dataset_idx, last));
+            let ended = last_ms.map_or(0, |last_ms| self.index_at_sim_ms(last_ms));
+            let ended_at_ms = (rows >= self.drivers[dataset_idx].records.len() && ended < index).then(|| self.moment_ms(ended)).flatten();
+            self.cars[dataset_idx].ended_at_ms = ended_at_ms;
+        }
+    }
+
+    /// Lines the datasets up from `session_start`, or from the earliest
+    /// dataset start when `None`, moving each by its manual offset.
+    /// Playback goes back to the start.
+    pub fn set_alignment(&mut self, session_start: Option<DateTime<Utc>>, manual_offsets_ms: Vec<i64>) {
+        (self.configured_start, self.manual_offsets_ms) = (session_start, manual_offsets_ms);
+        self.align();
+    }
+
+    /// Moves each dataset by its manual offset in `manual_offsets_ms`,
+    /// keeping the session start. Playback goes back to the start.
+    pub fn set_manual_offsets(&mut self, manual_offsets_ms: Vec<i64>) {
+        self.manual_offsets_ms = manual_offsets_ms;
+        self.align();
+    }
+
+    fn align(&mut self) {
+        let earliest = self.drivers.iter().map(|driver| &driver.records).filter(|data| !data.is_empty()).map(|data| data.origin).min();
+        self.session_start = self.configured_start.or(earliest).unwrap_or(DateTime::UNIX_EPOCH);
+        self.offsets_ms = (0..self.drivers.len())
+            .map(|i| (self.drivers[i].records.origin - self.session_start).num_milliseconds() + self.manual_offset_ms(i))
+            .collect();
+        let mut timeline = Timeline::default();
+        let mut rows = vec![0; self.drivers.len()];
+        loop {
+            let before = (timeline.len % TIMELINE_MARK_EVERY == 0).then(|| rows.clone());
+            let Some(due_ms) = self.next_moment(&mut rows) else {
+                break;
+            };
+            if let Some(rows) = before {
+                timeline.marks.push(Mark { due_ms, rows });
+            }
+            (timeline.len, timeline.last_ms) = (timeline.len + 1, Some(due_ms));
+        }
+        self.timeline = timeline;
+        self.checkpoints = None;
+        self.reset();
+    }
+
+    /// Sends a `Transition` to `sender` each time playing moves a car to
+    /// another LED or off the map, or stops sending for `None`. Seeking
+    /// replays rows without sending them.
+    pub fn send_transitions(&mut self, sender: Option<Sender<Transition>>) {
+        self.transitions = sender;
+    }
+
+    /// Keeps a checkpoint every `interval_ms` of simulated time to seek
+    /// from, or none for `None`, which saves their memory on short races
+    /// where replaying from the start is quick anyway.
+    pub fn set_checkpoint_interval(&mut self, interval_ms: Option<u64>) {
+        self.checkpoint_interval_ms = interval_ms.filter(|&ms| ms > 0);
+        self.checkpoints = None;
+    }
+
+    /// Plays the whole race once, copying the state at the start and every
+    /// `interval_ms` after, then goes back to before the first row.
+    fn make_checkpoints(&mut self, interval_ms: u64) {
+        self.reset();
+        let mut states = Vec::new();
+        let mut next_ms = 0;
+        loop {
+            if self.sim_elapsed_ms >= next_ms {
+                states.push(Checkpoint {
+                    index: self.current_index,
+                    sim_elapsed_ms: self.sim_elapsed_ms,
+                    cars: self.cars.iter().map(CarState::without_history).collect(),
+                    history_lens: self.cars.iter().map(|car| car.progress.history_len()).collect(),
+                    gap_arrivals: self.gap_history.as_ref().map_or(0, GapHistory::arrivals),
+                });
+                next_ms = (self.sim_elapsed_ms / interval_ms + 1) * interval_ms;
+            }
+            if !self.advance() {
+                break;
+            }
+        }
+        let histories = self.cars.iter_mut().map(|car| car.progress.take_history()).collect();
+        let gaps = self.gap_history.clone();
+        self.checkpoints = Some(Checkpoints { lap_settings: (self.start_finish_led, self.lap_debounce_leds), states, histories, gaps });
+        self.reset();
+    }
+
+    /// Puts playback at the last checkpoint at or before `index`, if
+    /// checkpoints are kept, and returns whether it did. Checkpoints made
+    /// without the gap history are made again once it is kept.
+    fn restore_checkpoint(&mut self, index: usize) -> bool {
+        let Some(interval_ms) = self.checkpoint_interval_ms else {
+            return false;
+        };
+        let lap_settings = (self.start_finish_led, self.lap_debounce_leds);
+        let stale = |checkpoints: &Checkpoints| checkpoints.lap_settings != lap_settings || (self.gap_history.is_some() && checkpoints.gaps.is_none());
+        if self.checkpoints.as_ref().is_none_or(stale) {
+            self.make_checkpoints(interval_ms);
+        }
+        let Some(checkpoints) = &self.checkpoints else {
+            return false;
+        };
+        let Some(checkpoint) = checkpoints.states[..checkpoints.states.partition_point(|state| state.index <= index)].last() else {
+            return false;
+        };
+        self.current_index = checkpoint.index;
+        self.sim_elapsed_ms = checkpoint.sim_elapsed_ms;
+        self.interpolated.clear();
+        let cars = checkpoint.cars.iter().zip(&checkpoint.history_lens).zip(&checkpoints.histories);
+        self.cars = cars
+            .map(|((car, &len), history)| {
+                let mut car = car.clone();
+                car.progress.restore_history(&history[..len]);
+                car
+            })
+            .collect();
+        if let (Some(gaps), Some(all)) = (&mut self.gap_history, &checkpoints.gaps) {
+            *gaps = all.rewound(checkpoint.gap_arrivals);
+        }
+        true
+    }
+
+    /// Date at simulated time 0.
+    pub fn session_start(&self) -> DateTime<Utc> {
+        self.session_start
+    }
+
+    pub fn date_at(&self, sim_ms: u64) -> DateTime<Utc> {
+        self.session_start + chrono::Duration::milliseconds(sim_ms as i64)
+    }
+
+    /// Simulated time of a dataset's origin after the session start, with its manual offset.
+    pub fn offset_ms(&self, dataset_idx: usize) -> i64 {
+        self.offsets_ms.get(dataset_idx).copied().unwrap_or(0)
+    }
+
+    pub fn manual_offset_ms(&self, dataset_idx: usize) -> i64 {
+        self.manual_offsets_ms.get(dataset_idx).copied().unwrap_or(0)
+    }
+
+    /// Simulated time at which a dataset's row is shown.
+    pub fn row_ms(&self, dataset_idx: usize, row: usize) -> Option<u64> {
+        Some(at_ms(self.offset_ms(dataset_idx), self.drivers.get(dataset_idx)?.records.get(row)?))
+    }
+
+    /// How many moments a row is due at, each once however many rows are
+    /// due then: index `i` has shown the first `i` of them.
+    pub fn moments(&self) -> usize {
+        self.timeline.len
+    }
+
+    /// Simulated time of the moment at `index`, the last shown once the
+    /// first `index + 1` are, `None` past the last.
+    pub fn moment_ms(&self, index: usize) -> Option<u64> {
+        if index >= self.timeline.len {
+            return None;
+        }
+        // Playback is usually at or just past the moment asked for
+        if index + 1 == self.current_index {
+            return Some(self.sim_elapsed_ms);
+        }
+        if index == self.current_index {
+            return self.next_due_ms();
+        }
+        let mark = &self.timeline.marks[index / TIMELINE_MARK_EVERY];
+        let mut rows = mark.rows.clone();
+        let mut due_ms = self.next_moment(&mut rows);
+        for _ in 0..index % TIMELINE_MARK_EVERY {
+            due_ms = self.next_moment(&mut rows);
+        }
+        due_ms
+    }
+
+    /// Simulated time of the first moment, `None` without rows.
+    pub fn first_moment_ms(&self) -> Option<u64> {
+        self.timeline.marks.first().map(|mark| mark.due_ms)
+    }
+
+    /// Simulated time of the last moment, `None` without rows.
+    pub fn last_moment_ms(&self) -> Option<u64> {
+        self.timeline.last_ms
+    }
+
+    /// The next moment after the first `rows` of each dataset, moving
+    /// `rows` on past the rows due then. `None` once every row is counted.
+    fn next_moment(&self, rows: &mut [usize]) -> Option<u64> {
+        let due_ms = rows.iter().enumerate().filter_map(|(dataset_idx, &row)| self.row_ms(dataset_idx, row)).min()?;
+        for (dataset_idx, row) in rows.iter_mut().enumerate() {
+            while self.row_ms(dataset_idx, *row).is_some_and(|ms| ms <= due_ms) {
+                *row += 1;
+            }
+        }
+        Some(due_ms)
+    }
+
+    /// When the first row no car has shown yet is due, the next moment.
+    fn next_due_ms(&self) -> Option<u64> {
+        self.cars.iter().enumerate().filter_map(|(dataset_idx, car)| self.row_ms(dataset_idx, car.rows)).min()
+    }
+
+    /// Back to before the first row.
+    pub fn reset(&mut self) {
+        self.current_index = 0;
+        self.sim_elapsed_ms = 0;
+        self.cars = vec![CarState::default(); self.drivers.len()];
+        self.interpolated.clear();
+        if let Some(gaps) = &mut self.gap_history {
+            *gaps = GapHistory::new(self.drivers.len(), self.coordinates.len());
+        }
+    }
+
+    /// Starts or stops keeping each driver's gap to the leader over time.
+    /// Starting replays the race up to the current row to fill it in.
+    pub fn track_gaps(&mut self, on: bool) {
+        if on == self.gap_history.is_some() {
+            return;
+        }
+        self.gap_history = on.then(GapHistory::default);
+        if on {
+            self.seek(self.current_index);
+        }
+    }
+
+    /// Each driver's gap to the leader so far, while `track_gaps` is on.
+    pub fn gap_history(&self) -> Option<&GapHistory> {
+        self.gap_history.as_ref()
+    }
+
+    /// Replays up to `index` without waiting, so trails, laps and gaps
+    /// match what playing up to that point would have shown. Replaying
+    /// starts from the last checkpoint before `index`, if checkpoints are
+    /// kept, otherwise from the start.
+    pub fn seek(&mut self, index: usize) {
+        self.replay(|sim| {
+            if !sim.restore_checkpoint(index) {
+                sim.reset();
+            }
+            while sim.current_index < index && sim.advance() {}
+        });
+    }
+
+    /// Runs `replay` without sending its transitions, for playing the race
+    /// through to look at rather than to show.
+    pub fn replay<T>(&mut self, replay: impl FnOnce(&mut Self) -> T) -> T {
+        let transitions = self.transitions.take();
+        let result = replay(self);
+        self.transitions = transitions;
+        result
+    }
+
+    /// Shows every row due by simulated time `t_ms`, replaying from the
+    /// start if that is before the row shown, and updates `led_states`.
+    pub fn advance_to(&mut self, t_ms: u64) {
+        if t_ms < self.sim_elapsed_ms {
+            self.seek(self.index_at_sim_ms(t_ms));
+        }
+        while self.next_step_ms().is_some_and(|step_ms| self.sim_elapsed_ms + step_ms <= t_ms) {
+            self.advance();
+        }
+        self.led_states = self.led_colors().into_iter().map(|[r, g, b]| Color32::from_rgb(r, g, b)).collect();
+    }
+
+    /// Color of every LED in the order of the coordinates file as of the
+    /// last `advance_to`, unlit LEDs black.
+    pub fn led_states(&self) -> &[Color32] {
+        &self.led_states
+    }
+
+    /// Whether every dataset has been played to its end.
+    pub fn is_finished(&self) -> bool {
+        self.next_step_ms().is_none()
+    }
+
+    /// Moves on to the next moment of the timeline, showing the rows due by
+    /// then. Returns `false` and leaves the state untouched at the end.
+    pub fn advance(&mut self) -> bool {
+        let Some(due_ms) = self.next_due_ms() else {
+            return false;
+        };
+        self.current_index += 1;
+        self.sim_elapsed_ms = due_ms;
+        self.interpolated.clear();
+        self.track_cars();
+        true
+    }
+
+    /// Matches the rows that just became due for each car to their LEDs and
+    /// updates the car's trail, lap counter and progress.
+    fn track_cars(&mut self) {
+        for dataset_idx in 0..self.cars.len() {
+            let moved = self.track_car(dataset_idx);
+            let car = &mut self.cars[dataset_idx];
+            if !moved && car.rows >= self.drivers[dataset_idx].records.len() {
+                car.ended_at_ms.get_or_insert(self.sim_elapsed_ms);
+            }
+        }
+    }
+
+    /// Shows the rows of one car due by now, and returns whether there were any.
+    fn track_car(&mut self, dataset_idx: usize) -> bool {
+        let (dataset, car, offset_ms) = (&self.drivers[dataset_idx].records, &mut self.cars[dataset_idx], self.offsets_ms[dataset_idx]);
+        let mut moved = false;
+        while let Some(sample) = dataset.get(car.rows).filter(|&sample| at_ms(offset_ms, sample) <= self.sim_elapsed_ms) {
+            car.rows += 1;
+            self.rows_applied += 1;
+            moved = true;
+            let Some(led) = sample.on_led() else {
+                if let Some(transitions) = self.transitions.as_ref().filter(|_| !car.off_map) {
+                    let _ = transitions.send(Transition { dataset_idx, sim_ms: self.sim_elapsed_ms, led: None });
+                }
+                // Nowhere on the LEDs, so nothing stale stays lit for the car
+                car.off_map = true;
+                car.clear_trail();
+                continue;
+            };
+            car.off_map = false;
+            if car.arrive(led, self.sim_elapsed_ms) {
+                if let Some(transitions) = &self.transitions {
+                    let _ = transitions.send(Transition { dataset_idx, sim_ms: self.sim_elapsed_ms, led: Some(led) });
+                }
+                if let Some(gaps) = &mut self.gap_history {
+                    gaps.arrive(dataset_idx, led, self.sim_elapsed_ms);
+                }
+            }
+            if let Some(line_led) = self.start_finish_led {
+                car.laps.observe(led, line_led, self.lap_debounce_leds);
+            }
+            car.progress.observe(&self.coordinates, led, self.sim_elapsed_ms);
+        }
+        moved
+    }
+
+    /// The time to wait before the next moment a row is due. `None` once
+    /// every dataset is exhausted.
+    pub fn next_step_ms(&self) -> Option<u64> {
+        self.step_ms_at(self.current_index)
+    }
+
+    /// The time between the moment at `index` and the one before it, or
+    /// the start for the first. `None` past the last moment.
+    pub fn step_ms_at(&self, index: usize) -> Option<u64> {
+        let previous = match index.checked_sub(1) {
+            Some(previous) => self.moment_ms(previous)?,
+            None => 0,
+        };
+        Some(self.moment_ms(index)? - previous)
+    }
+
+    /// The last index whose simulated time is at most `target_ms`, or the end.
+    pub fn index_at_sim_ms(&self, target_ms: u64) -> usize {
+        let marks = &self.timeline.marks;
+        let Some(before) = marks.partition_point(|mark| mark.due_ms <= target_ms).checked_sub(1) else {
+            return 0;
+        };
+        let mut rows = marks[before].rows.clone();
+        let mut index = before * TIMELINE_MARK_EVERY;
+        while index < self.timeline.len && self.next_moment(&mut rows).is_some_and(|due_ms| due_ms <= target_ms) {
+            index += 1;
+        }
+        index
+    }
+
+    /// Every time a car arrived on `led` over the whole race, as the
+    /// dataset, the index that shows it there and the simulated time, in
+    /// race order. Rows that stay on the LED count as one arrival.
+    pub fn arrivals(&self, led: usize) -> Vec<(usize, usize, u64)> {
+        let mut arrivals = Vec::new();
+        for (dataset_idx, data) in self.drivers.iter().map(|driver| &driver.records).enumerate() {
+            let on_led = |row| data.get(row).is_some_and(|sample| sample.led as usize == led);
+            for row in (0..data.len()).filter(|&row| on_led(row) && !row.checked_sub(1).is_some_and(on_led)) {
+                let sim_ms = self.row_ms(dataset_idx, row).unwrap();
+                arrivals.push((dataset_idx, self.index_at_sim_ms(sim_ms), sim_ms));
+            }
+        }
+        arrivals.sort_by_key(|&(dataset_idx, _, sim_ms)| (sim_ms, dataset_idx));
+        arrivals
+    }
+
+    /// The rows the last step showed, as the dataset and its row, in
+    /// dataset order. Usually one, more when rows are due at the same time.
+    pub fn last_step_rows(&self) -> Vec<(usize, usize)> {
+        if self.current_index == 0 {
+            return Vec::new();
+        }
+        let mut rows = Vec::new();
+        for (dataset_idx, car) in self.cars.iter().enumerate() {
+            let due_now = |row: &usize| self.row_ms(dataset_idx, *row) == Some(self.sim_elapsed_ms);
+            let mut shown: Vec<usize> = (0..car.rows).rev().take_while(due_now).collect();
+            shown.reverse();
+            rows.extend(shown.into_iter().map(|row| (dataset_idx, row)));
+        }
+        rows
+    }
+
+    /// Whether the car of `dataset_idx` is on a pit lane LED.
+    pub fn in_pit_lane(&self, dataset_idx: usize) -> bool {
+        let led = self.cars.get(dataset_idx).and_then(|car| car.trail.front());
+        led.and_then(|&led| self.coordinates.get(led)).is_some_and(|coord| coord.class == LedClass::Pitlane)
+    }
+
+    /// Telemetry of the row the car of `dataset_idx` shows, if its dataset has any.
+    pub fn telemetry(&self, dataset_idx: usize) -> Option<Telemetry> {
+        self.telemetry_at(dataset_idx, self.cars.get(dataset_idx)?.rows)
+    }
+
+    /// Telemetry of the last of the first `rows` rows of a dataset.
+    fn telemetry_at(&self, dataset_idx: usize, rows: usize) -> Option<Telemetry> {
+        self.drivers.get(dataset_idx)?.records.telemetry(rows.checked_sub(1)?)
+    }
+
+    /// Whether the car of `dataset_idx` has stopped, such as after a crash:
+    /// its last `stopped_after` rows shown were all on one LED. It goes
+    /// again as soon as a row puts it somewhere else.
+    pub fn is_stopped(&self, dataset_idx: usize) -> bool {
+        self.cars.get(dataset_idx).is_some_and(|car| self.stopped_at(dataset_idx, car.rows))
+    }
+
+    /// A car's color for markers drawn for it, dimmed while it has stopped.
+    pub fn marker_color(&self, dataset_idx: usize) -> Color32 {
+        let color = self.drivers[dataset_idx].color;
+        if self.is_stopped(dataset_idx) { color.gamma_multiply(STOPPED_DIM) } else { color }
+    }
+
+    /// `is_stopped` once the first `rows` rows of a dataset are shown.
+    fn stopped_at(&self, dataset_idx: usize, rows: usize) -> bool {
+        let (Some(dataset), Some(from)) = (self.drivers.get(dataset_idx).map(|driver| &driver.records), rows.checked_sub(self.stopped_after)) else {
+            return false;
+        };
+        let led = |row: usize| dataset.get(row).and_then(|sample| sample.on_led());
+        self.stopped_after > 0 && led(rows - 1).is_some() && (from..rows - 1).all(|row| led(row) == led(rows - 1))
+    }
+
+    /// Laps completed by all the cars together.
+    pub fn completed_laps(&self) -> u32 {
+        self.cars.iter().map(|car| car.laps.laps).sum()
+    }
+
+    /// The lap the car furthest ahead is on, 0 before anyone crossed the line.
+    pub fn leader_lap(&self) -> u32 {
+        self.cars.iter().map(|car| car.laps.lap()).max().unwrap_or(0)
+    }
+
+    /// Works out when the leader began each lap, as `leader_lap` goes up
+    /// playing through the race: the first time any car reached it. Each
+    /// car's laps are counted from its own rows, without playing the race,
+    /// so this can run on another thread. `None` without a start/finish LED.
+    pub fn lap_starts_job(&self) -> Option<impl FnOnce() -> Vec<u64> + Send + 'static> {
+        let line_led = self.start_finish_led?;
+        let debounce = self.lap_debounce_leds;
+        let datasets: Vec<_> = self.drivers.iter().map(|driver| &driver.records).cloned().zip(self.offsets_ms.clone()).collect();
+        Some(move || {
+            let mut lap_starts: Vec<u64> = Vec::new();
+            for (dataset, offset_ms) in datasets {
+                let (mut laps, mut shown_ms) = (LapCounter::default(), 0);
+                for row in 0..dataset.len() {
+                    let Some(sample) = dataset.get(row) else {
+                        break;
+                    };
+                    // Shown once every row before it is, as playing would
+                    shown_ms = shown_ms.max(at_ms(offset_ms, sample));
+                    let Some(led) = sample.on_led() else {
+                        continue;
+                    };
+                    let lap = laps.lap();
+                    laps.observe(led, line_led, debounce);
+                    if laps.lap() > lap {
+                        let nth = lap as usize;
+                        match lap_starts.get_mut(nth) {
+                            Some(start_ms) => *start_ms = (*start_ms).min(shown_ms),
+                            None => lap_starts.push(shown_ms),
+                        }
+                    }
+                }
+            }
+            lap_starts
+        })
+    }
+
+    /// LEDs lit by the visible cars with their colors, faded with age and
+    /// corrected, oldest first. Each LED appears once: the cars that were on it most
+    /// recently share it, their colors mixed, so no car hides another.
+    pub fn lit_leds(&self) -> Vec<(usize, Color32)> {
+        self.lit_leds_of(|dataset_idx| self.drivers[dataset_idx].visible)
+    }
+
+    /// `lit_leds` for the cars of the datasets `shown` picks, visible or not.
+    pub fn lit_leds_of(&self, shown: impl Fn(usize) -> bool) -> Vec<(usize, Color32)> {
+        self.leds_at_of(self.current_index, shown)
+    }
+
+    /// The LEDs lit once the first `index` moments of the timeline are
+    /// shown, as `lit_leds` would give after seeking there, worked out from
+    /// the datasets alone without moving playback. Cars are where their last
+    /// row due by then puts them, with no LED between rows.
+    pub fn leds_at(&self, index: usize) -> Vec<(usize, Color32)> {
+        self.leds_at_of(index, |dataset_idx| self.drivers[dataset_idx].visible)
+    }
+
+    /// `leds_at` for the cars of the datasets `shown` picks, visible or not.
+    /// At the moment playback shows, the cars are lit as playback left them,
+    /// which takes in a car between rows leading with the LED it is
+    /// interpolated to, and cars replayed from a recording.
+    pub fn leds_at_of(&self, index: usize, shown: impl Fn(usize) -> bool) -> Vec<(usize, Color32)> {
+        if index == self.current_index {
+            let trails = self.cars.iter().enumerate().filter(|&(dataset_idx, _)| shown(dataset_idx)).map(|(dataset_idx, car)| {
+                // An interpolated LED leads the car, its current LED joins the trail
+                let head = self.interpolated.get(dataset_idx).copied().flatten();
+                let leds = head.into_iter().chain(car.trail.iter().copied()).collect();
+                let times = head.map(|_| self.sim_elapsed_ms).into_iter().chain(car.trail_ms.iter().copied()).collect();
+                (dataset_idx, leds, times, car.rows)
+            });
+            return self.light(trails);
+        }
+        if index == 0 {
+            return Vec::new();
+        }
+        let rows = self.rows_at(index);
+        let trails = self.drivers.iter().map(|driver| &driver.records).zip(&self.offsets_ms).zip(rows).enumerate().filter(|&(dataset_idx, _)| shown(dataset_idx)).map(
+            |(dataset_idx, ((dataset, &offset_ms), rows))| {
+                // Back from the last row shown, one LED each time the car moved, up to where it left the map
+                let (mut trail, mut times): (Vec<usize>, Vec<u64>) = (Vec::new(), Vec::new());
+                for sample in (0..rows).rev().map_while(|row| dataset.get(row)) {
+                    let Some(led) = sample.on_led() else {
+                        break;
+                    };
+                    // Reached at the earliest row of a run on one LED
+                    if trail.last() == Some(&led) {
+                        *times.last_mut().unwrap() = at_ms(offset_ms, sample);
+                        continue;
+                    }
+                    if trail.len() > self.trail_length {
+                        break;
+                    }
+                    trail.push(led);
+                    times.push(at_ms(offset_ms, sample));
+                }
+                (dataset_idx, trail, times, rows)
+            },
+        );
+        self.light(trails)
+    }
+
+    /// How many rows of each dataset are shown once the first `index`
+    /// moments of the timeline are.
+    fn rows_at(&self, index: usize) -> Vec<usize> {
+        let Some(last) = index.min(self.timeline.len).checked_sub(1) else {
+            return vec![0; self.drivers.len()];
+        };
+        let mut rows = self.timeline.marks[last / TIMELINE_MARK_EVERY].rows.clone();
+        for _ in 0..=last % TIMELINE_MARK_EVERY {
+            self.next_moment(&mut rows);
+        }
+        rows
+    }
+
+    /// Colors the LEDs of each dataset's trail, given as its LEDs from the
+    /// car's own backwards, fading along it, with the simulated time the car
+    /// reached each and the rows of its dataset shown. Teammates on one LED
+    /// mix, and a car's fresher LED covers an older trail. A track flash then
+    /// lays white over every LED, lit or not, so each LED still appears once,
+    /// and the flash LED comes last, white over whatever is on it.
+    fn light(&self, trails: impl Iterator<Item = (usize, Vec<usize>, Vec<u64>, usize)>) -> Vec<(usize, Color32)> {
+        let mut by_led: BTreeMap<usize, (usize, Vec<Color32>)> = BTreeMap::new();
+        for (dataset_idx, leds, times, rows) in trails {
+            let color = self.drivers[dataset_idx].color;
+            // Teammates share a color, the trail can carry a second one to tell them apart
+            let trail_color = self.drivers[dataset_idx].trail_color.unwrap_or(color);
+            let mut dim = if self.spotlight.is_some_and(|spotlit| spotlit != dataset_idx) { SPOTLIGHT_DIM } else { 1.0 };
+            if self.stopped_at(dataset_idx, rows) {
+                dim *= STOPPED_DIM;
+            }
+            if self.throttle_car == Some(dataset_idx) {
+                if let Some(throttle) = self.telemetry_at(dataset_idx, rows).and_then(|telemetry| telemetry.throttle) {
+                    dim *= THROTTLE_DIM + (1.0 - THROTTLE_DIM) * throttle as f32 / 100.0;
+                }
+            }
+            let speeds = match self.trail_mode {
+                TrailMode::Fade => Vec::new(),
+                TrailMode::SpeedGradient => self.trail_speeds(&leds, &times),
+            };
+            let fastest = speeds.iter().copied().fold(0.0, f64::max);
+            for (age, &led) in leds.iter().enumerate().take(self.trail_length + 1).rev() {
+                let fade = 1.0 - age as f32 / (self.trail_length + 1) as f32;
+                let color = match speeds.get(age) {
+                    _ if age == 0 => color,
+                    Some(&speed) if fastest > 0.0 => slowed(trail_color, (speed / fastest) as f32),
+                    _ => trail_color,
+                };
+                let color = color.gamma_multiply(fade * dim);
+                let (freshest, colors) = by_led.entry(led).or_insert((age, Vec::new()));
+                if age < *freshest {
+                    (*freshest, *colors) = (age, vec![color]);
+                } else if age == *freshest {
+                    colors.push(color);
+                }
+            }
+        }
+        let mut by_age: Vec<_> = by_led.into_iter().map(|(led, (age, colors))| (age, led, render::mix(&colors))).collect();
+        by_age.sort_by_key(|&(age, led, _)| (std::cmp::Reverse(age), led));
+        let mut lit: Vec<(usize, Color32)> = by_age.into_iter().map(|(_, led, color)| (led, color)).collect();
+        if let Some(level) = self.track_flash.map(|level| level.clamp(0.0, 1.0)) {
+            let mut colors = vec![Color32::TRANSPARENT; self.coordinates.len()];
+            for (led, color) in lit {
+                colors[led] = color;
+            }
+            lit = colors.into_iter().enumerate().map(|(led, color)| (led, flashed(color, level))).collect();
+        }
+        let flash = self.flash_led.filter(|&led| led < self.coordinates.len()).map(|led| (led, Color32::WHITE));
+        lit.into_iter().map(|(led, color)| (led, self.correction.apply(color))).chain(flash).collect()
+    }
+
+    /// How fast the car went on from each LED of a trail to the one after
+    /// it, in coordinate units a millisecond, at the trail's LEDs and times
+    /// as `light` takes them. The current LED has none, so 0.
+    fn trail_speeds(&self, leds: &[usize], times: &[u64]) -> Vec<f64> {
+        let taken = leds.len().min(times.len()).min(self.trail_length + 1);
+        let mut speeds = vec![0.0; taken];
+        for age in 1..taken {
+            let (from, to) = (&self.coordinates[leds[age]], &self.coordinates[leds[age - 1]]);
+            let distance = (to.x_led - from.x_led).hypot(to.y_led - from.y_led);
+            speeds[age] = distance / times[age - 1].saturating_sub(times[age]).max(1) as f64;
+        }
+        speeds
+    }
+
+    /// Cars whose last row shown was too far from every LED to light one.
+    pub fn off_map_cars(&self) -> usize {
+        self.cars.iter().filter(|car| car.off_map).count()
+    }
+
+    /// The LEDs that more than one shown car is on, with those cars in
+    /// dataset order, for drawing a grid of stacked cars side by side.
+    pub fn stacked_cars(&self, shown: impl Fn(usize) -> bool) -> Vec<(usize, Vec<usize>)> {
+        let mut by_led: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (dataset_idx, car) in self.cars.iter().enumerate().filter(|&(dataset_idx, _)| shown(dataset_idx)) {
+            if let Some(&led) = car.trail.front() {
+                by_led.entry(led).or_default().push(dataset_idx);
+            }
+        }
+        by_led.into_iter().filter(|(_, cars)| cars.len() > 1).collect()
+    }
+
+    /// Final RGB of every LED as painted on screen, unlit LEDs black.
+    pub fn led_colors(&self) -> Vec<[u8; 3]> {
+        let mut colors = vec![[0; 3]; self.coordinates.len()];
+        for (led, color) in self.lit_leds() {
+            render::blend_over(&mut colors[led], color);
+        }
+        colors
+    }
+}
+
+/// `color` shifted towards `SLOW_TRAIL_COLOR` the lower `speed` is, 1
+/// being the fastest and keeping it as it is.
+fn slowed(color: Color32, speed: f32) -> Color32 {
+    let speed = speed.clamp(0.0, 1.0);
+    let channel = |fast: u8, slow: u8| (fast as f32 * speed + slow as f32 * (1.0 - speed)).round() as u8;
+    let ([r, g, b, _], [sr, sg, sb, _]) = (color.to_array(), SLOW_TRAIL_COLOR.to_array());
+    Color32::from_rgb(channel(r, sr), channel(g, sg), channel(b, sb))
+}
+
+/// `color` with white laid over it at `level`, both premultiplied.
+fn flashed(color: Color32, level: f32) -> Color32 {
+    let over = |channel: u8| (255.0 * level + channel as f32 * (1.0 - level)).round() as u8;
+    let [r, g, b, a] = color.to_array();
+    Color32::from_rgba_premultiplied(over(r), over(g), over(b), over(a))
+}
+
+/// Simulated time of a sample of a dataset whose origin is at `offset_ms`.
+fn at_ms(offset_ms: i64, sample: Sample) -> u64 {
+    (offset_ms + sample.t_ms as i64).max(0) as u64
+}
+
+/// Races made up for the tests of playback, here and in other modules.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+    use crate::data::RunRace;
+    use proptest::strategy::Strategy;
+    use proptest::test_runner::{Config, RngAlgorithm, TestCaseError, TestRng, TestRunner};
+
+    /// When the made-up rows are dated from.
+    pub fn start() -> DateTime<Utc> {
+        "2023-08-27T13:00:00Z".parse().unwrap()
+    }
+
+    /// `count` LEDs a unit apart along x.
+    pub fn leds_in_a_row(count: usize) -> Vec<LedCoordinate> {
+        (0..count).map(|x| LedCoordinate { x_led: x as f64, ..Default::default() }).collect()
+    }
+
+    /// A row dated `at_ms` after `start` at `(x_led, y_led)`, with a
+    /// `time_delta` of its own.
+    pub fn row(at_ms: i64, (x_led, y_led): (f64, f64), time_delta: u64) -> RunRace {
+        RunRace { date: start() + chrono::Duration::milliseconds(at_ms), x_led, y_led, time_delta, line: 0, telemetry: Default::default() }
+    }
+
+    /// A red car for each of `races` on `coordinates`, its rows matched to
+    /// the nearest LED within `snap_distance`.
+    pub fn simulation(coordinates: Vec<LedCoordinate>, snap_distance: Option<f64>, races: &[Vec<RunRace>]) -> Simulation {
+        let led_index = Arc::new(LedIndex::new(&coordinates).with_snap_distance(snap_distance));
+        let drivers = races.iter().map(|rows| Driver::new(Dataset::from_rows(rows, &led_index, 0), Color32::RED)).collect();
+        Simulation::new(coordinates, led_index, drivers)
+    }
+
+    /// Checks `property` on `cases` values of `strategy`, the same values
+    /// every run, panicking with the smallest failing one proptest shrinks it to.
+    pub fn check<S: Strategy>(cases: u32, strategy: S, property: impl Fn(S::Value) -> Result<(), TestCaseError>) {
+        let config = Config { cases, failure_persistence: None, ..Config::default() };
+        if let Err(e) = TestRunner::new_with_rng(config, TestRng::deterministic_rng(RngAlgorithm::ChaCha)).run(&strategy, property) {
+            panic!("{e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::RunRace;
+    use fixtures::row;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+    use std::cell::Cell;
+    use std::fs;
+    use std::sync::mpsc;
+
+    /// Races each property is checked on.
+    const CASES: u32 = 64;
+
+    /// `leds_at` worked out from the datasets alone, by a copy of `sim` back at the start.
+    fn from_data(sim: &Simulation, index: usize) -> Vec<(usize, Color32)> {
+        let mut copy = sim.clone();
+        copy.reset();
+        copy.leds_at(index)
+    }
+
+    /// A made-up car on ten LEDs in a row: starting up to 3 s late with up to
+    /// 40 rows at most 0.7 s apart, some dated the same as the row before.
+    fn car() -> impl Strategy<Value = Vec<RunRace>> {
+        (0..3000i64, vec((0..700u64, 0..10u8), 1..40)).prop_map(|(late_ms, steps)| {
+            let mut at_ms = late_ms;
+            steps
+                .into_iter()
+                .map(|(time_delta, led)| {
+                    at_ms += time_delta as i64;
+                    row(at_ms, (led as f64, 0.0), time_delta)
+                })
+                .collect()
+        })
+    }
+
+    /// A made-up race of up to five cars.
+    fn race() -> impl Strategy<Value = Vec<Vec<RunRace>>> {
+        vec(car(), 1..=5)
+    }
+
+    /// `race` played on its ten LEDs, each car its own color, laps counted at LED 0.
+    fn simulation_of(race: &[Vec<RunRace>]) -> Simulation {
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(10), None, race);
+        for (car, driver) in sim.drivers.iter_mut().enumerate() {
+            driver.color = Color32::from_rgb(50 * car as u8, 255 - 50 * car as u8, 100);
+        }
+        (sim.trail_length, sim.start_finish_led, sim.lap_debounce_leds) = (3, Some(0), 2);
+        sim
+    }
+
+    /// What each car shows and has counted, to compare two ways of getting
+    /// to one moment.
+    fn car_states(sim: &Simulation) -> Vec<(VecDeque<usize>, u32, u32, usize, bool)> {
+        sim.cars.iter().map(|car| (car.trail.clone(), car.laps.laps, car.laps.lap(), car.rows, car.off_map)).collect()
+    }
+
+    /// The simulated time of every moment of the timeline, in order.
+    fn moments(sim: &Simulation) -> Vec<u64> {
+        (0..sim.moments()).map(|index| sim.moment_ms(index).unwrap()).collect()
+    }
+
+    #[test]
+    fn the_timeline_has_every_moment_a_row_is_due_once_past_many_marks() {
+        // Rows often shared between cars and within one, so moments are fewer than rows
+        let cars = vec(vec((0..3i64, 0..10u8), TIMELINE_MARK_EVERY * 2), 3);
+        fixtures::check(4, cars, |cars| {
+            let race: Vec<Vec<RunRace>> = cars
+                .into_iter()
+                .map(|steps| {
+                    let mut at_ms = 0;
+                    steps
+                        .into_iter()
+                        .map(|(tenths, led)| {
+                            at_ms += tenths * 100;
+                            row(at_ms, (led as f64, 0.0), 0)
+                        })
+                        .collect()
+                })
+                .collect();
+            let mut sim = fixtures::simulation(fixtures::leds_in_a_row(10), None, &race);
+            let mut expected: Vec<u64> = (0..3).flat_map(|dataset_idx| (0..sim.drivers[dataset_idx].records.len()).map(move |row| (dataset_idx, row))).map(|(dataset_idx, row)| sim.row_ms(dataset_idx, row).unwrap()).collect();
+            expected.sort_unstable();
+            expected.dedup();
+            prop_assert!(expected.len() > TIMELINE_MARK_EVERY, "{} moments", expected.len());
+            prop_assert_eq!(moments(&sim), expected.clone());
+            prop_assert_eq!((sim.first_moment_ms(), sim.last_moment_ms()), (expected.first().copied(), expected.last().copied()));
+            for target_ms in (0..=expected.last().unwrap() + 100).step_by(50) {
+                prop_assert_eq!(sim.index_at_sim_ms(target_ms), expected.partition_point(|&due_ms| due_ms <= target_ms), "index at {} ms", target_ms);
+            }
+            prop_assert_eq!(sim.step_ms_at(expected.len()), None);
+            prop_assert_eq!(sim.step_ms_at(expected.len() + 5), None, "no panic past the end");
+            let mut played = Vec::new();
+            while sim.advance() {
+                played.push(sim.sim_elapsed_ms);
+            }
+            prop_assert_eq!(played, expected);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn replacing_a_dataset_plays_only_that_car_again() {
+        let in_place = Cell::new(0);
+        fixtures::check(CASES, (race(), any::<Index>(), any::<Index>(), car()), |(race, replaced, seek, replacement)| {
+            let mut sim = simulation_of(&race);
+            let dataset_idx = replaced.index(sim.drivers.len());
+            sim.seek(seek.index(sim.moments() + 1));
+            let replacement = fixtures::simulation(fixtures::leds_in_a_row(10), None, &[replacement]).drivers[0].records.clone();
+            let (session_start, sim_ms, rows) = (sim.session_start(), sim.sim_elapsed_ms, sim.rows_applied);
+            sim.replace_dataset(dataset_idx, replacement.clone());
+            if sim.session_start() == session_start {
+                in_place.set(in_place.get() + 1);
+                prop_assert!(sim.rows_applied - rows <= replacement.len() as u64, "only the new rows");
+            }
+
+            let mut replayed = simulation_of(&race);
+            replayed.drivers[dataset_idx].records = replacement;
+            replayed.align();
+            replayed.seek(replayed.index_at_sim_ms(sim_ms));
+            prop_assert_eq!((sim.current_index, sim.sim_elapsed_ms), (replayed.current_index, replayed.sim_elapsed_ms));
+            prop_assert_eq!(car_states(&sim), car_states(&replayed));
+            let ends = |sim: &Simulation| -> Vec<_> { sim.cars.iter().map(|car| (car.ended_at_ms, car.progress.distance)).collect() };
+            prop_assert_eq!(ends(&sim), ends(&replayed));
+            prop_assert_eq!(sim.lit_leds(), replayed.lit_leds());
+            Ok(())
+        });
+        assert!(in_place.get() > CASES / 4, "{} replaced in place", in_place.get());
+    }
+
+    #[test]
+    fn lap_starts_are_when_the_leader_lap_goes_up_playing_through() {
+        let laps = Cell::new(0);
+        fixtures::check(CASES, race(), |race| {
+            let mut sim = simulation_of(&race);
+            let found = sim.lap_starts_job().unwrap()();
+            let mut played = Vec::new();
+            while sim.advance() {
+                while (played.len() as u32) < sim.leader_lap() {
+                    played.push(sim.sim_elapsed_ms);
+                }
+            }
+            laps.set(laps.get() + found.len());
+            prop_assert_eq!(found, played);
+            Ok(())
+        });
+        assert!(laps.get() > CASES as usize, "{} lap starts", laps.get());
+        let mut sim = simulation_of(&[vec![row(0, (0.0, 0.0), 0)]]);
+        sim.start_finish_led = None;
+        assert!(sim.lap_starts_job().is_none());
+    }
+
+    #[test]
+    fn advance_to_shows_exactly_the_rows_due_by_then() {
+        // Forward and back, so replaying from the start is checked too
+        fixtures::check(CASES, (race(), vec(any::<Index>(), 10)), |(race, targets)| {
+            let mut sim = simulation_of(&race);
+            let end_ms = sim.last_moment_ms().unwrap();
+            for target in targets {
+                let t_ms = target.index(end_ms as usize + 501) as u64;
+                sim.advance_to(t_ms);
+                prop_assert!(sim.sim_elapsed_ms <= t_ms, "at {} ms for {} ms", sim.sim_elapsed_ms, t_ms);
+                for (dataset_idx, car) in sim.cars.iter().enumerate() {
+                    let due = (0..sim.drivers[dataset_idx].records.len()).filter(|&row| sim.row_ms(dataset_idx, row).unwrap() <= t_ms).count();
+                    prop_assert_eq!(car.rows, due, "rows of dataset {} shown by {} ms", dataset_idx, t_ms);
+                }
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn playing_moves_every_car_through_its_rows_in_order() {
+        fixtures::check(CASES, race(), |race| {
+            let mut sim = simulation_of(&race);
+            let (sender, receiver) = mpsc::channel();
+            sim.send_transitions(Some(sender));
+            while sim.advance() {}
+            let transitions: Vec<Transition> = receiver.try_iter().collect();
+            prop_assert!(transitions.windows(2).all(|pair| pair[0].sim_ms <= pair[1].sim_ms), "moves out of time order");
+            for (dataset_idx, data) in sim.drivers.iter().map(|driver| &driver.records).enumerate() {
+                let mut leds: Vec<usize> = (0..data.len()).map(|row| data.get(row).unwrap().led as usize).collect();
+                leds.dedup();
+                let moves: Vec<usize> = transitions.iter().filter(|move_| move_.dataset_idx == dataset_idx).filter_map(|move_| move_.led).collect();
+                prop_assert_eq!(moves, leds, "moves of dataset {}", dataset_idx);
+                prop_assert_eq!(sim.cars[dataset_idx].rows, data.len(), "every car plays to its end, not only the first");
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn seeking_anywhere_shows_what_stepping_there_does() {
+        fixtures::check(CASES, (race(), any::<bool>(), vec(any::<Index>(), 1..16)), |(race, checkpoints, origins)| {
+            let mut stepped = simulation_of(&race);
+            let mut sought = simulation_of(&race);
+            sought.set_checkpoint_interval(checkpoints.then_some(1000));
+            let moments = stepped.moments();
+            for (index, origin) in (0..=moments).zip(origins.iter().cycle()) {
+                if index > 0 {
+                    prop_assert!(stepped.advance());
+                }
+                // From wherever the last seek left it, behind or ahead
+                sought.seek(origin.index(moments + 1));
+                sought.seek(index);
+                prop_assert_eq!(sought.current_index, index);
+                prop_assert_eq!(car_states(&sought), car_states(&stepped), "cars at index {}", index);
+                prop_assert_eq!(sought.lit_leds(), stepped.lit_leds(), "LEDs at index {}", index);
+            }
+            prop_assert!(!stepped.advance() && stepped.is_finished());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn fixtures_with_broken_rows_play_what_they_can() {
+        let dir = Path::new("testdata/playback");
+        let paths = loader::scan_datasets(dir).unwrap();
+        let coordinates = data::read_coordinates(dir.join("led_coords.csv"), None).unwrap().records;
+        // Further than this from every LED is off the map
+        let led_index = Arc::new(LedIndex::new(&coordinates).with_snap_distance(Some(2.0)));
+        let race = loader::load_datasets(&paths, &led_index, loader::ReadOptions::default(), Palette::default(), &BTreeMap::new());
+        let keys = race.keys();
+        assert_eq!(keys, ["nodate", "nodelay", "offtrack", "unordered"]);
+        let issues: Vec<String> = race.issues.iter().map(|issue| issue.to_string()).collect();
+        assert_eq!(issues.len(), 2, "{issues:?}");
+        assert!(issues[0].starts_with("testdata/playback/time_delta_nodate_start.csv line 3: unrecognised date"), "{}", issues[0]);
+        assert_eq!(issues[1], "testdata/playback/time_delta_unordered_start.csv: moved 1 row into date order");
+
+        let mut sim = Simulation::new(coordinates, led_index, race.drivers);
+        let (sender, receiver) = mpsc::channel();
+        sim.send_transitions(Some(sender));
+        assert_eq!(moments(&sim), [0, 500, 1000, 1500]);
+        sim.advance_to(500);
+        assert!(sim.cars[2].off_map, "the row far from the track lights nothing");
+        sim.advance_to(1500);
+        let transitions: Vec<Transition> = receiver.try_iter().collect();
+        let moves: Vec<Vec<(u64, Option<usize>)>> =
+            (0..4).map(|dataset_idx| transitions.iter().filter(|move_| move_.dataset_idx == dataset_idx).map(|move_| (move_.sim_ms, move_.led)).collect()).collect();
+        assert_eq!(moves[0], [(0, Some(0)), (1000, Some(2))], "the undated row is left out");
+        assert_eq!(moves[1], [(0, Some(0)), (500, Some(1)), (1000, Some(2))], "delays come from the dates");
+        assert_eq!(moves[2], [(0, Some(0)), (500, None), (1000, Some(3))]);
+        assert_eq!(moves[3], [(0, Some(0)), (500, Some(1)), (1000, Some(2)), (1500, Some(3))], "played in date order");
+        assert!(sim.is_finished());
+    }
+
+    #[test]
+    fn advance_to_lights_the_leds_due_by_then_and_replays_going_back() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-sim", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let coordinates = dir.join("led_coords.csv");
+        fs::write(&coordinates, "x_led,y_led\n0,0\n1,0\n2,0\n").unwrap();
+        let dataset = dir.join("time_delta_albon.csv");
+        fs::write(
+            &dataset,
+            "date,x_led,y_led,time_delta\n\
+             2023-08-27T12:00:00.100Z,0,0,100\n\
+             2023-08-27T12:00:00.350Z,1,0,250\n\
+             2023-08-27T12:00:00.500Z,2,0,150\n\
+             2023-08-27T12:00:00.600Z,2,0,100\n\
+             2023-08-27T12:00:00.700Z,1,0,100\n",
+        )
+        .unwrap();
+        let (mut sim, issues) = Simulation::load(&coordinates, &[dataset, dir.join("time_delta_gone.csv")]).unwrap();
+        assert_eq!(issues.len(), 1, "the missing file is reported");
+        let color = sim.drivers[0].color;
+        let lit = |sim: &Simulation| sim.led_states().iter().position(|&state| state != Color32::BLACK);
+
+        sim.advance_to(99);
+        assert_eq!((sim.current_index, lit(&sim)), (0, None));
+        sim.advance_to(349);
+        assert_eq!((sim.current_index, lit(&sim)), (1, Some(0)));
+        assert_eq!(sim.led_states()[0], color);
+        sim.advance_to(10_000);
+        assert_eq!((sim.current_index, lit(&sim), sim.is_finished()), (5, Some(1), true));
+        assert_eq!(sim.arrivals(1), [(0, 2, 350), (0, 5, 700)]);
+        assert_eq!(sim.arrivals(2), [(0, 3, 500)], "staying on the LED is one arrival");
+
+        sim.advance_to(350);
+        assert_eq!((sim.current_index, sim.sim_elapsed_ms, lit(&sim)), (2, 350, Some(1)));
+        sim.drivers[0].visible = false;
+        sim.advance_to(350);
+        assert_eq!(lit(&sim), None);
+    }
+
+    #[test]
+    fn the_last_step_rows_are_the_ones_due_at_the_current_moment() {
+        let rows = |seconds: &[i64]| -> Vec<_> { seconds.iter().map(|&s| row(s * 1000, (0.0, 0.0), 1000)).collect() };
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(4), None, &[rows(&[0, 1, 3]), rows(&[0, 2, 3])]);
+        assert!(sim.last_step_rows().is_empty());
+        sim.advance();
+        assert_eq!(sim.last_step_rows(), [(0, 0), (1, 0)]);
+        sim.advance();
+        assert_eq!(sim.last_step_rows(), [(0, 1)]);
+        sim.advance();
+        assert_eq!(sim.last_step_rows(), [(1, 1)]);
+    }
+
+    #[test]
+    fn seeking_shows_the_same_trails_and_laps_as_playing() {
+        let rows: Vec<_> = [0, 1, 2, 3, 0, 1, 1, 2].iter().enumerate().map(|(i, &x)| row(i as i64 * 1000, (x as f64, 0.0), 1000)).collect();
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(4), None, &[rows]);
+        (sim.trail_length, sim.start_finish_led, sim.lap_debounce_leds) = (2, Some(0), 2);
+
+        let mut played = Vec::new();
+        while sim.advance() {
+            let car = &sim.cars[0];
+            played.push((car.trail.clone(), car.laps.laps, sim.lit_leds()));
+        }
+        assert_eq!(played[6].0, [1, 0, 3, 2, 1, 0], "staying on an LED keeps the trail");
+        assert!(played[4].1 > played[3].1, "crossing the line again counts a lap");
+        for (index, (trail, laps, lit)) in played.iter().enumerate().rev() {
+            sim.seek(index + 1);
+            let car = &sim.cars[0];
+            assert_eq!((&car.trail, car.laps.laps, &sim.lit_leds()), (trail, *laps, lit), "seeking to {}", index + 1);
+        }
+        sim.reset();
+        assert_eq!((sim.current_index, sim.cars[0].trail.len(), sim.lit_leds().len()), (0, 0, 0));
+    }
+
+    #[test]
+    fn a_car_beyond_the_snap_distance_is_off_the_map_with_nothing_lit() {
+        let mut coordinates = fixtures::leds_in_a_row(4);
+        coordinates[2].class = LedClass::Pitlane;
+        let rows: Vec<_> = [(0.0, 0.0), (1.0, 0.2), (1.0, 5.0), (2.0, 0.0)].into_iter().enumerate().map(|(i, at)| row(i as i64 * 1000, at, 1000)).collect();
+        let mut sim = fixtures::simulation(coordinates, Some(0.5), &[rows]);
+        sim.trail_length = 2;
+        sim.seek(3);
+        assert_eq!(sim.drivers[0].records.get(2).map(|sample| sample.on_led()), Some(None));
+        assert!(sim.cars[0].off_map);
+        assert!(sim.lit_leds().is_empty(), "no stale LED or trail is left lit");
+        assert!(!sim.in_pit_lane(0));
+        sim.advance();
+        assert_eq!((sim.cars[0].off_map, sim.cars[0].trail.front()), (false, Some(&2)));
+        assert!(sim.in_pit_lane(0));
+    }
+
+    #[test]
+    fn playing_sends_every_move_and_seeking_none() {
+        let rows: Vec<_> = [(0.0, 0.0), (0.0, 0.1), (1.0, 0.0), (1.0, 5.0), (2.0, 0.0)].into_iter().enumerate().map(|(i, at)| row(i as i64 * 1000, at, 1000)).collect();
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(4), Some(0.5), &[rows]);
+        let (sender, transitions) = std::sync::mpsc::channel();
+        sim.send_transitions(Some(sender));
+        while sim.advance() {}
+        let sent: Vec<_> = transitions.try_iter().map(|transition| (transition.sim_ms, transition.led)).collect();
+        assert_eq!(sent, [(1000, Some(0)), (3000, Some(1)), (4000, None), (5000, Some(2))], "staying on LED 0 sends nothing");
+        sim.seek(1);
+        sim.seek(5);
+        assert_eq!(transitions.try_iter().count(), 0);
+        sim.seek(3);
+        sim.advance();
+        assert_eq!(transitions.try_iter().map(|transition| transition.led).collect::<Vec<_>>(), [None]);
+    }
+
+    #[test]
+    fn cars_on_one_coordinate_are_all_lit_and_fan_out_around_it() {
+        let grid = |x_led: f64| vec![row(0, (x_led, 0.0), 1000)];
+        let colors = vec![Color32::RED, Color32::GREEN, Color32::BLUE, Color32::WHITE];
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(3), None, &[grid(1.0), grid(1.0), grid(1.0), grid(2.0)]);
+        for (driver, &color) in sim.drivers.iter_mut().zip(&colors) {
+            driver.color = color;
+        }
+        sim.advance();
+        assert_eq!(sim.lit_leds(), [(1, render::mix(&colors[..3])), (2, Color32::WHITE)], "no car on the shared LED is lost");
+        assert_eq!(sim.stacked_cars(|_| true), [(1, vec![0, 1, 2])]);
+        assert!(sim.stacked_cars(|dataset_idx| dataset_idx != 1 && dataset_idx != 2).is_empty());
+
+        let offsets = render::stack_offsets(3, 6.0);
+        assert_eq!(offsets[0], eframe::egui::vec2(0.0, -6.0), "the first car is drawn straight above");
+        assert!(offsets.iter().all(|offset| (offset.length() - 6.0).abs() < 1e-4));
+        assert!((offsets[1] - offsets[2]).length() > 6.0, "each car has its own spot");
+        assert_eq!(render::stack_offsets(1, 6.0), [eframe::egui::Vec2::ZERO]);
+    }
+
+    #[test]
+    fn a_speed_gradient_trail_shifts_where_the_car_slowed_and_seeks_the_same() {
+        // A LED every 100 ms, but a whole second from LED 2 to LED 3
+        let mut at_ms = 0;
+        let rows: Vec<_> = [(0, 0), (1, 100), (2, 100), (3, 1000), (4, 100)]
+            .into_iter()
+            .map(|(x, time_delta)| {
+                at_ms += time_delta as i64;
+                row(at_ms, (x as f64, 0.0), time_delta)
+            })
+            .collect();
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(5), None, &[rows]);
+        sim.drivers[0].color = Color32::BLUE;
+        sim.trail_length = 4;
+        sim.seek(sim.moments());
+        let faded = sim.lit_leds();
+
+        sim.trail_mode = TrailMode::SpeedGradient;
+        let graded = sim.lit_leds();
+        assert_eq!(graded.iter().map(|&(led, _)| led).collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+        for led in [0, 1, 3, 4] {
+            assert_eq!(graded[led], faded[led], "as fast as the fastest from LED {led}");
+        }
+        let [r, _, b, _] = graded[2].1.to_array();
+        assert!(r > b, "slowed from LED 2: {:?}", graded[2].1);
+        assert_eq!(from_data(&sim, sim.current_index), graded);
+        assert_eq!(slowed(Color32::BLUE, 0.0), SLOW_TRAIL_COLOR);
+    }
+
+    #[test]
+    fn a_track_flash_lights_every_led_over_the_cars_and_leaves_them_as_they_were() {
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(3), None, &[vec![row(0, (1.0, 0.0), 0)]]);
+        sim.advance();
+        let before = sim.lit_leds();
+        sim.track_flash = Some(1.0);
+        assert_eq!(sim.lit_leds(), [(0, Color32::WHITE), (1, Color32::WHITE), (2, Color32::WHITE)]);
+        sim.track_flash = Some(0.5);
+        let half = sim.lit_leds();
+        assert_eq!((half[0].1, half[1].1), (Color32::from_rgba_premultiplied(128, 128, 128, 128), Color32::from_rgb(255, 128, 128)));
+        sim.track_flash = None;
+        assert_eq!(sim.lit_leds(), before);
+    }
+
+    #[test]
+    fn the_throttle_car_is_as_bright_as_its_throttle_is_open() {
+        let rows: Vec<_> = [Some(100.0), Some(0.0), None]
+            .iter()
+            .enumerate()
+            .map(|(i, &throttle)| RunRace { telemetry: Telemetry { throttle, ..Default::default() }, ..row(i as i64 * 1000, (i as f64, 0.0), 1000) })
+            .collect();
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(3), None, &[rows]);
+        sim.throttle_car = Some(0);
+        sim.advance();
+        assert_eq!((sim.telemetry(0).unwrap().throttle, sim.lit_leds()), (Some(100.0), vec![(0, Color32::RED)]));
+        sim.advance();
+        assert_eq!(sim.lit_leds(), [(1, Color32::RED.gamma_multiply(THROTTLE_DIM))]);
+        assert_eq!(sim.lit_leds(), from_data(&sim, sim.current_index));
+        sim.advance();
+        assert_eq!(sim.lit_leds(), [(2, Color32::RED)], "a row without a throttle is not dimmed");
+    }
+
+    #[test]
+    fn a_car_on_one_led_for_long_enough_is_stopped_and_dimmed_until_it_moves() {
+        let rows: Vec<_> = [0.0, 1.0, 1.1, 0.9, 2.0].iter().enumerate().map(|(i, &x_led)| row(i as i64 * 1000, (x_led, 0.0), 1000)).collect();
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(3), None, &[rows]);
+        let mut stopped = Vec::new();
+        while sim.advance() {
+            stopped.push(sim.is_stopped(0));
+        }
+        assert_eq!(stopped, [false; 5], "never while `stopped_after` is 0");
+
+        sim.stopped_after = 3;
+        let mut stopped = Vec::new();
+        for index in 1..=5 {
+            sim.seek(index);
+            stopped.push(sim.is_stopped(0));
+        }
+        assert_eq!(stopped, [false, false, false, true, false]);
+        sim.seek(4);
+        assert_eq!(sim.lit_leds(), [(1, Color32::RED.gamma_multiply(STOPPED_DIM))]);
+        assert_eq!(sim.lit_leds(), from_data(&sim, 4));
+    }
+
+    #[test]
+    fn seeking_from_a_checkpoint_replays_a_fraction_of_the_rows_for_the_same_state() {
+        // An hour of 20 cars lapping 50 LEDs, a row a second each
+        let race: Vec<Vec<_>> = (0..20).map(|car| (0..3600).map(|s| row(s as i64 * 1000, (((s * (car + 1) / 4) % 50) as f64, 0.0), 1000)).collect()).collect();
+        let session = || {
+            let mut sim = fixtures::simulation(fixtures::leds_in_a_row(50), None, &race);
+            (sim.start_finish_led, sim.trail_length) = (Some(0), 10);
+            sim
+        };
+        let state = |sim: &Simulation| -> Vec<_> {
+            let cars = sim.cars.iter().map(|car| (car.trail.clone(), car.rows, car.laps.laps, car.progress.distance, car.progress.history_len(), car.ended_at_ms));
+            cars.collect()
+        };
+        let (mut from_scratch, mut checkpointed) = (session(), session());
+        checkpointed.set_checkpoint_interval(Some(DEFAULT_CHECKPOINT_INTERVAL_MS));
+        let end = from_scratch.moments();
+        checkpointed.seek(end / 2); // Makes the checkpoints
+
+        for index in [end, end / 3, 1, 0] {
+            let rows = (from_scratch.rows_applied, checkpointed.rows_applied);
+            from_scratch.seek(index);
+            checkpointed.seek(index);
+            let replayed = (from_scratch.rows_applied - rows.0, checkpointed.rows_applied - rows.1);
+            assert!(replayed.1 <= 60 * 20, "{index}: at most a minute of rows, not {replayed:?}");
+            if index == end {
+                assert_eq!(replayed.0, 20 * 3600, "the whole race from scratch");
+            }
+            assert_eq!((checkpointed.current_index, checkpointed.sim_elapsed_ms), (from_scratch.current_index, from_scratch.sim_elapsed_ms));
+            assert_eq!(state(&checkpointed), state(&from_scratch), "{index}");
+            assert_eq!(checkpointed.lit_leds(), from_scratch.lit_leds());
+        }
+
+        checkpointed.start_finish_led = Some(25);
+        from_scratch.start_finish_led = Some(25);
+        checkpointed.seek(end);
+        from_scratch.seek(end);
+        assert_eq!(state(&checkpointed), state(&from_scratch), "checkpoints follow the start/finish LED");
+
+        // Stepping back with the gap chart shown, as reverse playback does
+        checkpointed.track_gaps(true);
+        from_scratch.track_gaps(true);
+        for index in [end / 2, end / 2 - 1, end / 2 - 2] {
+            let rows = checkpointed.rows_applied;
+            checkpointed.seek(index);
+            from_scratch.seek(index);
+            assert!(checkpointed.rows_applied - rows <= 60 * 20, "{index}: from a checkpoint with the gaps too");
+            assert_eq!(state(&checkpointed), state(&from_scratch), "{index}");
+            let series = |sim: &Simulation| -> Vec<_> { (0..20).map(|car| sim.gap_history().unwrap().series(car).to_vec()).collect() };
+            assert_eq!(series(&checkpointed), series(&from_scratch), "{index}");
+        }
+    }
+
+    #[test]
+    fn leds_at_any_index_match_what_seeking_there_lights() {
+        let rows = |positions: &[(f64, f64)]| -> Vec<_> { positions.iter().enumerate().map(|(i, &at)| row(i as i64 * 1000, at, 1000)).collect() };
+        let race = [rows(&[(0.0, 0.0), (1.0, 0.0), (1.0, 0.1), (1.0, 5.0), (3.0, 0.0)]), rows(&[(0.0, 0.0), (1.0, 0.0)])];
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(5), Some(0.5), &race);
+        sim.drivers[1].color = Color32::BLUE;
+        sim.trail_length = 1;
+        let both = render::mix(&[Color32::RED, Color32::BLUE]);
+        let faded = |color: Color32| color.gamma_multiply(0.5);
+
+        assert!(sim.leds_at(0).is_empty());
+        assert_eq!(sim.leds_at(1), [(0, both)], "two cars on one LED mix");
+        assert_eq!(sim.leds_at(3), [(0, render::mix(&[faded(Color32::RED), faded(Color32::BLUE)])), (1, both)]);
+        assert_eq!(sim.leds_at(4), [(0, faded(Color32::BLUE)), (1, Color32::BLUE)], "the first car is off the map, the second stays where it ended");
+        assert_eq!(sim.leds_at(5), [(0, faded(Color32::BLUE)), (1, Color32::BLUE), (3, Color32::RED)]);
+        assert_eq!(sim.current_index, 0, "playback did not move");
+
+        for visible in [true, false] {
+            sim.drivers[1].visible = visible;
+            for index in 0..=6 {
+                sim.seek(index);
+                assert_eq!(from_data(&sim, index), sim.lit_leds(), "at index {index}");
+                assert_eq!(sim.leds_at(index), sim.lit_leds(), "at index {index}, as playback lit it");
+            }
+        }
+    }
+
+    #[test]
+    fn the_demo_race_loads_and_laps_the_oval() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-demo", std::process::id()));
+        let coordinates = data::demo().write(&dir).unwrap();
+        let datasets = loader::scan_datasets(&dir).unwrap();
+        let (mut sim, issues) = Simulation::load(&coordinates, &datasets).unwrap();
+        assert!(issues.is_empty(), "{issues:?}");
+        assert_eq!((sim.coordinates.len(), sim.drivers.len()), (data::DEMO_LEDS, 4));
+
+        sim.start_finish_led = Some(data::DEMO_START_FINISH_LED);
+        sim.advance_to(60_000);
+        assert!(sim.is_finished());
+        let laps: Vec<u32> = sim.cars.iter().map(|car| car.laps.lap()).collect();
+        assert_eq!(laps, [4, 3, 3, 3], "only the fastest car finishes a third lap in the minute");
+        let mut leds: Vec<usize> = sim.cars.iter().filter_map(|car| car.trail.front().copied()).collect();
+        leds.dedup();
+        assert_eq!(leds.len(), 4, "the cars spread out");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gaps_fill_in_when_turned_on_and_clear_on_reset() {
+        // The second car is on each LED three seconds after the first
+        let rows = |from_s: i64| -> Vec<_> { (0..4).map(|i| row((from_s + i * 5) * 1000, (i as f64, 0.0), 5000)).collect() };
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(4), None, &[rows(0), rows(3)]);
+        sim.seek(4);
+        assert!(sim.gap_history().is_none());
+        sim.track_gaps(true);
+        assert_eq!(sim.current_index, 4);
+        assert_eq!(sim.gap_history().unwrap().series(1), [[8.0, 3.0], [13.0, 3.0]], "the session starts a row before the first");
+        while sim.advance() {}
+        assert_eq!(sim.gap_history().unwrap().series(1).len(), 4);
+        assert!(sim.gap_history().unwrap().series(0).iter().all(|&[_, gap]| gap == 0.0));
+
+        sim.reset();
+        assert!(sim.gap_history().unwrap().series(1).is_empty());
+        sim.track_gaps(false);
+        assert!(sim.gap_history().is_none());
+    }
+
+    #[test]
+    fn datasets_line_up_by_date_and_by_their_offsets() {
+        // One file starts two seconds before the other, both with a row every second
+        let rows = |from_s: i64, x_led: f64| -> Vec<_> { (0..3).map(|i| row((from_s + i) * 1000, (x_led, 0.0), 1000)).collect() };
+        let mut sim = fixtures::simulation(fixtures::leds_in_a_row(4), None, &[rows(0, 0.0), rows(2, 1.0)]);
+        let start = fixtures::start();
+        assert_eq!(sim.session_start(), start - chrono::Duration::seconds(1));
+        assert_eq!((sim.offset_ms(0), sim.offset_ms(1)), (0, 2000));
+        assert_eq!(moments(&sim), [1000, 2000, 3000, 4000, 5000]);
+        sim.advance_to(3000);
+        assert_eq!((sim.cars[0].rows, sim.cars[1].rows), (3, 1), "rows of the same date are shown together");
+
+        sim.set_alignment(Some(start), vec![0, -2000]);
+        assert_eq!(moments(&sim), [0, 1000, 2000]);
+        sim.advance_to(0);
+        assert_eq!((sim.cars[0].rows, sim.cars[1].rows), (1, 1));
+        assert_eq!(sim.date_at(sim.sim_elapsed_ms), start);
+    }
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use eframe::egui::Color32;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clock::SimulationClock;
+
+/// The playback state at one moment, written to attach to a bug report.
+/// Unlike a session it is a record of what the app was doing, not meant to
+/// be read back.
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    pub written_at: DateTime<Utc>,
+    pub coordinates: PathBuf,
+    pub data_dir: PathBuf,
+    pub race: Option<String>,
+    pub leds: usize,
+    pub current_index: usize, // Moments of the timeline shown
+    pub moments: usize, // Moments in the whole timeline
+    pub sim_elapsed_ms: u64,
+    pub session_start: DateTime<Utc>,
+    pub clock_ms: f64, // Simulated time the clock reads, which runs on between rows
+    pub clock: SimulationClock,
+    pub speed: f64,
+    pub reverse: bool,
+    pub looping: bool,
+    pub race_started: bool,
+    pub paused: bool,
+    pub race_complete: bool,
+    pub cars: Vec<CarSnapshot>,
+    pub lit_leds: Vec<LitLed>, // As drawn, from playback
+    pub leds_at: Vec<LitLed>, // Worked out from the datasets alone, the same unless playback went wrong
+}
+
+/// One car of a `Snapshot`.
+#[derive(Debug, Serialize)]
+pub struct CarSnapshot {
+    pub key: String,
+    pub name: String,
+    pub visible: bool,
+    pub rows: usize, // Rows of the dataset shown so far
+    pub of_rows: usize,
+    pub trail: Vec<usize>, // The current LED first
+    pub interpolated: Option<usize>,
+    pub off_map: bool,
+    pub lap: u32,
+    pub ended_at_ms: Option<u64>,
+    pub offset_ms: i64, // Simulated time of the dataset's origin
+    pub manual_offset_ms: i64,
+}
+
+/// A lit LED and its color, premultiplied RGBA as it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LitLed {
+    pub led: usize,
+    pub rgba: [u8; 4],
+}
+
+impl LitLed {
+    pub fn all(lit: &[(usize, Color32)]) -> Vec<Self> {
+        lit.iter().map(|&(led, color)| Self { led, rgba: color.to_array() }).collect()
+    }
+}
+
+impl Snapshot {
+    /// Writes the snapshot as JSON to a file in `dir` named after when it was
+    /// taken, and returns its path.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let path = dir.join(format!("state-{}.json", self.written_at.format("%Y%m%d-%H%M%S%.3f")));
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(&path, text).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,31 @@
+use rodio::source::{SineWave, Source};
+use rodio::{OutputStream, OutputStreamHandle};
+use std::time::Duration;
+
+/// Pitch, length and loudness of the cue tone, short enough not to run
+/// into the next cue.
+const TONE_HZ: f32 = 880.0;
+const TONE: Duration = Duration::from_millis(150);
+const VOLUME: f32 = 0.2;
+
+/// The default audio output, open for as long as this is kept.
+pub struct Chime {
+    _stream: OutputStream, // The sound stops when it is dropped
+    handle: OutputStreamHandle,
+}
+
+impl Chime {
+    /// Opens the default audio output, failing when there is none.
+    pub fn open() -> Result<Self, String> {
+        let (stream, handle) = OutputStream::try_default().map_err(|e| format!("no audio output: {e}"))?;
+        Ok(Self { _stream: stream, handle })
+    }
+
+    /// Starts the cue tone without waiting for it to end.
+    pub fn play(&self) {
+        let tone = SineWave::new(TONE_HZ).take_duration(TONE).amplify(VOLUME);
+        if let Err(e) = self.handle.play_raw(tone) {
+            eprintln!("warning: cannot play the cue tone: {e}");
+        }
+    }
+}
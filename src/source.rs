@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Where the coordinates and race data files are read from: the
+/// filesystem in the native build, files fetched over HTTP in the browser.
+pub trait FileSource: Send + Sync {
+    /// Opens `path` to be read from `byte` on.
+    fn open_at(&self, path: &Path, byte: u64) -> io::Result<Box<dyn Read + Send>>;
+    /// The files directly in `dir`.
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+    /// The folders directly in `dir`.
+    fn dirs(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+}
+
+static SOURCE: OnceLock<WithAdded> = OnceLock::new();
+
+/// Reads the data files from `source` from now on. Only works before the
+/// first file is read, returns `false` if one already was.
+pub fn set(source: impl FileSource + 'static) -> bool {
+    SOURCE.set(WithAdded::new(source)).is_ok()
+}
+
+/// The source data files are read from, the filesystem unless `set` said
+/// otherwise, with the files `add` gave it.
+pub fn current() -> &'static dyn FileSource {
+    SOURCE.get_or_init(|| WithAdded::new(DiskFiles))
+}
+
+/// Adds a file at `path` to the current source, over one it has there, such
+/// as a file dropped on the page in the browser.
+pub fn add(path: impl AsRef<Path>, contents: impl Into<Arc<[u8]>>) {
+    let source = SOURCE.get_or_init(|| WithAdded::new(DiskFiles));
+    source.added.write().unwrap().insert(path, contents);
+}
+
+/// A source with files added to it while running, read ahead of its own.
+struct WithAdded {
+    source: Box<dyn FileSource>,
+    added: RwLock<MemoryFiles>,
+}
+
+impl WithAdded {
+    fn new(source: impl FileSource + 'static) -> Self {
+        Self { source: Box::new(source), added: RwLock::default() }
+    }
+
+    /// The entries of `dir` in the source and the added files, one of them
+    /// failing only if both do.
+    fn merge(theirs: io::Result<Vec<PathBuf>>, added: io::Result<Vec<PathBuf>>) -> io::Result<Vec<PathBuf>> {
+        let mut entries = match (theirs, added) {
+            (Err(e), Err(_)) => return Err(e),
+            (theirs, added) => [theirs.unwrap_or_default(), added.unwrap_or_default()].concat(),
+        };
+        entries.sort();
+        entries.dedup();
+        Ok(entries)
+    }
+}
+
+impl FileSource for WithAdded {
+    fn open_at(&self, path: &Path, byte: u64) -> io::Result<Box<dyn Read + Send>> {
+        let added = self.added.read().unwrap();
+        if added.files.contains_key(&normalize(path)) {
+            return added.open_at(path, byte);
+        }
+        self.source.open_at(path, byte)
+    }
+
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Self::merge(self.source.files(dir), self.added.read().unwrap().files(dir))
+    }
+
+    fn dirs(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Self::merge(self.source.dirs(dir), self.added.read().unwrap().dirs(dir))
+    }
+}
+
+/// Reads files straight from disk.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiskFiles;
+
+impl FileSource for DiskFiles {
+    fn open_at(&self, path: &Path, byte: u64) -> io::Result<Box<dyn Read + Send>> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(byte))?;
+        Ok(Box::new(file))
+    }
+
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_file()).collect())
+    }
+
+    fn dirs(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_dir()).collect())
+    }
+}
+
+/// Files held in memory under relative paths, such as the ones `fetch`
+/// downloads. Folders are only there as part of a file's path.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFiles {
+    files: BTreeMap<PathBuf, Arc<[u8]>>,
+}
+
+impl MemoryFiles {
+    pub fn insert(&mut self, path: impl AsRef<Path>, contents: impl Into<Arc<[u8]>>) {
+        self.files.insert(normalize(path.as_ref()), contents.into());
+    }
+
+    /// Files and folders directly in `dir`, with `dir` in front of their names.
+    fn entries(&self, dir: &Path, want_dirs: bool) -> io::Result<Vec<PathBuf>> {
+        let normalized = normalize(dir);
+        let mut entries: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter_map(|path| {
+                let mut rest = path.strip_prefix(&normalized).ok()?.components();
+                let name = rest.next()?;
+                (rest.next().is_some() == want_dirs).then(|| dir.join(name))
+            })
+            .collect();
+        if entries.is_empty() && !self.files.keys().any(|path| path.starts_with(&normalized)) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no files under {}", dir.display())));
+        }
+        entries.dedup();
+        Ok(entries)
+    }
+}
+
+impl FileSource for MemoryFiles {
+    fn open_at(&self, path: &Path, byte: u64) -> io::Result<Box<dyn Read + Send>> {
+        let contents = self.files.get(&normalize(path)).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} was not fetched", path.display())))?;
+        let mut reader = Cursor::new(contents.clone());
+        reader.set_position(byte);
+        Ok(Box::new(reader))
+    }
+
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.entries(dir, false)
+    }
+
+    fn dirs(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.entries(dir, true)
+    }
+}
+
+/// `path` without `.` parts, so `./a.csv` and `a.csv` are the same file.
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|component| *component != Component::CurDir).collect()
+}
+
+/// Lists the files `fetch` downloads, one path relative to the base URL per
+/// line. Blank lines and lines starting with `#` are skipped.
+pub const MANIFEST: &str = "files.txt";
+
+/// The files named in the `MANIFEST` at `base_url`, fetched one after the other.
+#[cfg(target_arch = "wasm32")]
+pub async fn fetch(base_url: &str) -> Result<MemoryFiles, String> {
+    let base_url = base_url.trim_end_matches('/');
+    let manifest = fetch_bytes(&format!("{base_url}/{MANIFEST}")).await?;
+    let manifest = String::from_utf8(manifest).map_err(|e| format!("{MANIFEST}: {e}"))?;
+    let mut source = MemoryFiles::default();
+    for name in manifest.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        source.insert(name, fetch_bytes(&format!("{base_url}/{name}")).await?);
+    }
+    Ok(source)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_bytes(url: &str) -> Result<Vec<u8>, String> {
+    let response = ehttp::fetch_async(ehttp::Request::get(url)).await.map_err(|e| format!("{url}: {e}"))?;
+    if !response.ok {
+        return Err(format!("{url}: {} {}", response.status, response.status_text));
+    }
+    Ok(response.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_files_list_and_read_like_a_folder() {
+        let mut source = MemoryFiles::default();
+        source.insert("led_coords.csv", b"x_led,y_led\n0,0\n".to_vec());
+        source.insert("monza/time_delta_sainz_start.csv", b"date,x_led,y_led\n".to_vec());
+        source.insert("monza/events.csv", b"".to_vec());
+
+        assert_eq!(source.files(Path::new(".")).unwrap(), [PathBuf::from("./led_coords.csv")]);
+        assert_eq!(source.dirs(Path::new(".")).unwrap(), [PathBuf::from("./monza")]);
+        assert_eq!(source.files(Path::new("./monza")).unwrap().len(), 2);
+        assert!(source.files(Path::new("spa")).is_err());
+
+        let mut text = String::new();
+        source.open_at(Path::new("./led_coords.csv"), 12).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "0,0\n");
+        assert!(source.open_at(Path::new("missing.csv"), 0).is_err());
+    }
+
+    #[test]
+    fn added_files_are_read_ahead_of_the_source() {
+        let mut fetched = MemoryFiles::default();
+        fetched.insert("led_coords.csv", b"x_led,y_led\n".to_vec());
+        fetched.insert("monza/time_delta_sainz_start.csv", b"".to_vec());
+        let source = WithAdded::new(fetched);
+        assert!(source.dirs(Path::new("dropped")).is_err());
+
+        source.added.write().unwrap().insert("led_coords.csv", b"x_led,y_led\n0,0\n".to_vec());
+        source.added.write().unwrap().insert("dropped/time_delta_albon_start.csv", b"".to_vec());
+        assert_eq!(source.dirs(Path::new(".")).unwrap(), [PathBuf::from("./dropped"), PathBuf::from("./monza")]);
+        assert_eq!(source.files(Path::new(".")).unwrap(), [PathBuf::from("./led_coords.csv")]);
+        assert_eq!(source.files(Path::new("dropped")).unwrap(), [PathBuf::from("dropped/time_delta_albon_start.csv")]);
+        let mut text = String::new();
+        source.open_at(Path::new("./led_coords.csv"), 0).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "x_led,y_led\n0,0\n", "the added file wins");
+        assert!(source.open_at(Path::new("monza/time_delta_sainz_start.csv"), 0).is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Naive formats tried after RFC 3339, interpreted as UTC.
+const NAIVE_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"];
+
+/// What the clock in the top bar shows, cycled by clicking it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockMode {
+    /// Time of day of the data, in UTC as recorded.
+    #[default]
+    Utc,
+    /// Time of day in this computer's time zone.
+    Local,
+    /// Time since lights out, the first row of the race.
+    Session,
+}
+
+impl ClockMode {
+    /// The mode a click on the clock switches to.
+    pub fn next(self) -> Self {
+        match self {
+            ClockMode::Utc => ClockMode::Local,
+            ClockMode::Local => ClockMode::Session,
+            ClockMode::Session => ClockMode::Utc,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ClockMode::Utc => "UTC time of day",
+            ClockMode::Local => "Local time of day",
+            ClockMode::Session => "Time since lights out",
+        }
+    }
+}
+
+/// Parses a recorded `date` value, trying in order: RFC 3339 (with `T` or a
+/// space separator), a naive date-time assumed to be UTC, and integer epoch
+/// milliseconds.
+pub fn parse_timestamp(s: &str) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%:z") {
+        return Ok(date.with_timezone(&Utc));
+    }
+    for format in NAIVE_FORMATS {
+        if let Ok(date) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(date.and_utc());
+        }
+    }
+    if let Ok(ms) = s.parse::<i64>() {
+        if let Some(date) = Utc.timestamp_millis_opt(ms).single() {
+            return Ok(date);
+        }
+    }
+    Err(format!(
+        "unrecognised date {s:?} (expected RFC 3339, `YYYY-MM-DD HH:MM:SS[.fff]` or epoch milliseconds)"
+    ))
+}
+
+/// A moment of the race, as `--start-at` takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaceTime {
+    /// Milliseconds since the first row.
+    Elapsed(u64),
+    Date(DateTime<Utc>),
+    /// On whichever day puts it nearest the rows, so a race run over
+    /// midnight is found on either side of it.
+    TimeOfDay(NaiveTime),
+}
+
+impl RaceTime {
+    /// The date this is in a race whose rows are dated from `first` to `last`.
+    pub fn resolve(self, first: DateTime<Utc>, last: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            RaceTime::Elapsed(ms) => first + chrono::Duration::milliseconds(ms as i64),
+            RaceTime::Date(date) => date,
+            RaceTime::TimeOfDay(time) => {
+                // How far outside the rows it is on a day, from the day before the first to the day after the last
+                let outside = |date: DateTime<Utc>| (first - date).max(date - last).max(chrono::Duration::zero());
+                let (from, to) = (first.date_naive().pred_opt().unwrap_or(first.date_naive()), last.date_naive().succ_opt().unwrap_or(last.date_naive()));
+                from.iter_days().take_while(|day| *day <= to).map(|day| day.and_time(time).and_utc()).min_by_key(|&date| outside(date)).unwrap_or(first)
+            }
+        }
+    }
+}
+
+/// Parses a time since the first row such as `5m30s`, `1h2m` or `+90.5`
+/// seconds, a time of day such as `15:04:22.500`, or a date in any format
+/// `parse_timestamp` takes.
+pub fn parse_race_time(s: &str) -> Result<RaceTime, String> {
+    let s = s.trim();
+    if let Some(ms) = parse_elapsed(s) {
+        return Ok(RaceTime::Elapsed(ms));
+    }
+    if let Some(time) = ["%H:%M:%S%.f", "%H:%M"].iter().find_map(|format| NaiveTime::parse_from_str(s, format).ok()) {
+        return Ok(RaceTime::TimeOfDay(time));
+    }
+    parse_timestamp(s)
+        .map(RaceTime::Date)
+        .map_err(|_| format!("`{s}` is not a time since the start like 5m30s, a time of day like 15:04:22.500 or a date"))
+}
+
+/// `5m30s`, `1h2m`, `90.5s` or `+90.5` in milliseconds. Hours, minutes and
+/// seconds each come at most once and in that order.
+fn parse_elapsed(s: &str) -> Option<u64> {
+    let (plus, s) = s.strip_prefix('+').map_or((false, s), |rest| (true, rest));
+    let ms = match s.parse::<f64>() {
+        Ok(seconds) if plus => seconds * 1000.0,
+        Ok(_) => return None, // A bare number is epoch milliseconds
+        Err(_) => {
+            let mut units = [('h', 3_600_000.0), ('m', 60_000.0), ('s', 1000.0)].into_iter();
+            let (mut ms, mut rest) = (0.0, s);
+            while !rest.is_empty() {
+                let end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+                let value: f64 = rest[..end].parse().ok()?;
+                let unit = rest[end..].chars().next()?;
+                let (_, scale) = units.find(|&(name, _)| name == unit)?;
+                ms += value * scale;
+                rest = &rest[end + unit.len_utf8()..];
+            }
+            ms
+        }
+    };
+    (!s.is_empty() && ms.is_finite() && ms >= 0.0).then(|| ms.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 9, 16, 13, 3, 4).unwrap() + chrono::Duration::milliseconds(213)
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        assert_eq!(parse_timestamp("2023-09-16T13:03:04.213000+00:00"), Ok(expected()));
+        assert_eq!(parse_timestamp("2023-09-16T15:03:04.213+02:00"), Ok(expected()));
+        assert_eq!(parse_timestamp("2023-09-16T13:03:04.213Z"), Ok(expected()));
+    }
+
+    #[test]
+    fn parses_space_separator() {
+        assert_eq!(parse_timestamp("2023-09-16 13:03:04.213"), Ok(expected()));
+        assert_eq!(parse_timestamp("2023-09-16 13:03:04.213+00:00"), Ok(expected()));
+    }
+
+    #[test]
+    fn parses_naive_as_utc() {
+        assert_eq!(parse_timestamp("2023-09-16T13:03:04.213"), Ok(expected()));
+        assert_eq!(
+            parse_timestamp("2023-09-16T13:03:04"),
+            Ok(Utc.with_ymd_and_hms(2023, 9, 16, 13, 3, 4).unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_epoch_millis() {
+        let ms = expected().timestamp_millis().to_string();
+        assert_eq!(parse_timestamp(&ms), Ok(expected()));
+    }
+
+    #[test]
+    fn parses_race_times() {
+        assert_eq!(parse_race_time("5m30s"), Ok(RaceTime::Elapsed(330_000)));
+        assert_eq!(parse_race_time("1h2m3.5s"), Ok(RaceTime::Elapsed(3_723_500)));
+        assert_eq!(parse_race_time("+90.5"), Ok(RaceTime::Elapsed(90_500)));
+        assert_eq!(parse_race_time("15:04:22.500"), Ok(RaceTime::TimeOfDay(NaiveTime::from_hms_milli_opt(15, 4, 22, 500).unwrap())));
+        assert_eq!(parse_race_time("2023-09-16T13:03:04.213Z"), Ok(RaceTime::Date(expected())));
+        for wrong in ["30s5m", "5x", "m", "+", "-5s"] {
+            assert!(parse_race_time(wrong).is_err(), "{wrong}");
+        }
+    }
+
+    #[test]
+    fn times_of_day_are_found_on_the_day_nearest_the_rows() {
+        let at = |day: u32, hour: u32, min: u32| Utc.with_ymd_and_hms(2023, 9, day, hour, min, 0).unwrap();
+        let time = |hour: u32, min: u32| RaceTime::TimeOfDay(NaiveTime::from_hms_opt(hour, min, 0).unwrap());
+        // A race from 23:50 to 00:20 the next day
+        let (first, last) = (at(16, 23, 50), at(17, 0, 20));
+        assert_eq!(time(23, 55).resolve(first, last), at(16, 23, 55));
+        assert_eq!(time(0, 5).resolve(first, last), at(17, 0, 5), "after midnight");
+        assert_eq!(time(0, 30).resolve(first, last), at(17, 0, 30), "just after the end");
+        assert_eq!(time(23, 0).resolve(first, last), at(16, 23, 0), "just before the start");
+        assert_eq!(time(13, 3).resolve(expected(), expected()), at(16, 13, 3));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let err = parse_timestamp("16/09/2023 13:03").unwrap_err();
+        assert!(err.contains("\"16/09/2023 13:03\""), "{err}");
+    }
+}
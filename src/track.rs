@@ -0,0 +1,184 @@
+use crate::data::LedCoordinate;
+
+/// Uniform grid over the LED positions, so matching a car position to its
+/// nearest LED only looks at the few cells around it instead of every LED.
+pub struct LedIndex {
+    points: Vec<(f64, f64)>,
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+    cell: f64,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+    snap_distance: Option<f64>, // Furthest a position may be from its LED, unlimited when unset
+}
+
+impl LedIndex {
+    pub fn new(coordinates: &[LedCoordinate]) -> Self {
+        let points: Vec<(f64, f64)> = coordinates.iter().map(|coord| (coord.x_led, coord.y_led)).collect();
+        let (min_x, max_x, min_y, max_y) = points.iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY),
+            |(min_x, max_x, min_y, max_y), &(x, y)| (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y)),
+        );
+        if points.is_empty() {
+            return Self { points, min_x: 0.0, min_y: 0.0, max_x: 0.0, max_y: 0.0, cell: 1.0, cols: 0, rows: 0, cells: Vec::new(), snap_distance: None };
+        }
+
+        // Size cells so there is roughly one LED per cell
+        let (width, height) = (max_x - min_x, max_y - min_y);
+        let cell = (width.max(height) / points.len() as f64)
+            .max((width * height / points.len() as f64).sqrt())
+            .max(f64::EPSILON);
+        let cols = (width / cell) as usize + 1;
+        let rows = (height / cell) as usize + 1;
+
+        let mut index = Self { points, min_x, min_y, max_x, max_y, cell, cols, rows, cells: vec![Vec::new(); cols * rows], snap_distance: None };
+        for led in 0..index.points.len() {
+            let (x, y) = index.points[led];
+            let (col, row) = index.cell_of(x, y);
+            index.cells[row * cols + col].push(led);
+        }
+        index
+    }
+
+    /// Lets `snap` match positions only to LEDs at most `distance` away.
+    pub fn with_snap_distance(mut self, distance: Option<f64>) -> Self {
+        self.snap_distance = distance;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Index of the LED closest to `(x, y)`, or `None` if there are no LEDs.
+    pub fn nearest(&self, x: f64, y: f64) -> Option<usize> {
+        self.closest(x, y).map(|(_, led)| led)
+    }
+
+    /// `nearest`, but `None` as well when the closest LED is further away
+    /// than the snap distance: the position is off the map.
+    pub fn snap(&self, x: f64, y: f64) -> Option<usize> {
+        let (dist, led) = self.closest(x, y)?;
+        self.snap_distance.is_none_or(|snap| dist <= snap * snap).then_some(led)
+    }
+
+    /// Whether `(x, y)` is on the track as far as can be told: inside the
+    /// box around the LEDs, or with a snap distance close enough to snap to
+    /// one. A car mostly elsewhere has its data in other coordinates.
+    pub fn on_track(&self, x: f64, y: f64) -> bool {
+        let inside = !self.points.is_empty() && (self.min_x..=self.max_x).contains(&x) && (self.min_y..=self.max_y).contains(&y);
+        inside || (self.snap_distance.is_some() && self.snap(x, y).is_some())
+    }
+
+    /// The closest LED with its squared distance from `(x, y)`.
+    fn closest(&self, x: f64, y: f64) -> Option<(f64, usize)> {
+        self.closest_where(x, y, |_, _| true)
+    }
+
+    /// `closest`, of only the LEDs `keep` takes given their index and squared distance.
+    fn closest_where(&self, x: f64, y: f64, keep: impl Fn(usize, f64) -> bool) -> Option<(f64, usize)> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let (col, row) = self.cell_of(x, y);
+        let mut best: Option<(f64, usize)> = None;
+
+        // Search rings of cells outwards. Points beyond ring `r` are at least
+        // `r * cell` away, so stop once the best match is closer than that.
+        for ring in 0..self.cols.max(self.rows) {
+            for (c, r) in self.ring_cells(col, row, ring) {
+                for &led in &self.cells[r * self.cols + c] {
+                    let (px, py) = self.points[led];
+                    let dist = (px - x).powi(2) + (py - y).powi(2);
+                    if best.is_none_or(|(best_dist, _)| dist < best_dist) && keep(led, dist) {
+                        best = Some((dist, led));
+                    }
+                }
+            }
+            if let Some((best_dist, _)) = best {
+                let reach = ring as f64 * self.cell;
+                if best_dist <= reach * reach {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// Grid cell containing `(x, y)`, clamped to the grid.
+    fn cell_of(&self, x: f64, y: f64) -> (usize, usize) {
+        let col = ((x - self.min_x) / self.cell).max(0.0) as usize;
+        let row = ((y - self.min_y) / self.cell).max(0.0) as usize;
+        (col.min(self.cols - 1), row.min(self.rows - 1))
+    }
+
+    /// Cells at Chebyshev distance `ring` from `(col, row)` that lie inside the grid.
+    fn ring_cells(&self, col: usize, row: usize, ring: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let ring = ring as isize;
+        let (col, row) = (col as isize, row as isize);
+        (-ring..=ring)
+            .flat_map(move |dy| (-ring..=ring).map(move |dx| (dx, dy)))
+            .filter(move |&(dx, dy)| dx.abs() == ring || dy.abs() == ring)
+            .map(move |(dx, dy)| (col + dx, row + dy))
+            .filter(|&(c, r)| c >= 0 && r >= 0 && (c as usize) < self.cols && (r as usize) < self.rows)
+            .map(|(c, r)| (c as usize, r as usize))
+    }
+}
+
+/// How far apart the LEDs are, in track units.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Spacing {
+    /// Mean distance from each LED to its nearest neighbour.
+    pub average: f64,
+    /// The smallest of those distances. LEDs at the very same point are
+    /// left out, as nothing keeps them apart anyway.
+    pub min: f64,
+}
+
+impl Spacing {
+    /// Zero for fewer than two LEDs at different points.
+    pub fn of(coordinates: &[LedCoordinate]) -> Self {
+        let index = LedIndex::new(coordinates);
+        let nearest: Vec<f64> = coordinates
+            .iter()
+            .filter_map(|a| index.closest_where(a.x_led, a.y_led, |_, dist| dist > 0.0))
+            .map(|(dist, _)| dist.sqrt())
+            .collect();
+        if nearest.is_empty() {
+            return Self::default();
+        }
+        Self {
+            average: nearest.iter().sum::<f64>() / nearest.len() as f64,
+            min: nearest.iter().copied().fold(f64::INFINITY, f64::min),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_only_within_the_snap_distance() {
+        let leds: Vec<LedCoordinate> = (0..4).map(|i| LedCoordinate { x_led: i as f64 * 10.0, ..Default::default() }).collect();
+        let index = LedIndex::new(&leds);
+        assert_eq!((index.nearest(31.0, 50.0), index.snap(31.0, 50.0)), (Some(3), Some(3)), "no snap distance matches anything");
+
+        let index = index.with_snap_distance(Some(5.0));
+        assert_eq!(index.snap(12.0, 4.0), Some(1));
+        assert_eq!(index.snap(31.0, 50.0), None);
+        assert_eq!(index.nearest(31.0, 50.0), Some(3));
+        assert_eq!(LedIndex::new(&[]).snap(0.0, 0.0), None);
+    }
+
+    #[test]
+    fn spacing_leaves_out_leds_at_the_same_point() {
+        let leds: Vec<LedCoordinate> = [0.0, 10.0, 25.0, 25.0].iter().map(|&x_led| LedCoordinate { x_led, ..Default::default() }).collect();
+        assert_eq!(Spacing::of(&leds), Spacing { average: 12.5, min: 10.0 });
+        assert_eq!(Spacing::of(&leds[2..]), Spacing::default());
+        assert_eq!(Spacing::of(&[]), Spacing::default());
+    }
+}
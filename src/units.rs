@@ -0,0 +1,36 @@
+/// km/h in one metre per second.
+const KMH_PER_METRE_PER_SECOND: f64 = 3.6;
+
+/// A speed of `units_per_second` coordinate units a second as shown, with
+/// its unit: km/h when `metres_per_unit` gives the real size of a unit.
+pub fn speed(units_per_second: f64, metres_per_unit: Option<f64>) -> (f64, &'static str) {
+    match metres_per_unit {
+        Some(metres) => (units_per_second * metres * KMH_PER_METRE_PER_SECOND, "km/h"),
+        None => (units_per_second, "units/s"),
+    }
+}
+
+/// A distance of `units` coordinate units as shown, with its unit: metres
+/// when `metres_per_unit` gives the real size of a unit.
+pub fn distance(units: f64, metres_per_unit: Option<f64>) -> (f64, &'static str) {
+    match metres_per_unit {
+        Some(metres) => (units * metres, "m"),
+        None => (units, "units"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_kmh_and_metres_when_the_scale_is_known() {
+        // 50 units of 2 m in 1.2 s is 100 m in 1.2 s, 83.3 m/s
+        let (kmh, unit) = speed(50.0 / 1.2, Some(2.0));
+        assert!((kmh - 300.0).abs() < 1e-9);
+        assert_eq!(unit, "km/h");
+        assert_eq!(distance(12.5, Some(2.0)), (25.0, "m"));
+        assert_eq!(speed(4.0, None), (4.0, "units/s"));
+        assert_eq!(distance(12.5, None), (12.5, "units"));
+    }
+}
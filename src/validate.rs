@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bookmarks::format_sim_ms;
+use crate::data::{self, DataIssue};
+use crate::loader::{self, ReadOptions};
+use crate::track::LedIndex;
+
+/// What `--validate` found in one input file.
+#[derive(Debug)]
+pub struct Checked {
+    pub path: PathBuf,
+    /// What the file holds, or why it cannot be used.
+    pub summary: Result<String, String>,
+    /// Problems that still leave it usable.
+    pub issues: Vec<DataIssue>,
+}
+
+/// Reads the LED coordinates and every dataset in `paths` the way playback
+/// would, as `options` say, without opening the window. `missing` are the
+/// problems finding the datasets, each one fatal. The datasets are matched
+/// to the LEDs for how much of each is on the track, or read on their own
+/// if the coordinates cannot be.
+pub fn check(coordinates_path: &Path, paths: &[PathBuf], missing: Vec<DataIssue>, options: ReadOptions, snap_distance: Option<f64>) -> Vec<Checked> {
+    let mut checked = Vec::new();
+    let led_index = match data::read_coordinates(coordinates_path, options.delimiter) {
+        Ok(coordinates) => {
+            let summary = format!("{} LEDs, {}", coordinates.records.len(), layout(coordinates_path, options.delimiter));
+            checked.push(Checked { path: coordinates_path.to_path_buf(), summary: Ok(summary), issues: coordinates.issues.clone() });
+            LedIndex::new(&coordinates.records)
+        }
+        Err(e) => {
+            checked.push(Checked { path: coordinates_path.to_path_buf(), summary: Err(e.to_string()), issues: Vec::new() });
+            LedIndex::new(&[])
+        }
+    };
+    let led_index = Arc::new(led_index.with_snap_distance(snap_distance));
+    checked.extend(missing.into_iter().map(|issue| Checked { path: issue.file, summary: Err(issue.message), issues: Vec::new() }));
+
+    for path in paths {
+        let (summary, issues) = match loader::read_dataset(path, &led_index, options) {
+            Ok((dataset, issues)) => {
+                let span = dataset.date(0).zip(dataset.len().checked_sub(1).and_then(|last| dataset.date(last)));
+                let summary = match span {
+                    Some((first, last)) => {
+                        let on_track = dataset.on_track.map_or_else(String::new, |share| format!(", {:.1}% on the track", share * 100.0));
+                        Ok(format!(
+                            "{} rows over {} from {} to {}{on_track}, {}",
+                            dataset.len(),
+                            format_sim_ms((last - first).num_milliseconds().max(0) as u64),
+                            first.format("%Y-%m-%d %H:%M:%S%.3f"),
+                            last.format("%Y-%m-%d %H:%M:%S%.3f"),
+                            layout(path, options.delimiter)
+                        ))
+                    }
+                    None => Err("no rows".to_string()),
+                };
+                (summary, issues)
+            }
+            Err(e) => (Err(e), Vec::new()),
+        };
+        checked.push(Checked { path: path.clone(), summary, issues });
+    }
+    checked
+}
+
+/// How the fields of `path` are read, with `configured` as the delimiter if set.
+fn layout(path: &Path, configured: Option<u8>) -> String {
+    if data::is_parquet(path) {
+        return "Parquet".to_string();
+    }
+    match data::delimiter(path, configured) {
+        b',' => "comma separated".to_string(),
+        b'\t' => "tab separated".to_string(),
+        b';' => "semicolon separated".to_string(),
+        delimiter => format!("separated by {:?}", delimiter as char),
+    }
+}
+
+/// A line for each file checked, those that cannot be used marked as
+/// errors, with its problems below it, and a count of both to end. Passes
+/// when there are no errors, nor any problems at all when `strict`.
+pub fn report(checked: &[Checked], strict: bool) -> (String, bool) {
+    let mut text = String::new();
+    let (mut errors, mut warnings) = (0, 0);
+    for Checked { path, summary, issues } in checked {
+        match summary {
+            Ok(summary) => text += &format!("{}: {summary}\n", path.display()),
+            Err(e) => {
+                errors += 1;
+                text += &format!("{}: error: {e}\n", path.display());
+            }
+        }
+        for issue in issues {
+            warnings += 1;
+            match issue.line {
+                Some(line) => text += &format!("  warning: line {line}: {}\n", issue.message),
+                None => text += &format!("  warning: {}\n", issue.message),
+            }
+        }
+    }
+    let passed = errors == 0 && !(strict && warnings > 0);
+    let verdict = match (passed, strict && warnings > 0) {
+        (true, _) => "ok",
+        (false, true) if errors == 0 => "failed, --strict is set",
+        (false, _) => "failed",
+    };
+    text += &format!("{} checked: {}, {}, {verdict}\n", counted(checked.len(), "input"), counted(errors, "error"), counted(warnings, "warning"));
+    (text, passed)
+}
+
+/// `1 error`, `2 errors`.
+fn counted(count: usize, thing: &str) -> String {
+    format!("{count} {thing}{}", if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn each_file_is_summed_up_and_unusable_ones_fail_the_check() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-validate", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let coordinates = dir.join("led_coords.csv");
+        fs::write(&coordinates, "x_led,y_led\n0,0\n1,0\n2,0\n").unwrap();
+        let good = dir.join("time_delta_albon_start.csv");
+        let rows = "date,x_led,y_led,time_delta\n2023-08-27T12:01:00Z,0,0,0\n2023-08-27T12:01:01.5Z,1,0,1500\n2023-08-27T12:01:01Z,9,9,0\n";
+        fs::write(&good, rows).unwrap();
+        let empty = dir.join("time_delta_sainz_start.tsv");
+        fs::write(&empty, "date\tx_led\ty_led\ttime_delta\n").unwrap();
+        let gone = dir.join("time_delta_norris_start.csv");
+        let options = ReadOptions::default();
+
+        let checked = check(&coordinates, &[good.clone(), empty.clone(), gone.clone()], Vec::new(), options, None);
+        let summaries: Vec<_> = checked.iter().map(|checked| checked.summary.clone().map_err(|e| e.split(':').next().unwrap().to_string())).collect();
+        assert_eq!(summaries[0], Ok("3 LEDs, comma separated".to_string()));
+        assert_eq!(
+            summaries[1],
+            Ok("3 rows over 0:01.500 from 2023-08-27 12:01:00.000 to 2023-08-27 12:01:01.500, 66.7% on the track, comma separated".to_string())
+        );
+        assert_eq!(summaries[2], Err("no rows".to_string()));
+        assert!(summaries[3].is_err(), "cannot be read");
+        assert_eq!(checked[1].issues.len(), 1, "the row out of order, and none for the one off the track");
+
+        let (text, passed) = report(&checked, false);
+        assert!(!passed);
+        assert!(text.contains("time_delta_albon_start.csv: 3 rows over 0:01.500 from 2023-08-27 12:01:00.000 to 2023-08-27 12:01:01.500, 66.7% on the track, comma separated\n  warning: moved 1 row into date order\n"), "{text}");
+        assert!(text.ends_with("4 inputs checked: 2 errors, 1 warning, failed\n"), "{text}");
+        let (text, passed) = report(&checked[..2], true);
+        assert!(!passed && text.ends_with("failed, --strict is set\n"), "{text}");
+        assert!(report(&checked[..2], false).1);
+        assert!(check(&dir.join("missing.csv"), &[], Vec::new(), options, None)[0].summary.is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
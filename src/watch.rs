@@ -0,0 +1,136 @@
+// The web build reads its files once when the page loads, so it has no
+// watcher and `FileWatcher::start` always fails there
+#![cfg_attr(target_arch = "wasm32", allow(unused_imports, dead_code))]
+
+#[cfg(not(target_arch = "wasm32"))]
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use web_time::Instant;
+
+/// How long a changed file must stay unchanged before it is reported, so a
+/// file still being written is not read half way.
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Watches files for changes on disk. The folders holding them are watched
+/// rather than the files, so editors and scripts that write a new file and
+/// rename it over the old one are noticed too.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher, // Stops watching when dropped
+    receiver: Receiver<notify::Result<notify::Event>>,
+    /// Each file as given, and as the events name it.
+    files: Vec<(PathBuf, PathBuf)>,
+    changed: BTreeSet<usize>,
+    last_change: Option<Instant>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileWatcher {
+    pub fn start(files: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        let files: Vec<_> = files.iter().map(|file| (file.clone(), absolute(file))).collect();
+        let dirs: BTreeSet<_> = files.iter().filter_map(|(_, watched)| watched.parent()).collect();
+        for dir in dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("cannot watch {}: {e}", dir.display()))?;
+        }
+        Ok(Self { _watcher: watcher, receiver, files, changed: BTreeSet::new(), last_change: None })
+    }
+
+    /// The files that changed, once none has changed for a moment.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        self.poll_at(Instant::now())
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Vec<PathBuf> {
+        for event in self.receiver.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("warning: watching files: {e}");
+                    continue;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+                continue;
+            }
+            for path in &event.paths {
+                if let Some(i) = self.files.iter().position(|(_, watched)| watched == path) {
+                    self.changed.insert(i);
+                    self.last_change = Some(now);
+                }
+            }
+        }
+        match self.last_change {
+            Some(last) if now.duration_since(last) >= SETTLE => {
+                self.last_change = None;
+                let changed = std::mem::take(&mut self.changed);
+                changed.into_iter().map(|i| self.files[i].0.clone()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct FileWatcher;
+
+#[cfg(target_arch = "wasm32")]
+impl FileWatcher {
+    pub fn start(_files: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        Err("files fetched by the browser cannot be watched".into())
+    }
+
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// `path` with its folder resolved, the way change events name it.
+fn absolute(path: &Path) -> PathBuf {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    match path.file_name() {
+        Some(name) => dir.join(name),
+        None => dir,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::thread;
+
+    #[test]
+    fn changes_are_reported_once_they_settle() {
+        let dir = std::env::temp_dir().join(format!("f1-led-{}-watch", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (watched, other) = (dir.join("led_coords.csv"), dir.join("notes.txt"));
+        fs::write(&watched, "x_led,y_led\n").unwrap();
+        let mut watcher = FileWatcher::start(std::slice::from_ref(&watched)).unwrap();
+
+        fs::write(&other, "not watched").unwrap();
+        fs::write(&watched, "x_led,y_led\n0,0\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut changed = Vec::new();
+        while changed.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+            assert!(watcher.poll_at(Instant::now()).is_empty(), "reported before settling");
+            changed = watcher.poll_at(Instant::now() + SETTLE);
+        }
+        assert_eq!(changed, [watched]);
+        assert!(watcher.poll_at(Instant::now() + SETTLE).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use eframe::egui::{self, Vec2, ViewportBuilder, ViewportCommand, WindowLevel};
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::config::{Config, MIN_WINDOW_SIZE};
+
+/// How the native window opens and what it lets the keyboard change, as
+/// the config and command line ask, e.g. for a display left running as
+/// signage.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowOptions {
+    pub size: Option<[u32; 2]>, // Inner size in points
+    pub fullscreen: bool,
+    pub borderless: bool, // No title bar or borders
+    pub monitor: Option<usize>,
+    pub always_on_top: bool,
+    pub hide_cursor_after: Option<Duration>, // Of the pointer not moving
+    pub kiosk: bool, // Fullscreen that the keyboard cannot leave
+}
+
+impl WindowOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            size: config.window_size,
+            fullscreen: config.fullscreen || config.kiosk,
+            borderless: config.borderless,
+            monitor: config.monitor,
+            always_on_top: config.always_on_top,
+            hide_cursor_after: config.hide_cursor_after.map(Duration::from_secs_f64),
+            kiosk: config.kiosk,
+        }
+    }
+
+    /// The window as it first opens. Going fullscreen waits for the first
+    /// frame when a monitor is asked for, so it happens on that monitor.
+    pub fn viewport(&self) -> ViewportBuilder {
+        let mut viewport = ViewportBuilder::default()
+            .with_min_inner_size(MIN_WINDOW_SIZE.map(|points| points as f32))
+            .with_fullscreen(self.fullscreen && self.monitor.is_none())
+            .with_decorations(!self.borderless);
+        if let Some(size) = self.size {
+            viewport = viewport.with_inner_size(size.map(|points| points as f32));
+        }
+        if self.always_on_top {
+            viewport = viewport.with_always_on_top();
+        }
+        viewport
+    }
+
+    /// Commands that put the window as asked once it is open, over the size,
+    /// position and fullscreen state kept from last time, which win over the
+    /// `viewport` ones. eframe cannot list the monitors, so they are taken
+    /// to be side by side, left to right, each of `monitor_size`, the size
+    /// of the one the window opened on. Nothing until that size is known
+    /// when a monitor is asked for.
+    pub fn startup_commands(&self, monitor_size: Option<Vec2>) -> Option<Vec<ViewportCommand>> {
+        let mut commands = Vec::new();
+        if let Some(monitor) = self.monitor {
+            commands.push(ViewportCommand::OuterPosition(egui::pos2(monitor as f32 * monitor_size?.x, 0.0)));
+        }
+        if let Some(size) = self.size {
+            commands.push(ViewportCommand::InnerSize(size.map(|points| points as f32).into()));
+        }
+        if self.fullscreen {
+            commands.push(ViewportCommand::Fullscreen(true));
+        }
+        if self.borderless {
+            commands.push(ViewportCommand::Decorations(false));
+        }
+        if self.always_on_top {
+            commands.push(ViewportCommand::WindowLevel(WindowLevel::AlwaysOnTop));
+        }
+        Some(commands)
+    }
+
+    /// Whether to go fullscreen, or leave it, on F11 or Escape pressed while
+    /// the window is `fullscreen` or not. A kiosk stays as it is.
+    pub fn toggle_fullscreen(&self, fullscreen: bool, f11: bool, escape: bool) -> Option<bool> {
+        if self.kiosk {
+            None
+        } else if f11 {
+            Some(!fullscreen)
+        } else if escape && fullscreen {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// Hides the pointer once it has stayed still over the window for a while,
+/// and shows it again as soon as it moves.
+#[derive(Debug, Clone)]
+pub struct CursorHider {
+    after: Duration,
+    moved: Instant,
+    hidden: bool,
+}
+
+impl CursorHider {
+    pub fn new(after: Duration, now: Instant) -> Self {
+        Self { after, moved: now, hidden: false }
+    }
+
+    /// Whether the pointer is to be shown or hidden now that it `moved` or
+    /// not, when that changed.
+    pub fn update(&mut self, moved: bool, now: Instant) -> Option<bool> {
+        if moved {
+            self.moved = now;
+        }
+        let hide = now.saturating_duration_since(self.moved) >= self.after;
+        (hide != self.hidden).then(|| {
+            self.hidden = hide;
+            !hide
+        })
+    }
+
+    /// How long until the pointer is hidden, if it is shown.
+    pub fn until_hidden(&self, now: Instant) -> Option<Duration> {
+        (!self.hidden).then(|| self.after.saturating_sub(now.saturating_duration_since(self.moved)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_commands_put_the_window_on_its_monitor_before_going_fullscreen() {
+        let options = WindowOptions { fullscreen: true, monitor: Some(2), size: Some([800, 600]), ..Default::default() };
+        assert!(!options.viewport().fullscreen.unwrap(), "fullscreen waits for the monitor");
+        assert_eq!(options.startup_commands(None), None);
+        assert_eq!(
+            options.startup_commands(Some(egui::vec2(1920.0, 1080.0))).unwrap(),
+            [ViewportCommand::OuterPosition(egui::pos2(3840.0, 0.0)), ViewportCommand::InnerSize(egui::vec2(800.0, 600.0)), ViewportCommand::Fullscreen(true)]
+        );
+        assert_eq!(WindowOptions::default().startup_commands(None), Some(Vec::new()));
+    }
+
+    #[test]
+    fn f11_and_escape_change_fullscreen_unless_a_kiosk() {
+        let options = WindowOptions::default();
+        assert_eq!(options.toggle_fullscreen(false, true, false), Some(true));
+        assert_eq!(options.toggle_fullscreen(true, true, false), Some(false));
+        assert_eq!(options.toggle_fullscreen(true, false, true), Some(false));
+        assert_eq!(options.toggle_fullscreen(false, false, true), None, "escape only leaves fullscreen");
+        let kiosk = WindowOptions { kiosk: true, fullscreen: true, ..Default::default() };
+        assert_eq!((kiosk.toggle_fullscreen(true, true, false), kiosk.toggle_fullscreen(true, false, true)), (None, None));
+    }
+
+    #[test]
+    fn the_cursor_hides_when_still_and_shows_when_moved() {
+        let start = Instant::now();
+        let mut cursor = CursorHider::new(Duration::from_secs(3), start);
+        assert_eq!(cursor.update(false, start + Duration::from_secs(1)), None);
+        assert_eq!(cursor.until_hidden(start + Duration::from_secs(1)), Some(Duration::from_secs(2)));
+        assert_eq!(cursor.update(false, start + Duration::from_secs(3)), Some(false));
+        assert_eq!((cursor.update(false, start + Duration::from_secs(4)), cursor.until_hidden(start)), (None, None));
+        assert_eq!(cursor.update(true, start + Duration::from_secs(5)), Some(true));
+        assert_eq!(cursor.update(false, start + Duration::from_secs(7)), None, "still since it moved");
+    }
+}